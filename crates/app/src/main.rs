@@ -1,9 +1,15 @@
 use anyhow::Result;
 use crossterm::event::{Event, EventStream, KeyEventKind};
 use futures::StreamExt;
-use rtfm_core::app_state::AppState;
+use rtfm_core::app_state::{self, AppState};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
 use ui::tui::{self, Tui};
 
+/// How often followed files are checked for new lines. Local files are also
+/// refreshed as soon as the directory watcher reports them changed.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
 fn setup_logger() -> Result<(), fern::InitError> {
     fern::Dispatch::new()
         .format(|out, message, record| {
@@ -28,7 +34,8 @@ struct App {
 
 impl App {
     fn new() -> Result<Self> {
-        let app_state = AppState::new();
+        let mut app_state = AppState::new();
+        app_state.set_background_jobs(true);
         Ok(Self {
             app_state,
             tui: Tui::new()?,
@@ -38,9 +45,13 @@ impl App {
     async fn run(&mut self) -> Result<()> {
         self.tui.enter()?;
         let mut event_stream = EventStream::new();
+        let mut follow_timer = tokio::time::interval(FOLLOW_INTERVAL);
+        follow_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
         'main: loop {
             self.app_state.task_manager.process_pending_tasks();
+            self.app_state.prompt_task_conflicts();
+            self.app_state.sync_watches();
 
             if let Some(timer) = self.app_state.notification_timer {
                 if timer.elapsed().as_secs() > 3 {
@@ -49,19 +60,15 @@ impl App {
                 }
             }
 
-            self.tui.terminal.draw(|frame| {
-                ui::layout::render_main_layout(frame, &self.app_state);
-            })?;
+            self.tui.draw(&mut self.app_state)?;
 
             tokio::select! {
                 biased;
                 maybe_event = event_stream.next() => {
                     if let Some(Ok(event)) = maybe_event {
                         if let Event::Key(key) = event {
-                            if key.kind == KeyEventKind::Press {
-                                if !tui::handle_key_press(key, &mut self.app_state) {
-                                    break 'main;
-                                }
+                            if key.kind == KeyEventKind::Press && !tui::handle_key_press(key, &mut self.app_state) {
+                                break 'main;
                             }
                         }
                     } else {
@@ -74,6 +81,18 @@ impl App {
                         self.app_state.get_active_tab_mut().update_entries(show_hidden);
                     }
                 }
+                (index, update) = app_state::next_tab_update(&mut self.app_state.tabs) => {
+                    self.app_state.apply_tab_update(index, update);
+                }
+                answer = app_state::next_lookup(&mut self.app_state.lookup) => {
+                    self.app_state.apply_lookup(answer);
+                }
+                changes = self.app_state.watcher.next_changes() => {
+                    self.app_state.apply_dir_changes(&changes);
+                }
+                _ = follow_timer.tick(), if self.app_state.is_following() => {
+                    self.app_state.poll_follows();
+                }
             }
         }
         self.tui.exit()?;
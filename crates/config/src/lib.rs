@@ -1,19 +1,20 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
-use directories::ProjectDirs;
+use std::path::{Path, PathBuf};
+use directories::{ProjectDirs, UserDirs};
+use indexmap::IndexMap;
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
 pub enum BackendType {
+    /// The best protocol the terminal is found to support at startup.
+    #[default]
+    Auto,
     Kitty,
-    // Sixel, // Will be added back later
-}
-
-impl Default for BackendType {
-    fn default() -> Self {
-        BackendType::Kitty
-    }
+    Sixel,
+    /// Unicode half blocks, for terminals without a graphics protocol. Also
+    /// used when the chosen protocol isn't supported.
+    HalfBlocks,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Copy)]
@@ -28,7 +29,7 @@ impl Default for Resolution {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct PreviewConfig {
     #[serde(default)]
     pub backend: BackendType,
@@ -36,6 +37,35 @@ pub struct PreviewConfig {
     pub progressive: bool,
     #[serde(default)]
     pub resolution: Resolution,
+    /// Draw the first page of PDFs above their text, rendered with `pdftoppm`.
+    #[serde(default)]
+    pub pdf_raster: bool,
+    /// Seconds an external previewer may run before it's killed.
+    #[serde(default = "default_external_timeout")]
+    pub external_timeout: f64,
+    /// Shell commands that preview files in place of the built-in previewers,
+    /// keyed by MIME type (`video/*`) or file name glob (`*.log`). The first
+    /// rule that matches wins. The file's path is passed as `$1`, and added
+    /// to the end of commands that don't use it.
+    #[serde(default)]
+    pub external: IndexMap<String, String>,
+}
+
+fn default_external_timeout() -> f64 {
+    3.0
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            backend: BackendType::default(),
+            progressive: false,
+            resolution: Resolution::default(),
+            pdf_raster: false,
+            external_timeout: default_external_timeout(),
+            external: IndexMap::new(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Default)]
@@ -48,6 +78,49 @@ pub struct Config {
     pub bookmarks: HashMap<String, PathBuf>,
     #[serde(default)]
     pub preview: PreviewConfig,
+    #[serde(default)]
+    pub remotes: BTreeMap<String, RemoteProfile>,
+}
+
+/// An SFTP connection profile, listed under "Remotes" in the left pane.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RemoteProfile {
+    pub host: String,
+    #[serde(default = "default_ssh_port")]
+    pub port: u16,
+    pub user: String,
+    /// Directory to open on the remote host. Defaults to `/`.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    #[serde(default)]
+    pub identity_file: Option<PathBuf>,
+}
+
+fn default_ssh_port() -> u16 {
+    22
+}
+
+impl RemoteProfile {
+    /// The `sftp://user@host:port/path` location of this profile.
+    pub fn url(&self) -> PathBuf {
+        let path = self.path.as_deref().unwrap_or(Path::new("/")).to_string_lossy().to_string();
+        PathBuf::from(format!(
+            "sftp://{}@{}:{}/{}",
+            self.user,
+            self.host,
+            self.port,
+            path.trim_start_matches('/')
+        ))
+    }
+
+    /// The identity file with a leading `~` expanded to the home directory.
+    pub fn identity_path(&self) -> Option<PathBuf> {
+        let path = self.identity_file.as_ref()?;
+        match (path.strip_prefix("~"), UserDirs::new()) {
+            (Ok(rest), Some(user_dirs)) => Some(user_dirs.home_dir().join(rest)),
+            _ => Some(path.clone()),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Default)]
@@ -55,9 +128,23 @@ pub struct Keybindings {
     // Add keybindings here later
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Theme {
-    // Add theme settings here later
+    /// Color scheme for syntax highlighted previews: a built-in name such as
+    /// "base16-ocean.dark", "InspiredGitHub" or "Solarized (light)", or the
+    /// path of a `.tmTheme` file.
+    #[serde(default = "default_syntax_theme")]
+    pub syntax: String,
+}
+
+fn default_syntax_theme() -> String {
+    "base16-ocean.dark".to_string()
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self { syntax: default_syntax_theme() }
+    }
 }
 
 pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
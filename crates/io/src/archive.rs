@@ -0,0 +1,489 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarBz2,
+    TarXz,
+    TarZst,
+    SevenZip,
+}
+
+// Longest suffixes first so that `.tar.gz` wins over a bare `.gz`.
+const SUFFIXES: &[(&str, ArchiveFormat)] = &[
+    (".tar.gz", ArchiveFormat::TarGz),
+    (".tar.bz2", ArchiveFormat::TarBz2),
+    (".tar.xz", ArchiveFormat::TarXz),
+    (".tar.zst", ArchiveFormat::TarZst),
+    (".tgz", ArchiveFormat::TarGz),
+    (".tbz2", ArchiveFormat::TarBz2),
+    (".tbz", ArchiveFormat::TarBz2),
+    (".txz", ArchiveFormat::TarXz),
+    (".tzst", ArchiveFormat::TarZst),
+    (".tar", ArchiveFormat::Tar),
+    (".zip", ArchiveFormat::Zip),
+    (".7z", ArchiveFormat::SevenZip),
+];
+
+impl ArchiveFormat {
+    /// Detects the archive format from the file name.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        SUFFIXES
+            .iter()
+            .find(|(suffix, _)| name.len() > suffix.len() && name.ends_with(suffix))
+            .map(|(_, format)| *format)
+    }
+}
+
+/// Returns true if `path` is an archive on disk that can be browsed.
+pub fn is_archive_file(path: &Path) -> bool {
+    ArchiveFormat::from_path(path).is_some() && path.is_file()
+}
+
+/// Splits a virtual path such as `/data/backup.zip/docs/a.txt` into the
+/// archive on disk and the path inside it (`docs/a.txt`).
+pub fn split_archive_path(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let mut ancestors: Vec<&Path> = path.ancestors().collect();
+    ancestors.reverse();
+    ancestors
+        .into_iter()
+        .find(|ancestor| is_archive_file(ancestor))
+        .map(|archive| {
+            let inner = path.strip_prefix(archive).unwrap_or(Path::new("")).to_path_buf();
+            (archive.to_path_buf(), inner)
+        })
+}
+
+/// Returns the archive file name without its archive extension,
+/// e.g. `release-1.0` for `release-1.0.tar.gz`.
+pub fn archive_stem(path: &Path) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let lower = name.to_lowercase();
+    for (suffix, _) in SUFFIXES {
+        if lower.len() > suffix.len() && lower.ends_with(suffix) {
+            return name[..name.len() - suffix.len()].to_string();
+        }
+    }
+    name
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// Path of the entry as stored in the archive.
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub size: u64,
+    /// Size as stored, for formats that compress each entry on its own (zip).
+    pub compressed_size: Option<u64>,
+}
+
+/// Returns the entry path relative to the extraction root, or `None` if it is
+/// absolute or climbs out of the root with `..`.
+pub fn safe_entry_path(name: &Path) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(path)
+}
+
+fn unsafe_path_error(name: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Archive contains an unsafe path: {}", name.display()),
+    )
+}
+
+fn open_tar(path: &Path, format: ArchiveFormat, read_bytes: Arc<AtomicU64>) -> io::Result<tar::Archive<Box<dyn Read>>> {
+    let file = CountingReader { inner: BufReader::new(File::open(path)?), count: read_bytes };
+    Ok(tar::Archive::new(decompress(file, format)?))
+}
+
+/// Wraps `reader` in the decompressor a tarball of `format` needs.
+fn decompress<'a, R: Read + 'a>(reader: R, format: ArchiveFormat) -> io::Result<Box<dyn Read + 'a>> {
+    Ok(match format {
+        ArchiveFormat::Tar => Box::new(reader),
+        ArchiveFormat::TarGz => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+        ArchiveFormat::TarBz2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+        ArchiveFormat::TarXz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+        ArchiveFormat::TarZst => Box::new(zstd::stream::read::Decoder::new(reader)?),
+        ArchiveFormat::Zip | ArchiveFormat::SevenZip => unreachable!("not a tar format"),
+    })
+}
+
+fn unsupported_format(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("Unsupported archive format: {}", path.display()),
+    )
+}
+
+/// Lists every entry of the archive without extracting anything.
+pub fn list_entries(archive: &Path) -> io::Result<Vec<ArchiveEntry>> {
+    let format = ArchiveFormat::from_path(archive).ok_or_else(|| unsupported_format(archive))?;
+    let file = File::open(archive)?;
+    let len = file.metadata()?.len();
+    read_index(BufReader::new(file), len, format)
+}
+
+/// Lists every entry of an archive of `format` that is `len` bytes long,
+/// read from `reader`. Zip and 7z archives are indexed by seeking to their
+/// directory; tarballs are decompressed on the fly, skipping the contents.
+pub fn read_index<R: Read + Seek>(reader: R, len: u64, format: ArchiveFormat) -> io::Result<Vec<ArchiveEntry>> {
+    let mut entries = Vec::new();
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(reader)?;
+            for i in 0..zip.len() {
+                let file = zip.by_index_raw(i)?;
+                entries.push(ArchiveEntry {
+                    path: PathBuf::from(file.name()),
+                    is_dir: file.is_dir(),
+                    size: file.size(),
+                    compressed_size: Some(file.compressed_size()),
+                });
+            }
+        }
+        ArchiveFormat::SevenZip => {
+            let reader = sevenz_rust::SevenZReader::new(reader, len, sevenz_rust::Password::empty())
+                .map_err(io::Error::other)?;
+            for file in &reader.archive().files {
+                entries.push(ArchiveEntry {
+                    path: PathBuf::from(&file.name),
+                    is_dir: file.is_directory,
+                    size: file.size,
+                    compressed_size: None,
+                });
+            }
+        }
+        _ => {
+            let mut tar = tar::Archive::new(decompress(reader, format)?);
+            for entry in tar.entries()? {
+                let entry = entry?;
+                entries.push(ArchiveEntry {
+                    path: entry.path()?.into_owned(),
+                    is_dir: entry.header().entry_type().is_dir(),
+                    size: entry.size(),
+                    compressed_size: None,
+                });
+            }
+        }
+    }
+    Ok(entries)
+}
+
+struct CachedIndex {
+    archive: PathBuf,
+    modified: Option<SystemTime>,
+    entries: Arc<Vec<ArchiveEntry>>,
+}
+
+// Browsing re-lists the same archive on every navigation step, and listing a
+// compressed tarball means decompressing all of it, so keep the last index.
+static INDEX_CACHE: Mutex<Option<CachedIndex>> = Mutex::new(None);
+
+fn cached_entries(archive: &Path) -> io::Result<Arc<Vec<ArchiveEntry>>> {
+    let modified = fs::metadata(archive)?.modified().ok();
+    let mut cache = INDEX_CACHE.lock().unwrap();
+    if let Some(cached) = cache.as_ref() {
+        if cached.archive == archive && cached.modified == modified {
+            return Ok(cached.entries.clone());
+        }
+    }
+    let entries = Arc::new(list_entries(archive)?);
+    *cache = Some(CachedIndex { archive: archive.to_path_buf(), modified, entries: entries.clone() });
+    Ok(entries)
+}
+
+/// Lists the direct children of `dir` inside the archive.
+///
+/// Directories that only exist implicitly, as prefixes of file paths, are
+/// included. Returned paths are relative to the archive root.
+pub fn list_dir(archive: &Path, dir: &Path) -> io::Result<Vec<ArchiveEntry>> {
+    let mut children: BTreeMap<OsString, ArchiveEntry> = BTreeMap::new();
+    for entry in cached_entries(archive)?.iter() {
+        let Some(path) = safe_entry_path(&entry.path) else {
+            continue;
+        };
+        let Ok(rest) = path.strip_prefix(dir) else {
+            continue;
+        };
+        let mut components = rest.components();
+        let Some(first) = components.next() else {
+            continue;
+        };
+        let name = first.as_os_str().to_owned();
+        if components.next().is_some() {
+            children
+                .entry(name.clone())
+                .and_modify(|child| child.is_dir = true)
+                .or_insert_with(|| ArchiveEntry { path: dir.join(&name), is_dir: true, size: 0, compressed_size: None });
+        } else {
+            let child = ArchiveEntry { path: dir.join(first), ..entry.clone() };
+            children.insert(name, child);
+        }
+    }
+    Ok(children.into_values().collect())
+}
+
+/// Reads at most `limit` bytes of the file `entry` inside the archive.
+pub fn read_entry(archive: &Path, entry: &Path, limit: u64) -> io::Result<Vec<u8>> {
+    let format = ArchiveFormat::from_path(archive).ok_or_else(|| unsupported_format(archive))?;
+    let not_found = || io::Error::new(io::ErrorKind::NotFound, format!("{} not found in archive", entry.display()));
+    let mut buffer = Vec::new();
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(BufReader::new(File::open(archive)?))?;
+            for i in 0..zip.len() {
+                let file = zip.by_index(i)?;
+                if !file.is_dir() && safe_entry_path(Path::new(file.name())).as_deref() == Some(entry) {
+                    file.take(limit).read_to_end(&mut buffer)?;
+                    return Ok(buffer);
+                }
+            }
+            Err(not_found())
+        }
+        ArchiveFormat::SevenZip => {
+            let mut reader = sevenz_rust::SevenZReader::open(archive, sevenz_rust::Password::empty())
+                .map_err(io::Error::other)?;
+            let mut found = false;
+            reader
+                .for_each_entries(|file, data| {
+                    if file.is_directory() || safe_entry_path(Path::new(file.name())).as_deref() != Some(entry) {
+                        return Ok(true);
+                    }
+                    found = true;
+                    data.take(limit).read_to_end(&mut buffer)?;
+                    Ok(false)
+                })
+                .map_err(io::Error::other)?;
+            if found { Ok(buffer) } else { Err(not_found()) }
+        }
+        _ => {
+            let mut tar = open_tar(archive, format, Arc::default())?;
+            for file in tar.entries()? {
+                let file = file?;
+                if file.header().entry_type().is_file() && safe_entry_path(&file.path()?).as_deref() == Some(entry) {
+                    file.take(limit).read_to_end(&mut buffer)?;
+                    return Ok(buffer);
+                }
+            }
+            Err(not_found())
+        }
+    }
+}
+
+/// Largest entry [`open_entry`] decompresses into memory.
+pub const MAX_OPEN_SIZE: u64 = 64 * 1024 * 1024;
+
+struct CachedEntry {
+    archive: PathBuf,
+    modified: Option<SystemTime>,
+    entry: PathBuf,
+    bytes: Arc<[u8]>,
+}
+
+// Paging through a preview reopens the file for every page, and an entry can
+// only be read by decompressing it from the start, so keep the last one.
+static ENTRY_CACHE: Mutex<Option<CachedEntry>> = Mutex::new(None);
+
+/// The whole of the file `entry` inside the archive, decompressed into
+/// memory. Fails for entries larger than [`MAX_OPEN_SIZE`].
+pub fn open_entry(archive: &Path, entry: &Path) -> io::Result<Arc<[u8]>> {
+    let too_large = || {
+        io::Error::new(
+            io::ErrorKind::FileTooLarge,
+            format!("{} is too large to open inside the archive, extract it first", entry.display()),
+        )
+    };
+    let modified = fs::metadata(archive)?.modified().ok();
+    let mut cache = ENTRY_CACHE.lock().unwrap();
+    if let Some(cached) = cache.as_ref() {
+        if cached.archive == archive && cached.modified == modified && cached.entry == entry {
+            return Ok(cached.bytes.clone());
+        }
+    }
+    // The index says how large it is without decompressing anything.
+    let listed = cached_entries(archive)?;
+    if listed.iter().any(|listed| listed.size > MAX_OPEN_SIZE && safe_entry_path(&listed.path).as_deref() == Some(entry)) {
+        return Err(too_large());
+    }
+    let bytes = read_entry(archive, entry, MAX_OPEN_SIZE + 1)?;
+    if bytes.len() as u64 > MAX_OPEN_SIZE {
+        return Err(too_large());
+    }
+    let bytes: Arc<[u8]> = bytes.into();
+    *cache = Some(CachedEntry { archive: archive.to_path_buf(), modified, entry: entry.to_path_buf(), bytes: bytes.clone() });
+    Ok(bytes)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractPlan {
+    /// Directory the archive contents are written into.
+    pub dest: PathBuf,
+    /// Top-level item the extraction creates inside the parent directory.
+    pub target: PathBuf,
+}
+
+/// Decides where `archive` should be extracted when unpacking it into `parent`.
+///
+/// Archives with a single top-level entry are unpacked directly into `parent`;
+/// anything else goes into a new folder named after the archive. Fails if any
+/// entry would escape the destination.
+pub fn plan_extraction(archive: &Path, parent: &Path) -> io::Result<ExtractPlan> {
+    let mut top_level = BTreeSet::new();
+    for entry in list_entries(archive)? {
+        let path = safe_entry_path(&entry.path).ok_or_else(|| unsafe_path_error(&entry.path))?;
+        if let Some(first) = path.components().next() {
+            top_level.insert(first.as_os_str().to_owned());
+        }
+    }
+
+    if top_level.len() == 1 {
+        let name = top_level.into_iter().next().unwrap();
+        Ok(ExtractPlan { dest: parent.to_path_buf(), target: parent.join(name) })
+    } else {
+        let folder = parent.join(archive_stem(archive));
+        Ok(ExtractPlan { dest: folder.clone(), target: folder })
+    }
+}
+
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+fn unpack_file(out_path: &Path, reader: &mut dyn Read, mode: Option<u32>) -> io::Result<()> {
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = File::create(out_path)?;
+    io::copy(reader, &mut file)?;
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(mode & 0o777))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    Ok(())
+}
+
+/// Extracts `archive` into `dest`, overwriting existing files.
+///
+/// `on_progress` is called with the fraction of the archive processed so far.
+/// Links and special files are skipped.
+pub fn extract(archive: &Path, dest: &Path, on_progress: impl FnMut(f32)) -> io::Result<()> {
+    fs::create_dir_all(dest)?;
+    unpack(archive, Path::new(""), dest, on_progress)
+}
+
+/// Extracts the file or directory `entry` of the archive to `dest`, so that
+/// `dest` becomes the copy of `entry`.
+pub fn extract_entry(archive: &Path, entry: &Path, dest: &Path, on_progress: impl FnMut(f32)) -> io::Result<()> {
+    unpack(archive, entry, dest, on_progress)
+}
+
+/// Maps an archive entry to its output path when unpacking the `prefix`
+/// subtree to `dest`. Returns `Ok(None)` for entries outside the subtree.
+fn output_path(name: &Path, prefix: &Path, dest: &Path) -> io::Result<Option<PathBuf>> {
+    let path = safe_entry_path(name).ok_or_else(|| unsafe_path_error(name))?;
+    Ok(path.strip_prefix(prefix).ok().map(|rest| {
+        if rest.as_os_str().is_empty() {
+            dest.to_path_buf()
+        } else {
+            dest.join(rest)
+        }
+    }))
+}
+
+fn unpack(archive: &Path, prefix: &Path, dest: &Path, mut on_progress: impl FnMut(f32)) -> io::Result<()> {
+    let format = ArchiveFormat::from_path(archive).ok_or_else(|| unsupported_format(archive))?;
+
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(BufReader::new(File::open(archive)?))?;
+            let total = zip.len().max(1);
+            for i in 0..zip.len() {
+                let mut file = zip.by_index(i)?;
+                let name = PathBuf::from(file.name());
+                if let Some(out_path) = output_path(&name, prefix, dest)? {
+                    if file.is_dir() {
+                        fs::create_dir_all(out_path)?;
+                    } else if file.is_symlink() {
+                        log::warn!("Skipping symlink {:?} in {:?}", name, archive);
+                    } else {
+                        let mode = file.unix_mode();
+                        unpack_file(&out_path, &mut file, mode)?;
+                    }
+                }
+                on_progress((i + 1) as f32 / total as f32);
+            }
+        }
+        ArchiveFormat::SevenZip => {
+            let mut reader = sevenz_rust::SevenZReader::open(archive, sevenz_rust::Password::empty())
+                .map_err(io::Error::other)?;
+            let total = reader.archive().files.len().max(1);
+            let mut done = 0;
+            reader
+                .for_each_entries(|entry, data| {
+                    let name = PathBuf::from(entry.name());
+                    if let Some(out_path) = output_path(&name, prefix, dest)? {
+                        if entry.is_directory() {
+                            fs::create_dir_all(out_path)?;
+                        } else {
+                            unpack_file(&out_path, data, None)?;
+                        }
+                    }
+                    done += 1;
+                    on_progress(done as f32 / total as f32);
+                    Ok(true)
+                })
+                .map_err(io::Error::other)?;
+        }
+        _ => {
+            let total = fs::metadata(archive)?.len().max(1);
+            let read_bytes = Arc::new(AtomicU64::new(0));
+            let mut tar = open_tar(archive, format, read_bytes.clone())?;
+            for entry in tar.entries()? {
+                let mut entry = entry?;
+                let name = entry.path()?.into_owned();
+                if let Some(out_path) = output_path(&name, prefix, dest)? {
+                    let entry_type = entry.header().entry_type();
+                    if entry_type.is_dir() {
+                        fs::create_dir_all(out_path)?;
+                    } else if entry_type.is_file() {
+                        let mode = entry.header().mode().ok();
+                        unpack_file(&out_path, &mut entry, mode)?;
+                    } else {
+                        log::warn!("Skipping {:?} entry {:?} in {:?}", entry_type, name, archive);
+                    }
+                }
+                on_progress(read_bytes.load(Ordering::Relaxed) as f32 / total as f32);
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,7 @@
-use std::path::PathBuf;
-use tokio::fs;
+use crate::vfs::{self, Vfs};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
@@ -8,15 +10,35 @@ pub enum ProgressEvent {
     Update(f32),
     Completed,
     Error(String),
+    /// The task stopped before changing anything because this is in the way.
+    Conflict(PathBuf),
 }
 
-pub async fn copy_file_task(
+/// Runs a blocking filesystem job, forwarding its progress to `progress_tx`.
+async fn run_blocking_job<F>(
     task_id: Uuid,
-    src: PathBuf,
-    dest: PathBuf,
-    progress_tx: mpsc::Sender<(Uuid, ProgressEvent)>,
-) {
-    let result = fs::copy(&src, &dest).await;
+    progress_tx: &mpsc::Sender<(Uuid, ProgressEvent)>,
+    job: F,
+) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn FnMut(f32)) -> io::Result<()> + Send + 'static,
+{
+    let tx = progress_tx.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut last_reported = 0.0;
+        job(&mut |progress| {
+            // Only report whole-percent steps so large jobs don't flood the channel.
+            if progress - last_reported >= 0.01 {
+                last_reported = progress;
+                let _ = tx.blocking_send((task_id, ProgressEvent::Update(progress)));
+            }
+        })
+    })
+    .await
+    .unwrap_or_else(|e| Err(io::Error::other(e)))
+}
+
+async fn send_result(task_id: Uuid, progress_tx: &mpsc::Sender<(Uuid, ProgressEvent)>, result: io::Result<()>) {
     match result {
         Ok(_) => {
             let _ = progress_tx.send((task_id, ProgressEvent::Completed)).await;
@@ -27,97 +49,126 @@ pub async fn copy_file_task(
     }
 }
 
+fn copy_with_progress(vfs: &dyn Vfs, src: &Path, dest: &Path, on_progress: &mut dyn FnMut(f32)) -> io::Result<()> {
+    let total = vfs::total_size(vfs, src)?.max(1);
+    let mut copied = 0;
+    vfs::copy_recursive(vfs, src, dest, &mut |n| {
+        copied += n;
+        on_progress(copied as f32 / total as f32);
+    })
+}
+
+pub async fn copy_file_task(
+    task_id: Uuid,
+    vfs: Arc<dyn Vfs>,
+    src: PathBuf,
+    dest: PathBuf,
+    progress_tx: mpsc::Sender<(Uuid, ProgressEvent)>,
+) {
+    let result = run_blocking_job(task_id, &progress_tx, move |on_progress| {
+        copy_with_progress(vfs.as_ref(), &src, &dest, on_progress)
+    })
+    .await;
+    send_result(task_id, &progress_tx, result).await;
+}
+
 pub async fn create_directory_task(
     task_id: Uuid,
+    vfs: Arc<dyn Vfs>,
     path: PathBuf,
     progress_tx: mpsc::Sender<(Uuid, ProgressEvent)>,
 ) {
-    let result = fs::create_dir(path).await;
-    match result {
-        Ok(_) => {
-            let _ = progress_tx.send((task_id, ProgressEvent::Completed)).await;
-        }
-        Err(e) => {
-            let _ = progress_tx.send((task_id, ProgressEvent::Error(e.to_string()))).await;
-        }
-    }
+    let result = run_blocking_job(task_id, &progress_tx, move |_| vfs.create_dir(&path)).await;
+    send_result(task_id, &progress_tx, result).await;
 }
 
 pub async fn create_file_task(
     task_id: Uuid,
+    vfs: Arc<dyn Vfs>,
     path: PathBuf,
     progress_tx: mpsc::Sender<(Uuid, ProgressEvent)>,
 ) {
-    let result = fs::File::create(path).await;
-    match result {
-        Ok(_) => {
-            let _ = progress_tx.send((task_id, ProgressEvent::Completed)).await;
-        }
-        Err(e) => {
-            let _ = progress_tx.send((task_id, ProgressEvent::Error(e.to_string()))).await;
-        }
-    }
+    let result = run_blocking_job(task_id, &progress_tx, move |_| vfs.create(&path).map(|_| ())).await;
+    send_result(task_id, &progress_tx, result).await;
 }
 
 pub async fn delete_item_task(
     task_id: Uuid,
+    vfs: Arc<dyn Vfs>,
     path: PathBuf,
     progress_tx: mpsc::Sender<(Uuid, ProgressEvent)>,
 ) {
-    let result = if path.is_dir() {
-        fs::remove_dir_all(&path).await
-    } else {
-        fs::remove_file(&path).await
-    };
-
-    match result {
-        Ok(_) => {
-            let _ = progress_tx.send((task_id, ProgressEvent::Completed)).await;
-        }
-        Err(e) => {
-            let _ = progress_tx.send((task_id, ProgressEvent::Error(e.to_string()))).await;
-        }
-    }
+    let result = run_blocking_job(task_id, &progress_tx, move |_| vfs.remove(&path)).await;
+    send_result(task_id, &progress_tx, result).await;
 }
 
 pub async fn move_item_task(
     task_id: Uuid,
+    vfs: Arc<dyn Vfs>,
     src: PathBuf,
     dest: PathBuf,
     progress_tx: mpsc::Sender<(Uuid, ProgressEvent)>,
 ) {
-    let result = fs::rename(&src, &dest).await;
-    match result {
-        Ok(_) => {
-            let _ = progress_tx.send((task_id, ProgressEvent::Completed)).await;
-        }
-        Err(e) => {
-            let _ = progress_tx.send((task_id, ProgressEvent::Error(e.to_string()))).await;
+    let result = run_blocking_job(task_id, &progress_tx, move |on_progress| {
+        match vfs.rename(&src, &dest) {
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                copy_with_progress(vfs.as_ref(), &src, &dest, on_progress)?;
+                vfs.remove(&src)
+            }
+            result => result,
         }
-    }
+    })
+    .await;
+    send_result(task_id, &progress_tx, result).await;
 }
 
-use tokio::fs::File;
-use tokio::io::{AsyncBufReadExt, BufReader};
-
-const PREVIEW_LINE_COUNT: usize = 100;
-
-pub async fn load_text_preview(path: PathBuf) -> Result<String, String> {
-    let file = File::open(path).await.map_err(|e| e.to_string())?;
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines();
-
-    let mut content = String::new();
-    for _ in 0..PREVIEW_LINE_COUNT {
-        match lines.next_line().await {
-            Ok(Some(line)) => {
-                content.push_str(&line);
-                content.push('\n');
-            }
-            Ok(None) => break, // End of file
-            Err(e) => return Err(e.to_string()),
+/// Extracts `archive` into `parent`, or a new folder in it, as
+/// [`crate::archive::plan_extraction`] decides. Listing the archive to decide
+/// can mean decompressing all of it, so that's part of the task too. Unless
+/// `overwrite` is set, the task reports a conflict instead of extracting over
+/// something that exists.
+pub async fn extract_archive_task(
+    task_id: Uuid,
+    archive: PathBuf,
+    parent: PathBuf,
+    overwrite: bool,
+    progress_tx: mpsc::Sender<(Uuid, ProgressEvent)>,
+) {
+    let planned = tokio::task::spawn_blocking({
+        let archive = archive.clone();
+        move || {
+            let plan = crate::archive::plan_extraction(&archive, &parent)?;
+            let exists = plan.target.symlink_metadata().is_ok();
+            Ok((plan, exists))
         }
-    }
+    })
+    .await
+    .unwrap_or_else(|e| Err(io::Error::other(e)));
+    let plan = match planned {
+        Ok((plan, true)) if !overwrite => {
+            let _ = progress_tx.send((task_id, ProgressEvent::Conflict(plan.target))).await;
+            return;
+        }
+        Ok((plan, _)) => plan,
+        Err(e) => return send_result(task_id, &progress_tx, Err(e)).await,
+    };
+    let result = run_blocking_job(task_id, &progress_tx, move |on_progress| {
+        crate::archive::extract(&archive, &plan.dest, on_progress)
+    })
+    .await;
+    send_result(task_id, &progress_tx, result).await;
+}
 
-    Ok(content)
+pub async fn extract_entry_task(
+    task_id: Uuid,
+    archive: PathBuf,
+    entry: PathBuf,
+    dest: PathBuf,
+    progress_tx: mpsc::Sender<(Uuid, ProgressEvent)>,
+) {
+    let result = run_blocking_job(task_id, &progress_tx, move |on_progress| {
+        crate::archive::extract_entry(&archive, &entry, &dest, on_progress)
+    })
+    .await;
+    send_result(task_id, &progress_tx, result).await;
 }
@@ -1 +1,3 @@
+pub mod archive;
 pub mod fs_ops;
+pub mod vfs;
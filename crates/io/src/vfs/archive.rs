@@ -0,0 +1,81 @@
+use super::{read_only_error, Metadata, Vfs, VfsEntry, VfsFile};
+use crate::archive;
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+
+/// Read-only view of zip, tar and 7z archives on the local disk.
+///
+/// Paths continue past the archive file, e.g. `/data/backup.zip/docs/a.txt`.
+/// The archive file itself still reads as a regular file.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ArchiveFs;
+
+fn split(path: &Path) -> io::Result<(PathBuf, PathBuf)> {
+    archive::split_archive_path(path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} is not inside an archive", path.display())))
+}
+
+impl Vfs for ArchiveFs {
+    fn handles(&self, path: &Path) -> bool {
+        archive::split_archive_path(path).is_some()
+    }
+
+    fn is_read_only(&self, _path: &Path) -> bool {
+        true
+    }
+
+    fn can_enter(&self, path: &Path) -> bool {
+        archive::is_archive_file(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<VfsEntry>> {
+        let (archive, inner) = split(path)?;
+        Ok(archive::list_dir(&archive, &inner)?
+            .into_iter()
+            .map(|entry| VfsEntry {
+                path: path.join(entry.path.file_name().unwrap_or_default()),
+                metadata: Metadata { is_dir: entry.is_dir, len: entry.size, ..Default::default() },
+            })
+            .collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let (archive, inner) = split(path)?;
+        if inner.as_os_str().is_empty() {
+            return Ok(Metadata::from(&fs::metadata(&archive)?));
+        }
+        let parent = inner.parent().unwrap_or(Path::new(""));
+        archive::list_dir(&archive, parent)?
+            .into_iter()
+            .find(|entry| entry.path == inner)
+            .map(|entry| Metadata { is_dir: entry.is_dir, len: entry.size, ..Default::default() })
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display())))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        let (archive, inner) = split(path)?;
+        if inner.as_os_str().is_empty() {
+            return Ok(Box::new(fs::File::open(&archive)?));
+        }
+        // Compressed entries can't seek, so the entry is decompressed into
+        // memory, up to a limit.
+        Ok(Box::new(Cursor::new(archive::open_entry(&archive, &inner)?)))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Err(read_only_error(path))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        Err(read_only_error(path))
+    }
+
+    fn rename(&self, from: &Path, _to: &Path) -> io::Result<()> {
+        Err(read_only_error(from))
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        Err(read_only_error(path))
+    }
+}
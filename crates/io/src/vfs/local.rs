@@ -0,0 +1,55 @@
+use super::{DirIter, Metadata, Vfs, VfsEntry, VfsFile};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LocalFs;
+
+impl Vfs for LocalFs {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<VfsEntry>> {
+        Ok(self.read_dir_iter(path)?.filter_map(|res| res.ok()).collect())
+    }
+
+    fn read_dir_iter(&self, path: &Path) -> io::Result<DirIter> {
+        Ok(Box::new(fs::read_dir(path)?.map(|res| {
+            let entry = res?;
+            let path = entry.path();
+            // Follow symlinks, but still list dangling ones.
+            let metadata = fs::metadata(&path).or_else(|_| entry.metadata())?;
+            Ok(VfsEntry { path, metadata: Metadata::from(&metadata) })
+        })))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        Ok(Metadata::from(&fs::metadata(path)?))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn local_path(&self, path: &Path) -> Option<PathBuf> {
+        Some(path.to_path_buf())
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(fs::File::create(path)?))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        if fs::symlink_metadata(path)?.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use super::{Metadata, Vfs, VfsEntry, VfsFile};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+/// A [`Vfs`] operation, used to inject failures into [`MemoryFs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    ReadDir,
+    Metadata,
+    Open,
+    Create,
+    CreateDir,
+    Rename,
+    Remove,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Dir,
+    File(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    node: Node,
+    modified: SystemTime,
+}
+
+#[derive(Debug)]
+struct State {
+    entries: BTreeMap<PathBuf, Entry>,
+    failures: HashMap<(Operation, PathBuf), io::ErrorKind>,
+    // Logical clock so modification times are deterministic but still change.
+    clock: u64,
+}
+
+impl State {
+    fn tick(&mut self) -> SystemTime {
+        self.clock += 1;
+        SystemTime::UNIX_EPOCH + Duration::from_secs(self.clock)
+    }
+
+    fn check(&self, operation: Operation, path: &Path) -> io::Result<()> {
+        match self.failures.get(&(operation, path.to_path_buf())) {
+            Some(kind) => Err(io::Error::new(
+                *kind,
+                format!("injected {:?} failure for {}", operation, path.display()),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn get(&self, path: &Path) -> io::Result<&Entry> {
+        self.entries.get(path).ok_or_else(|| not_found(path))
+    }
+
+    fn require_dir(&self, path: &Path) -> io::Result<()> {
+        match self.get(path)?.node {
+            Node::Dir => Ok(()),
+            Node::File(_) => Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                format!("{} is not a directory", path.display()),
+            )),
+        }
+    }
+
+    fn require_parent_dir(&self, path: &Path) -> io::Result<()> {
+        self.require_dir(path.parent().ok_or_else(|| not_found(path))?)
+    }
+
+    fn insert(&mut self, path: PathBuf, node: Node) {
+        let modified = self.tick();
+        if !self.entries.contains_key(&path) {
+            self.touch_parent(&path, modified);
+        }
+        self.entries.insert(path, Entry { node, modified });
+    }
+
+    /// Like on a real disk, adding or removing an entry updates the
+    /// directory's modification time.
+    fn touch_parent(&mut self, path: &Path, modified: SystemTime) {
+        if let Some(parent) = path.parent().and_then(|parent| self.entries.get_mut(parent)) {
+            parent.modified = modified;
+        }
+    }
+
+    /// Removes `path` and everything below it, returning the removed entries.
+    fn take_tree(&mut self, path: &Path) -> Vec<(PathBuf, Entry)> {
+        let paths: Vec<_> = self.entries.keys().filter(|p| p.starts_with(path)).cloned().collect();
+        if !paths.is_empty() {
+            let modified = self.tick();
+            self.touch_parent(path, modified);
+        }
+        paths
+            .into_iter()
+            .map(|p| {
+                let entry = self.entries.remove(&p).unwrap();
+                (p, entry)
+            })
+            .collect()
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path.display()))
+}
+
+/// An in-memory filesystem rooted at `/`, for tests.
+///
+/// Clones share the same tree, so a test can keep a handle to populate and
+/// inspect the filesystem while the code under test owns another. Failures can
+/// be injected per operation and path with [`MemoryFs::fail`].
+#[derive(Debug, Clone)]
+pub struct MemoryFs {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for MemoryFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        let mut state = State { entries: BTreeMap::new(), failures: HashMap::new(), clock: 0 };
+        state.insert(PathBuf::from("/"), Node::Dir);
+        Self { state: Arc::new(Mutex::new(state)) }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Creates the directory `path` and any missing parents.
+    pub fn add_dir(&self, path: impl AsRef<Path>) -> &Self {
+        let mut state = self.lock();
+        for ancestor in path.as_ref().ancestors().collect::<Vec<_>>().into_iter().rev() {
+            if !state.entries.contains_key(ancestor) {
+                state.insert(ancestor.to_path_buf(), Node::Dir);
+            }
+        }
+        self
+    }
+
+    /// Creates or replaces the file `path`, creating any missing parents.
+    pub fn add_file(&self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) -> &Self {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            self.add_dir(parent);
+        }
+        self.lock().insert(path.to_path_buf(), Node::File(contents.into()));
+        self
+    }
+
+    /// Makes every later `operation` on `path` fail with `kind`.
+    pub fn fail(&self, operation: Operation, path: impl AsRef<Path>, kind: io::ErrorKind) -> &Self {
+        self.lock().failures.insert((operation, path.as_ref().to_path_buf()), kind);
+        self
+    }
+
+    pub fn clear_failures(&self) {
+        self.lock().failures.clear();
+    }
+}
+
+/// Writes straight into the file's node, so the content is visible while the
+/// file is still open, like on a real disk.
+struct MemoryWriter {
+    state: Arc<Mutex<State>>,
+    path: PathBuf,
+}
+
+impl Write for MemoryWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        let modified = state.tick();
+        match state.entries.get_mut(&self.path) {
+            Some(Entry { node: Node::File(data), modified: entry_modified }) => {
+                data.extend_from_slice(buf);
+                *entry_modified = modified;
+                Ok(buf.len())
+            }
+            _ => Err(not_found(&self.path)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Vfs for MemoryFs {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<VfsEntry>> {
+        let state = self.lock();
+        state.check(Operation::ReadDir, path)?;
+        state.require_dir(path)?;
+        Ok(state
+            .entries
+            .iter()
+            .filter(|(child, _)| child.parent() == Some(path))
+            .map(|(child, entry)| VfsEntry { path: child.clone(), metadata: metadata_of(entry) })
+            .collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let state = self.lock();
+        state.check(Operation::Metadata, path)?;
+        Ok(metadata_of(state.get(path)?))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        let state = self.lock();
+        state.check(Operation::Open, path)?;
+        match &state.get(path)?.node {
+            Node::File(data) => Ok(Box::new(Cursor::new(data.clone()))),
+            Node::Dir => Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("{} is a directory", path.display()),
+            )),
+        }
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        let mut state = self.lock();
+        state.check(Operation::Create, path)?;
+        state.require_parent_dir(path)?;
+        if let Some(Entry { node: Node::Dir, .. }) = state.entries.get(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("{} is a directory", path.display()),
+            ));
+        }
+        state.insert(path.to_path_buf(), Node::File(Vec::new()));
+        Ok(Box::new(MemoryWriter { state: self.state.clone(), path: path.to_path_buf() }))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.lock();
+        state.check(Operation::CreateDir, path)?;
+        state.require_parent_dir(path)?;
+        if state.entries.contains_key(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+        state.insert(path.to_path_buf(), Node::Dir);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut state = self.lock();
+        state.check(Operation::Rename, from)?;
+        state.get(from)?;
+        state.require_parent_dir(to)?;
+        if to.starts_with(from) && to != from {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot move {} into itself", from.display()),
+            ));
+        }
+        // Like rename(2), an existing file or empty directory is replaced.
+        if state.entries.keys().any(|p| p.starts_with(to) && p != to) {
+            return Err(io::Error::new(
+                io::ErrorKind::DirectoryNotEmpty,
+                format!("{} is not empty", to.display()),
+            ));
+        }
+        state.take_tree(to);
+        for (path, entry) in state.take_tree(from) {
+            let relative = path.strip_prefix(from).unwrap();
+            let moved = if relative.as_os_str().is_empty() { to.to_path_buf() } else { to.join(relative) };
+            state.entries.insert(moved, entry);
+        }
+        let modified = state.tick();
+        state.touch_parent(to, modified);
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.lock();
+        state.check(Operation::Remove, path)?;
+        state.get(path)?;
+        state.take_tree(path);
+        Ok(())
+    }
+}
+
+fn metadata_of(entry: &Entry) -> Metadata {
+    match &entry.node {
+        Node::Dir => Metadata { is_dir: true, modified: Some(entry.modified), ..Default::default() },
+        Node::File(data) => Metadata {
+            len: data.len() as u64,
+            modified: Some(entry.modified),
+            ..Default::default()
+        },
+    }
+}
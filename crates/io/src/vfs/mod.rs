@@ -0,0 +1,331 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+mod archive;
+mod local;
+mod memory;
+#[cfg(feature = "sftp")]
+mod sftp;
+
+pub use self::archive::ArchiveFs;
+pub use self::local::LocalFs;
+pub use self::memory::{MemoryFs, Operation};
+#[cfg(feature = "sftp")]
+pub use self::sftp::{SftpFs, SftpLocation};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub is_dir: bool,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    /// Unix permission bits, if the backend knows them.
+    pub mode: Option<u32>,
+    /// Set for FIFOs, sockets and device nodes, which must not be opened casually.
+    pub special: Option<SpecialFile>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialKind {
+    Fifo,
+    Socket,
+    CharDevice,
+    BlockDevice,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpecialFile {
+    pub kind: SpecialKind,
+    /// Major and minor numbers of a device node.
+    pub device: Option<(u32, u32)>,
+}
+
+impl SpecialKind {
+    /// Reads the file type bits of a Unix `st_mode`.
+    pub fn from_mode(mode: u32) -> Option<Self> {
+        match mode & 0o170000 {
+            0o010000 => Some(Self::Fifo),
+            0o140000 => Some(Self::Socket),
+            0o020000 => Some(Self::CharDevice),
+            0o060000 => Some(Self::BlockDevice),
+            _ => None,
+        }
+    }
+}
+
+/// Splits a Linux `dev_t` into major and minor numbers, like glibc's
+/// `major()`/`minor()`.
+pub fn split_device(dev: u64) -> (u32, u32) {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    (major as u32, minor as u32)
+}
+
+impl From<&fs::Metadata> for Metadata {
+    #[cfg(unix)]
+    fn from(metadata: &fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        let special = SpecialKind::from_mode(metadata.mode()).map(|kind| SpecialFile {
+            kind,
+            device: matches!(kind, SpecialKind::CharDevice | SpecialKind::BlockDevice)
+                .then(|| split_device(metadata.rdev())),
+        });
+        Self {
+            is_dir: metadata.is_dir(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            mode: Some(metadata.mode() & 0o7777),
+            special,
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn from(metadata: &fs::Metadata) -> Self {
+        Self {
+            is_dir: metadata.is_dir(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VfsEntry {
+    pub path: PathBuf,
+    pub metadata: Metadata,
+}
+
+impl VfsEntry {
+    pub fn name(&self) -> String {
+        self.path.file_name().unwrap_or_default().to_string_lossy().to_string()
+    }
+}
+
+/// Entries of a directory, produced as they are read.
+pub type DirIter = Box<dyn Iterator<Item = io::Result<VfsEntry>> + Send>;
+
+pub trait VfsFile: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> VfsFile for T {}
+
+/// A filesystem backend. Paths are always absolute and may carry a
+/// backend-specific shape, such as a path that continues inside an archive.
+pub trait Vfs: Send + Sync + fmt::Debug {
+    /// Whether this backend serves `path`. Used by [`Router`] to pick a backend.
+    fn handles(&self, _path: &Path) -> bool {
+        true
+    }
+
+    /// Whether entries can be created in, or removed from, the directory `path`.
+    fn is_read_only(&self, _path: &Path) -> bool {
+        false
+    }
+
+    /// Whether the file `path` can be browsed like a directory, as archives
+    /// can when [`ArchiveFs`] is mounted.
+    fn can_enter(&self, _path: &Path) -> bool {
+        false
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<VfsEntry>>;
+
+    /// Like [`Vfs::read_dir`], but backends that can should read lazily so
+    /// huge directories can be shown while they are still being listed.
+    fn read_dir_iter(&self, path: &Path) -> io::Result<DirIter> {
+        Ok(Box::new(self.read_dir(path)?.into_iter().map(Ok)))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+    fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>>;
+    /// Creates or truncates the file at `path`.
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>>;
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    /// Removes a file, or a directory with all of its contents.
+    fn remove(&self, path: &Path) -> io::Result<()>;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok()
+    }
+
+    /// The directory above `path`, or `None` at the top, like the root of a
+    /// remote host. Worked out from the path alone, without touching the
+    /// backend.
+    fn parent(&self, path: &Path) -> Option<PathBuf> {
+        path.parent().map(Path::to_path_buf)
+    }
+
+    /// Where `path` is on the local disk, for handing it to other programs.
+    /// `None` for files that only this backend can read.
+    fn local_path(&self, _path: &Path) -> Option<PathBuf> {
+        None
+    }
+}
+
+pub(crate) fn read_only_error(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("{} is read-only", path.display()),
+    )
+}
+
+/// Dispatches every call to the first mounted backend that handles the path,
+/// falling back to a default backend.
+///
+/// Creating, removing and renaming change the parent directory, so those are
+/// routed by the parent. This keeps e.g. an archive file deletable even though
+/// the archive backend serves its contents.
+#[derive(Debug, Clone)]
+pub struct Router {
+    mounts: Vec<Arc<dyn Vfs>>,
+    fallback: Arc<dyn Vfs>,
+}
+
+impl Router {
+    pub fn new(fallback: Arc<dyn Vfs>) -> Self {
+        Self { mounts: Vec::new(), fallback }
+    }
+
+    pub fn mount(mut self, backend: Arc<dyn Vfs>) -> Self {
+        self.mounts.push(backend);
+        self
+    }
+
+    fn route_index(&self, path: &Path) -> Option<usize> {
+        self.mounts.iter().position(|backend| backend.handles(path))
+    }
+
+    fn route(&self, path: &Path) -> &dyn Vfs {
+        match self.route_index(path) {
+            Some(index) => self.mounts[index].as_ref(),
+            None => self.fallback.as_ref(),
+        }
+    }
+
+    fn route_parent(&self, path: &Path) -> &dyn Vfs {
+        self.route(path.parent().unwrap_or(path))
+    }
+}
+
+impl Vfs for Router {
+    fn is_read_only(&self, path: &Path) -> bool {
+        self.route(path).is_read_only(path)
+    }
+
+    fn can_enter(&self, path: &Path) -> bool {
+        self.route(path).can_enter(path)
+    }
+
+    fn local_path(&self, path: &Path) -> Option<PathBuf> {
+        self.route(path).local_path(path)
+    }
+
+    fn parent(&self, path: &Path) -> Option<PathBuf> {
+        self.route(path).parent(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<VfsEntry>> {
+        self.route(path).read_dir(path)
+    }
+
+    fn read_dir_iter(&self, path: &Path) -> io::Result<DirIter> {
+        self.route(path).read_dir_iter(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.route(path).metadata(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        self.route(path).open(path)
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        self.route_parent(path).create(path)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        self.route_parent(path).create_dir(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let parent = |path: &Path| path.parent().unwrap_or(path).to_path_buf();
+        if self.route_index(&parent(from)) != self.route_index(&parent(to)) {
+            return Err(io::Error::new(
+                io::ErrorKind::CrossesDevices,
+                "cannot rename across filesystems",
+            ));
+        }
+        self.route_parent(from).rename(from, to)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        self.route_parent(path).remove(path)
+    }
+}
+
+/// The default filesystem: the local disk, with archives browsable as
+/// read-only directories.
+pub fn local() -> Arc<dyn Vfs> {
+    Arc::new(Router::new(Arc::new(LocalFs)).mount(Arc::new(ArchiveFs)))
+}
+
+/// Returns the total size in bytes of a file or directory tree.
+pub fn total_size(vfs: &dyn Vfs, path: &Path) -> io::Result<u64> {
+    let metadata = vfs.metadata(path)?;
+    if !metadata.is_dir {
+        return Ok(metadata.len);
+    }
+    let mut total = 0;
+    for entry in vfs.read_dir(path)? {
+        total += total_size(vfs, &entry.path)?;
+    }
+    Ok(total)
+}
+
+/// Copies a file or directory tree from `src` to `dest`, which may live on
+/// different backends. `on_copied` is called with the number of bytes written.
+pub fn copy_recursive(vfs: &dyn Vfs, src: &Path, dest: &Path, on_copied: &mut dyn FnMut(u64)) -> io::Result<()> {
+    // A copy inside the directory it copies would be listed and copied again
+    // until the path got too long, and a file copied onto itself is emptied.
+    if dest.starts_with(src) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot copy {} into itself", src.display()),
+        ));
+    }
+    let metadata = vfs.metadata(src)?;
+    if metadata.special.is_some() {
+        // Reading a FIFO or device would block or never end.
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a regular file", src.display()),
+        ));
+    }
+    if metadata.is_dir {
+        if !vfs.exists(dest) {
+            vfs.create_dir(dest)?;
+        }
+        for entry in vfs.read_dir(src)? {
+            copy_recursive(vfs, &entry.path, &dest.join(entry.name()), on_copied)?;
+        }
+        return Ok(());
+    }
+
+    let mut reader = vfs.open(src)?;
+    let mut writer = vfs.create(dest)?;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..n])?;
+        on_copied(n as u64);
+    }
+    writer.flush()
+}
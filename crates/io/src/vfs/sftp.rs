@@ -0,0 +1,286 @@
+use super::{Metadata, SpecialFile, SpecialKind, Vfs, VfsEntry, VfsFile};
+use ssh2::{CheckResult, ErrorCode, KnownHostFileKind, Session, Sftp};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+const SCHEME: &str = "sftp://";
+const DEFAULT_PORT: u16 = 22;
+const TIMEOUT_MS: u32 = 15_000;
+
+/// A parsed `sftp://user@host:port/path` location.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SftpLocation {
+    pub user: String,
+    pub host: String,
+    pub port: u16,
+    /// Absolute path on the remote host.
+    pub path: PathBuf,
+}
+
+impl SftpLocation {
+    pub fn parse(path: &Path) -> Option<Self> {
+        let rest = path.to_str()?.strip_prefix(SCHEME)?;
+        let (authority, remote_path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host_port)) => (user.to_string(), host_port),
+            None => (std::env::var("USER").unwrap_or_default(), authority),
+        };
+        let (host, port) = match host_port.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None => (host_port, DEFAULT_PORT),
+        };
+        if host.is_empty() || user.is_empty() {
+            return None;
+        }
+        Some(Self { user, host: host.to_string(), port, path: PathBuf::from(remote_path) })
+    }
+
+    /// Key identifying the connection this location needs.
+    fn connection_key(&self) -> String {
+        format!("{}@{}:{}", self.user, self.host, self.port)
+    }
+}
+
+fn invalid_location(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid SFTP location: {}", path.display()))
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(PathBuf::from)
+}
+
+struct Connection {
+    // The session has to outlive the SFTP channel opened on it.
+    _session: Session,
+    sftp: Sftp,
+}
+
+/// SFTP backend for `sftp://` paths.
+///
+/// Connections are opened on first use and shared afterwards. Hosts must be
+/// listed in `~/.ssh/known_hosts`; authentication uses the configured identity
+/// file for the host, then the SSH agent, then the default keys in `~/.ssh`.
+#[derive(Default)]
+pub struct SftpFs {
+    identities: HashMap<String, PathBuf>,
+    connections: Mutex<HashMap<String, Arc<Connection>>>,
+}
+
+impl fmt::Debug for SftpFs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SftpFs")
+            .field("identities", &self.identities)
+            .field("connections", &self.connections.lock().unwrap().keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl SftpFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses `identity_file` to authenticate as `user` on `host`.
+    pub fn with_identity(mut self, user: &str, host: &str, port: u16, identity_file: PathBuf) -> Self {
+        self.identities.insert(format!("{}@{}:{}", user, host, port), identity_file);
+        self
+    }
+
+    fn connect(&self, location: &SftpLocation) -> io::Result<Arc<Connection>> {
+        let key = location.connection_key();
+        if let Some(connection) = self.connections.lock().unwrap().get(&key) {
+            return Ok(connection.clone());
+        }
+
+        log::info!("Connecting to {}", key);
+        let tcp = connect_tcp(&location.host, location.port)?;
+        tcp.set_read_timeout(Some(Duration::from_millis(TIMEOUT_MS as u64)))?;
+        let mut session = Session::new()?;
+        session.set_timeout(TIMEOUT_MS);
+        session.set_tcp_stream(tcp);
+        session.handshake()?;
+        self.verify_host_key(&session, location)?;
+        self.authenticate(&session, location)?;
+
+        let connection = Arc::new(Connection { sftp: session.sftp()?, _session: session });
+        self.connections.lock().unwrap().insert(key, connection.clone());
+        Ok(connection)
+    }
+
+    fn verify_host_key(&self, session: &Session, location: &SftpLocation) -> io::Result<()> {
+        let mut known_hosts = session.known_hosts()?;
+        if let Some(home) = home_dir() {
+            let file = home.join(".ssh/known_hosts");
+            if file.exists() {
+                known_hosts.read_file(&file, KnownHostFileKind::OpenSSH)?;
+            }
+        }
+        let (key, _) = session
+            .host_key()
+            .ok_or_else(|| io::Error::other("Server did not send a host key"))?;
+        match known_hosts.check_port(&location.host, location.port, key) {
+            CheckResult::Match => Ok(()),
+            CheckResult::NotFound => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Unknown host {}: connect once with ssh to add it to known_hosts", location.host),
+            )),
+            CheckResult::Mismatch => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Host key for {} does not match known_hosts", location.host),
+            )),
+            CheckResult::Failure => Err(io::Error::other("Failed to check the host key")),
+        }
+    }
+
+    fn authenticate(&self, session: &Session, location: &SftpLocation) -> io::Result<()> {
+        let user = location.user.as_str();
+        if let Some(identity) = self.identities.get(&location.connection_key()) {
+            session.userauth_pubkey_file(user, None, identity, None)?;
+            return Ok(());
+        }
+
+        if session.userauth_agent(user).is_ok() && session.authenticated() {
+            return Ok(());
+        }
+        if let Some(home) = home_dir() {
+            for key in ["id_ed25519", "id_ecdsa", "id_rsa"] {
+                let identity = home.join(".ssh").join(key);
+                if identity.exists() && session.userauth_pubkey_file(user, None, &identity, None).is_ok() {
+                    return Ok(());
+                }
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Authentication failed for {}", location.connection_key()),
+        ))
+    }
+
+    /// Runs `op` on the connection for `path`. A session error means the
+    /// connection itself broke, so it's dropped and the next call reconnects.
+    fn run<T>(&self, path: &Path, op: impl FnOnce(&Sftp, &SftpLocation) -> Result<T, ssh2::Error>) -> io::Result<T> {
+        let location = SftpLocation::parse(path).ok_or_else(|| invalid_location(path))?;
+        let connection = self.connect(&location)?;
+        op(&connection.sftp, &location).map_err(|e| {
+            if matches!(e.code(), ErrorCode::Session(_)) {
+                let key = location.connection_key();
+                log::warn!("Dropping the connection to {}: {}", key, e);
+                let mut connections = self.connections.lock().unwrap();
+                if connections.get(&key).is_some_and(|cached| Arc::ptr_eq(cached, &connection)) {
+                    connections.remove(&key);
+                }
+            }
+            e.into()
+        })
+    }
+}
+
+/// Connects to the first address of `host` that answers in time.
+fn connect_tcp(host: &str, port: u16) -> io::Result<TcpStream> {
+    let timeout = Duration::from_millis(TIMEOUT_MS as u64);
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, format!("No address found for {}", host));
+    for address in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(tcp) => return Ok(tcp),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+fn metadata_from_stat(stat: &ssh2::FileStat) -> Metadata {
+    Metadata {
+        is_dir: stat.is_dir(),
+        len: stat.size.unwrap_or(0),
+        modified: stat.mtime.map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+        mode: stat.perm.map(|perm| perm & 0o7777),
+        // SFTP doesn't report device numbers.
+        special: stat
+            .perm
+            .and_then(SpecialKind::from_mode)
+            .map(|kind| SpecialFile { kind, device: None }),
+    }
+}
+
+fn remove_recursive(sftp: &Sftp, path: &Path) -> Result<(), ssh2::Error> {
+    if sftp.lstat(path)?.is_dir() {
+        for (child, _) in sftp.readdir(path)? {
+            remove_recursive(sftp, &child)?;
+        }
+        sftp.rmdir(path)?;
+    } else {
+        sftp.unlink(path)?;
+    }
+    Ok(())
+}
+
+impl Vfs for SftpFs {
+    fn handles(&self, path: &Path) -> bool {
+        path.to_str().is_some_and(|path| path.starts_with(SCHEME))
+    }
+
+    fn parent(&self, path: &Path) -> Option<PathBuf> {
+        if SftpLocation::parse(path)?.path == Path::new("/") {
+            return None;
+        }
+        path.parent().map(Path::to_path_buf)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<VfsEntry>> {
+        self.run(path, |sftp, location| {
+            Ok(sftp
+                .readdir(&location.path)?
+                .into_iter()
+                .map(|(remote_path, stat)| {
+                    // Follow symlinks so linked directories can be entered.
+                    let stat = if stat.file_type().is_symlink() {
+                        sftp.stat(&remote_path).unwrap_or(stat)
+                    } else {
+                        stat
+                    };
+                    VfsEntry {
+                        path: path.join(remote_path.file_name().unwrap_or_default()),
+                        metadata: metadata_from_stat(&stat),
+                    }
+                })
+                .collect())
+        })
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.run(path, |sftp, location| Ok(metadata_from_stat(&sftp.stat(&location.path)?)))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn VfsFile>> {
+        self.run(path, |sftp, location| Ok(Box::new(sftp.open(&location.path)?) as Box<dyn VfsFile>))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        self.run(path, |sftp, location| Ok(Box::new(sftp.create(&location.path)?) as Box<dyn Write + Send>))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        self.run(path, |sftp, location| sftp.mkdir(&location.path, 0o755))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let to_location = SftpLocation::parse(to).ok_or_else(|| invalid_location(to))?;
+        let from_location = SftpLocation::parse(from).ok_or_else(|| invalid_location(from))?;
+        if from_location.connection_key() != to_location.connection_key() {
+            return Err(io::Error::new(io::ErrorKind::CrossesDevices, "cannot rename across hosts"));
+        }
+        self.run(from, |sftp, location| sftp.rename(&location.path, &to_location.path, None))
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        self.run(path, |sftp, location| remove_recursive(sftp, &location.path))
+    }
+}
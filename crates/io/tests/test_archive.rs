@@ -0,0 +1,131 @@
+use io::archive::{self, ArchiveFormat};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use tempdir::TempDir;
+
+fn write_zip(path: &Path, files: &[(&str, &str)]) {
+    let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+    for (name, content) in files {
+        zip.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(content.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+fn write_tar_gz(path: &Path, files: &[(&str, &str)]) {
+    let encoder = flate2::write::GzEncoder::new(File::create(path).unwrap(), flate2::Compression::fast());
+    let mut tar = tar::Builder::new(encoder);
+    for (name, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, name, content.as_bytes()).unwrap();
+    }
+    tar.into_inner().unwrap().finish().unwrap();
+}
+
+#[test]
+fn test_format_detection() {
+    assert_eq!(ArchiveFormat::from_path(Path::new("a.tar.gz")), Some(ArchiveFormat::TarGz));
+    assert_eq!(ArchiveFormat::from_path(Path::new("a.TZST")), Some(ArchiveFormat::TarZst));
+    assert_eq!(ArchiveFormat::from_path(Path::new("a.7z")), Some(ArchiveFormat::SevenZip));
+    assert_eq!(ArchiveFormat::from_path(Path::new("a.gz")), None);
+    assert_eq!(archive::archive_stem(Path::new("/x/release-1.0.tar.xz")), "release-1.0");
+}
+
+#[test]
+fn test_single_top_level_extracts_in_place() {
+    let tmp_dir = TempDir::new("test").unwrap();
+    let zip_path = tmp_dir.path().join("project.zip");
+    write_zip(&zip_path, &[("project/a.txt", "a"), ("project/src/b.txt", "b")]);
+
+    let plan = archive::plan_extraction(&zip_path, tmp_dir.path()).unwrap();
+    assert_eq!(plan.dest, tmp_dir.path());
+    assert_eq!(plan.target, tmp_dir.path().join("project"));
+
+    archive::extract(&zip_path, &plan.dest, |_| {}).unwrap();
+    assert_eq!(fs::read_to_string(tmp_dir.path().join("project/src/b.txt")).unwrap(), "b");
+}
+
+#[test]
+fn test_multiple_top_level_extracts_into_folder() {
+    let tmp_dir = TempDir::new("test").unwrap();
+    let tar_path = tmp_dir.path().join("bundle.tar.gz");
+    write_tar_gz(&tar_path, &[("a.txt", "a"), ("b.txt", "b")]);
+
+    let plan = archive::plan_extraction(&tar_path, tmp_dir.path()).unwrap();
+    assert_eq!(plan.dest, tmp_dir.path().join("bundle"));
+    assert_eq!(plan.target, plan.dest);
+
+    let mut last_progress = 0.0;
+    archive::extract(&tar_path, &plan.dest, |p| last_progress = p).unwrap();
+    assert_eq!(fs::read_to_string(tmp_dir.path().join("bundle/a.txt")).unwrap(), "a");
+    assert_eq!(fs::read_to_string(tmp_dir.path().join("bundle/b.txt")).unwrap(), "b");
+    assert!(last_progress > 0.0);
+}
+
+#[test]
+fn test_unsafe_paths_are_rejected() {
+    let tmp_dir = TempDir::new("test").unwrap();
+    let dest = tmp_dir.path().join("dest");
+
+    for name in ["../evil.txt", "/tmp/evil.txt", "ok/../../evil.txt"] {
+        let zip_path = tmp_dir.path().join("evil.zip");
+        write_zip(&zip_path, &[("fine.txt", "ok"), (name, "evil")]);
+
+        assert!(archive::plan_extraction(&zip_path, &dest).is_err(), "{} was accepted", name);
+        assert!(archive::extract(&zip_path, &dest, |_| {}).is_err(), "{} was extracted", name);
+        assert!(!tmp_dir.path().join("evil.txt").exists());
+    }
+}
+
+#[test]
+fn test_browse_archive_contents() {
+    let tmp_dir = TempDir::new("test").unwrap();
+    let zip_path = tmp_dir.path().join("docs.zip");
+    // No explicit directory entries: `guide/` only exists as a prefix.
+    write_zip(&zip_path, &[("readme.txt", "hi"), ("guide/intro.md", "# Intro"), ("guide/img/a.png", "png")]);
+
+    let (archive, inner) = archive::split_archive_path(&zip_path.join("guide/intro.md")).unwrap();
+    assert_eq!(archive, zip_path);
+    assert_eq!(inner, Path::new("guide/intro.md"));
+    assert!(archive::split_archive_path(tmp_dir.path()).is_none());
+
+    let root = archive::list_dir(&zip_path, Path::new("")).unwrap();
+    let names: Vec<_> = root.iter().map(|e| (e.path.to_str().unwrap(), e.is_dir)).collect();
+    assert_eq!(names, vec![("guide", true), ("readme.txt", false)]);
+
+    let guide = archive::list_dir(&zip_path, Path::new("guide")).unwrap();
+    assert_eq!(guide.len(), 2);
+
+    let content = archive::read_entry(&zip_path, Path::new("guide/intro.md"), 1024).unwrap();
+    assert_eq!(content, b"# Intro");
+
+    let dest = tmp_dir.path().join("guide-copy");
+    archive::extract_entry(&zip_path, Path::new("guide"), &dest, |_| {}).unwrap();
+    assert_eq!(fs::read_to_string(dest.join("img/a.png")).unwrap(), "png");
+    assert!(!dest.join("readme.txt").exists());
+}
+
+#[test]
+fn test_read_index_from_a_stream() {
+    let tmp_dir = TempDir::new("test").unwrap();
+    let zip_path = tmp_dir.path().join("a.zip");
+    let tar_path = tmp_dir.path().join("a.tar.gz");
+    let text = "compressible ".repeat(100);
+    write_zip(&zip_path, &[("docs/long.txt", &text)]);
+    write_tar_gz(&tar_path, &[("docs/long.txt", &text), ("short.txt", "x")]);
+
+    let bytes = fs::read(&zip_path).unwrap();
+    let entries = archive::read_index(std::io::Cursor::new(&bytes), bytes.len() as u64, ArchiveFormat::Zip).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!((entries[0].path.as_path(), entries[0].size), (Path::new("docs/long.txt"), 1300));
+    assert!(entries[0].compressed_size.unwrap() < 100);
+
+    let bytes = fs::read(&tar_path).unwrap();
+    let entries = archive::read_index(std::io::Cursor::new(&bytes), bytes.len() as u64, ArchiveFormat::TarGz).unwrap();
+    let sizes: Vec<_> = entries.iter().map(|e| (e.path.to_str().unwrap(), e.size, e.compressed_size)).collect();
+    assert_eq!(sizes, [("docs/long.txt", 1300, None), ("short.txt", 1, None)]);
+}
@@ -0,0 +1,88 @@
+//! The round-trip test needs an SSH server. Point it at one with e.g.
+//! `RTFM_SFTP_TEST_URL=sftp://$USER@localhost:22/tmp cargo test -p io --features sftp -- --ignored`
+//! after adding the host to `~/.ssh/known_hosts` and loading a key into the agent.
+#![cfg(feature = "sftp")]
+
+use io::vfs::{self, LocalFs, Router, SftpFs, SftpLocation, Vfs};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempdir::TempDir;
+
+#[test]
+fn test_parse_sftp_location() {
+    let location = SftpLocation::parse(Path::new("sftp://alice@example.com:2222/srv/data")).unwrap();
+    assert_eq!(location.user, "alice");
+    assert_eq!(location.host, "example.com");
+    assert_eq!(location.port, 2222);
+    assert_eq!(location.path, Path::new("/srv/data"));
+
+    let root = SftpLocation::parse(Path::new("sftp://alice@example.com")).unwrap();
+    assert_eq!(root.port, 22);
+    assert_eq!(root.path, Path::new("/"));
+
+    assert!(SftpLocation::parse(Path::new("/home/alice")).is_none());
+    assert!(SftpLocation::parse(Path::new("sftp://alice@example.com:port/")).is_none());
+}
+
+#[test]
+fn test_remote_roots_have_no_parent() {
+    let vfs = Router::new(Arc::new(LocalFs)).mount(Arc::new(SftpFs::new()));
+    assert_eq!(vfs.parent(Path::new("sftp://alice@example.com/srv")), Some(PathBuf::from("sftp://alice@example.com")));
+    assert_eq!(vfs.parent(Path::new("sftp://alice@example.com")), None);
+    assert_eq!(vfs.parent(Path::new("sftp://alice@example.com/")), None);
+    assert_eq!(vfs.parent(Path::new("/home/alice")), Some(PathBuf::from("/home")));
+}
+
+#[test]
+fn test_unreachable_hosts_fail_instead_of_hanging() {
+    // Bound but never accepted from, then closed: nothing listens there.
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let sftp = SftpFs::new();
+    let location = PathBuf::from(format!("sftp://alice@127.0.0.1:{}/", port));
+    let started = std::time::Instant::now();
+    assert!(sftp.metadata(&location).is_err());
+    // Nothing was kept, so the next call tries again rather than failing on
+    // a broken connection.
+    assert!(sftp.read_dir(&location).is_err());
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    assert!(!format!("{:?}", sftp).contains("alice@"));
+}
+
+#[test]
+#[ignore = "needs an SSH server, see RTFM_SFTP_TEST_URL"]
+fn test_sftp_round_trip() {
+    let url = std::env::var("RTFM_SFTP_TEST_URL").expect("RTFM_SFTP_TEST_URL is not set");
+    let vfs: Arc<dyn Vfs> = Arc::new(Router::new(Arc::new(LocalFs)).mount(Arc::new(SftpFs::new())));
+
+    let remote_dir = PathBuf::from(url).join(format!("rtfm-test-{}", std::process::id()));
+    vfs.create_dir(&remote_dir).unwrap();
+
+    // Upload a local tree.
+    let tmp_dir = TempDir::new("test").unwrap();
+    let local_src = tmp_dir.path().join("src");
+    fs::create_dir(&local_src).unwrap();
+    fs::write(local_src.join("a.txt"), "hello").unwrap();
+    let remote_src = remote_dir.join("src");
+    vfs::copy_recursive(vfs.as_ref(), &local_src, &remote_src, &mut |_| {}).unwrap();
+
+    let entries = vfs.read_dir(&remote_src).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name(), "a.txt");
+    assert_eq!(entries[0].metadata.len, 5);
+
+    let mut content = String::new();
+    vfs.open(&remote_src.join("a.txt")).unwrap().read_to_string(&mut content).unwrap();
+    assert_eq!(content, "hello");
+
+    // Rename on the server, then download.
+    let renamed = remote_dir.join("renamed");
+    vfs.rename(&remote_src, &renamed).unwrap();
+    let local_dest = tmp_dir.path().join("dest");
+    vfs::copy_recursive(vfs.as_ref(), &renamed, &local_dest, &mut |_| {}).unwrap();
+    assert_eq!(fs::read_to_string(local_dest.join("a.txt")).unwrap(), "hello");
+
+    vfs.remove(&remote_dir).unwrap();
+    assert!(!vfs.exists(&remote_dir));
+}
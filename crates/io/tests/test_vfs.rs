@@ -0,0 +1,127 @@
+use io::archive;
+use io::vfs::{self, MemoryFs, Operation, Vfs};
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Write};
+use std::path::Path;
+use tempdir::TempDir;
+
+#[test]
+fn test_local_vfs_copy_tree() {
+    let tmp_dir = TempDir::new("test").unwrap();
+    let src = tmp_dir.path().join("src");
+    fs::create_dir_all(src.join("nested")).unwrap();
+    fs::write(src.join("a.txt"), "a").unwrap();
+    fs::write(src.join("nested/b.txt"), "bb").unwrap();
+
+    let vfs = vfs::local();
+    assert_eq!(vfs::total_size(vfs.as_ref(), &src).unwrap(), 3);
+
+    let dest = tmp_dir.path().join("dest");
+    let mut copied = 0;
+    vfs::copy_recursive(vfs.as_ref(), &src, &dest, &mut |n| copied += n).unwrap();
+    assert_eq!(copied, 3);
+    assert_eq!(fs::read_to_string(dest.join("nested/b.txt")).unwrap(), "bb");
+
+    let entries = vfs.read_dir(&dest).unwrap();
+    assert_eq!(entries.len(), 2);
+    vfs.remove(&dest).unwrap();
+    assert!(!vfs.exists(&dest));
+}
+
+#[test]
+fn test_archive_contents_are_read_only() {
+    let tmp_dir = TempDir::new("test").unwrap();
+    let zip_path = tmp_dir.path().join("docs.zip");
+    let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+    zip.start_file("a.txt", zip::write::SimpleFileOptions::default()).unwrap();
+    zip.write_all(b"inside").unwrap();
+    zip.finish().unwrap();
+
+    let vfs = vfs::local();
+    let inner = zip_path.join("a.txt");
+    assert!(vfs.is_read_only(&zip_path));
+    assert!(!vfs.is_read_only(tmp_dir.path()));
+    assert_eq!(vfs.metadata(&inner).unwrap().len, 6);
+
+    let mut content = String::new();
+    vfs.open(&inner).unwrap().read_to_string(&mut content).unwrap();
+    assert_eq!(content, "inside");
+    assert!(vfs.remove(&inner).is_err());
+    assert!(vfs.create(&zip_path.join("new.txt")).is_err());
+
+    // The archive file itself lives in a writable directory.
+    assert!(!vfs.metadata(&zip_path).unwrap().is_dir);
+    vfs.rename(&zip_path, &tmp_dir.path().join("renamed.zip")).unwrap();
+    assert!(tmp_dir.path().join("renamed.zip").exists());
+}
+
+#[test]
+fn test_archive_entries_too_large_for_memory_are_not_opened() {
+    let tmp_dir = TempDir::new("test").unwrap();
+    let zip_path = tmp_dir.path().join("logs.zip");
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default().large_file(true);
+    for name in ["small.log", "huge.log"] {
+        zip.start_file(name, options).unwrap();
+        zip.write_all(b"line\n").unwrap();
+    }
+    let mut zip = zip.finish().unwrap().into_inner();
+    // Claim a size past the limit in the zip64 extra field of the second
+    // central directory entry.
+    let central = zip.windows(4).rposition(|window| window == b"PK\x01\x02").unwrap();
+    let extra = central + zip[central..].windows(4).position(|window| window == b"\x01\x00\x10\x00").unwrap() + 4;
+    zip[extra..extra + 8].copy_from_slice(&(archive::MAX_OPEN_SIZE + 1).to_le_bytes());
+    fs::write(&zip_path, zip).unwrap();
+
+    let vfs = vfs::local();
+    let mut content = String::new();
+    vfs.open(&zip_path.join("small.log")).unwrap().read_to_string(&mut content).unwrap();
+    assert_eq!(content, "line\n");
+    let error = vfs.open(&zip_path.join("huge.log")).err().unwrap();
+    assert_eq!(error.kind(), ErrorKind::FileTooLarge);
+}
+
+#[test]
+fn test_memory_fs() {
+    let fs = MemoryFs::new();
+    fs.add_file("/a/b/c.txt", "hello");
+    assert!(fs.metadata(Path::new("/a/b")).unwrap().is_dir);
+    assert_eq!(fs.read_dir(Path::new("/a")).unwrap()[0].name(), "b");
+
+    vfs::copy_recursive(&fs, Path::new("/a"), Path::new("/copy"), &mut |_| {}).unwrap();
+    fs.rename(Path::new("/copy/b"), Path::new("/moved")).unwrap();
+    let mut content = String::new();
+    fs.open(Path::new("/moved/c.txt")).unwrap().read_to_string(&mut content).unwrap();
+    assert_eq!(content, "hello");
+    assert!(!fs.exists(Path::new("/copy/b/c.txt")));
+
+    assert_eq!(fs.create_dir(Path::new("/missing/dir")).unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(fs.create_dir(Path::new("/moved")).unwrap_err().kind(), ErrorKind::AlreadyExists);
+
+    fs.fail(Operation::Open, "/moved/c.txt", ErrorKind::PermissionDenied);
+    assert_eq!(fs.open(Path::new("/moved/c.txt")).err().unwrap().kind(), ErrorKind::PermissionDenied);
+    fs.clear_failures();
+
+    fs.remove(Path::new("/moved")).unwrap();
+    assert!(!fs.exists(Path::new("/moved/c.txt")));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_special_files_are_flagged() {
+    use io::vfs::{LocalFs, SpecialKind};
+
+    let null = LocalFs.metadata(Path::new("/dev/null")).unwrap();
+    let special = null.special.unwrap();
+    assert_eq!(special.kind, SpecialKind::CharDevice);
+    assert_eq!(special.device, Some((1, 3)));
+
+    let tmp_dir = TempDir::new("test").unwrap();
+    let regular = tmp_dir.path().join("a.txt");
+    fs::write(&regular, "a").unwrap();
+    assert_eq!(LocalFs.metadata(&regular).unwrap().special, None);
+    assert_eq!(vfs::split_device(0x0000_0801), (8, 1));
+
+    let err = vfs::copy_recursive(&LocalFs, Path::new("/dev/null"), &tmp_dir.path().join("null"), &mut |_| {});
+    assert_eq!(err.unwrap_err().kind(), ErrorKind::InvalidInput);
+}
@@ -1,9 +1,18 @@
-use std::fs;
-use std::io::Read;
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::AtomicBool;
+use std::task::Poll;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use crate::task_manager::{TaskManager, TaskKind};
-use humansize::{format_size, BINARY};
 use crate::clipboard::{Clipboard, ClipboardMode};
+use crate::listing::{Listing, ListingUpdate};
+use crate::preview::{self, Follow, FollowPoll, PageJob, PageRead, Preview, PreviewJob, Previewer, TextPage, TextTarget, BYTES_PER_ROW, HEX_PAGE_SIZE, TEXT_PAGE_SIZE};
+use crate::mime;
+use crate::watcher::{DirChange, DirWatcher};
+use io::archive::{self, ArchiveFormat};
+use io::vfs::{ArchiveFs, LocalFs, Router, Vfs, VfsEntry};
+#[cfg(feature = "sftp")]
+use io::vfs;
 use directories::UserDirs;
 use config::Config;
 use log;
@@ -23,6 +32,9 @@ pub enum InputMode {
     Normal,
     Create,
     Rename,
+    Open,
+    /// Jump to an offset in the hex preview.
+    Offset,
 }
 
 #[derive(Debug, Clone)]
@@ -35,27 +47,79 @@ pub struct DirEntry {
     pub name: String,
     pub path: PathBuf,
     pub is_dir: bool,
+    /// Guessed from the name while listing; replaced by the type detected
+    /// from the content once the entry has been previewed.
+    pub mime: Option<&'static str>,
 }
 
-#[derive(Debug, Clone)]
+impl DirEntry {
+    pub fn new(name: String, path: PathBuf, is_dir: bool) -> Self {
+        let mime = if is_dir { Some(mime::DIRECTORY) } else { mime::from_extension(&path) };
+        Self { name, path, is_dir, mime }
+    }
+}
+
+#[derive(Debug)]
 pub struct TabState {
     pub id: usize,
     pub current_dir: PathBuf,
     pub entries: Vec<DirEntry>,
     pub cursor: usize,
-    pub preview_content: Option<String>,
+    pub preview_content: Option<Arc<Preview>>,
     pub preview_scroll: (u16, u16),
+    /// The nodes folded in a tree preview, by index.
+    pub preview_folds: BTreeSet<usize>,
+    /// Show Markdown previews as highlighted source instead of rendered. Kept
+    /// while moving between files.
+    pub markdown_source: bool,
+    /// How many rows the preview pane showed when last drawn.
+    pub preview_rows: u16,
+    /// The previewed file, if its end is kept in view as it grows.
+    pub follow: Option<Follow>,
+    pub vfs: Arc<dyn Vfs>,
+    /// List directories and generate previews on background tasks instead of
+    /// blocking. Only takes effect inside a tokio runtime.
+    pub background_jobs: bool,
+    /// The listing still streaming into `entries`, if any.
+    pub listing: Option<Listing>,
+    /// What a listing that rescans the current directory has read so far.
+    /// It replaces `entries` once complete, so they don't empty meanwhile.
+    pub rescan: Option<Vec<DirEntry>>,
+    /// Watcher changes to the current directory that came in while it was
+    /// being listed, applied once the listing is complete.
+    pub pending_changes: Option<DirChange>,
+    pub previewer: Arc<Previewer>,
+    /// The preview being generated for the selected entry, if any.
+    pub preview_job: Option<PreviewJob>,
+    /// The page of paged text or hex being read, with the line it's for
+    /// when jumping to one.
+    pub page_job: Option<(Option<u64>, PageJob<PageRead>)>,
+    /// The check for lines appended to the followed file, if one is running.
+    pub follow_job: Option<PageJob<FollowPoll>>,
 }
 
 impl TabState {
-    pub fn new(id: usize) -> Self {
+    pub fn new(id: usize, vfs: Arc<dyn Vfs>, current_dir: PathBuf) -> Self {
         Self {
             id,
-            current_dir: std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
+            current_dir,
             entries: Vec::new(),
             cursor: 0,
             preview_content: None,
             preview_scroll: (0, 0),
+            preview_folds: BTreeSet::new(),
+            markdown_source: false,
+            preview_rows: 0,
+            follow: None,
+            vfs,
+            background_jobs: false,
+            listing: None,
+            rescan: None,
+            pending_changes: None,
+            previewer: Arc::new(Previewer::default()),
+            preview_job: None,
+            page_job: None,
+            follow_job: None,
         }
     }
 
@@ -65,31 +129,159 @@ impl TabState {
     }
 
     pub fn update_entries(&mut self, show_hidden: bool) {
-        self.entries = match fs::read_dir(&self.current_dir) {
+        self.cursor = 0;
+        // Replacing a running listing cancels it.
+        self.listing = None;
+        self.rescan = None;
+        self.pending_changes = None;
+        if self.background_jobs && tokio::runtime::Handle::try_current().is_ok() {
+            self.entries.clear();
+            self.listing = Some(Listing::start(self.vfs.clone(), self.current_dir.clone()));
+        } else {
+            self.entries = self.read_entries(show_hidden);
+        }
+        self.update_preview();
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.listing.is_some()
+    }
+
+    /// Merges a batch from the background listing into `entries`, keeping the
+    /// cursor on the same entry.
+    pub fn add_batch(&mut self, batch: Vec<VfsEntry>, show_hidden: bool) {
+        let selected = self.get_selected_entry_path();
+        let mut batch = dir_entries(batch, show_hidden);
+        sort_entries(&mut batch);
+
+        // Both sides are sorted, so a merge keeps this linear per batch.
+        let mut merged = Vec::with_capacity(self.entries.len() + batch.len());
+        let mut batch = batch.into_iter().peekable();
+        for entry in self.entries.drain(..) {
+            while let Some(new) = batch.next_if(|new| compare_entries(new, &entry).is_lt()) {
+                merged.push(new);
+            }
+            merged.push(entry);
+        }
+        merged.extend(batch);
+        self.entries = merged;
+
+        match selected {
+            Some(path) => {
+                self.cursor = self.entries.iter().position(|e| e.path == path).unwrap_or(0);
+            }
+            None => self.update_preview(),
+        }
+    }
+
+    fn read_entries(&self, show_hidden: bool) -> Vec<DirEntry> {
+        let mut entries: Vec<DirEntry> = match self.vfs.read_dir(&self.current_dir) {
             Ok(entries) => entries
-                .filter_map(|res| res.ok())
-                .filter(|entry| {
-                    if show_hidden {
-                        true
-                    } else {
-                        !entry.file_name().to_string_lossy().starts_with('.')
-                    }
-                })
-                .map(|entry| {
-                    let path = entry.path();
-                    let is_dir = path.is_dir();
-                    let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
-                    DirEntry { name, path, is_dir }
-                })
+                .into_iter()
+                .map(|entry| DirEntry::new(entry.name(), entry.path, entry.metadata.is_dir))
+                .filter(|entry| show_hidden || !entry.name.starts_with('.'))
                 .collect(),
             Err(e) => {
                 log::error!("Failed to read directory {:?}: {}", self.current_dir, e);
                 vec![]
             }
         };
-        self.entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
-        self.cursor = 0;
+        sort_entries(&mut entries);
+        entries
+    }
+
+    /// Re-reads the current directory, keeping the cursor on the same entry.
+    /// With background jobs, the entries are replaced once the listing is
+    /// complete.
+    pub fn reload_entries(&mut self, show_hidden: bool) {
+        if self.background_jobs && tokio::runtime::Handle::try_current().is_ok() {
+            self.pending_changes = None;
+            self.rescan = Some(Vec::new());
+            self.listing = Some(Listing::start(self.vfs.clone(), self.current_dir.clone()));
+            return;
+        }
+        let selected = self.get_selected_entry_path();
+        self.entries = self.read_entries(show_hidden);
+        self.restore_cursor(selected);
+    }
+
+    /// Shows the entries of a finished rescan.
+    fn finish_rescan(&mut self, mut entries: Vec<DirEntry>) {
+        let selected = self.get_selected_entry_path();
+        sort_entries(&mut entries);
+        self.entries = entries;
+        self.restore_cursor(selected);
+    }
+
+    /// Updates just the given entries of the current directory, e.g. after a
+    /// file watcher reported them as changed. The cursor stays on the same entry.
+    pub fn apply_changes(&mut self, paths: &BTreeSet<PathBuf>, show_hidden: bool) {
+        let selected = self.get_selected_entry_path();
+        self.entries.retain(|entry| !paths.contains(&entry.path));
+        for path in paths.iter().filter(|path| path.parent() == Some(self.current_dir.as_path())) {
+            let Ok(metadata) = self.vfs.metadata(path) else {
+                continue; // Removed.
+            };
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            if show_hidden || !name.starts_with('.') {
+                self.entries.push(DirEntry::new(name, path.clone(), metadata.is_dir));
+            }
+        }
+        sort_entries(&mut self.entries);
+        self.restore_cursor(selected);
+    }
+
+    /// Puts the cursor back on `selected`, or keeps its position if that entry
+    /// is gone. Refreshes the preview if the selection or its content changed.
+    fn restore_cursor(&mut self, selected: Option<PathBuf>) {
+        if let Some(index) = selected.as_ref().and_then(|path| self.entries.iter().position(|e| &e.path == path)) {
+            self.cursor = index;
+        } else {
+            self.cursor = self.cursor.min(self.entries.len().saturating_sub(1));
+        }
+        // The selected file may have been modified.
+        if self.get_selected_entry_path() == selected && self.follow.is_none() && self.refresh_page() {
+            return;
+        }
+        // Cheap enough to always redo otherwise.
+        let scroll = self.preview_scroll;
+        let folds = std::mem::take(&mut self.preview_folds);
         self.update_preview();
+        // A followed file stays scrolled to its end instead.
+        if self.get_selected_entry_path() == selected && self.follow.is_none() {
+            self.preview_scroll = scroll;
+            self.preview_folds = folds;
+        }
+    }
+
+    /// Reads the page of a paged text or hex preview of the selected file
+    /// again where it is, rather than from the start. `false` if the preview
+    /// isn't one, or it can't be read.
+    fn refresh_page(&mut self) -> bool {
+        let selected = self.get_selected_entry_path();
+        let row = self.preview_scroll.0 as i64;
+        let result = match self.preview_content.as_deref() {
+            // One on its way is read afresh anyway.
+            Some(Preview::Paged(_) | Preview::Hex(_)) if self.page_job.is_some() => return true,
+            Some(Preview::Paged(page)) if selected.as_ref() == Some(&page.path) => {
+                let page = page.clone();
+                self.read_page(None, move |vfs, _| {
+                    let fresh = page.refresh(vfs)?;
+                    let row = if fresh.offset == page.offset { row } else { 0 };
+                    Ok((Preview::Paged(fresh), row))
+                })
+            }
+            Some(Preview::Hex(dump)) if selected.as_ref() == Some(&dump.path) => {
+                let dump = dump.clone();
+                self.read_page(None, move |vfs, _| {
+                    let fresh = dump.refresh(vfs)?;
+                    let row = if fresh.offset == dump.offset { row } else { 0 };
+                    Ok((Preview::Hex(fresh), row))
+                })
+            }
+            _ => return false,
+        };
+        result.is_ok()
     }
 
     pub fn move_cursor_down(&mut self, _show_hidden: bool) {
@@ -109,7 +301,7 @@ impl TabState {
 
     pub fn enter_directory(&mut self, show_hidden: bool) {
         if let Some(entry) = self.entries.get(self.cursor) {
-            if entry.is_dir {
+            if entry.is_dir || self.vfs.can_enter(&entry.path) {
                 self.current_dir = entry.path.clone();
                 self.update_entries(show_hidden);
             }
@@ -118,83 +310,467 @@ impl TabState {
     }
 
     pub fn leave_directory(&mut self, show_hidden: bool) {
-        if let Some(parent) = self.current_dir.parent() {
-            self.current_dir = parent.to_path_buf();
+        if let Some(parent) = self.vfs.parent(&self.current_dir) {
+            self.current_dir = parent;
             self.update_entries(show_hidden);
         }
         self.update_preview();
     }
 
     pub fn update_preview(&mut self) {
+        if let Some(follow) = &self.follow {
+            if self.get_selected_entry_path().as_ref() == Some(&follow.path) {
+                self.poll_follow();
+                return;
+            }
+            self.follow = None;
+            self.follow_job = None;
+        }
         self.preview_scroll = (0, 0);
+        self.preview_folds.clear();
+        // Replacing a running job cancels it.
+        self.preview_job = None;
+        self.page_job = None;
         let Some(selected_entry) = self.entries.get(self.cursor) else {
             self.preview_content = None;
             return;
         };
 
-        if selected_entry.is_dir {
-            let entries = match fs::read_dir(&selected_entry.path) {
-                Ok(entries) => {
-                    let mut names = entries
-                        .filter_map(|res| res.ok())
-                        .map(|entry| {
-                            let mut name = entry.file_name().to_string_lossy().to_string();
-                            if entry.path().is_dir() {
-                                name.push('/');
-                            }
-                            name
-                        })
-                        .collect::<Vec<_>>();
-                    names.sort();
-                    names.join("\n")
-                }
-                Err(e) => format!("Error reading directory:\n{}", e),
-            };
-            self.preview_content = Some(entries);
+        let path = selected_entry.path.clone();
+        // Even a stat can be slow, e.g. on a remote filesystem, so the cache
+        // is looked up on the background job too.
+        if self.background_jobs && tokio::runtime::Handle::try_current().is_ok() {
+            self.preview_content = None;
+            self.preview_job = Some(PreviewJob::start(self.vfs.clone(), self.previewer.clone(), path));
         } else {
-            const MAX_PREVIEW_SIZE: u64 = 1024 * 1024; // 1MB
-            let file_content = match fs::File::open(&selected_entry.path) {
-                Ok(mut file) => {
-                    let metadata = file.metadata().ok();
-                    let file_size = metadata.as_ref().map_or(0, |m| m.len());
-
-                    if file_size > MAX_PREVIEW_SIZE {
-                        format!(
-                            "File is too large to preview ({} > 1MB)",
-                            format_size(file_size, BINARY)
-                        )
-                    } else {
-                        let mut buffer = Vec::new();
-                        match file.read_to_end(&mut buffer) {
-                            Ok(_) => match String::from_utf8(buffer) {
-                                Ok(s) => s,
-                                Err(_) => "[Binary File]".to_string(),
-                            },
-                            Err(e) => format!("Error reading file:\n{}", e),
-                        }
-                    }
+            let loaded = self.previewer.load(self.vfs.as_ref(), &path, &AtomicBool::new(false));
+            self.show_preview(&path, loaded);
+        }
+    }
+
+    /// Shows a preview loaded for `path`, and stores the MIME type detected
+    /// on its entry.
+    fn show_preview(&mut self, path: &Path, loaded: Option<(Arc<Preview>, Option<&'static str>)>) {
+        let Some((preview, mime)) = loaded else {
+            self.preview_content = None;
+            return;
+        };
+        self.preview_content = Some(preview);
+        let Some(mime) = mime else {
+            return;
+        };
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.path == path) {
+            entry.mime = Some(mime);
+        }
+    }
+
+    /// Scrolls the preview by `rows`. Hex dumps and paged text load the
+    /// neighbouring page when the view gets close to either end of the one
+    /// they have.
+    pub fn scroll_preview(&mut self, rows: i32) {
+        // Looking back through a followed file stops following it, as in
+        // `less +F`.
+        if rows < 0 {
+            self.follow = None;
+        }
+        let row = self.preview_scroll.0 as i64 + rows as i64;
+        if let Some(Preview::Paged(page)) = self.preview_content.as_deref() {
+            // Lines vary in length, so the page is found from the line on top.
+            let anchor = page.starts.get(self.preview_scroll.0 as usize).copied().unwrap_or(page.offset);
+            let bottom = row + self.preview_rows as i64;
+            let offset = page.starts.get(bottom.max(0) as usize).copied().unwrap_or(page.end);
+            let near_start = row < 0 && page.offset > 0;
+            let near_end = (bottom >= page.rows() as i64 || offset + TEXT_PAGE_SIZE / 4 > page.end) && page.end < page.len;
+            // Unless the next page is already on its way.
+            if (near_start || near_end) && self.page_job.is_none() {
+                let (path, page) = (page.path.clone(), page.clone());
+                let result = self.read_page(None, move |vfs, _| {
+                    let page = page.seek(vfs, anchor.saturating_sub(TEXT_PAGE_SIZE / 2))?;
+                    let row = page.row_of(anchor) as i64 + rows as i64;
+                    Ok((Preview::Paged(page), row))
+                });
+                if let Err(e) = result {
+                    log::warn!("Failed to page through {:?}: {}", path, e);
                 }
-                Err(e) => format!("Error opening file:\n{}", e),
+                return;
+            }
+            let last_row = self.last_text_row(page) as i64;
+            self.preview_scroll.0 = row.clamp(0, last_row) as u16;
+            return;
+        }
+        if let Some(Preview::Hex(dump)) = self.preview_content.as_deref() {
+            let offset = (dump.offset as i64 + row * BYTES_PER_ROW as i64).max(0) as u64;
+            let near_start = row < 0 && dump.offset > 0;
+            let near_end = offset + HEX_PAGE_SIZE / 4 > dump.end() && dump.end() < dump.len;
+            if (near_start || near_end) && self.page_job.is_none() {
+                let path = dump.path.clone();
+                if let Err(e) = self.seek_preview(offset) {
+                    log::warn!("Failed to page through {:?}: {}", path, e);
+                }
+                return;
+            }
+            let last_row = dump.rows().saturating_sub(1) as i64;
+            self.preview_scroll.0 = row.clamp(0, last_row.min(u16::MAX as i64)) as u16;
+            return;
+        }
+        // The top row of a tree is the one folds apply to, so it stays a node.
+        if let Some(Preview::Tree(tree)) = self.preview_content.as_deref() {
+            let last_row = tree.rows(&self.preview_folds).count().saturating_sub(1) as i64;
+            self.preview_scroll.0 = row.clamp(0, last_row.min(u16::MAX as i64)) as u16;
+            return;
+        }
+        self.preview_scroll.0 = row.clamp(0, u16::MAX as i64) as u16;
+    }
+
+    /// The node on the top row of a tree preview, which folding applies to.
+    pub fn preview_tree_node(&self) -> Option<usize> {
+        let Some(Preview::Tree(tree)) = self.preview_content.as_deref() else {
+            return None;
+        };
+        tree.rows(&self.preview_folds).nth(self.preview_scroll.0 as usize)
+    }
+
+    /// Folds or unfolds the node on the top row of a tree preview. The node
+    /// stays on top.
+    pub fn toggle_preview_fold(&mut self) {
+        let Some(node) = self.preview_tree_node() else {
+            return;
+        };
+        if !self.preview_folds.remove(&node) {
+            self.preview_folds.insert(node);
+        }
+    }
+
+    /// Folds every container of a tree preview, or unfolds everything if
+    /// anything is folded, and goes back to the top.
+    pub fn toggle_all_preview_folds(&mut self) {
+        let Some(Preview::Tree(tree)) = self.preview_content.as_deref() else {
+            return;
+        };
+        self.preview_folds = match self.preview_folds.is_empty() {
+            true => (0..tree.nodes.len()).filter(|&index| tree.is_foldable(index)).collect(),
+            false => BTreeSet::new(),
+        };
+        self.preview_scroll.0 = 0;
+    }
+
+    /// Switches Markdown previews between rendered and source views.
+    pub fn toggle_markdown_source(&mut self) {
+        self.markdown_source = !self.markdown_source;
+        if let Some(Preview::Markdown { .. }) = self.preview_content.as_deref() {
+            self.preview_scroll = (0, 0);
+        }
+    }
+
+    /// Shows `offset` at the top of the hex preview, reading the page around
+    /// it if needed. Fails if the preview isn't a hex dump.
+    pub fn seek_preview(&mut self, offset: u64) -> std::io::Result<()> {
+        let Some(Preview::Hex(dump)) = self.preview_content.as_deref() else {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "only hex previews can jump to an offset"));
+        };
+        let offset = preview::row_start(offset.min(dump.len.saturating_sub(1)));
+        if dump.contains(offset) && (offset + HEX_PAGE_SIZE / 4 <= dump.end() || dump.end() == dump.len) {
+            self.preview_scroll.0 = ((offset - dump.offset) / BYTES_PER_ROW) as u16;
+            return Ok(());
+        }
+        let dump = dump.clone();
+        self.read_page(None, move |vfs, _| {
+            // Start the page well before the target so scrolling back up
+            // doesn't need another read right away.
+            let page = dump.seek(vfs, offset.saturating_sub(HEX_PAGE_SIZE / 2))?;
+            let row = (offset.saturating_sub(page.offset) / BYTES_PER_ROW) as i64;
+            Ok((Preview::Hex(page), row))
+        })
+    }
+
+    /// Shows `target` at the top of the paged text preview, reading the page
+    /// around it. A line number is found by counting lines from the start of
+    /// the file, on a background job if enabled; one past the end shows the
+    /// end. Fails if the preview isn't paged text.
+    pub fn jump_preview(&mut self, target: TextTarget) -> std::io::Result<()> {
+        let Some(Preview::Paged(page)) = self.preview_content.as_deref() else {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "only paged text previews can jump to a line"));
+        };
+        if target != TextTarget::End {
+            self.follow = None;
+        }
+        let line = match target {
+            TextTarget::Line(line) => Some(line),
+            _ => None,
+        };
+        let page = page.clone();
+        self.read_page(line, move |vfs, cancelled| {
+            let (page, row) = match target {
+                TextTarget::Start => (page.seek(vfs, 0)?, 0),
+                // The last page is read whole, and the view ends with it.
+                TextTarget::End => (page.seek(vfs, page.len.saturating_sub(TEXT_PAGE_SIZE))?, usize::MAX),
+                TextTarget::Offset(offset) => {
+                    let page = page.seek(vfs, offset.saturating_sub(TEXT_PAGE_SIZE / 2))?;
+                    let row = page.row_of(offset);
+                    (page, row)
+                }
+                TextTarget::Line(line) => page.seek_line(vfs, line, cancelled)?,
             };
-            self.preview_content = Some(file_content);
+            Ok((Preview::Paged(page), row.min(u16::MAX as usize) as i64))
+        })
+    }
+
+    /// Replaces the page of a paged text or hex preview with the one `read`
+    /// finds: on a background job if enabled, replacing one still running,
+    /// and straight away otherwise. `line` is the line jumped to, if any.
+    fn read_page(
+        &mut self,
+        line: Option<u64>,
+        read: impl FnOnce(&dyn Vfs, &AtomicBool) -> PageRead + Send + 'static,
+    ) -> std::io::Result<()> {
+        self.page_job = None;
+        if self.background_jobs && tokio::runtime::Handle::try_current().is_ok() {
+            let vfs = self.vfs.clone();
+            self.page_job = Some((line, PageJob::start(move |cancelled| read(vfs.as_ref(), cancelled))));
+            return Ok(());
+        }
+        let (page, row) = read(self.vfs.as_ref(), &AtomicBool::new(false))?;
+        self.show_page(page, row);
+        Ok(())
+    }
+
+    fn show_page(&mut self, page: Preview, row: i64) {
+        match page {
+            Preview::Paged(page) => self.show_text_page(page, row),
+            page => {
+                self.preview_scroll.0 = row.clamp(0, u16::MAX as i64) as u16;
+                self.preview_content = Some(Arc::new(page));
+            }
         }
     }
 
+    /// Starts or stops keeping the end of the previewed file in view as it
+    /// grows. Returns `false` if it can't be followed because it isn't text.
+    pub fn toggle_follow(&mut self) -> bool {
+        self.follow_job = None;
+        if self.follow.take().is_some() {
+            return true;
+        }
+        let Some(path) = self.get_selected_entry_path() else {
+            return false;
+        };
+        // Hex dumps, images and documents aren't text, however their ends look.
+        let page = match self.preview_content.as_deref() {
+            None | Some(Preview::Hex(_) | Preview::Image(_) | Preview::Document { .. }) => return false,
+            Some(Preview::Paged(page)) => Some(page),
+            Some(_) => None,
+        };
+        match Follow::start(self.vfs.as_ref(), &path, page) {
+            Ok(Some((follow, last))) => {
+                self.follow = Some(follow);
+                self.show_text_page(last, i64::MAX);
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                log::warn!("Failed to follow {:?}: {}", path, e);
+                false
+            }
+        }
+    }
+
+    /// Shows what's been appended to the followed file since last time,
+    /// checking on a background job if enabled.
+    pub fn poll_follow(&mut self) {
+        let Some(mut follow) = self.follow.take() else {
+            return;
+        };
+        let page = match self.preview_content.as_deref() {
+            Some(Preview::Paged(page)) => Some(page),
+            _ => None,
+        };
+        if self.background_jobs && tokio::runtime::Handle::try_current().is_ok() {
+            // One check at a time: a slow one just puts off the next.
+            if self.follow_job.is_none() {
+                let (vfs, mut follow, page) = (self.vfs.clone(), follow.clone(), page.cloned());
+                self.follow_job = Some(PageJob::start(move |_| {
+                    let result = follow.poll(vfs.as_ref(), page.as_ref());
+                    (follow, result)
+                }));
+            }
+            self.follow = Some(follow);
+            return;
+        }
+        let result = follow.poll(self.vfs.as_ref(), page);
+        self.show_followed((follow, result));
+    }
+
+    /// Follows the file as a poll left it, showing its last page if it
+    /// changed.
+    fn show_followed(&mut self, (follow, result): FollowPoll) {
+        match result {
+            Ok(Some(last)) => self.show_text_page(last, i64::MAX),
+            Ok(None) => {}
+            // Likely gone for a moment while a log is rotated; the next poll
+            // tries again.
+            Err(e) => log::debug!("Failed to poll {:?}: {}", follow.path, e),
+        }
+        self.follow = Some(follow);
+    }
+
+    fn show_text_page(&mut self, page: TextPage, row: i64) {
+        let last_row = self.last_text_row(&page) as i64;
+        self.preview_scroll.0 = row.clamp(0, last_row) as u16;
+        self.preview_content = Some(Arc::new(Preview::Paged(page)));
+    }
+
+    /// The last row paged text can scroll to: the one that fills the view
+    /// with the end of the file, or the page's last line before the next is
+    /// read.
+    fn last_text_row(&self, page: &TextPage) -> usize {
+        let last_row = match page.end < page.len {
+            true => page.rows().saturating_sub(1),
+            false => page.rows().saturating_sub(self.preview_rows.max(1) as usize),
+        };
+        last_row.min(u16::MAX as usize)
+    }
+
+    /// The preview of the selected entry as plain text.
+    pub fn preview_text(&self) -> Option<&str> {
+        self.preview_content.as_deref().map(Preview::text)
+    }
+
+    pub fn is_preview_loading(&self) -> bool {
+        self.preview_job.is_some()
+    }
+
+    /// The line being looked for in paged text, while lines are counted.
+    pub fn jumping_to(&self) -> Option<u64> {
+        self.page_job.as_ref().and_then(|(line, _)| *line)
+    }
+
     pub fn get_selected_entry_path(&self) -> Option<PathBuf> {
         self.entries.get(self.cursor).map(|e| e.path.clone())
     }
 }
 
 
+/// Progress from one of a tab's background jobs.
+#[derive(Debug)]
+pub enum TabUpdate {
+    Listing(ListingUpdate),
+    /// The finished preview with its MIME type, or `None` if the job died.
+    Preview(Option<(Arc<Preview>, Option<&'static str>)>),
+    /// Another page of the preview and its top row, or `None` if the job died.
+    Page(Option<PageRead>),
+    /// The followed file after a check, or `None` if the job died.
+    Follow(Option<FollowPoll>),
+}
+
+/// Waits for the next update from the background jobs of any tab and returns
+/// it with the tab's index. Never returns if no tab has jobs running.
+pub async fn next_tab_update(tabs: &mut [TabState]) -> (usize, TabUpdate) {
+    std::future::poll_fn(|cx| {
+        for (index, tab) in tabs.iter_mut().enumerate() {
+            if let Some(listing) = tab.listing.as_mut() {
+                if let Poll::Ready(update) = listing.poll_batch(cx) {
+                    return Poll::Ready((index, TabUpdate::Listing(update)));
+                }
+            }
+            if let Some(job) = tab.preview_job.as_mut() {
+                if let Poll::Ready(content) = job.poll_result(cx) {
+                    return Poll::Ready((index, TabUpdate::Preview(content)));
+                }
+            }
+            if let Some((_, job)) = tab.page_job.as_mut() {
+                if let Poll::Ready(result) = job.poll_result(cx) {
+                    return Poll::Ready((index, TabUpdate::Page(result)));
+                }
+            }
+            if let Some(job) = tab.follow_job.as_mut() {
+                if let Poll::Ready(polled) = job.poll_result(cx) {
+                    return Poll::Ready((index, TabUpdate::Follow(polled)));
+                }
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Something the UI has to ask the filesystem about before it can go on,
+/// which can mean connecting to a remote host first.
+#[derive(Debug)]
+pub enum Lookup {
+    /// Opening a location in a new tab; the answer is whether it's a directory.
+    Open(PathBuf),
+    /// Pasting into a directory; the answer is whether a name is taken.
+    Paste(Clipboard, PathBuf),
+}
+
+impl Lookup {
+    /// What's being waited for, for the status area.
+    pub fn status(&self) -> String {
+        match self {
+            Lookup::Open(path) => format!("Connecting to {}…", path.display()),
+            Lookup::Paste(_, destination) => format!("Checking {}…", destination.display()),
+        }
+    }
+}
+
+/// Waits for the answer to the pending lookup, or `None` if its job died.
+/// Never returns while there's no lookup.
+pub async fn next_lookup(lookup: &mut Option<(Lookup, PageJob<std::io::Result<bool>>)>) -> Option<std::io::Result<bool>> {
+    std::future::poll_fn(|cx| match lookup.as_mut() {
+        Some((_, job)) => job.poll_result(cx),
+        None => Poll::Pending,
+    })
+    .await
+}
+
+/// Directories first, then by name.
+fn compare_entries(a: &DirEntry, b: &DirEntry) -> std::cmp::Ordering {
+    b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name))
+}
+
+fn dir_entries(batch: Vec<VfsEntry>, show_hidden: bool) -> Vec<DirEntry> {
+    batch
+        .into_iter()
+        .map(|entry| DirEntry::new(entry.name(), entry.path, entry.metadata.is_dir))
+        .filter(|entry| show_hidden || !entry.name.starts_with('.'))
+        .collect()
+}
+
+fn sort_entries(entries: &mut [DirEntry]) {
+    entries.sort_by(compare_entries);
+}
+
+/// Local disk and archives, plus SFTP when built with the `sftp` feature.
+fn build_vfs(config: &Config) -> Arc<dyn Vfs> {
+    let router = Router::new(Arc::new(LocalFs)).mount(Arc::new(ArchiveFs));
+    #[cfg(feature = "sftp")]
+    let router = {
+        let mut sftp = vfs::SftpFs::new();
+        for profile in config.remotes.values() {
+            if let Some(identity) = profile.identity_path() {
+                sftp = sftp.with_identity(&profile.user, &profile.host, profile.port, identity);
+            }
+        }
+        router.mount(Arc::new(sftp))
+    };
+    #[cfg(not(feature = "sftp"))]
+    let _ = config;
+    Arc::new(router)
+}
+
 #[derive(Debug)]
 pub struct AppState {
     pub tabs: Vec<TabState>,
     pub active_tab_index: usize,
     pub show_tabs: bool,
     pub task_manager: TaskManager,
+    pub watcher: DirWatcher,
     pub clipboard: Clipboard,
     pub show_terminal: bool,
     pub show_hidden_files: bool, // Re-add this
+    /// Whether tabs use background jobs, see [`TabState::background_jobs`].
+    pub background_jobs: bool,
+    pub previewer: Arc<Previewer>,
     pub focus: FocusBlock,
     pub xdg_dirs: Vec<(String, PathBuf)>,
     pub xdg_cursor: usize,
@@ -202,9 +778,16 @@ pub struct AppState {
     pub bookmarks_cursor: usize,
     #[cfg(feature = "mounts")]
     pub mounts: Vec<proc_mounts::MountInfo>,
-    #[cfg(feature = "mounts")]
+    /// SFTP profiles from the config as (name, url), listed after the mounts.
+    pub remotes: Vec<(String, PathBuf)>,
     pub disks_cursor: usize,
     pub config: Config,
+    /// Write the config back to disk when it changes, e.g. on bookmarking.
+    /// Off for states built with [`AppState::with_vfs`].
+    pub persist_config: bool,
+    /// Where new tabs open: the directory the app was started in.
+    pub start_dir: PathBuf,
+    pub vfs: Arc<dyn Vfs>,
     pub show_confirmation: bool,
     pub confirmation_message: String,
     pub path_to_delete: Option<PathBuf>,
@@ -215,6 +798,9 @@ pub struct AppState {
     pub create_file_type: Option<CreateFileType>,
     pub path_to_rename: Option<PathBuf>,
     pub pending_paste: Option<(Clipboard, PathBuf)>,
+    /// A lookup running on a background job, see [`next_lookup`].
+    pub lookup: Option<(Lookup, PageJob<std::io::Result<bool>>)>,
+    pub pending_extract: Option<(PathBuf, PathBuf)>,
     pub notification: Option<String>,
     pub notification_timer: Option<std::time::Instant>,
     pub input_dialog_error: Option<String>,
@@ -224,6 +810,13 @@ pub struct AppState {
 pub enum ActionToConfirm {
     Delete,
     Paste,
+    Extract,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AppState {
@@ -244,16 +837,22 @@ impl AppState {
             xdg_dirs.push(("Home".to_string(), user_dirs.home_dir().to_path_buf()));
         }
 
-        let bookmarks = config.bookmarks.clone().into_iter().collect();
+        let vfs = build_vfs(&config);
+        let start_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+        let mut app_state = Self::with_vfs(config, vfs, start_dir);
+        app_state.xdg_dirs = xdg_dirs;
+        app_state.persist_config = true;
+        app_state.watcher = DirWatcher::new();
+        app_state.sync_watches();
 
         #[cfg(feature = "mounts")]
-        let mounts = {
+        {
             const IGNORED_FS_TYPES: &[&str] = &[
                 "proc", "sysfs", "devtmpfs", "devpts", "tmpfs", "securityfs",
                 "cgroup", "cgroup2", "pstore", "bpf", "efivarfs", "debugfs",
                 "hugetlbfs", "mqueue"
             ];
-            match MountIter::new() {
+            app_state.mounts = match MountIter::new() {
                 Ok(iter) => iter
                     .filter_map(|res| res.ok())
                     .filter(|mount| !IGNORED_FS_TYPES.contains(&mount.fstype.as_str()))
@@ -262,31 +861,53 @@ impl AppState {
                     log::error!("Failed to get mounts: {}", e);
                     Vec::new()
                 }
-            }
-        };
+            };
+        }
+
+        app_state
+    }
+
+    /// Builds the state on top of `vfs` with a single tab in `start_dir`,
+    /// without looking at the user's directories or mounts or saving the
+    /// config.
+    pub fn with_vfs(config: Config, vfs: Arc<dyn Vfs>, start_dir: PathBuf) -> Self {
+        let bookmarks = config.bookmarks.clone().into_iter().collect();
+        let remotes = config
+            .remotes
+            .iter()
+            .map(|(name, profile)| (name.clone(), profile.url()))
+            .collect();
 
         let show_hidden_files = false;
-        let mut initial_tab = TabState::new(0);
+        let previewer = Arc::new(Previewer::new(&config));
+        let mut initial_tab = TabState::new(0, vfs.clone(), start_dir.clone());
+        initial_tab.previewer = previewer.clone();
         initial_tab.update_entries(show_hidden_files);
 
         Self {
             tabs: vec![initial_tab],
             active_tab_index: 0,
             show_tabs: false, // Hidden by default with one tab
-            task_manager: TaskManager::new(),
+            task_manager: TaskManager::new(vfs.clone()),
+            watcher: DirWatcher::disabled(),
             clipboard: Clipboard::new(),
             show_terminal: false,
             show_hidden_files,
+            background_jobs: false,
+            previewer,
             focus: FocusBlock::Middle,
-            xdg_dirs,
+            xdg_dirs: Vec::new(),
             xdg_cursor: 0,
             bookmarks,
             bookmarks_cursor: 0,
             #[cfg(feature = "mounts")]
-            mounts,
-            #[cfg(feature = "mounts")]
+            mounts: Vec::new(),
+            remotes,
             disks_cursor: 0,
             config,
+            persist_config: false,
+            start_dir,
+            vfs,
             show_confirmation: false,
             confirmation_message: String::new(),
             path_to_delete: None,
@@ -297,12 +918,127 @@ impl AppState {
             create_file_type: None,
             path_to_rename: None,
             pending_paste: None,
+            lookup: None,
+            pending_extract: None,
             notification: None,
             notification_timer: None,
             input_dialog_error: None,
         }
     }
 
+    /// The directories whose contents are on screen: every tab's current
+    /// directory and the directory previewed in the active tab.
+    pub fn visible_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = self.tabs.iter().map(|tab| tab.current_dir.clone()).collect();
+        let active_tab = self.get_active_tab();
+        if let Some(entry) = active_tab.entries.get(active_tab.cursor).filter(|entry| entry.is_dir) {
+            dirs.push(entry.path.clone());
+        }
+        dirs
+    }
+
+    pub fn sync_watches(&mut self) {
+        let dirs = self.visible_dirs();
+        self.watcher.sync(dirs);
+    }
+
+    pub fn set_background_jobs(&mut self, enabled: bool) {
+        self.background_jobs = enabled;
+        for tab in &mut self.tabs {
+            tab.background_jobs = enabled;
+        }
+    }
+
+    /// Applies an update from the background jobs of tab `index`.
+    pub fn apply_tab_update(&mut self, index: usize, update: TabUpdate) {
+        let show_hidden = self.show_hidden_files;
+        let Some(tab) = self.tabs.get_mut(index) else {
+            return;
+        };
+        match update {
+            TabUpdate::Preview(loaded) => {
+                if let Some(job) = tab.preview_job.take() {
+                    tab.show_preview(&job.path, loaded);
+                }
+            }
+            TabUpdate::Page(result) => {
+                let Some((line, _)) = tab.page_job.take() else {
+                    return;
+                };
+                match (result, line) {
+                    (Some(Ok((page, row))), line) => {
+                        // Like any jump to a line, this stops following.
+                        if line.is_some() {
+                            tab.follow = None;
+                        }
+                        tab.show_page(page, row);
+                    }
+                    (Some(Err(e)), Some(line)) => self.set_notification(format!("Cannot jump to line {}: {}", line, e)),
+                    (Some(Err(e)), None) => self.set_notification(format!("Cannot read the preview: {}", e)),
+                    (None, _) => {}
+                }
+            }
+            TabUpdate::Follow(polled) => {
+                tab.follow_job = None;
+                let Some((follow, result)) = polled else {
+                    return;
+                };
+                // Unless following stopped, or moved on to another file, while
+                // the file was checked.
+                if tab.follow.as_ref().is_some_and(|current| current.path == follow.path) {
+                    tab.show_followed((follow, result));
+                }
+            }
+            TabUpdate::Listing(Some(Ok(batch))) => match tab.rescan.as_mut() {
+                Some(rescan) => rescan.extend(dir_entries(batch, show_hidden)),
+                None => tab.add_batch(batch, show_hidden),
+            },
+            TabUpdate::Listing(Some(Err(e))) => {
+                log::error!("Failed to read directory {:?}: {}", tab.current_dir, e);
+                tab.listing = None;
+                tab.rescan = None;
+                tab.pending_changes = None;
+                let message = format!("Cannot read {}: {}", tab.current_dir.display(), e);
+                self.set_notification(message);
+            }
+            TabUpdate::Listing(None) => {
+                tab.listing = None;
+                if let Some(entries) = tab.rescan.take() {
+                    tab.finish_rescan(entries);
+                }
+                match tab.pending_changes.take() {
+                    Some(DirChange::Rescan) => tab.reload_entries(show_hidden),
+                    Some(DirChange::Entries(paths)) => tab.apply_changes(&paths, show_hidden),
+                    None => {}
+                }
+            }
+        }
+    }
+
+    /// Applies changes reported by the watcher to all tabs and the preview.
+    pub fn apply_dir_changes(&mut self, changes: &HashMap<PathBuf, DirChange>) {
+        let show_hidden = self.show_hidden_files;
+        for tab in &mut self.tabs {
+            if tab.is_loading() {
+                // The listing may have read the directory before it changed.
+                if let Some(change) = changes.get(&tab.current_dir) {
+                    tab.pending_changes.get_or_insert_with(|| DirChange::Entries(BTreeSet::new())).extend(change);
+                }
+                continue;
+            }
+            match changes.get(&tab.current_dir) {
+                Some(DirChange::Rescan) => tab.reload_entries(show_hidden),
+                Some(DirChange::Entries(paths)) => tab.apply_changes(paths, show_hidden),
+                None => {
+                    let previewed = tab.entries.get(tab.cursor).filter(|entry| entry.is_dir);
+                    if previewed.is_some_and(|entry| changes.contains_key(&entry.path)) {
+                        tab.update_preview();
+                    }
+                }
+            }
+        }
+    }
+
     pub fn toggle_tabs(&mut self) {
         self.show_tabs = !self.show_tabs;
     }
@@ -335,11 +1071,8 @@ impl AppState {
                 if self.bookmarks_cursor < max { self.bookmarks_cursor += 1; }
             },
             FocusBlock::Disks => {
-                #[cfg(feature = "mounts")]
-                {
-                    let max = self.mounts.len().saturating_sub(1);
-                    if self.disks_cursor < max { self.disks_cursor += 1; }
-                }
+                let max = (self.mount_count() + self.remotes.len()).saturating_sub(1);
+                if self.disks_cursor < max { self.disks_cursor += 1; }
             },
             FocusBlock::Middle => {}, // Should not happen
         }
@@ -355,10 +1088,7 @@ impl AppState {
                 if self.bookmarks_cursor > 0 { self.bookmarks_cursor -= 1; }
             },
             FocusBlock::Disks => {
-                #[cfg(feature = "mounts")]
-                {
-                    if self.disks_cursor > 0 { self.disks_cursor -= 1; }
-                }
+                if self.disks_cursor > 0 { self.disks_cursor -= 1; }
             },
             FocusBlock::Middle => {}, // Should not happen
         }
@@ -370,6 +1100,7 @@ impl AppState {
             FocusBlock::Xdg => self.xdg_dirs.get(self.xdg_cursor).map(|(_, path)| path.clone()),
             FocusBlock::Bookmarks => self.bookmarks.get(self.bookmarks_cursor).map(|(_, path)| path.clone()),
             FocusBlock::Disks => {
+                // Remotes are only opened on demand, connecting is too slow for cursor moves.
                 #[cfg(feature = "mounts")]
                 {
                     self.mounts.get(self.disks_cursor).map(|mount| mount.dest.clone())
@@ -390,6 +1121,151 @@ impl AppState {
         }
     }
 
+    pub fn mount_count(&self) -> usize {
+        #[cfg(feature = "mounts")]
+        {
+            self.mounts.len()
+        }
+        #[cfg(not(feature = "mounts"))]
+        {
+            0
+        }
+    }
+
+    /// Opens the remote under the cursor of the mounts/remotes block in a new tab.
+    pub fn open_left_pane_selection(&mut self) {
+        if self.focus != FocusBlock::Disks {
+            return;
+        }
+        let Some(index) = self.disks_cursor.checked_sub(self.mount_count()) else {
+            return;
+        };
+        if let Some((_, url)) = self.remotes.get(index).cloned() {
+            self.open_location(url);
+        }
+    }
+
+    /// Opens a local path or `sftp://` URL in a new tab, once it's been
+    /// checked to be a directory.
+    pub fn open_location(&mut self, path: PathBuf) {
+        let lookup_path = path.clone();
+        self.look_up(Lookup::Open(path), move |vfs| Ok(vfs.metadata(&lookup_path)?.is_dir));
+    }
+
+    /// Runs `job` for `lookup` on a background job when tabs use them, and
+    /// right away otherwise. A new lookup replaces one still running.
+    fn look_up(&mut self, lookup: Lookup, job: impl FnOnce(&dyn Vfs) -> std::io::Result<bool> + Send + 'static) {
+        if self.background_jobs && tokio::runtime::Handle::try_current().is_ok() {
+            let vfs = self.vfs.clone();
+            self.lookup = Some((lookup, PageJob::start(move |_| job(vfs.as_ref()))));
+        } else {
+            self.lookup = None;
+            let answer = job(self.vfs.as_ref());
+            self.finish_lookup(lookup, answer);
+        }
+    }
+
+    /// Applies the answer from [`next_lookup`].
+    pub fn apply_lookup(&mut self, answer: Option<std::io::Result<bool>>) {
+        if let (Some((lookup, _)), Some(answer)) = (self.lookup.take(), answer) {
+            self.finish_lookup(lookup, answer);
+        }
+    }
+
+    fn finish_lookup(&mut self, lookup: Lookup, answer: std::io::Result<bool>) {
+        match (lookup, answer) {
+            (Lookup::Open(path), Ok(true)) => {
+                self.new_tab();
+                let show_hidden = self.show_hidden_files;
+                self.get_active_tab_mut().set_current_dir(path, show_hidden);
+            }
+            (Lookup::Open(path), Ok(false)) => self.set_notification(format!("{} is not a directory", path.display())),
+            (Lookup::Open(path), Err(e)) => self.set_notification(format!("Cannot open {}: {}", path.display(), e)),
+            (Lookup::Paste(clipboard, destination), Ok(true)) => {
+                self.confirmation_message = "A file with the same name already exists. Overwrite? (y/n)".to_string();
+                self.show_confirmation = true;
+                self.action_to_confirm = Some(ActionToConfirm::Paste);
+                self.pending_paste = Some((clipboard, destination));
+            }
+            (Lookup::Paste(clipboard, destination), Ok(false)) => self.execute_paste(clipboard, destination),
+            (Lookup::Paste(_, destination), Err(e)) => {
+                self.set_notification(format!("Cannot paste into {}: {}", destination.display(), e))
+            }
+        }
+    }
+
+    /// Opens the location typed into the input dialog.
+    pub fn open_location_from_input(&mut self) {
+        let location = std::mem::take(&mut self.input_buffer);
+        self.input_mode = InputMode::Normal;
+        if !location.is_empty() {
+            self.open_location(PathBuf::from(location));
+        }
+    }
+
+    /// Prompts for an offset to jump to in the hex preview, or a line in
+    /// paged text.
+    pub fn prompt_preview_offset(&mut self) {
+        if !matches!(self.get_active_tab().preview_content.as_deref(), Some(Preview::Hex(_) | Preview::Paged(_))) {
+            self.set_notification("Only hex previews and large text files can be jumped through".to_string());
+            return;
+        }
+        self.input_mode = InputMode::Offset;
+        self.input_buffer.clear();
+        self.show_input_dialog = true;
+        self.input_dialog_error = None;
+    }
+
+    /// Jumps to the offset typed into the input dialog.
+    pub fn seek_preview_from_input(&mut self) {
+        let len = match self.get_active_tab().preview_content.as_deref() {
+            Some(Preview::Hex(dump)) => dump.len,
+            Some(Preview::Paged(page)) => return self.jump_preview_from_input(page.len),
+            _ => 0,
+        };
+        let Some(offset) = preview::parse_offset(&self.input_buffer, len) else {
+            self.input_dialog_error = Some("Enter a decimal or 0x hex offset, or a percentage.".to_string());
+            self.show_input_dialog = true;
+            return;
+        };
+        self.input_buffer.clear();
+        self.input_mode = InputMode::Normal;
+        if let Err(e) = self.get_active_tab_mut().seek_preview(offset) {
+            self.set_notification(format!("Cannot jump to {:#x}: {}", offset, e));
+        }
+    }
+
+    fn jump_preview_from_input(&mut self, len: u64) {
+        let Some(target) = preview::parse_text_target(&self.input_buffer, len) else {
+            self.input_dialog_error = Some("Enter a line number, a percentage, ^ or $.".to_string());
+            self.show_input_dialog = true;
+            return;
+        };
+        let input = std::mem::take(&mut self.input_buffer);
+        self.input_mode = InputMode::Normal;
+        if let Err(e) = self.get_active_tab_mut().jump_preview(target) {
+            self.set_notification(format!("Cannot jump to {}: {}", input.trim(), e));
+        }
+    }
+
+    /// Starts or stops following the previewed file as it grows.
+    pub fn toggle_follow(&mut self) {
+        if !self.get_active_tab_mut().toggle_follow() {
+            self.set_notification("Only text files can be followed".to_string());
+        }
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.tabs.iter().any(|tab| tab.follow.is_some())
+    }
+
+    /// Brings the previews of followed files up to date.
+    pub fn poll_follows(&mut self) {
+        for tab in &mut self.tabs {
+            tab.poll_follow();
+        }
+    }
+
     pub fn toggle_hidden_files(&mut self) {
         self.show_hidden_files = !self.show_hidden_files;
         for tab in &mut self.tabs {
@@ -407,29 +1283,65 @@ impl AppState {
     pub fn cut_selection(&mut self) {
         let selected_path = self.get_active_tab().get_selected_entry_path();
         if let Some(path) = selected_path {
+            if self.refuse_if_read_only(path.parent().unwrap_or(&path)) {
+                return;
+            }
             self.clipboard.cut(vec![path]);
         }
     }
 
+    /// Reports and returns true if entries can't be added to or removed from `dir`.
+    fn refuse_if_read_only(&mut self, dir: &Path) -> bool {
+        if self.vfs.is_read_only(dir) {
+            self.set_notification(format!("{} is read-only", dir.display()));
+            return true;
+        }
+        false
+    }
+
+    /// Archives are extracted with the local filesystem, so only into a
+    /// directory on it, not e.g. one on an SFTP server.
+    fn refuse_if_not_local(&mut self, dir: &Path) -> bool {
+        if self.vfs.local_path(dir).is_none() {
+            self.set_notification(format!("Cannot extract into {}: not a local directory", dir.display()));
+            return true;
+        }
+        false
+    }
+
     pub fn paste(&mut self) {
         if self.clipboard.paths.is_empty() {
             return;
         }
 
         let destination = self.get_active_tab().current_dir.clone();
-        let conflict = self.clipboard.paths.iter().any(|src_path| {
-            let dest_path = destination.join(src_path.file_name().unwrap());
-            dest_path.exists()
-        });
-
-        if conflict {
-            self.confirmation_message = "A file with the same name already exists. Overwrite? (y/n)".to_string();
-            self.show_confirmation = true;
-            self.action_to_confirm = Some(ActionToConfirm::Paste);
-            self.pending_paste = Some((self.clipboard.clone(), destination));
-        } else {
-            self.execute_paste(self.clipboard.clone(), destination);
+        if self.refuse_if_read_only(&destination) {
+            return;
         }
+        let extracts = self.clipboard.mode == Some(ClipboardMode::Copy)
+            && self.clipboard.paths.iter().any(|src_path| archive::split_archive_path(src_path).is_some());
+        if extracts && self.refuse_if_not_local(&destination) {
+            return;
+        }
+        // Also catches a copy onto itself, which would empty a file.
+        let into_itself = self
+            .clipboard
+            .paths
+            .iter()
+            .find(|src_path| destination.join(src_path.file_name().unwrap()).starts_with(src_path));
+        if let Some(src_path) = into_itself {
+            let message = format!("Cannot paste {:?} into itself", src_path.file_name().unwrap());
+            self.set_notification(message);
+            return;
+        }
+        let dest_paths: Vec<PathBuf> = self
+            .clipboard
+            .paths
+            .iter()
+            .map(|src_path| destination.join(src_path.file_name().unwrap()))
+            .collect();
+        let lookup = Lookup::Paste(self.clipboard.clone(), destination);
+        self.look_up(lookup, move |vfs| Ok(dest_paths.iter().any(|dest_path| vfs.exists(dest_path))));
     }
 
     fn execute_paste(&mut self, clipboard: Clipboard, destination: PathBuf) {
@@ -437,9 +1349,10 @@ impl AppState {
         for src_path in &clipboard.paths {
             let dest_path = destination.join(src_path.file_name().unwrap());
             let description = format!("{:?} {:?} -> {:?}", mode, src_path.file_name().unwrap(), destination);
-            let task_kind = match mode {
-                ClipboardMode::Copy => TaskKind::Copy { src: src_path.clone(), dest: dest_path },
-                ClipboardMode::Move => TaskKind::Move { src: src_path.clone(), dest: dest_path },
+            let task_kind = match (mode.clone(), archive::split_archive_path(src_path)) {
+                (ClipboardMode::Copy, Some((archive, entry))) => TaskKind::ExtractEntry { archive, entry, dest: dest_path },
+                (ClipboardMode::Copy, None) => TaskKind::Copy { src: src_path.clone(), dest: dest_path },
+                (ClipboardMode::Move, _) => TaskKind::Move { src: src_path.clone(), dest: dest_path },
             };
             self.task_manager.add_task(task_kind, description);
         }
@@ -481,7 +1394,9 @@ impl AppState {
         }
         log::info!("new_tab called. Current tab count: {}", self.tabs.len());
         let new_id = self.tabs.len();
-        let mut new_tab = TabState::new(new_id);
+        let mut new_tab = TabState::new(new_id, self.vfs.clone(), self.start_dir.clone());
+        new_tab.background_jobs = self.background_jobs;
+        new_tab.previewer = self.previewer.clone();
         new_tab.update_entries(self.show_hidden_files);
         self.tabs.push(new_tab);
         self.active_tab_index = new_id;
@@ -511,14 +1426,19 @@ impl AppState {
         if !name.is_empty() {
             self.config.bookmarks.insert(name.clone(), path.clone());
             self.bookmarks.push((name, path));
-            if let Err(e) = config::save_config(&self.config) {
-                log::error!("Failed to save config: {}", e);
+            if self.persist_config {
+                if let Err(e) = config::save_config(&self.config) {
+                    log::error!("Failed to save config: {}", e);
+                }
             }
         }
     }
 
     pub fn delete_selection(&mut self) {
         if let Some(path) = self.get_active_tab().get_selected_entry_path() {
+            if self.refuse_if_read_only(path.parent().unwrap_or(&path)) {
+                return;
+            }
             self.path_to_delete = Some(path.clone());
             self.confirmation_message = format!("Are you sure you want to delete {:?}? (y/n)", path.file_name().unwrap());
             self.show_confirmation = true;
@@ -539,6 +1459,7 @@ impl AppState {
             match action {
                 ActionToConfirm::Delete => self.confirm_delete(),
                 ActionToConfirm::Paste => self.confirm_paste(),
+                ActionToConfirm::Extract => self.confirm_extract(),
             }
         }
         self.show_confirmation = false;
@@ -549,6 +1470,57 @@ impl AppState {
         self.action_to_confirm = None;
         self.path_to_delete = None;
         self.pending_paste = None;
+        self.pending_extract = None;
+    }
+
+    pub fn set_notification(&mut self, message: String) {
+        self.notification = Some(message);
+        self.notification_timer = Some(std::time::Instant::now());
+    }
+
+    pub fn extract_selection(&mut self) {
+        let Some(archive) = self.get_active_tab().get_selected_entry_path() else {
+            return;
+        };
+        if ArchiveFormat::from_path(&archive).is_none() {
+            self.set_notification("Not a supported archive".to_string());
+            return;
+        }
+
+        let parent = self.get_active_tab().current_dir.clone();
+        if self.refuse_if_read_only(&parent) || self.refuse_if_not_local(&parent) {
+            return;
+        }
+        self.execute_extract(archive, parent, false);
+    }
+
+    fn execute_extract(&mut self, archive: PathBuf, parent: PathBuf, overwrite: bool) {
+        let description = format!("Extract {:?} -> {:?}", archive.file_name().unwrap(), parent);
+        self.task_manager.add_task(TaskKind::Extract { archive, parent, overwrite }, description);
+    }
+
+    /// Asks whether to overwrite the destination of an extraction that found
+    /// it taken, once no other question is open.
+    pub fn prompt_task_conflicts(&mut self) {
+        if self.show_confirmation || self.show_input_dialog {
+            return;
+        }
+        while let Some((kind, target)) = self.task_manager.take_conflict() {
+            if let TaskKind::Extract { archive, parent, .. } = kind {
+                self.confirmation_message =
+                    format!("{:?} already exists. Overwrite? (y/n)", target.file_name().unwrap_or_default());
+                self.show_confirmation = true;
+                self.action_to_confirm = Some(ActionToConfirm::Extract);
+                self.pending_extract = Some((archive, parent));
+                return;
+            }
+        }
+    }
+
+    fn confirm_extract(&mut self) {
+        if let Some((archive, parent)) = self.pending_extract.take() {
+            self.execute_extract(archive, parent, true);
+        }
     }
 
     pub fn create_item(&mut self) {
@@ -560,9 +1532,13 @@ impl AppState {
         self.input_buffer.clear();
 
         let current_dir = self.get_active_tab().current_dir.clone();
+        if self.refuse_if_read_only(&current_dir) {
+            self.create_file_type = None;
+            return;
+        }
         let new_item_path = current_dir.join(&new_item_name);
 
-        if new_item_path.exists() {
+        if self.vfs.exists(&new_item_path) {
             self.input_dialog_error = Some("A file with this name already exists.".to_string());
             self.show_input_dialog = true;
             self.input_buffer = new_item_name;
@@ -586,6 +1562,9 @@ impl AppState {
 
     pub fn rename_selection(&mut self) {
         if let Some(path) = self.get_active_tab().get_selected_entry_path() {
+            if self.refuse_if_read_only(path.parent().unwrap_or(&path)) {
+                return;
+            }
             self.path_to_rename = Some(path.clone());
             self.input_buffer = path.file_name().unwrap_or_default().to_string_lossy().to_string();
             self.input_mode = InputMode::Rename;
@@ -605,7 +1584,7 @@ impl AppState {
                 return;
             }
             let new_path = path_to_rename.with_file_name(&new_name);
-            if new_path.exists() {
+            if self.vfs.exists(&new_path) {
                 self.input_dialog_error = Some("A file with this name already exists.".to_string());
                 self.show_input_dialog = true;
                 return;
@@ -1,3 +1,7 @@
 pub mod app_state;
 pub mod task_manager;
 pub mod clipboard;
+pub mod listing;
+pub mod mime;
+pub mod preview;
+pub mod watcher;
@@ -0,0 +1,71 @@
+use io::vfs::{Vfs, VfsEntry};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Entries per batch once a listing is under way.
+const BATCH_SIZE: usize = 2000;
+/// A partial batch is sent after this long, so slow directories show up gradually.
+const BATCH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A directory being listed by a background task.
+///
+/// Dropping the listing cancels it: the task stops at the next batch.
+pub struct Listing {
+    pub dir: PathBuf,
+    batches: mpsc::Receiver<std::io::Result<Vec<VfsEntry>>>,
+}
+
+impl fmt::Debug for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Listing").field("dir", &self.dir).finish()
+    }
+}
+
+impl Listing {
+    /// Starts listing `dir` on the blocking thread pool. Needs a tokio runtime.
+    pub fn start(vfs: Arc<dyn Vfs>, dir: PathBuf) -> Self {
+        let (tx, rx) = mpsc::channel(4);
+        let path = dir.clone();
+        tokio::task::spawn_blocking(move || {
+            let entries = match vfs.read_dir_iter(&path) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    let _ = tx.blocking_send(Err(e));
+                    return;
+                }
+            };
+
+            let mut batch = Vec::new();
+            let mut last_sent = Instant::now();
+            for entry in entries {
+                match entry {
+                    Ok(entry) => batch.push(entry),
+                    Err(e) => log::warn!("Skipping entry in {:?}: {}", path, e),
+                }
+                if batch.len() >= BATCH_SIZE || (!batch.is_empty() && last_sent.elapsed() >= BATCH_INTERVAL) {
+                    if tx.blocking_send(Ok(std::mem::take(&mut batch))).is_err() {
+                        log::debug!("Listing of {:?} cancelled", path);
+                        return;
+                    }
+                    last_sent = Instant::now();
+                }
+            }
+            if !batch.is_empty() {
+                let _ = tx.blocking_send(Ok(batch));
+            }
+        });
+        Self { dir, batches: rx }
+    }
+
+    /// Polls for the next batch; `Ready(None)` once the listing is complete.
+    pub fn poll_batch(&mut self, cx: &mut Context<'_>) -> Poll<Option<std::io::Result<Vec<VfsEntry>>>> {
+        self.batches.poll_recv(cx)
+    }
+}
+
+/// What a tab's listing produced: a batch, an error, or `None` when complete.
+pub type ListingUpdate = Option<std::io::Result<Vec<VfsEntry>>>;
@@ -0,0 +1,144 @@
+//! MIME types of files, from their first bytes where possible and from the
+//! extension otherwise.
+
+use std::path::Path;
+
+pub const DIRECTORY: &str = "inode/directory";
+pub const TEXT: &str = "text/plain";
+pub const BINARY: &str = "application/octet-stream";
+
+/// Extensions of formats whose content doesn't identify them (mostly text),
+/// and of formats `infer` only recognizes as a generic container.
+const EXTENSIONS: &[(&str, &str)] = &[
+    // Text and markup
+    ("txt", "text/plain"),
+    ("log", "text/plain"),
+    ("md", "text/markdown"),
+    ("markdown", "text/markdown"),
+    ("rst", "text/x-rst"),
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("xml", "text/xml"),
+    ("svg", "image/svg+xml"),
+    ("csv", "text/csv"),
+    ("tsv", "text/tab-separated-values"),
+    ("json", "application/json"),
+    ("toml", "application/toml"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("ini", "text/plain"),
+    ("conf", "text/plain"),
+    // Source code
+    ("rs", "text/x-rust"),
+    ("c", "text/x-c"),
+    ("h", "text/x-c"),
+    ("cpp", "text/x-c++"),
+    ("hpp", "text/x-c++"),
+    ("cc", "text/x-c++"),
+    ("go", "text/x-go"),
+    ("java", "text/x-java"),
+    ("py", "text/x-python"),
+    ("rb", "text/x-ruby"),
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("ts", "text/x-typescript"),
+    ("sh", "text/x-shellscript"),
+    ("bash", "text/x-shellscript"),
+    ("zsh", "text/x-shellscript"),
+    ("lua", "text/x-lua"),
+    ("sql", "text/x-sql"),
+    // Images
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("bmp", "image/bmp"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    // Documents
+    ("pdf", "application/pdf"),
+    ("docx", "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+    ("xlsx", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+    ("pptx", "application/vnd.openxmlformats-officedocument.presentationml.presentation"),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("ods", "application/vnd.oasis.opendocument.spreadsheet"),
+    ("odp", "application/vnd.oasis.opendocument.presentation"),
+    ("epub", "application/epub+zip"),
+    // Archives
+    ("zip", "application/zip"),
+    ("jar", "application/java-archive"),
+    ("tar", "application/x-tar"),
+    ("gz", "application/gzip"),
+    ("tgz", "application/gzip"),
+    ("bz2", "application/x-bzip2"),
+    ("xz", "application/x-xz"),
+    ("zst", "application/zstd"),
+    ("7z", "application/x-7z-compressed"),
+    ("rar", "application/vnd.rar"),
+    // Audio and video
+    ("mp3", "audio/mpeg"),
+    ("flac", "audio/x-flac"),
+    ("ogg", "audio/ogg"),
+    ("opus", "audio/opus"),
+    ("wav", "audio/x-wav"),
+    ("m4a", "audio/m4a"),
+    ("mp4", "video/mp4"),
+    ("m4v", "video/x-m4v"),
+    ("mkv", "video/x-matroska"),
+    ("webm", "video/webm"),
+    ("mov", "video/quicktime"),
+    ("avi", "video/x-msvideo"),
+];
+
+/// Formats that are ZIP files underneath. `infer` may stop at `application/zip`
+/// for these, in which case the extension is more specific.
+const ZIP_BASED: &[&str] = &["docx", "xlsx", "pptx", "odt", "ods", "odp", "epub", "jar"];
+
+/// The MIME type of the file at `path` whose content starts with `head`:
+/// the magic bytes decide where they're known, then the extension, then
+/// whether `head` looks like text.
+pub fn detect(path: &Path, head: &[u8]) -> &'static str {
+    let by_extension = from_extension(path);
+    match infer::get(head).map(|kind| kind.mime_type()) {
+        Some("application/zip") if has_extension(path, ZIP_BASED) => by_extension.unwrap_or("application/zip"),
+        Some(mime) => mime,
+        None => by_extension.unwrap_or_else(|| if looks_binary(head) { BINARY } else { TEXT }),
+    }
+}
+
+/// The MIME type usually meant by the extension of `path`, if it's one we know.
+pub fn from_extension(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    EXTENSIONS.iter().find(|(ext, _)| *ext == extension).map(|(_, mime)| *mime)
+}
+
+/// Whether `mime` names a format that's plain text underneath, like source
+/// code or JSON.
+pub fn is_textual(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || matches!(
+            mime,
+            "application/json" | "application/toml" | "application/yaml" | "application/xml" | "image/svg+xml"
+        )
+}
+
+/// Whether `bytes` aren't UTF-8 text. A multi-byte character cut off at the
+/// end doesn't count, since `bytes` may be just the start of a file.
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    if bytes.contains(&0) {
+        return true;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => false,
+        Err(e) => e.error_len().is_some(),
+    }
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.iter().any(|known| known.eq_ignore_ascii_case(ext)))
+}
@@ -0,0 +1,124 @@
+use super::{SpanStyle, StyledLine, StyledSpan};
+
+/// The 16 basic colors as xterm shows them.
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Splits terminal output into lines of styled spans. SGR sequences set the
+/// style; other escape sequences and control characters are dropped. Styles
+/// carry over from one line to the next, as in a terminal.
+pub(super) fn styled_lines(output: &str) -> Vec<StyledLine> {
+    let mut style = SpanStyle::default();
+    let mut lines = Vec::new();
+    for line in output.lines() {
+        let mut spans = Vec::new();
+        let mut rest = line;
+        while let Some(at) = rest.find('\x1b') {
+            push(&mut spans, &rest[..at], style);
+            rest = &rest[at + 1..];
+            if let Some(csi) = rest.strip_prefix('[') {
+                // Parameters, then a final byte from `@` to `~`.
+                let Some(end) = csi.find(|c: char| ('@'..='~').contains(&c)) else {
+                    rest = "";
+                    break;
+                };
+                if csi[end..].starts_with('m') {
+                    apply_sgr(&mut style, &csi[..end]);
+                }
+                rest = &csi[end + 1..];
+            } else if let Some(osc) = rest.strip_prefix(']') {
+                // Window titles and hyperlinks, ended by BEL or ST.
+                let end = match (osc.find('\x07'), osc.find("\x1b\\")) {
+                    (Some(bel), Some(st)) if st < bel => st + 2,
+                    (Some(bel), _) => bel + 1,
+                    (None, Some(st)) => st + 2,
+                    (None, None) => osc.len(),
+                };
+                rest = &osc[end..];
+            } else {
+                rest = rest.char_indices().nth(1).map_or("", |(at, _)| &rest[at..]);
+            }
+        }
+        push(&mut spans, rest, style);
+        lines.push(spans);
+    }
+    lines
+}
+
+fn push(spans: &mut StyledLine, text: &str, style: SpanStyle) {
+    let text: String = text.chars().filter(|&c| c == '\t' || !c.is_control()).collect();
+    if text.is_empty() {
+        return;
+    }
+    match spans.last_mut() {
+        Some(last) if last.style == style => last.text.push_str(&text),
+        _ => spans.push(StyledSpan { text, style }),
+    }
+}
+
+fn apply_sgr(style: &mut SpanStyle, params: &str) {
+    let mut codes = params.split([';', ':']).map(|code| code.parse::<u16>().unwrap_or(0));
+    while let Some(code) = codes.next() {
+        match code {
+            0 => *style = SpanStyle::default(),
+            1 => style.bold = true,
+            3 => style.italic = true,
+            4 => style.underline = true,
+            22 => style.bold = false,
+            23 => style.italic = false,
+            24 => style.underline = false,
+            30..=37 => style.fg = Some(PALETTE[usize::from(code - 30)]),
+            90..=97 => style.fg = Some(PALETTE[usize::from(code - 90 + 8)]),
+            39 => style.fg = None,
+            // Spans have no background, but its parameters are skipped all
+            // the same.
+            38 | 48 => {
+                let color = match codes.next() {
+                    Some(5) => codes.next().map(indexed_color),
+                    Some(2) => match (codes.next(), codes.next(), codes.next()) {
+                        (Some(r), Some(g), Some(b)) => Some((r as u8, g as u8, b as u8)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if code == 38 {
+                    style.fg = color;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A color of the 256-color palette: the basic 16, a 6×6×6 cube, then 24
+/// grays.
+fn indexed_color(index: u16) -> (u8, u8, u8) {
+    let level = |value: u16| if value == 0 { 0 } else { (55 + value * 40) as u8 };
+    match index {
+        0..=15 => PALETTE[usize::from(index)],
+        16..=231 => {
+            let index = index - 16;
+            (level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        _ => {
+            let gray = (8 + (index.min(255) - 232) * 10) as u8;
+            (gray, gray, gray)
+        }
+    }
+}
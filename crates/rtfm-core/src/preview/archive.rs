@@ -0,0 +1,219 @@
+use super::registry::{FilePreviewer, PreviewRequest};
+use super::{HexDump, Preview};
+use humansize::{format_size, BINARY};
+use io::archive::{self, ArchiveEntry, ArchiveFormat};
+use io::vfs::VfsFile;
+use std::collections::BTreeMap;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Component, Path};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Entries listed at most; the totals still cover the whole archive.
+const MAX_LINES: usize = 5000;
+
+/// The entries of zip, tar (plain or compressed) and 7z archives as a tree,
+/// read from the archive's index without extracting anything.
+#[derive(Debug, Default)]
+pub struct ArchivePreviewer;
+
+impl FilePreviewer for ArchivePreviewer {
+    fn name(&self) -> &'static str {
+        "archive"
+    }
+
+    fn handles(&self, mime: &str) -> bool {
+        matches!(
+            mime,
+            "application/zip"
+                | "application/java-archive"
+                | "application/x-7z-compressed"
+                | "application/x-tar"
+                | "application/gzip"
+                | "application/x-bzip2"
+                | "application/x-xz"
+                | "application/zstd"
+        )
+    }
+
+    fn preview(&self, request: &PreviewRequest, file: &mut dyn VfsFile) -> Option<Preview> {
+        let Some(format) = archive_format(request.path, request.mime) else {
+            // A single compressed file rather than a tarball.
+            return Some(match HexDump::read(request.vfs, request.path, request.metadata.len, 0) {
+                Ok(dump) => Preview::Hex(dump),
+                Err(e) => Preview::Text(format!("Error reading file:\n{}", e)),
+            });
+        };
+        let entries = file.seek(SeekFrom::Start(0)).and_then(|_| {
+            let reader = Cancellable { inner: file, cancelled: request.cancelled };
+            archive::read_index(BufReader::new(reader), request.metadata.len, format)
+        });
+        match entries {
+            Ok(entries) => Some(Preview::Text(describe(&entries, request.metadata.len))),
+            Err(_) if request.is_cancelled() => None,
+            Err(e) => Some(Preview::Text(format!("Error reading archive:\n{}", e))),
+        }
+    }
+}
+
+/// Zip and 7z archives are recognized by content; compressed tarballs need
+/// the name to tell them from a single compressed file.
+fn archive_format(path: &Path, mime: &str) -> Option<ArchiveFormat> {
+    match mime {
+        "application/zip" | "application/java-archive" => Some(ArchiveFormat::Zip),
+        "application/x-7z-compressed" => Some(ArchiveFormat::SevenZip),
+        "application/x-tar" => Some(ArchiveFormat::Tar),
+        _ => ArchiveFormat::from_path(path).filter(|format| !matches!(format, ArchiveFormat::Zip | ArchiveFormat::SevenZip)),
+    }
+}
+
+/// Makes reads fail once the preview is cancelled, so a long decompression
+/// stops early.
+struct Cancellable<'a, R: ?Sized> {
+    inner: &'a mut R,
+    cancelled: &'a AtomicBool,
+}
+
+impl<R: Read + ?Sized> Read for Cancellable<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(std::io::Error::other("cancelled"));
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Seek + ?Sized> Seek for Cancellable<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    children: BTreeMap<String, Node>,
+    is_dir: bool,
+    /// For directories, the sums over everything inside.
+    size: u64,
+    compressed_size: Option<u64>,
+}
+
+impl Node {
+    fn insert(&mut self, entry: &ArchiveEntry) {
+        // `.` and `..` are left out, like extraction does.
+        let names: Vec<String> = entry
+            .path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        let Some((last, parents)) = names.split_last() else {
+            return;
+        };
+        let mut node = self;
+        for name in parents {
+            node = node.children.entry(name.clone()).or_default();
+            node.is_dir = true;
+        }
+        let leaf = node.children.entry(last.clone()).or_default();
+        leaf.is_dir |= entry.is_dir;
+        if !entry.is_dir {
+            leaf.size = entry.size;
+            leaf.compressed_size = entry.compressed_size;
+        }
+    }
+
+    /// Adds up the sizes of directories. Returns the number of files and
+    /// directories below this node.
+    fn sum_up(&mut self) -> (usize, usize) {
+        let (mut files, mut dirs) = (0, 0);
+        if self.children.is_empty() {
+            return (files, dirs);
+        }
+        self.size = 0;
+        self.compressed_size = Some(0);
+        for child in self.children.values_mut() {
+            let (child_files, child_dirs) = child.sum_up();
+            files += child_files + usize::from(!child.is_dir);
+            dirs += child_dirs + usize::from(child.is_dir);
+            // Sizes come from the archive's headers, which can claim anything.
+            self.size = self.size.saturating_add(child.size);
+            self.compressed_size = self.compressed_size.zip(child.compressed_size).map(|(a, b)| a.saturating_add(b));
+        }
+        (files, dirs)
+    }
+
+    /// Directories first, then files, each by name.
+    fn sorted_children(&self) -> Vec<(&String, &Node)> {
+        let mut children: Vec<_> = self.children.iter().collect();
+        children.sort_by_key(|(_, child)| !child.is_dir);
+        children
+    }
+
+    /// Appends a row for each entry below this node. Top-level entries
+    /// (`prefix` is `None`) get no tree lines.
+    fn rows(&self, prefix: Option<&str>, rows: &mut Vec<[String; 3]>) {
+        let children = self.sorted_children();
+        let count = children.len();
+        for (index, (name, child)) in children.into_iter().enumerate() {
+            if rows.len() >= MAX_LINES {
+                return;
+            }
+            let (mut label, child_prefix) = match prefix {
+                None => (name.clone(), String::new()),
+                Some(prefix) if index + 1 == count => (format!("{}└── {}", prefix, name), format!("{}    ", prefix)),
+                Some(prefix) => (format!("{}├── {}", prefix, name), format!("{}│   ", prefix)),
+            };
+            if child.is_dir {
+                label.push('/');
+            }
+            rows.push([label, format_size(child.size, BINARY), ratio(child.compressed_size, child.size)]);
+            child.rows(Some(&child_prefix), rows);
+        }
+    }
+}
+
+/// The stored size as a share of the original, e.g. `45%`.
+fn ratio(compressed_size: Option<u64>, size: u64) -> String {
+    match compressed_size {
+        Some(compressed) if size > 0 => format!("{}%", (compressed as f64 * 100.0 / size as f64).round()),
+        _ => String::new(),
+    }
+}
+
+/// A summary of the archive, followed by its entries as a tree. Without
+/// per-entry compressed sizes the archive's own size is used for the total.
+fn describe(entries: &[ArchiveEntry], archive_len: u64) -> String {
+    let mut root = Node { is_dir: true, ..Node::default() };
+    for entry in entries {
+        root.insert(entry);
+    }
+    let (files, dirs) = root.sum_up();
+    let compressed = root.compressed_size.filter(|_| !entries.is_empty()).unwrap_or(archive_len);
+
+    let mut text = format!(
+        "{} {}, {} {}\nTotal: {} ({} compressed, {})\n",
+        files,
+        if files == 1 { "file" } else { "files" },
+        dirs,
+        if dirs == 1 { "directory" } else { "directories" },
+        format_size(root.size, BINARY),
+        format_size(compressed, BINARY),
+        ratio(Some(compressed), root.size),
+    );
+
+    let mut rows = Vec::new();
+    root.rows(None, &mut rows);
+    let label_width = rows.iter().map(|[label, ..]| label.chars().count()).max().unwrap_or(0);
+    let size_width = rows.iter().map(|[_, size, _]| size.len()).max().unwrap_or(0);
+    for [label, size, ratio] in &rows {
+        let line = format!("\n{:<label_width$}  {:>size_width$}  {:>4}", label, size, ratio);
+        text.push_str(line.trim_end());
+    }
+    let listed = rows.len();
+    if listed < files + dirs {
+        text.push_str(&format!("\n… {} more", files + dirs - listed));
+    }
+    text
+}
@@ -0,0 +1,181 @@
+use super::office::{attribute, element_texts, header, open_zip, read_part, text_of, truncate, walk_xml, word_count};
+use super::registry::{FilePreviewer, PreviewRequest};
+use super::{Preview, MAX_PREVIEW_SIZE};
+use ::io::vfs::VfsFile;
+use quick_xml::events::Event;
+use std::collections::HashMap;
+use std::io::{self, Read, Seek};
+use zip::ZipArchive;
+
+/// Elements that end a line of text.
+const BLOCKS: &[&[u8]] = &[
+    b"p", b"div", b"h1", b"h2", b"h3", b"h4", b"h5", b"h6", b"li", b"tr", b"blockquote", b"pre", b"section",
+];
+
+/// The title, author and language of an EPUB book, its chapter and word
+/// counts, and the text of its first chapters.
+#[derive(Debug, Default)]
+pub struct EpubPreviewer;
+
+impl FilePreviewer for EpubPreviewer {
+    fn name(&self) -> &'static str {
+        "epub"
+    }
+
+    fn handles(&self, mime: &str) -> bool {
+        mime == "application/epub+zip"
+    }
+
+    fn preview(&self, request: &PreviewRequest, file: &mut dyn VfsFile) -> Option<Preview> {
+        let mut zip = match open_zip(file) {
+            Ok(zip) => zip,
+            Err(e) => return Some(Preview::Text(format!("Error reading book:\n{}", e))),
+        };
+        Some(match book(&mut zip, request) {
+            Ok(Some(text)) => Preview::Document { text, image: None },
+            Ok(None) => return None,
+            Err(e) => Preview::Text(format!("Error reading book:\n{}", e)),
+        })
+    }
+}
+
+fn missing(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{} is missing", what))
+}
+
+/// Builds the preview; `None` if cancelled midway.
+fn book<R: Read + Seek>(zip: &mut ZipArchive<R>, request: &PreviewRequest) -> io::Result<Option<String>> {
+    // The container points at the package document, which has the metadata
+    // and the reading order.
+    let container = read_part(zip, "META-INF/container.xml")?.ok_or_else(|| missing("META-INF/container.xml"))?;
+    let mut package_path = None;
+    walk_xml(&container, |event| {
+        if let Event::Empty(element) | Event::Start(element) = event {
+            if element.local_name().as_ref() == b"rootfile" && package_path.is_none() {
+                package_path = attribute(&element, b"full-path");
+            }
+        }
+    })?;
+    let package_path = package_path.ok_or_else(|| missing("The package document"))?;
+    let package = read_part(zip, &package_path)?.ok_or_else(|| missing(&package_path))?;
+    let base = package_path.rsplit_once('/').map_or("", |(dir, _)| dir);
+
+    let metadata = element_texts(&package, &["title", "creator", "language"])?;
+    let mut manifest = HashMap::new();
+    let mut spine = Vec::new();
+    walk_xml(&package, |event| {
+        if let Event::Empty(element) | Event::Start(element) = event {
+            match element.local_name().as_ref() {
+                b"item" => {
+                    if let (Some(id), Some(href)) = (attribute(&element, b"id"), attribute(&element, b"href")) {
+                        manifest.insert(id, href);
+                    }
+                }
+                b"itemref" => spine.extend(attribute(&element, b"idref")),
+                _ => {}
+            }
+        }
+    })?;
+
+    // Words are counted over the whole book; the text stops at what the
+    // preview can show.
+    let mut text = String::new();
+    let mut words = 0;
+    for href in spine.iter().filter_map(|id| manifest.get(id)) {
+        if request.is_cancelled() {
+            return Ok(None);
+        }
+        let path = resolve(base, href);
+        let Some(chapter) = read_part(zip, &path)? else {
+            continue;
+        };
+        let chapter = xhtml_text(&chapter)?;
+        words += word_count(&chapter);
+        if (text.len() as u64) < MAX_PREVIEW_SIZE && !chapter.is_empty() {
+            text.push_str(&chapter);
+            text.push_str("\n\n");
+        }
+    }
+
+    let mut preview = header(&[
+        ("Title", metadata.get("title").cloned()),
+        ("Author", metadata.get("creator").cloned()),
+        ("Language", metadata.get("language").cloned()),
+        ("Chapters", Some(spine.len().to_string())),
+        ("Words", Some(words.to_string())),
+    ]);
+    preview.push_str("\n\n");
+    preview.push_str(text.trim_end());
+    truncate(&mut preview);
+    Ok(Some(preview))
+}
+
+/// The zip path of `href`, relative to the package document in `base`.
+fn resolve(base: &str, href: &str) -> String {
+    let href = percent_decode(href.split('#').next().unwrap_or(href));
+    let mut parts: Vec<&str> = base.split('/').filter(|part| !part.is_empty()).collect();
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match (bytes[i], hex.and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The readable text of an XHTML chapter: one line per paragraph, heading
+/// or list item, with the markup's own line breaks and indentation collapsed.
+fn xhtml_text(xhtml: &str) -> io::Result<String> {
+    let mut lines = vec![String::new()];
+    let mut hidden = 0;
+    walk_xml(xhtml, |event| match event {
+        Event::Start(element) if matches!(element.local_name().as_ref(), b"head" | b"script" | b"style") => hidden += 1,
+        Event::End(element) if matches!(element.local_name().as_ref(), b"head" | b"script" | b"style") => hidden -= 1,
+        Event::Start(element) | Event::Empty(element) if BLOCKS.contains(&element.local_name().as_ref()) => {
+            lines.push(String::new());
+        }
+        Event::Empty(element) if element.local_name().as_ref() == b"br" => lines.push(String::new()),
+        Event::End(element) if BLOCKS.contains(&element.local_name().as_ref()) => lines.push(String::new()),
+        Event::Text(text) if hidden == 0 => {
+            let line = lines.last_mut().unwrap();
+            let text = text_of(&text);
+            if text.starts_with(char::is_whitespace) && !line.ends_with(' ') {
+                line.push(' ');
+            }
+            line.push_str(&text.split_whitespace().collect::<Vec<_>>().join(" "));
+            if text.ends_with(char::is_whitespace) && !text.trim().is_empty() {
+                line.push(' ');
+            }
+        }
+        _ => {}
+    })?;
+    Ok(lines
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
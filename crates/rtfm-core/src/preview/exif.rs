@@ -0,0 +1,78 @@
+use super::office::header;
+use ::exif::{Exif, Field, In, Reader, Tag, Value};
+use std::io::Cursor;
+
+/// The EXIF data of a photo as lines like `Camera:   Canon EOS R5`, with
+/// the image's size. `None` if the image has no EXIF data.
+pub(super) fn describe(bytes: &[u8], width: u32, height: u32) -> Option<String> {
+    let exif = Reader::new().read_from_container(&mut Cursor::new(bytes)).ok()?;
+    let field = |tag| exif.get_field(tag, In::PRIMARY);
+    let text = |tag| field(tag).and_then(ascii);
+
+    // Models often repeat the make, as in "Canon" and "Canon EOS R5".
+    let camera = match (text(Tag::Make), text(Tag::Model)) {
+        (Some(make), Some(model)) if !model.starts_with(&make) => Some(format!("{} {}", make, model)),
+        (make, model) => model.or(make),
+    };
+    let taken = field(Tag::DateTimeOriginal).or_else(|| field(Tag::DateTime));
+    let exposure = [
+        field(Tag::ExposureTime).map(|field| field.display_value().with_unit(&exif).to_string()),
+        rational(field(Tag::FNumber)).map(|f_number| format!("f/{}", f_number)),
+        field(Tag::PhotographicSensitivity).and_then(|field| field.value.get_uint(0)).map(|iso| format!("ISO {}", iso)),
+        rational(field(Tag::FocalLength)).map(|focal_length| format!("{} mm", focal_length)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+
+    Some(header(&[
+        ("Camera", camera),
+        ("Lens", text(Tag::LensModel)),
+        ("Taken", taken.map(|field| field.display_value().to_string())),
+        ("Exposure", (!exposure.is_empty()).then(|| exposure.join(", "))),
+        ("GPS", gps(&exif)),
+        ("Size", Some(format!("{}x{}", width, height))),
+    ]))
+}
+
+fn ascii(field: &Field) -> Option<String> {
+    let Value::Ascii(values) = &field.value else {
+        return None;
+    };
+    let value = String::from_utf8_lossy(values.first()?);
+    let value = value.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+fn rational(field: Option<&Field>) -> Option<f64> {
+    match &field?.value {
+        Value::Rational(values) => values.first().map(|value| value.to_f64()),
+        _ => None,
+    }
+    .filter(|value| value.is_finite())
+}
+
+/// The position in decimal degrees, e.g. `35.658581, 139.745433, 40 m`.
+fn gps(exif: &Exif) -> Option<String> {
+    let coordinate = |tag, reference, negative: &[u8]| {
+        let Value::Rational(parts) = &exif.get_field(tag, In::PRIMARY)?.value else {
+            return None;
+        };
+        let [degrees, minutes, seconds] = parts.get(..3)? else {
+            return None;
+        };
+        let value = degrees.to_f64() + minutes.to_f64() / 60.0 + seconds.to_f64() / 3600.0;
+        let negative = exif.get_field(reference, In::PRIMARY).is_some_and(|field| {
+            matches!(&field.value, Value::Ascii(values) if values.first().is_some_and(|value| value == negative))
+        });
+        value.is_finite().then_some(if negative { -value } else { value })
+    };
+    let latitude = coordinate(Tag::GPSLatitude, Tag::GPSLatitudeRef, b"S")?;
+    let longitude = coordinate(Tag::GPSLongitude, Tag::GPSLongitudeRef, b"W")?;
+    let mut position = format!("{:.6}, {:.6}", latitude, longitude);
+    if let Some(altitude) = rational(exif.get_field(Tag::GPSAltitude, In::PRIMARY)) {
+        let below = exif.get_field(Tag::GPSAltitudeRef, In::PRIMARY).and_then(|field| field.value.get_uint(0)) == Some(1);
+        position.push_str(&format!(", {} m", if below { -altitude } else { altitude }));
+    }
+    Some(position)
+}
@@ -0,0 +1,201 @@
+use super::ansi::styled_lines;
+use super::{Preview, MAX_PREVIEW_SIZE};
+use config::PreviewConfig;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// How often a running command is checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// The top-level MIME types; a pattern starting with one is a MIME type
+/// rather than a file name glob.
+const MIME_TYPES: &[&str] = &["application", "audio", "font", "image", "inode", "message", "model", "multipart", "text", "video"];
+
+#[derive(Debug)]
+struct Rule {
+    pattern: String,
+    is_mime: bool,
+    command: String,
+}
+
+/// Shell commands from `[preview.external]` that preview files in place of
+/// the built-in previewers, like `bat` or `mediainfo`. Their output is shown
+/// with its colors.
+#[derive(Debug, Default)]
+pub struct ExternalPreviewers {
+    rules: Vec<Rule>,
+    timeout: Duration,
+}
+
+impl ExternalPreviewers {
+    pub fn new(config: &PreviewConfig) -> Self {
+        let rules = config
+            .external
+            .iter()
+            .map(|(pattern, command)| {
+                let is_mime = pattern
+                    .split_once('/')
+                    .is_some_and(|(kind, subtype)| MIME_TYPES.contains(&kind) && !subtype.contains('/'));
+                Rule { pattern: pattern.clone(), is_mime, command: command.clone() }
+            })
+            .collect();
+        Self { rules, timeout: timeout(config) }
+    }
+
+    /// The command of the first rule that matches `path` or its MIME type.
+    /// Globs match the file name, or the whole path if they have a `/`.
+    pub fn command(&self, path: &Path, mime: &str) -> Option<&str> {
+        let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let full_path = path.to_string_lossy();
+        self.rules
+            .iter()
+            .find(|rule| match (rule.is_mime, rule.pattern.contains('/')) {
+                (true, _) => wildcard_match(&rule.pattern, mime),
+                (false, true) => wildcard_match(&rule.pattern, &full_path),
+                (false, false) => wildcard_match(&rule.pattern, &name),
+            })
+            .map(|rule| rule.command.as_str())
+    }
+
+    /// Runs `command` on the local file `path` and shows what it prints.
+    /// `None` if it fails, times out or is cancelled, in which case the
+    /// built-in previewer takes over.
+    pub fn run(&self, command: &str, path: &Path, cancelled: &AtomicBool) -> Option<Preview> {
+        let script = match command.contains("$1") || command.contains("${1") {
+            true => command.to_string(),
+            false => format!("{} \"$1\"", command),
+        };
+        let child = match Command::new("sh")
+            .args(["-c", &script, "rtfm"])
+            .arg(path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                log::warn!("Failed to run {:?}: {}", command, e);
+                return None;
+            }
+        };
+
+        let name = format!("{:?} on {:?}", command, path);
+        let (status, output) = wait_for_output(child, &name, MAX_PREVIEW_SIZE, self.timeout, cancelled)?;
+        if output.is_empty() && !status.success() {
+            log::warn!("{} failed with {}", name, status);
+            return None;
+        }
+
+        let lines = styled_lines(&String::from_utf8_lossy(&output));
+        let text = lines
+            .iter()
+            .map(|line| line.iter().map(|span| span.text.as_str()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n");
+        Some(Preview::Highlighted { text, lines })
+    }
+}
+
+/// How long external programs may run, from `external_timeout`.
+pub(crate) fn timeout(config: &PreviewConfig) -> Duration {
+    Duration::try_from_secs_f64(config.external_timeout).unwrap_or(Duration::from_secs(3))
+}
+
+/// Waits for `child` to exit and returns its status with up to `limit` bytes
+/// of what it printed. `None` if it fails, or is killed because it ran past
+/// `timeout` or was cancelled. `name` says what it is in the log.
+pub(crate) fn wait_for_output(
+    mut child: Child,
+    name: &str,
+    limit: u64,
+    timeout: Duration,
+    cancelled: &AtomicBool,
+) -> Option<(ExitStatus, Vec<u8>)> {
+    // Read on another thread so a chatty command can't fill the pipe and
+    // stall while it's waited on.
+    let Some(mut stdout) = child.stdout.take() else {
+        kill(child);
+        return None;
+    };
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let result = stdout.by_ref().take(limit).read_to_end(&mut output);
+        let _ = tx.send(result.map(|_| output));
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if cancelled.load(Ordering::Relaxed) => {
+                kill(child);
+                return None;
+            }
+            Ok(None) if Instant::now() >= deadline => {
+                log::warn!("{} took longer than {:?}", name, timeout);
+                kill(child);
+                return None;
+            }
+            Ok(None) => std::thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                log::warn!("Failed to wait for {}: {}", name, e);
+                kill(child);
+                return None;
+            }
+        }
+    };
+    // Something the command started in the background can hold on to
+    // its output; that isn't waited for past the deadline either.
+    match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(Ok(output)) => Some((status, output)),
+        Ok(Err(e)) => {
+            log::warn!("Failed to read the output of {}: {}", name, e);
+            None
+        }
+        Err(_) => {
+            log::warn!("{} left its output open", name);
+            None
+        }
+    }
+}
+
+fn kill(mut child: Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Matches `text` against a pattern where `*` stands for any run of
+/// characters and `?` for any one.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // The last `*` and where in the text it was tried, to backtrack to.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
@@ -0,0 +1,162 @@
+use super::registry::{FilePreviewer, PreviewRequest};
+use super::Preview;
+use io::vfs::{Vfs, VfsFile};
+use std::fmt::Write;
+use std::io::{Read, SeekFrom};
+use std::path::{Path, PathBuf};
+
+pub const BYTES_PER_ROW: u64 = 16;
+/// How much of the file one page of the dump holds.
+pub const HEX_PAGE_SIZE: u64 = 64 * 1024;
+/// Bytes read from the start of the file to detect its type.
+const MAGIC_LEN: usize = 8192;
+
+/// A hex + ASCII dump of one page of a binary file. Larger files are paged
+/// through by seeking, so only `HEX_PAGE_SIZE` bytes are ever in memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexDump {
+    pub path: PathBuf,
+    /// Size of the whole file.
+    pub len: u64,
+    /// The MIME type detected from the file's magic bytes.
+    pub kind: Option<&'static str>,
+    /// Offset of the first byte of the page, always a multiple of `BYTES_PER_ROW`.
+    pub offset: u64,
+    /// The page rendered one row per line: offset, hex bytes, ASCII.
+    pub text: String,
+}
+
+impl HexDump {
+    /// Dumps the page of `path` that starts at the row containing `offset`.
+    pub fn read(vfs: &dyn Vfs, path: &Path, len: u64, offset: u64) -> std::io::Result<Self> {
+        let mut file = vfs.open(path)?;
+        let mut magic = Vec::with_capacity(MAGIC_LEN);
+        file.by_ref().take(MAGIC_LEN as u64).read_to_end(&mut magic)?;
+        let kind = infer::get(&magic).map(|kind| kind.mime_type());
+        Self::read_page(file.as_mut(), path, len, kind, offset)
+    }
+
+    /// The first page of a file that has already been read into memory.
+    pub fn from_bytes(path: &Path, bytes: &[u8]) -> Self {
+        let page = &bytes[..bytes.len().min(HEX_PAGE_SIZE as usize)];
+        Self {
+            path: path.to_path_buf(),
+            len: bytes.len() as u64,
+            kind: infer::get(bytes).map(|kind| kind.mime_type()),
+            offset: 0,
+            text: format_rows(0, page),
+        }
+    }
+
+    /// The page starting at the row containing `offset`, which is clamped to
+    /// the file. Keeps the detected type instead of reading the magic again.
+    pub fn seek(&self, vfs: &dyn Vfs, offset: u64) -> std::io::Result<Self> {
+        let mut file = vfs.open(&self.path)?;
+        Self::read_page(file.as_mut(), &self.path, self.len, self.kind, offset)
+    }
+
+    /// This page read again from a file that may have changed size.
+    pub fn refresh(&self, vfs: &dyn Vfs) -> std::io::Result<Self> {
+        let len = vfs.metadata(&self.path)?.len;
+        let mut file = vfs.open(&self.path)?;
+        Self::read_page(file.as_mut(), &self.path, len, self.kind, self.offset)
+    }
+
+    fn read_page(file: &mut dyn io::vfs::VfsFile, path: &Path, len: u64, kind: Option<&'static str>, offset: u64) -> std::io::Result<Self> {
+        let offset = row_start(offset.min(len.saturating_sub(1)));
+        file.seek(SeekFrom::Start(offset))?;
+        let mut page = Vec::with_capacity(HEX_PAGE_SIZE.min(len - offset.min(len)) as usize);
+        file.take(HEX_PAGE_SIZE).read_to_end(&mut page)?;
+        Ok(Self { path: path.to_path_buf(), len, kind, offset, text: format_rows(offset, &page) })
+    }
+
+    /// Number of rows in this page.
+    pub fn rows(&self) -> u64 {
+        self.text.lines().count() as u64
+    }
+
+    /// Offset just past the last byte of this page.
+    pub fn end(&self) -> u64 {
+        (self.offset + HEX_PAGE_SIZE).min(self.len)
+    }
+
+    pub fn contains(&self, offset: u64) -> bool {
+        offset >= self.offset && offset < self.end()
+    }
+
+    /// One-line description for the pane title, e.g. `application/zip, 0x0-0xffff of 1.2 MiB`.
+    pub fn header(&self) -> String {
+        let size = humansize::format_size(self.len, humansize::BINARY);
+        format!(
+            "{}, {:#x}-{:#x} of {}",
+            self.kind.unwrap_or("binary data"),
+            self.offset,
+            self.end().saturating_sub(1),
+            size
+        )
+    }
+}
+
+/// Rounds `offset` down to the start of its row.
+pub fn row_start(offset: u64) -> u64 {
+    offset - offset % BYTES_PER_ROW
+}
+
+/// Formats `bytes` like `hexdump -C`, numbering rows from `offset`.
+pub fn format_rows(offset: u64, bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len() / BYTES_PER_ROW as usize * 78);
+    for (index, row) in bytes.chunks(BYTES_PER_ROW as usize).enumerate() {
+        let _ = write!(text, "{:08x}  ", offset + index as u64 * BYTES_PER_ROW);
+        for column in 0..BYTES_PER_ROW as usize {
+            match row.get(column) {
+                Some(byte) => {
+                    let _ = write!(text, "{:02x} ", byte);
+                }
+                None => text.push_str("   "),
+            }
+            if column == 7 {
+                text.push(' ');
+            }
+        }
+        text.push_str(" |");
+        text.extend(row.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }));
+        text.push_str("|\n");
+    }
+    text.pop();
+    text
+}
+
+/// Parses an offset typed by the user: decimal, `0x` hex, or a percentage
+/// of the file such as `50%`.
+pub fn parse_offset(input: &str, len: u64) -> Option<u64> {
+    let input = input.trim();
+    if let Some(percent) = input.strip_suffix('%') {
+        let percent: f64 = percent.trim().parse().ok().filter(|p| (0.0..=100.0).contains(p))?;
+        return Some((len as f64 * percent / 100.0) as u64);
+    }
+    match input.strip_prefix("0x").or_else(|| input.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => input.parse().ok(),
+    }
+}
+
+/// The fallback for anything no other previewer handles.
+#[derive(Debug, Default)]
+pub struct HexPreviewer;
+
+impl FilePreviewer for HexPreviewer {
+    fn name(&self) -> &'static str {
+        "hex"
+    }
+
+    fn handles(&self, _mime: &str) -> bool {
+        true
+    }
+
+    fn preview(&self, request: &PreviewRequest, _file: &mut dyn VfsFile) -> Option<Preview> {
+        Some(match HexDump::read(request.vfs, request.path, request.metadata.len, 0) {
+            Ok(dump) => Preview::Hex(dump),
+            Err(e) => Preview::Text(format!("Error reading file:\n{}", e)),
+        })
+    }
+}
@@ -0,0 +1,231 @@
+use super::{SpanStyle, StyledLine, StyledSpan};
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{FontStyle, Style, Theme, ThemeSet};
+use syntect::parsing::{SyntaxDefinition, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+/// Lines past this are shown without highlighting, which gets slow on huge files.
+const MAX_HIGHLIGHTED_LINES: usize = 5000;
+/// How many lines at either end of a file are searched for a modeline.
+const MODELINE_LINES: usize = 5;
+
+/// The bundled syntaxes don't include TOML.
+const TOML_SYNTAX: &str = r#"%YAML 1.2
+---
+name: TOML
+file_extensions: [toml]
+scope: source.toml
+contexts:
+  main:
+    - match: '#.*$'
+      scope: comment.line.number-sign.toml
+    - match: '^\s*(\[\[?)([^\]]*)(\]\]?)'
+      captures:
+        1: punctuation.definition.table.toml
+        2: entity.name.section.toml
+        3: punctuation.definition.table.toml
+    - match: '([A-Za-z0-9_.-]+)\s*(=)'
+      captures:
+        1: entity.name.tag.toml
+        2: keyword.operator.assignment.toml
+    - match: '"""'
+      push: multiline_basic
+    - match: "'''"
+      push: multiline_literal
+    - match: '"'
+      push: basic
+    - match: "'[^']*'"
+      scope: string.quoted.single.toml
+    - match: '\b(true|false)\b'
+      scope: constant.language.boolean.toml
+    - match: '\d{4}-\d{2}-\d{2}([Tt ]\d{2}:\d{2}:\d{2}(\.\d+)?([Zz]|[+-]\d{2}:\d{2})?)?'
+      scope: constant.other.datetime.toml
+    - match: '[+-]?(0x[0-9A-Fa-f_]+|0o[0-7_]+|0b[01_]+|\d[\d_]*(\.\d[\d_]*)?([eE][+-]?\d+)?|inf|nan)\b'
+      scope: constant.numeric.toml
+  basic:
+    - meta_scope: string.quoted.double.toml
+    - match: '\\.'
+      scope: constant.character.escape.toml
+    - match: '"'
+      pop: true
+  multiline_basic:
+    - meta_scope: string.quoted.triple.toml
+    - match: '\\.'
+      scope: constant.character.escape.toml
+    - match: '"""'
+      pop: true
+  multiline_literal:
+    - meta_scope: string.quoted.triple.toml
+    - match: "'''"
+      pop: true
+"#;
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(|| {
+        let defaults = SyntaxSet::load_defaults_newlines();
+        if defaults.find_syntax_by_extension("toml").is_some() {
+            return defaults;
+        }
+        let mut builder = defaults.into_builder();
+        match SyntaxDefinition::load_from_str(TOML_SYNTAX, true, None) {
+            Ok(toml) => builder.add(toml),
+            Err(e) => log::error!("Failed to load the TOML syntax: {}", e),
+        }
+        builder.build()
+    })
+}
+
+/// Picks a syntax for `text`: a vim or emacs modeline wins, then the
+/// extension, then the first line (shebangs, `<?xml`, ...), then the whole
+/// file name (`Makefile`, `.bashrc`).
+pub fn detect_syntax(path: &Path, text: &str) -> Option<&'static SyntaxReference> {
+    let syntaxes = syntax_set();
+    let from_modeline = || modeline_language(text).and_then(|language| syntaxes.find_syntax_by_token(&language));
+    let from_extension = || {
+        let extension = path.extension()?.to_str()?;
+        syntaxes
+            .find_syntax_by_extension(extension)
+            .or_else(|| syntaxes.find_syntax_by_extension(&extension.to_lowercase()))
+    };
+    let from_first_line = || syntaxes.find_syntax_by_first_line(text.lines().next()?);
+    let from_file_name = || syntaxes.find_syntax_by_extension(path.file_name()?.to_str()?);
+
+    from_modeline()
+        .or_else(from_extension)
+        .or_else(from_first_line)
+        .or_else(from_file_name)
+        .filter(|syntax| syntax.name != "Plain Text")
+}
+
+/// The language named by a modeline such as `vim: set ft=python:` or
+/// `-*- mode: rust -*-`.
+fn modeline_language(text: &str) -> Option<String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let tail = lines.len().saturating_sub(MODELINE_LINES).max(MODELINE_LINES.min(lines.len()));
+    lines[..MODELINE_LINES.min(lines.len())]
+        .iter()
+        .chain(&lines[tail..])
+        .find_map(|line| vim_modeline(line).or_else(|| emacs_modeline(line)))
+}
+
+fn vim_modeline(line: &str) -> Option<String> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| line.find(marker))?;
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|option| {
+            let (key, value) = option.split_once('=')?;
+            matches!(key, "ft" | "filetype" | "syntax").then(|| value.to_string())
+        })
+        .filter(|language| !language.is_empty())
+}
+
+fn emacs_modeline(line: &str) -> Option<String> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let inner = line[start..end].trim();
+    if !inner.contains(':') {
+        return Some(inner.to_string()).filter(|language| !language.is_empty());
+    }
+    inner.split(';').find_map(|option| {
+        let (key, value) = option.split_once(':')?;
+        (key.trim().eq_ignore_ascii_case("mode")).then(|| value.trim().to_string())
+    })
+}
+
+/// Highlights source code with the configured color scheme.
+#[derive(Clone)]
+pub struct Highlighter {
+    theme: Theme,
+}
+
+impl fmt::Debug for Highlighter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Highlighter").field("theme", &self.theme.name).finish()
+    }
+}
+
+impl Default for Highlighter {
+    fn default() -> Self {
+        Self::new(DEFAULT_THEME)
+    }
+}
+
+impl Highlighter {
+    /// `theme` is a built-in theme name or the path of a `.tmTheme` file.
+    pub fn new(theme: &str) -> Self {
+        let mut themes = ThemeSet::load_defaults();
+        let theme = if let Some(builtin) = themes.themes.remove(theme) {
+            builtin
+        } else {
+            match ThemeSet::get_theme(theme) {
+                Ok(custom) => custom,
+                Err(e) => {
+                    log::warn!("Unknown syntax theme {:?} ({}), using {}", theme, e, DEFAULT_THEME);
+                    themes.themes.remove(DEFAULT_THEME).unwrap_or_default()
+                }
+            }
+        };
+        Self { theme }
+    }
+
+    /// Highlights `text` if a syntax is detected for it. Returns `None` for
+    /// plain text, or if `cancelled` gets set.
+    pub fn highlight(&self, path: &Path, text: &str, cancelled: &AtomicBool) -> Option<Vec<StyledLine>> {
+        let syntax = detect_syntax(path, text)?;
+        match self.highlight_syntax(syntax, text, cancelled) {
+            Ok(lines) => lines,
+            Err(e) => {
+                log::warn!("Failed to highlight {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Highlights a code block in `language`, a name like `rust` or an
+    /// extension like `rs`. Returns `None` if the language is unknown.
+    pub fn highlight_code(&self, language: &str, code: &str) -> Option<Vec<StyledLine>> {
+        let syntax = syntax_set().find_syntax_by_token(language)?;
+        self.highlight_syntax(syntax, code, &AtomicBool::new(false)).ok()?
+    }
+
+    fn highlight_syntax(
+        &self,
+        syntax: &SyntaxReference,
+        text: &str,
+        cancelled: &AtomicBool,
+    ) -> Result<Option<Vec<StyledLine>>, syntect::Error> {
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        let mut lines = Vec::new();
+        for (index, line) in LinesWithEndings::from(text).enumerate() {
+            if cancelled.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            let line = if index < MAX_HIGHLIGHTED_LINES {
+                let ranges = highlighter.highlight_line(line, syntax_set())?;
+                ranges.into_iter().map(|(style, text)| styled_span(style, text)).collect()
+            } else {
+                vec![StyledSpan { text: line.trim_end_matches(['\n', '\r']).to_string(), style: SpanStyle::default() }]
+            };
+            lines.push(line);
+        }
+        Ok(Some(lines))
+    }
+}
+
+fn styled_span(style: Style, text: &str) -> StyledSpan {
+    StyledSpan {
+        text: text.trim_end_matches(['\n', '\r']).to_string(),
+        style: SpanStyle {
+            fg: Some((style.foreground.r, style.foreground.g, style.foreground.b)),
+            bold: style.font_style.contains(FontStyle::BOLD),
+            italic: style.font_style.contains(FontStyle::ITALIC),
+            underline: style.font_style.contains(FontStyle::UNDERLINE),
+        },
+    }
+}
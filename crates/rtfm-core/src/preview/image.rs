@@ -0,0 +1,110 @@
+use ::image::imageops::FilterType;
+use ::image::{ImageFormat, ImageResult, RgbaImage};
+use super::registry::{FilePreviewer, PreviewRequest};
+use super::{exif, read_up_to, Preview};
+use config::Resolution;
+use humansize::{format_size, BINARY};
+use io::vfs::VfsFile;
+use std::fmt;
+
+/// Images are decoded in memory, so there's a limit even though they're scaled down.
+const MAX_IMAGE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// A decoded image, scaled down to the configured resolution.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ImagePreview {
+    /// The format and original size, e.g. `PNG image, 1920x1080`. Shown
+    /// where graphics aren't available.
+    pub description: String,
+    /// The original size in pixels.
+    pub width: u32,
+    pub height: u32,
+    pub pixels: RgbaImage,
+}
+
+impl fmt::Debug for ImagePreview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImagePreview")
+            .field("description", &self.description)
+            .field("scaled", &self.pixels.dimensions())
+            .finish()
+    }
+}
+
+impl ImagePreview {
+    /// Decodes `bytes` and scales the result to fit within `resolution`.
+    /// Only the first frame of an animation is kept.
+    pub fn decode(bytes: &[u8], format: ImageFormat, resolution: &Resolution) -> ImageResult<Self> {
+        let image = ::image::load_from_memory_with_format(bytes, format)?;
+        let (width, height) = (image.width(), image.height());
+        let image = if width > resolution.width || height > resolution.height {
+            image.resize(resolution.width.max(1), resolution.height.max(1), FilterType::Triangle)
+        } else {
+            image
+        };
+        let name = format.extensions_str().first().copied().unwrap_or("unknown").to_uppercase();
+        Ok(Self {
+            description: format!("{} image, {}x{}", name, width, height),
+            width,
+            height,
+            pixels: image.into_rgba8(),
+        })
+    }
+}
+
+/// The image format with MIME type `mime`, if it's one we can preview.
+fn format_for(mime: &str) -> Option<ImageFormat> {
+    match mime {
+        "image/png" => Some(ImageFormat::Png),
+        "image/jpeg" => Some(ImageFormat::Jpeg),
+        "image/gif" => Some(ImageFormat::Gif),
+        "image/webp" => Some(ImageFormat::WebP),
+        _ => None,
+    }
+}
+
+/// Decodes images to be drawn with the terminal's graphics protocol. Photos
+/// get their EXIF data shown below them.
+#[derive(Debug)]
+pub struct ImagePreviewer {
+    /// Images are scaled down to fit this before they're cached.
+    resolution: Resolution,
+}
+
+impl ImagePreviewer {
+    pub fn new(resolution: Resolution) -> Self {
+        Self { resolution }
+    }
+}
+
+impl FilePreviewer for ImagePreviewer {
+    fn name(&self) -> &'static str {
+        "image"
+    }
+
+    fn handles(&self, mime: &str) -> bool {
+        format_for(mime).is_some()
+    }
+
+    fn preview(&self, request: &PreviewRequest, file: &mut dyn VfsFile) -> Option<Preview> {
+        let format = format_for(request.mime)?;
+        if request.metadata.len > MAX_IMAGE_SIZE {
+            return Some(Preview::Text(format!(
+                "Image is too large to preview ({})",
+                format_size(request.metadata.len, BINARY)
+            )));
+        }
+        let mut buffer = request.head.to_vec();
+        if let Err(e) = read_up_to(file, &mut buffer, MAX_IMAGE_SIZE, request.cancelled)? {
+            return Some(Preview::Text(format!("Error reading file:\n{}", e)));
+        }
+        Some(match ImagePreview::decode(&buffer, format, &self.resolution) {
+            Ok(_) if request.is_cancelled() => return None,
+            Ok(image) => match exif::describe(&buffer, image.width, image.height) {
+                Some(text) => Preview::Document { text, image: Some(image) },
+                None => Preview::Image(image),
+            },
+            Err(e) => Preview::Text(format!("Error decoding image:\n{}", e)),
+        })
+    }
+}
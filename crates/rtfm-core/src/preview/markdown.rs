@@ -0,0 +1,361 @@
+use super::registry::{FilePreviewer, PreviewRequest};
+use super::structured::header_table;
+use super::text::read_text;
+use super::{Highlighter, Preview, SpanStyle, StyledLine, StyledSpan};
+use ::io::vfs::VfsFile;
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+const HEADING_COLOR: (u8, u8, u8) = (0x61, 0xaf, 0xef);
+const CODE_COLOR: (u8, u8, u8) = (0xd1, 0x9a, 0x66);
+const LINK_COLOR: (u8, u8, u8) = (0x56, 0xb6, 0xc2);
+const DIM_COLOR: (u8, u8, u8) = (0x7f, 0x84, 0x8e);
+const BULLETS: [&str; 3] = ["• ", "◦ ", "▪ "];
+const RULE_WIDTH: usize = 40;
+
+/// Markdown files, rendered with styled headings, emphasis, lists, tables
+/// and highlighted code blocks. The highlighted source comes along for the
+/// other view.
+#[derive(Debug)]
+pub struct MarkdownPreviewer {
+    highlighter: Highlighter,
+}
+
+impl MarkdownPreviewer {
+    pub fn new(highlighter: Highlighter) -> Self {
+        Self { highlighter }
+    }
+}
+
+impl FilePreviewer for MarkdownPreviewer {
+    fn name(&self) -> &'static str {
+        "markdown"
+    }
+
+    fn handles(&self, mime: &str) -> bool {
+        mime == "text/markdown"
+    }
+
+    fn preview(&self, request: &PreviewRequest, file: &mut dyn VfsFile) -> Option<Preview> {
+        let text = match read_text(request, file)? {
+            Ok(text) => text,
+            Err(preview) => return Some(preview),
+        };
+        let source = match self.highlighter.highlight(request.path, &text, request.cancelled) {
+            Some(lines) => lines,
+            None if request.is_cancelled() => return None,
+            None => text.lines().map(|line| vec![span(line, SpanStyle::default())]).collect(),
+        };
+        let rendered = Renderer::new(&self.highlighter).render(&text);
+        Some(Preview::Markdown { text, source, rendered })
+    }
+}
+
+fn span(text: &str, style: SpanStyle) -> StyledSpan {
+    StyledSpan { text: text.to_string(), style }
+}
+
+fn color(fg: (u8, u8, u8)) -> SpanStyle {
+    SpanStyle { fg: Some(fg), ..SpanStyle::default() }
+}
+
+/// Turns Markdown events into styled lines. Soft line breaks are kept, so
+/// paragraphs wrap where the source does.
+struct Renderer<'a> {
+    highlighter: &'a Highlighter,
+    lines: Vec<StyledLine>,
+    /// The line being written, if any.
+    line: Option<StyledLine>,
+    /// Nested inline styles; the last one applies.
+    styles: Vec<SpanStyle>,
+    /// What starts each line in nested block quotes and list items.
+    indent: Vec<StyledSpan>,
+    /// The marker of a list item whose first line hasn't started yet.
+    marker: Option<String>,
+    /// The next number of each nested list; `None` for bullet lists.
+    lists: Vec<Option<u64>>,
+    /// The target and text so far of the links and images being read.
+    links: Vec<(String, String)>,
+    /// The language and code of the code block being read.
+    code: Option<(String, String)>,
+    /// The rows of the table being read.
+    table: Option<Vec<Vec<String>>>,
+}
+
+impl<'a> Renderer<'a> {
+    fn new(highlighter: &'a Highlighter) -> Self {
+        Self {
+            highlighter,
+            lines: Vec::new(),
+            line: None,
+            styles: Vec::new(),
+            indent: Vec::new(),
+            marker: None,
+            lists: Vec::new(),
+            links: Vec::new(),
+            code: None,
+            table: None,
+        }
+    }
+
+    fn render(mut self, text: &str) -> Vec<StyledLine> {
+        let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+        for event in Parser::new_ext(text, options) {
+            self.event(event);
+        }
+        self.finish_line();
+        while self.lines.last().is_some_and(Vec::is_empty) {
+            self.lines.pop();
+        }
+        self.lines
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.write(&text, self.style()),
+            Event::Code(code) | Event::InlineMath(code) | Event::DisplayMath(code) => {
+                self.write(&code, SpanStyle { fg: Some(CODE_COLOR), ..self.style() })
+            }
+            Event::Html(html) | Event::InlineHtml(html) => {
+                let mut lines = html.lines().peekable();
+                while let Some(line) = lines.next() {
+                    self.write(line, color(DIM_COLOR));
+                    if lines.peek().is_some() || html.ends_with('\n') {
+                        self.finish_line();
+                    }
+                }
+            }
+            Event::FootnoteReference(name) => self.write(&format!("[^{}]", name), color(DIM_COLOR)),
+            Event::SoftBreak | Event::HardBreak => self.finish_line(),
+            Event::Rule => {
+                self.blank_line();
+                self.push_line(vec![span(&"─".repeat(RULE_WIDTH), color(DIM_COLOR))]);
+                self.blank_line();
+            }
+            Event::TaskListMarker(checked) => self.write(if checked { "[x] " } else { "[ ] " }, self.style()),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {}
+            Tag::Heading { level, .. } => {
+                self.blank_line();
+                let style = SpanStyle { fg: Some(HEADING_COLOR), bold: true, ..SpanStyle::default() };
+                self.styles.push(match level {
+                    HeadingLevel::H1 => SpanStyle { underline: true, ..style },
+                    HeadingLevel::H2 => style,
+                    _ => SpanStyle { fg: None, ..style },
+                });
+            }
+            Tag::BlockQuote(_) => {
+                self.blank_line();
+                self.indent.push(span("│ ", color(DIM_COLOR)));
+            }
+            Tag::CodeBlock(kind) => {
+                self.blank_line();
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info.split([' ', ',']).next().unwrap_or_default().to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.code = Some((language, String::new()));
+            }
+            Tag::List(start) => {
+                // A nested list starts below its item's first line.
+                if self.lists.is_empty() {
+                    self.blank_line();
+                }
+                self.finish_line();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.finish_line();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => BULLETS[(self.lists.len().max(1) - 1) % BULLETS.len()].to_string(),
+                };
+                self.indent.push(span(&" ".repeat(marker.chars().count()), SpanStyle::default()));
+                self.marker = Some(marker);
+            }
+            Tag::FootnoteDefinition(name) => {
+                self.blank_line();
+                self.write(&format!("[^{}]: ", name), color(DIM_COLOR));
+            }
+            Tag::Table(_) => {
+                self.blank_line();
+                self.table = Some(Vec::new());
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    table.push(Vec::new());
+                }
+            }
+            Tag::TableCell => {
+                if let Some(row) = self.table.as_mut().and_then(|table| table.last_mut()) {
+                    row.push(String::new());
+                }
+            }
+            Tag::Emphasis => self.push_style(SpanStyle { italic: true, ..SpanStyle::default() }),
+            Tag::Strong => self.push_style(SpanStyle { bold: true, ..SpanStyle::default() }),
+            // There's no strikethrough in span styles; the text is dimmed.
+            Tag::Strikethrough => self.push_style(color(DIM_COLOR)),
+            Tag::Link { dest_url, .. } => {
+                self.push_style(SpanStyle { fg: Some(LINK_COLOR), underline: true, ..SpanStyle::default() });
+                self.links.push((dest_url.to_string(), String::new()));
+            }
+            Tag::Image { dest_url, .. } => {
+                self.push_style(color(DIM_COLOR));
+                self.write("[image: ", self.style());
+                self.links.push((dest_url.to_string(), String::new()));
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::FootnoteDefinition => {
+                self.finish_line();
+                self.blank_line();
+            }
+            TagEnd::Heading(_) => {
+                self.styles.pop();
+                self.finish_line();
+                self.blank_line();
+            }
+            TagEnd::BlockQuote(_) => {
+                self.finish_line();
+                self.indent.pop();
+                self.blank_line();
+            }
+            TagEnd::CodeBlock => {
+                let Some((language, code)) = self.code.take() else {
+                    return;
+                };
+                let lines = match language.is_empty() {
+                    true => None,
+                    false => self.highlighter.highlight_code(&language, &code),
+                };
+                let lines = lines.unwrap_or_else(|| code.lines().map(|line| vec![span(line, color(CODE_COLOR))]).collect());
+                for line in lines {
+                    let mut spans = vec![span("  ", SpanStyle::default())];
+                    spans.extend(line);
+                    self.push_line(spans);
+                }
+                self.blank_line();
+            }
+            TagEnd::List(_) => {
+                self.finish_line();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.blank_line();
+                }
+            }
+            TagEnd::Item => {
+                self.finish_line();
+                self.indent.pop();
+                self.marker = None;
+            }
+            TagEnd::Table => {
+                let rows = self.table.take().unwrap_or_default();
+                for (i, line) in header_table(&rows).iter().enumerate() {
+                    let style = SpanStyle { bold: i == 0, ..SpanStyle::default() };
+                    self.push_line(vec![span(line, style)]);
+                }
+                self.blank_line();
+            }
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough => {
+                self.styles.pop();
+            }
+            TagEnd::Link => {
+                self.styles.pop();
+                // Autolinks already show their target.
+                if let Some((target, _)) = self.links.pop().filter(|(target, text)| target != text && !target.is_empty()) {
+                    self.write(&format!(" ({})", target), color(DIM_COLOR));
+                }
+            }
+            TagEnd::Image => {
+                self.write("]", self.style());
+                self.styles.pop();
+                if let Some((target, _)) = self.links.pop().filter(|(target, _)| !target.is_empty()) {
+                    self.write(&format!(" ({})", target), color(DIM_COLOR));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn style(&self) -> SpanStyle {
+        self.styles.last().copied().unwrap_or_default()
+    }
+
+    /// Adds `style` to the current one.
+    fn push_style(&mut self, style: SpanStyle) {
+        let current = self.style();
+        self.styles.push(SpanStyle {
+            fg: style.fg.or(current.fg),
+            bold: style.bold || current.bold,
+            italic: style.italic || current.italic,
+            underline: style.underline || current.underline,
+        });
+    }
+
+    /// Adds text to the current line, or to the code block or table cell
+    /// being read.
+    fn write(&mut self, text: &str, style: SpanStyle) {
+        if let Some((_, code)) = &mut self.code {
+            code.push_str(text);
+            return;
+        }
+        if let Some(cell) = self.table.as_mut().and_then(|table| table.last_mut()).and_then(|row| row.last_mut()) {
+            cell.push_str(text);
+            return;
+        }
+        if text.is_empty() {
+            return;
+        }
+        if let Some((_, link_text)) = self.links.last_mut() {
+            link_text.push_str(text);
+        }
+        let line = self.line.get_or_insert_with(Vec::new);
+        if line.is_empty() {
+            // The innermost item's marker goes on its first line in place
+            // of the indent.
+            let marker = self.marker.take();
+            let depth = self.indent.len();
+            for (i, indent) in self.indent.iter().enumerate() {
+                match marker.as_deref().filter(|_| i + 1 == depth) {
+                    Some(marker) => line.push(span(marker, color(DIM_COLOR))),
+                    None => line.push(indent.clone()),
+                }
+            }
+        }
+        line.push(span(text, style));
+    }
+
+    fn finish_line(&mut self) {
+        if let Some(line) = self.line.take() {
+            self.lines.push(line);
+        }
+    }
+
+    /// A whole line, indented like the text around it.
+    fn push_line(&mut self, spans: StyledLine) {
+        self.finish_line();
+        let mut line = self.indent.clone();
+        line.extend(spans);
+        self.lines.push(line);
+    }
+
+    /// Separates blocks with one empty line.
+    fn blank_line(&mut self) {
+        self.finish_line();
+        if self.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.lines.push(Vec::new());
+        }
+    }
+}
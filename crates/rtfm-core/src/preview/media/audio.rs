@@ -0,0 +1,425 @@
+use super::{
+    be_u32, channels, invalid, le_u16, le_u32, le_u64, read_at, sample_rate, stream, MediaInfo, MAX_HEADER_SIZE,
+};
+use ::io::vfs::VfsFile;
+use id3::TagLike;
+use std::io::{self, SeekFrom};
+use std::time::Duration;
+
+/// How far into the audio the first MPEG frame is looked for.
+const FRAME_SEARCH_LEN: u64 = 64 * 1024;
+/// The last Ogg page, which has the total length, is looked for this far
+/// from the end.
+const OGG_TAIL_LEN: u64 = 64 * 1024;
+
+/// Where the audio starts after an ID3v2 tag at `offset`, which MP3 files
+/// and some FLAC files begin with.
+fn skip_id3v2(file: &mut dyn VfsFile, offset: u64) -> io::Result<u64> {
+    let header = read_at(file, offset, 10)?;
+    if header.len() < 10 || !header.starts_with(b"ID3") {
+        return Ok(offset);
+    }
+    // Sizes are "syncsafe": 7 bits per byte.
+    let size = header[6..10].iter().fold(0, |size, byte| size << 7 | u64::from(byte & 0x7f));
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    Ok(offset + 10 + size + footer)
+}
+
+/// An MPEG audio frame header.
+#[derive(Debug)]
+struct MpegFrame {
+    /// 1 for MPEG-1, 2 for MPEG-2 and 2.5.
+    version: u8,
+    layer: u8,
+    bitrate: u32,
+    sample_rate: u32,
+    mono: bool,
+    padding: bool,
+}
+
+impl MpegFrame {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        const BITRATES: [[u32; 15]; 5] = [
+            [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+            [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+            [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+            [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+            [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+        ];
+        let header = bytes.get(..4)?;
+        if header[0] != 0xff || header[1] & 0xe0 != 0xe0 {
+            return None;
+        }
+        let (version, rate_divisor) = match (header[1] >> 3) & 3 {
+            0 => (2, 4),
+            2 => (2, 2),
+            3 => (1, 1),
+            _ => return None,
+        };
+        let layer = match (header[1] >> 1) & 3 {
+            0 => return None,
+            bits => 4 - bits,
+        };
+        let table = match (version, layer) {
+            (1, layer) => layer as usize - 1,
+            (_, 1) => 3,
+            _ => 4,
+        };
+        let bitrate = *BITRATES[table].get(usize::from(header[2] >> 4)).filter(|&&bitrate| bitrate > 0)?;
+        let sample_rate = [44100, 48000, 32000].get(usize::from((header[2] >> 2) & 3))? / rate_divisor;
+        Some(Self {
+            version,
+            layer,
+            bitrate: bitrate * 1000,
+            sample_rate,
+            mono: header[3] >> 6 == 3,
+            padding: header[2] & 2 != 0,
+        })
+    }
+
+    fn samples(&self) -> u32 {
+        match (self.layer, self.version) {
+            (1, _) => 384,
+            (3, 2) => 576,
+            _ => 1152,
+        }
+    }
+
+    fn len(&self) -> usize {
+        let slot = if self.layer == 1 { 4 } else { 1 };
+        let slots = self.samples() / 8 * self.bitrate / self.sample_rate / slot;
+        (slots + u32::from(self.padding)) as usize * slot as usize
+    }
+
+    /// The frame count from a Xing/Info or VBRI header, which encoders put
+    /// in the first frame of variable bitrate files.
+    fn vbr_frames(&self, frame: &[u8]) -> Option<u32> {
+        let side_info = match (self.version, self.mono) {
+            (1, false) => 32,
+            (1, true) | (2, false) => 17,
+            _ => 9,
+        };
+        let xing = frame.get(4 + side_info..)?;
+        if xing.starts_with(b"Xing") || xing.starts_with(b"Info") {
+            return be_u32(xing, 4).filter(|flags| flags & 1 != 0).and_then(|_| be_u32(xing, 8));
+        }
+        let vbri = frame.get(36..)?;
+        vbri.starts_with(b"VBRI").then(|| be_u32(vbri, 14)).flatten()
+    }
+}
+
+/// The first of two consecutive frames in `bytes`; one header alone is too
+/// easily found in random data.
+fn find_frame(bytes: &[u8]) -> Option<(usize, MpegFrame)> {
+    (0..bytes.len()).find_map(|at| {
+        let frame = MpegFrame::parse(&bytes[at..])?;
+        let next = at + frame.len();
+        (next + 4 > bytes.len() || MpegFrame::parse(&bytes[next..]).is_some()).then_some((at, frame))
+    })
+}
+
+pub(super) fn mp3(file: &mut dyn VfsFile, len: u64) -> io::Result<MediaInfo> {
+    let mut info = MediaInfo::default();
+    file.seek(SeekFrom::Start(0))?;
+    // A broken tag shouldn't hide the rest.
+    let tag = id3::Tag::read_from2(&mut *file).ok();
+    if let Some(tag) = &tag {
+        let track = tag.track().map(|track| match tag.total_tracks() {
+            Some(total) => format!("{}/{}", track, total),
+            None => track.to_string(),
+        });
+        let date = tag.date_recorded().map(|date| date.to_string()).or_else(|| tag.year().map(|year| year.to_string()));
+        for (name, value) in [
+            ("TITLE", tag.title().map(str::to_string)),
+            ("ARTIST", tag.artist().map(str::to_string)),
+            ("ALBUM", tag.album().map(str::to_string)),
+            ("DATE", date),
+            ("TRACKNUMBER", track),
+            ("GENRE", tag.genre_parsed().map(|genre| genre.into_owned())),
+        ] {
+            info.set_tag(name, value.as_deref().unwrap_or_default());
+        }
+    }
+
+    let start = skip_id3v2(file, 0)?;
+    let mut end = len;
+    if len >= 128 {
+        let id3v1 = read_at(file, len - 128, 128)?;
+        if id3v1.starts_with(b"TAG") {
+            end -= 128;
+            for (name, range) in [("TITLE", 3..33), ("ARTIST", 33..63), ("ALBUM", 63..93), ("DATE", 93..97)] {
+                info.set_tag(name, &String::from_utf8_lossy(&id3v1[range]));
+            }
+        }
+    }
+
+    let bytes = read_at(file, start, FRAME_SEARCH_LEN)?;
+    let Some((at, frame)) = find_frame(&bytes) else {
+        // The length tag is only a fallback; it's often missing or wrong.
+        info.duration = tag.and_then(|tag| tag.duration()).map(|ms| Duration::from_millis(ms.into()));
+        return Ok(info);
+    };
+    let name = ["MP1", "MP2", "MP3"][usize::from(frame.layer - 1)];
+    info.streams.push((
+        "Audio",
+        stream([Some(name.to_string()), sample_rate(frame.sample_rate), channels(if frame.mono { 1 } else { 2 })]),
+    ));
+    info.duration = match frame.vbr_frames(&bytes[at..]) {
+        Some(frames) => {
+            Duration::try_from_secs_f64(f64::from(frames) * f64::from(frame.samples()) / f64::from(frame.sample_rate)).ok()
+        }
+        None => {
+            // Constant bitrate: the length follows from the size.
+            info.bitrate = Some(u64::from(frame.bitrate));
+            let audio = end.saturating_sub(start + at as u64);
+            Duration::try_from_secs_f64(audio as f64 * 8.0 / f64::from(frame.bitrate)).ok()
+        }
+    };
+    Ok(info)
+}
+
+/// Reads a Vorbis comment block, as used by FLAC, Vorbis and Opus. A block
+/// cut short still gives the tags before the cut.
+fn vorbis_comments(bytes: &[u8], info: &mut MediaInfo) {
+    let Some(vendor) = le_u32(bytes, 0) else {
+        return;
+    };
+    let mut at = 4 + vendor as usize;
+    let Some(count) = le_u32(bytes, at) else {
+        return;
+    };
+    at += 4;
+    for _ in 0..count {
+        let Some(len) = le_u32(bytes, at) else {
+            return;
+        };
+        let Some(comment) = bytes.get(at + 4..at + 4 + len as usize) else {
+            return;
+        };
+        if let Some((name, value)) = String::from_utf8_lossy(comment).split_once('=') {
+            info.set_tag(name, value);
+        }
+        at += 4 + len as usize;
+    }
+}
+
+pub(super) fn flac(file: &mut dyn VfsFile, len: u64) -> io::Result<MediaInfo> {
+    let mut info = MediaInfo::default();
+    let mut offset = skip_id3v2(file, 0)?;
+    if read_at(file, offset, 4)? != b"fLaC" {
+        return Err(invalid("Not a FLAC file"));
+    }
+    offset += 4;
+    // Metadata blocks: a one-byte type with a "last block" flag, then a
+    // 24-bit length.
+    while offset + 4 <= len {
+        let header = read_at(file, offset, 4)?;
+        let size = u64::from(be_u32(&header, 0).unwrap_or_default() & 0xff_ffff);
+        match header[0] & 0x7f {
+            0 => {
+                let block = read_at(file, offset + 4, 34)?;
+                if block.len() < 18 {
+                    return Err(invalid("Truncated FLAC stream info"));
+                }
+                let rate = u32::from(block[10]) << 12 | u32::from(block[11]) << 4 | u32::from(block[12]) >> 4;
+                let channel_count = u32::from((block[12] >> 1) & 7) + 1;
+                let bits = u32::from((block[12] & 1) << 4 | block[13] >> 4) + 1;
+                let samples = u64::from(block[13] & 0xf) << 32 | u64::from(be_u32(&block, 14).unwrap_or_default());
+                if rate > 0 && samples > 0 {
+                    info.duration = Duration::try_from_secs_f64(samples as f64 / f64::from(rate)).ok();
+                }
+                info.streams.push((
+                    "Audio",
+                    stream([
+                        Some("FLAC".to_string()),
+                        sample_rate(rate),
+                        Some(format!("{}-bit", bits)),
+                        channels(channel_count),
+                    ]),
+                ));
+            }
+            4 => vorbis_comments(&read_at(file, offset + 4, size.min(MAX_HEADER_SIZE))?, &mut info),
+            _ => {}
+        }
+        offset += 4 + size;
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+    Ok(info)
+}
+
+/// The first `count` packets of the first logical stream in an Ogg file,
+/// with its serial number. Packets can span pages.
+fn ogg_packets(file: &mut dyn VfsFile, count: usize) -> io::Result<(u32, Vec<Vec<u8>>)> {
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+    let mut serial = None;
+    let mut offset = 0;
+    while packets.len() < count {
+        let header = read_at(file, offset, 27)?;
+        if header.len() < 27 {
+            break;
+        }
+        if !header.starts_with(b"OggS") {
+            return Err(invalid("Not an Ogg page"));
+        }
+        let page_serial = le_u32(&header, 14).unwrap_or_default();
+        let segments = read_at(file, offset + 27, u64::from(header[26]))?;
+        let body_len: u64 = segments.iter().map(|&len| u64::from(len)).sum();
+        let body_offset = offset + 27 + segments.len() as u64;
+        offset = body_offset + body_len;
+        if *serial.get_or_insert(page_serial) != page_serial {
+            continue;
+        }
+        let body = read_at(file, body_offset, body_len)?;
+        let mut at = 0;
+        for &len in &segments {
+            packet.extend_from_slice(body.get(at..at + usize::from(len)).unwrap_or_default());
+            at += usize::from(len);
+            // A segment shorter than 255 bytes ends the packet.
+            if len < 255 {
+                packets.push(std::mem::take(&mut packet));
+                if packets.len() == count {
+                    break;
+                }
+            }
+        }
+        if packet.len() as u64 > MAX_HEADER_SIZE {
+            packets.push(packet);
+            break;
+        }
+    }
+    Ok((serial.unwrap_or_default(), packets))
+}
+
+pub(super) fn ogg(file: &mut dyn VfsFile, len: u64) -> io::Result<MediaInfo> {
+    let mut info = MediaInfo::default();
+    let (serial, packets) = ogg_packets(file, 2)?;
+    let Some(identification) = packets.first() else {
+        return Ok(info);
+    };
+    // Granule positions count samples, at 48 kHz for Opus.
+    let (granule_rate, pre_skip, comments) = if identification.starts_with(b"\x01vorbis") {
+        let rate = le_u32(identification, 12).unwrap_or_default();
+        let channel_count = identification.get(11).copied().unwrap_or_default();
+        info.streams.push((
+            "Audio",
+            stream([Some("Vorbis".to_string()), sample_rate(rate), channels(channel_count.into())]),
+        ));
+        (rate, 0, packets.get(1).and_then(|packet| packet.strip_prefix(b"\x03vorbis")))
+    } else if identification.starts_with(b"OpusHead") {
+        let rate = le_u32(identification, 12).unwrap_or_default();
+        let channel_count = identification.get(9).copied().unwrap_or_default();
+        info.streams.push((
+            "Audio",
+            stream([Some("Opus".to_string()), sample_rate(rate), channels(channel_count.into())]),
+        ));
+        let pre_skip = le_u16(identification, 10).unwrap_or_default();
+        (48000, pre_skip.into(), packets.get(1).and_then(|packet| packet.strip_prefix(b"OpusTags")))
+    } else {
+        return Ok(info);
+    };
+    if let Some(comments) = comments {
+        vorbis_comments(comments, &mut info);
+    }
+
+    // The last page of the stream has the total sample count.
+    let tail_start = len.saturating_sub(OGG_TAIL_LEN);
+    let tail = read_at(file, tail_start, OGG_TAIL_LEN)?;
+    let granule = tail
+        .windows(4)
+        .enumerate()
+        .rev()
+        .filter(|(_, magic)| *magic == b"OggS")
+        .filter(|&(at, _)| le_u32(&tail, at + 14) == Some(serial))
+        .find_map(|(at, _)| le_u64(&tail, at + 6).filter(|&granule| granule != u64::MAX));
+    if let Some(granule) = granule.filter(|_| granule_rate > 0) {
+        let samples = granule.saturating_sub(pre_skip);
+        info.duration = Duration::try_from_secs_f64(samples as f64 / f64::from(granule_rate)).ok();
+    }
+    Ok(info)
+}
+
+pub(super) fn wav(file: &mut dyn VfsFile, len: u64) -> io::Result<MediaInfo> {
+    let mut info = MediaInfo::default();
+    let header = read_at(file, 0, 12)?;
+    if !header.starts_with(b"RIFF") || header.get(8..12) != Some(b"WAVE") {
+        return Err(invalid("Not a WAVE file"));
+    }
+    let mut byte_rate = 0;
+    let mut data_len = None;
+    let mut offset = 12;
+    while offset + 8 <= len {
+        let chunk = read_at(file, offset, 8)?;
+        let size = u64::from(le_u32(&chunk, 4).unwrap_or_default());
+        match &chunk[..4] {
+            b"fmt " => {
+                let format = read_at(file, offset + 8, size.min(40))?;
+                let mut tag = le_u16(&format, 0).unwrap_or_default();
+                if tag == 0xfffe {
+                    // WAVE_FORMAT_EXTENSIBLE keeps the real format in its sub-format GUID.
+                    tag = le_u16(&format, 24).unwrap_or_default();
+                }
+                let name = match tag {
+                    1 => "PCM".to_string(),
+                    2 => "ADPCM".to_string(),
+                    3 => "IEEE float".to_string(),
+                    6 => "A-law".to_string(),
+                    7 => "µ-law".to_string(),
+                    0x55 => "MP3".to_string(),
+                    tag => format!("format {:#06x}", tag),
+                };
+                byte_rate = le_u32(&format, 8).unwrap_or_default();
+                let bits = le_u16(&format, 14).filter(|&bits| bits > 0).map(|bits| format!("{}-bit", bits));
+                info.streams.push((
+                    "Audio",
+                    stream([
+                        Some(name),
+                        sample_rate(le_u32(&format, 4).unwrap_or_default()),
+                        bits,
+                        channels(le_u16(&format, 2).unwrap_or_default().into()),
+                    ]),
+                ));
+            }
+            // Streamed files may not know the data size and leave it at the maximum.
+            b"data" => data_len = Some(size.min(len - offset - 8)),
+            b"LIST" => {
+                let list = read_at(file, offset + 8, size.min(MAX_HEADER_SIZE))?;
+                if list.starts_with(b"INFO") {
+                    info_chunks(&list[4..], &mut info);
+                }
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even size.
+        offset += 8 + size + (size & 1);
+    }
+    if let (Some(data_len), true) = (data_len, byte_rate > 0) {
+        info.duration = Duration::try_from_secs_f64(data_len as f64 / f64::from(byte_rate)).ok();
+        info.bitrate = Some(u64::from(byte_rate) * 8);
+    }
+    Ok(info)
+}
+
+/// The tags in the `INFO` list of a WAVE file.
+fn info_chunks(bytes: &[u8], info: &mut MediaInfo) {
+    let mut at = 0;
+    while let Some(size) = le_u32(bytes, at + 4) {
+        let size = size as usize;
+        let Some(value) = bytes.get(at + 8..at + 8 + size) else {
+            return;
+        };
+        let name = match &bytes[at..at + 4] {
+            b"INAM" => "TITLE",
+            b"IART" => "ARTIST",
+            b"IPRD" => "ALBUM",
+            b"ICRD" => "DATE",
+            b"ITRK" => "TRACKNUMBER",
+            b"IGNR" => "GENRE",
+            _ => "",
+        };
+        info.set_tag(name, &String::from_utf8_lossy(value));
+        at += 8 + size + (size & 1);
+    }
+}
@@ -0,0 +1,200 @@
+use super::{channels, invalid, read_at, resolution, sample_rate, stream, MediaInfo, MAX_HEADER_SIZE};
+use ::io::vfs::VfsFile;
+use std::io;
+use std::time::Duration;
+
+const EBML: u32 = 0x1a45_dfa3;
+const SEGMENT: u32 = 0x1853_8067;
+const INFO: u32 = 0x1549_a966;
+const TRACKS: u32 = 0x1654_ae6b;
+const CLUSTER: u32 = 0x1f43_b675;
+const TIMESTAMP_SCALE: u32 = 0x2a_d7b1;
+const DURATION: u32 = 0x4489;
+const TITLE: u32 = 0x7ba9;
+const TRACK_ENTRY: u32 = 0xae;
+const TRACK_TYPE: u32 = 0x83;
+const CODEC_ID: u32 = 0x86;
+const LANGUAGE: u32 = 0x22_b59c;
+const VIDEO: u32 = 0xe0;
+const PIXEL_WIDTH: u32 = 0xb0;
+const PIXEL_HEIGHT: u32 = 0xba;
+const AUDIO: u32 = 0xe1;
+const SAMPLING_FREQUENCY: u32 = 0xb5;
+const CHANNELS: u32 = 0x9f;
+
+/// A variable-length integer: the number of leading zero bits in the first
+/// byte gives the length. Element IDs keep the length marker, sizes don't.
+/// Returns the value and its length; `None` for an unknown size (all ones).
+fn vint(bytes: &[u8], keep_marker: bool) -> Option<(Option<u64>, usize)> {
+    let first = *bytes.first()?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 || bytes.len() < len {
+        return None;
+    }
+    let marker = if keep_marker { 0 } else { 0x80u8 >> (len - 1) };
+    let value = bytes[1..len].iter().fold(u64::from(first & !marker), |value, &byte| value << 8 | u64::from(byte));
+    let unknown = !keep_marker && value == (1 << (7 * len)) - 1;
+    Some(((!unknown).then_some(value), len))
+}
+
+/// An element's ID, size (`None` if unknown) and header length.
+fn element_header(bytes: &[u8]) -> Option<(u32, Option<u64>, usize)> {
+    let (id, id_len) = vint(bytes, true)?;
+    let (size, size_len) = vint(&bytes[id_len..], false)?;
+    Some((id? as u32, size, id_len + size_len))
+}
+
+/// The child elements in `bytes`, up to the first one that's cut off.
+fn children(bytes: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    let mut at = 0;
+    std::iter::from_fn(move || {
+        let (id, size, header_len) = element_header(bytes.get(at..)?)?;
+        let start = at + header_len;
+        let data = bytes.get(start..start.checked_add(usize::try_from(size?).ok()?)?)?;
+        at = start + data.len();
+        Some((id, data))
+    })
+}
+
+fn uint(data: &[u8]) -> u64 {
+    data.iter().take(8).fold(0, |value, &byte| value << 8 | u64::from(byte))
+}
+
+fn float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f32::from_be_bytes(data.try_into().ok()?).into()),
+        8 => Some(f64::from_be_bytes(data.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+fn string(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).trim_end_matches('\0').to_string()
+}
+
+/// Matroska and WebM files: the segment info for the title and duration, and
+/// the track list for the streams. Both come before the first cluster of
+/// media data, which is where reading stops.
+pub(super) fn read(file: &mut dyn VfsFile, len: u64) -> io::Result<MediaInfo> {
+    let mut info = MediaInfo::default();
+    let header = read_at(file, 0, 16)?;
+    let Some((EBML, Some(size), header_len)) = element_header(&header) else {
+        return Err(invalid("Not a Matroska file"));
+    };
+    let segment_offset = header_len as u64 + size;
+    let header = read_at(file, segment_offset, 16)?;
+    let Some((SEGMENT, size, header_len)) = element_header(&header) else {
+        return Err(invalid("Missing Matroska segment"));
+    };
+    let mut offset = segment_offset + header_len as u64;
+    let end = size.map_or(len, |size| (offset + size).min(len));
+
+    let (mut has_info, mut has_tracks) = (false, false);
+    while offset < end && !(has_info && has_tracks) {
+        let header = read_at(file, offset, 16)?;
+        let Some((id, Some(size), header_len)) = element_header(&header) else {
+            break;
+        };
+        let data_offset = offset + header_len as u64;
+        match id {
+            INFO => {
+                segment_info(&read_at(file, data_offset, size.min(MAX_HEADER_SIZE))?, &mut info);
+                has_info = true;
+            }
+            TRACKS => {
+                tracks(&read_at(file, data_offset, size.min(MAX_HEADER_SIZE))?, &mut info);
+                has_tracks = true;
+            }
+            CLUSTER => break,
+            _ => {}
+        }
+        offset = data_offset + size;
+    }
+    Ok(info)
+}
+
+fn segment_info(bytes: &[u8], info: &mut MediaInfo) {
+    // Timestamps are in nanoseconds times the scale divided by a million,
+    // so by default in milliseconds.
+    let mut scale = 1_000_000;
+    let mut duration = None;
+    for (id, data) in children(bytes) {
+        match id {
+            TIMESTAMP_SCALE => scale = uint(data),
+            DURATION => duration = float(data),
+            TITLE => info.set_tag("TITLE", &string(data)),
+            _ => {}
+        }
+    }
+    if let Some(duration) = duration.filter(|duration| duration.is_finite() && *duration > 0.0) {
+        info.duration = Duration::try_from_secs_f64(duration * scale as f64 / 1e9).ok();
+    }
+}
+
+fn tracks(bytes: &[u8], info: &mut MediaInfo) {
+    for (_, entry) in children(bytes).filter(|&(id, _)| id == TRACK_ENTRY) {
+        let (mut kind, mut codec, mut language) = (0, None, None);
+        let (mut width, mut height, mut rate, mut channel_count) = (0, 0, 0.0, 0);
+        for (id, data) in children(entry) {
+            match id {
+                TRACK_TYPE => kind = uint(data),
+                CODEC_ID => codec = Some(codec_name(&string(data))),
+                LANGUAGE => language = Some(string(data)).filter(|language| language != "und"),
+                VIDEO => {
+                    for (id, data) in children(data) {
+                        match id {
+                            PIXEL_WIDTH => width = uint(data) as u32,
+                            PIXEL_HEIGHT => height = uint(data) as u32,
+                            _ => {}
+                        }
+                    }
+                }
+                AUDIO => {
+                    for (id, data) in children(data) {
+                        match id {
+                            SAMPLING_FREQUENCY => rate = float(data).unwrap_or_default(),
+                            CHANNELS => channel_count = uint(data) as u32,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        match kind {
+            1 => info.streams.push(("Video", stream([codec, resolution(width, height), language]))),
+            2 => info.streams.push((
+                "Audio",
+                stream([codec, sample_rate(rate as u32), channels(channel_count), language]),
+            )),
+            17 => info.streams.push(("Subtitle", stream([codec, language]))),
+            _ => {}
+        }
+    }
+}
+
+fn codec_name(codec: &str) -> String {
+    let name = match codec {
+        "V_MPEG4/ISO/AVC" => "H.264",
+        "V_MPEGH/ISO/HEVC" => "H.265",
+        "V_AV1" => "AV1",
+        "V_VP8" => "VP8",
+        "V_VP9" => "VP9",
+        "V_THEORA" => "Theora",
+        "A_OPUS" => "Opus",
+        "A_VORBIS" => "Vorbis",
+        "A_FLAC" => "FLAC",
+        "A_AC3" => "AC-3",
+        "A_EAC3" => "E-AC-3",
+        "A_DTS" => "DTS",
+        "A_MPEG/L3" => "MP3",
+        "S_TEXT/UTF8" => "SubRip",
+        "S_TEXT/ASS" | "S_TEXT/SSA" => "ASS",
+        "S_TEXT/WEBVTT" => "WebVTT",
+        "S_HDMV/PGS" => "PGS",
+        codec if codec.starts_with("A_AAC") => "AAC",
+        codec if codec.starts_with("A_PCM") => "PCM",
+        codec => codec,
+    };
+    name.to_string()
+}
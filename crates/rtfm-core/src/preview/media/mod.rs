@@ -0,0 +1,196 @@
+use super::office::header;
+use super::registry::{FilePreviewer, PreviewRequest};
+use super::{HexDump, Preview};
+use ::io::vfs::VfsFile;
+use std::io::{self, Read, SeekFrom};
+use std::time::Duration;
+
+mod audio;
+mod mkv;
+mod mp4;
+
+/// Tag blocks and header elements are read in memory up to this size; cover
+/// art can make them much larger than the text they hold.
+const MAX_HEADER_SIZE: u64 = 1024 * 1024;
+
+/// Tags, duration, bitrate and streams of audio and video files, read from
+/// their headers in pure Rust: MP3 (ID3), FLAC, Ogg Vorbis and Opus, WAV,
+/// MP4/QuickTime and Matroska/WebM.
+#[derive(Debug, Default)]
+pub struct MediaPreviewer;
+
+impl FilePreviewer for MediaPreviewer {
+    fn name(&self) -> &'static str {
+        "media"
+    }
+
+    fn handles(&self, mime: &str) -> bool {
+        matches!(
+            mime,
+            "audio/mpeg"
+                | "audio/x-flac"
+                | "audio/ogg"
+                | "audio/opus"
+                | "audio/x-wav"
+                | "audio/m4a"
+                | "video/mp4"
+                | "video/x-m4v"
+                | "video/quicktime"
+                | "video/x-matroska"
+                | "video/webm"
+        )
+    }
+
+    fn preview(&self, request: &PreviewRequest, file: &mut dyn VfsFile) -> Option<Preview> {
+        let len = request.metadata.len;
+        let info = match request.mime {
+            "audio/mpeg" => audio::mp3(file, len),
+            "audio/x-flac" => audio::flac(file, len),
+            "audio/ogg" | "audio/opus" => audio::ogg(file, len),
+            "audio/x-wav" => audio::wav(file, len),
+            "video/x-matroska" | "video/webm" => mkv::read(file, len),
+            _ => mp4::read(file, len),
+        };
+        if request.is_cancelled() {
+            return None;
+        }
+        Some(match info {
+            Ok(info) if !info.is_empty() => Preview::Document { text: info.describe(len), image: None },
+            // Nothing we understand, e.g. an MP3 without a single valid frame.
+            Ok(_) => match HexDump::read(request.vfs, request.path, len, 0) {
+                Ok(dump) => Preview::Hex(dump),
+                Err(e) => Preview::Text(format!("Error reading file:\n{}", e)),
+            },
+            Err(e) => Preview::Text(format!("Error reading media file:\n{}", e)),
+        })
+    }
+}
+
+/// What the headers of an audio or video file say. Files are often only
+/// partly tagged, so everything is optional.
+#[derive(Debug, Default)]
+struct MediaInfo {
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    date: Option<String>,
+    track: Option<String>,
+    genre: Option<String>,
+    duration: Option<Duration>,
+    /// Bits per second, if the format states it. Otherwise the average is
+    /// worked out from the file size.
+    bitrate: Option<u64>,
+    /// One line per stream, e.g. `("Video", "H.264, 1920x1080")`.
+    streams: Vec<(&'static str, String)>,
+}
+
+impl MediaInfo {
+    /// Sets a tag from its Vorbis comment name, e.g. `ARTIST`. The other
+    /// formats map their own names onto these. The first value wins.
+    fn set_tag(&mut self, name: &str, value: &str) {
+        let tag = match name.to_ascii_uppercase().as_str() {
+            "TITLE" => &mut self.title,
+            "ARTIST" => &mut self.artist,
+            "ALBUM" => &mut self.album,
+            "DATE" | "YEAR" => &mut self.date,
+            "TRACKNUMBER" => &mut self.track,
+            "GENRE" => &mut self.genre,
+            _ => return,
+        };
+        let value = value.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+        if tag.is_none() && !value.is_empty() {
+            *tag = Some(value.to_string());
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        let tags = [&self.title, &self.artist, &self.album, &self.date, &self.track, &self.genre];
+        tags.iter().all(|tag| tag.is_none()) && self.duration.is_none() && self.streams.is_empty()
+    }
+
+    fn describe(&self, len: u64) -> String {
+        let seconds = self.duration.map(|duration| duration.as_secs_f64()).filter(|&seconds| seconds > 0.0);
+        let bitrate = self.bitrate.or_else(|| seconds.map(|seconds| (len as f64 * 8.0 / seconds) as u64));
+        let mut fields = vec![
+            ("Title", self.title.clone()),
+            ("Artist", self.artist.clone()),
+            ("Album", self.album.clone()),
+            ("Date", self.date.clone()),
+            ("Track", self.track.clone()),
+            ("Genre", self.genre.clone()),
+            ("Duration", self.duration.map(format_duration)),
+            ("Bitrate", bitrate.map(|bitrate| (bitrate + 500) / 1000).filter(|&kbps| kbps > 0).map(|kbps| format!("{} kb/s", kbps))),
+        ];
+        fields.extend(self.streams.iter().map(|(kind, stream)| (*kind, Some(stream.clone()))));
+        header(&fields)
+    }
+}
+
+/// `3:05`, or `1:02:03` for an hour or more.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64().round() as u64;
+    match seconds / 3600 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+/// A stream line from the parts that are known, e.g. `AAC, 48 kHz, stereo`.
+fn stream(parts: impl IntoIterator<Item = Option<String>>) -> String {
+    parts.into_iter().flatten().collect::<Vec<_>>().join(", ")
+}
+
+fn sample_rate(hz: u32) -> Option<String> {
+    (hz > 0).then(|| format!("{} kHz", hz as f64 / 1000.0))
+}
+
+fn channels(count: u32) -> Option<String> {
+    match count {
+        0 => None,
+        1 => Some("mono".to_string()),
+        2 => Some("stereo".to_string()),
+        6 => Some("5.1".to_string()),
+        8 => Some("7.1".to_string()),
+        count => Some(format!("{} channels", count)),
+    }
+}
+
+fn resolution(width: u32, height: u32) -> Option<String> {
+    (width > 0 && height > 0).then(|| format!("{}x{}", width, height))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Up to `len` bytes at `offset`; fewer at the end of the file.
+fn read_at(file: &mut dyn VfsFile, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    file.take(len).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn be_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn be_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_u64(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn le_u64(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
@@ -0,0 +1,202 @@
+use super::{be_u16, be_u32, be_u64, channels, invalid, read_at, resolution, sample_rate, stream, MediaInfo};
+use ::io::vfs::VfsFile;
+use std::io;
+use std::time::Duration;
+
+/// Boxes that hold only other boxes, down to the ones read below.
+const CONTAINERS: &[&[u8; 4]] = &[b"moov", b"trak", b"mdia", b"minf", b"stbl", b"udta", b"ilst"];
+/// Boxes nest a handful of levels deep; anything more is a broken file.
+const MAX_DEPTH: usize = 8;
+/// Small boxes like headers and iTunes tags are read whole up to this size.
+const MAX_BOX_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, Default)]
+struct Track {
+    /// `vide`, `soun`, `sbtl`, …
+    handler: [u8; 4],
+    codec: Option<[u8; 4]>,
+    width: u32,
+    height: u32,
+    channels: u32,
+    sample_rate: u32,
+}
+
+#[derive(Debug, Default)]
+struct Mp4 {
+    info: MediaInfo,
+    tracks: Vec<Track>,
+}
+
+/// MP4, M4A and QuickTime files: the movie header for the duration, the
+/// track headers and sample descriptions for the streams, and the iTunes
+/// metadata list for tags. The media data isn't touched.
+pub(super) fn read(file: &mut dyn VfsFile, len: u64) -> io::Result<MediaInfo> {
+    let mut mp4 = Mp4::default();
+    mp4.walk(file, 0, len, b"", 0)?;
+    let Mp4 { mut info, tracks } = mp4;
+    for track in tracks {
+        let codec = track.codec.map(|codec| codec_name(&codec));
+        match &track.handler {
+            b"vide" => info.streams.push(("Video", stream([codec, resolution(track.width, track.height)]))),
+            b"soun" => info.streams.push((
+                "Audio",
+                stream([codec, sample_rate(track.sample_rate), channels(track.channels)]),
+            )),
+            b"sbtl" | b"subt" | b"text" => info.streams.push(("Subtitle", stream([codec]))),
+            _ => {}
+        }
+    }
+    Ok(info)
+}
+
+impl Mp4 {
+    /// Reads the boxes between `start` and `end`, whose parent is `parent`.
+    fn walk(&mut self, file: &mut dyn VfsFile, start: u64, end: u64, parent: &[u8], depth: usize) -> io::Result<()> {
+        if depth > MAX_DEPTH {
+            return Ok(());
+        }
+        let mut offset = start;
+        while offset + 8 <= end {
+            let header = read_at(file, offset, 16)?;
+            if header.len() < 8 {
+                break;
+            }
+            let kind: [u8; 4] = header[4..8].try_into().unwrap();
+            // A size of 1 means a 64-bit size follows; 0 means up to the end.
+            let (header_len, size) = match be_u32(&header, 0).unwrap_or_default() {
+                1 => (16, be_u64(&header, 8).unwrap_or_default()),
+                0 => (8, end - offset),
+                size => (8, u64::from(size)),
+            };
+            if size < header_len {
+                return Err(invalid("Malformed MP4 box"));
+            }
+            let body = offset + header_len;
+            let box_end = offset.saturating_add(size).min(end);
+            if parent == b"ilst" {
+                self.tag(&kind, &read_at(file, body, box_end.saturating_sub(body).min(MAX_BOX_SIZE))?);
+            } else if CONTAINERS.contains(&&kind) {
+                if &kind == b"trak" {
+                    self.tracks.push(Track::default());
+                }
+                self.walk(file, body, box_end, &kind, depth + 1)?;
+            } else if &kind == b"meta" {
+                // A full box with version and flags in MP4, a plain container
+                // in QuickTime.
+                let peek = read_at(file, body, 8)?;
+                let skip = if peek.get(4..8) == Some(b"hdlr") { 0 } else { 4 };
+                self.walk(file, body + skip, box_end, &kind, depth + 1)?;
+            } else if matches!(&kind, b"mvhd" | b"tkhd" | b"hdlr" | b"stsd") {
+                let data = read_at(file, body, box_end.saturating_sub(body).min(MAX_BOX_SIZE))?;
+                self.header(&kind, parent, &data);
+            }
+            offset = box_end;
+        }
+        Ok(())
+    }
+
+    fn header(&mut self, kind: &[u8; 4], parent: &[u8], data: &[u8]) {
+        let version = data.first().copied().unwrap_or_default();
+        match kind {
+            b"mvhd" => {
+                // A duration of all ones means it isn't known.
+                let (timescale, duration) = match version {
+                    1 => (be_u32(data, 20), be_u64(data, 24).filter(|&d| d != u64::MAX)),
+                    _ => (be_u32(data, 12), be_u32(data, 16).filter(|&d| d != u32::MAX).map(u64::from)),
+                };
+                if let (Some(timescale), Some(duration)) = (timescale.filter(|&t| t > 0), duration) {
+                    self.info.duration = Duration::try_from_secs_f64(duration as f64 / f64::from(timescale)).ok();
+                }
+            }
+            // The display size, in 16.16 fixed point, ends the track header.
+            b"tkhd" if data.len() >= 8 => {
+                if let Some(track) = self.tracks.last_mut() {
+                    track.width = be_u32(data, data.len() - 8).unwrap_or_default() >> 16;
+                    track.height = be_u32(data, data.len() - 4).unwrap_or_default() >> 16;
+                }
+            }
+            b"hdlr" if parent == b"mdia" => {
+                if let (Some(track), Some(handler)) = (self.tracks.last_mut(), data.get(8..12)) {
+                    track.handler = handler.try_into().unwrap();
+                }
+            }
+            // The first sample description names the codec; audio and video
+            // descriptions go on with the format's basics.
+            b"stsd" => {
+                let Some(track) = self.tracks.last_mut() else {
+                    return;
+                };
+                let Some(entry) = data.get(8..) else {
+                    return;
+                };
+                track.codec = entry.get(4..8).map(|codec| codec.try_into().unwrap());
+                match &track.handler {
+                    b"soun" => {
+                        track.channels = be_u16(entry, 24).unwrap_or_default().into();
+                        track.sample_rate = be_u32(entry, 32).unwrap_or_default() >> 16;
+                    }
+                    b"vide" if track.width == 0 => {
+                        track.width = be_u16(entry, 32).unwrap_or_default().into();
+                        track.height = be_u16(entry, 34).unwrap_or_default().into();
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// An iTunes metadata item; its value is in a `data` box after a type
+    /// and a locale.
+    fn tag(&mut self, kind: &[u8; 4], item: &[u8]) {
+        let size = be_u32(item, 0).unwrap_or_default() as usize;
+        if item.get(4..8) != Some(b"data") || size < 16 {
+            return;
+        }
+        let Some(value) = item.get(16..size.min(item.len())) else {
+            return;
+        };
+        let name = match kind {
+            b"\xa9nam" => "TITLE",
+            b"\xa9ART" => "ARTIST",
+            b"\xa9alb" => "ALBUM",
+            b"\xa9day" => "DATE",
+            b"\xa9gen" => "GENRE",
+            b"trkn" => {
+                let track = be_u16(value, 2).unwrap_or_default();
+                let total = be_u16(value, 4).unwrap_or_default();
+                let track = match (track, total) {
+                    (0, _) => return,
+                    (track, 0) => track.to_string(),
+                    (track, total) => format!("{}/{}", track, total),
+                };
+                self.info.set_tag("TRACKNUMBER", &track);
+                return;
+            }
+            _ => return,
+        };
+        self.info.set_tag(name, &String::from_utf8_lossy(value));
+    }
+}
+
+fn codec_name(codec: &[u8; 4]) -> String {
+    match codec {
+        b"avc1" | b"avc3" => "H.264",
+        b"hvc1" | b"hev1" => "H.265",
+        b"av01" => "AV1",
+        b"vp08" => "VP8",
+        b"vp09" => "VP9",
+        b"mp4v" => "MPEG-4 Visual",
+        b"mp4a" => "AAC",
+        b"ac-3" => "AC-3",
+        b"ec-3" => "E-AC-3",
+        b"Opus" => "Opus",
+        b"fLaC" => "FLAC",
+        b"alac" => "ALAC",
+        b".mp3" => "MP3",
+        b"tx3g" => "Timed Text",
+        b"wvtt" => "WebVTT",
+        codec => return String::from_utf8_lossy(codec).trim().to_string(),
+    }
+    .to_string()
+}
@@ -0,0 +1,413 @@
+use crate::mime;
+use config::Config;
+use io::vfs::{Metadata, SpecialFile, SpecialKind, Vfs};
+use lru::LruCache;
+use std::fmt;
+use std::future::Future;
+use std::io::Read;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use tokio::sync::oneshot;
+
+mod ansi;
+mod archive;
+mod epub;
+mod exif;
+mod external;
+mod hex;
+mod highlight;
+mod image;
+mod markdown;
+mod media;
+mod office;
+mod paged;
+mod pdf;
+mod registry;
+mod structured;
+mod text;
+
+pub use self::archive::ArchivePreviewer;
+pub use self::epub::EpubPreviewer;
+pub use self::external::ExternalPreviewers;
+pub use self::hex::{format_rows, parse_offset, row_start, HexDump, HexPreviewer, BYTES_PER_ROW, HEX_PAGE_SIZE};
+pub use self::highlight::{detect_syntax, Highlighter};
+pub use self::image::{ImagePreview, ImagePreviewer};
+pub use self::markdown::MarkdownPreviewer;
+pub use self::media::MediaPreviewer;
+pub use self::office::OfficePreviewer;
+pub use self::paged::{line_offset, parse_text_target, Follow, FollowPoll, PageJob, PageRead, TextPage, TextTarget, TEXT_PAGE_SIZE};
+pub use self::pdf::PdfPreviewer;
+pub use self::registry::{FilePreviewer, PreviewRequest, PreviewerRegistry};
+pub use self::structured::{DataTree, StructuredPreviewer, TreeNode, ValueKind};
+pub use self::text::TextPreviewer;
+
+pub const MAX_PREVIEW_SIZE: u64 = 1024 * 1024; // 1MB
+const CACHE_CAPACITY: usize = 64;
+const READ_CHUNK: usize = 64 * 1024;
+/// How much of a file is sniffed to detect its type.
+const SNIFF_LEN: u64 = 8192;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpanStyle {
+    /// Foreground color as RGB; `None` keeps the terminal's color.
+    pub fg: Option<(u8, u8, u8)>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyledSpan {
+    pub text: String,
+    pub style: SpanStyle,
+}
+
+pub type StyledLine = Vec<StyledSpan>;
+
+/// What the right pane shows for an entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preview {
+    Text(String),
+    /// Source code with syntax highlighting, one list of spans per line.
+    Highlighted { text: String, lines: Vec<StyledLine> },
+    /// A page of a binary file as a hex dump.
+    Hex(HexDump),
+    /// A page of a text file too large to read whole.
+    Paged(TextPage),
+    /// A decoded image, drawn with the terminal's graphics protocol.
+    Image(ImagePreview),
+    /// Text extracted from a document or a file's metadata, optionally with a
+    /// picture drawn above it: a document's first page, or the photo itself.
+    Document { text: String, image: Option<ImagePreview> },
+    /// A JSON, TOML or YAML document whose containers can be folded.
+    Tree(DataTree),
+    /// A Markdown file, rendered and as highlighted source. `text` is the
+    /// source.
+    Markdown { text: String, rendered: Vec<StyledLine>, source: Vec<StyledLine> },
+}
+
+impl Preview {
+    /// The preview as plain text.
+    pub fn text(&self) -> &str {
+        match self {
+            Preview::Text(text)
+            | Preview::Highlighted { text, .. }
+            | Preview::Document { text, .. }
+            | Preview::Markdown { text, .. } => text,
+            Preview::Hex(dump) => &dump.text,
+            Preview::Paged(page) => &page.text,
+            Preview::Tree(tree) => &tree.text,
+            Preview::Image(image) => &image.description,
+        }
+    }
+
+    /// The image to draw, if the preview has one.
+    pub fn image(&self) -> Option<&ImagePreview> {
+        match self {
+            Preview::Image(image) | Preview::Document { image: Some(image), .. } => Some(image),
+            _ => None,
+        }
+    }
+}
+
+/// A preview is reused while the file's size and mtime stay the same.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl CacheKey {
+    fn new(path: &Path, metadata: &Metadata) -> Self {
+        Self { path: path.to_path_buf(), modified: metadata.modified, len: metadata.len }
+    }
+}
+
+/// A generated preview and the MIME type it was picked by.
+struct Cached {
+    preview: Arc<Preview>,
+    mime: &'static str,
+}
+
+/// Generates previews and keeps the recent ones. Shared by all tabs.
+pub struct Previewer {
+    cache: Mutex<LruCache<CacheKey, Cached>>,
+    registry: PreviewerRegistry,
+    /// Tried before the registry, on files on the local disk.
+    external: ExternalPreviewers,
+}
+
+impl fmt::Debug for Previewer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Previewer")
+            .field("cached", &self.cache.lock().unwrap().len())
+            .field("registry", &self.registry)
+            .field("external", &self.external)
+            .finish()
+    }
+}
+
+impl Default for Previewer {
+    fn default() -> Self {
+        Self::new(&Config::default())
+    }
+}
+
+impl Previewer {
+    /// A previewer with the built-in renderers: text, Markdown, data files, images, PDFs,
+    /// archive listings, office documents, ebooks, media tags, and a hex dump
+    /// for everything else.
+    pub fn new(config: &Config) -> Self {
+        let mut registry = PreviewerRegistry::new();
+        registry.register(Box::new(HexPreviewer));
+        let highlighter = Highlighter::new(&config.theme.syntax);
+        registry.register(Box::new(TextPreviewer::new(highlighter.clone())));
+        registry.register(Box::new(MarkdownPreviewer::new(highlighter)));
+        registry.register(Box::new(ImagePreviewer::new(config.preview.resolution)));
+        let pdf = PdfPreviewer::new(config.preview.pdf_raster, config.preview.resolution, external::timeout(&config.preview));
+        registry.register(Box::new(pdf));
+        registry.register(Box::new(ArchivePreviewer));
+        registry.register(Box::new(OfficePreviewer));
+        registry.register(Box::new(EpubPreviewer));
+        registry.register(Box::new(MediaPreviewer));
+        registry.register(Box::new(StructuredPreviewer));
+        let mut previewer = Self::with_registry(registry);
+        previewer.external = ExternalPreviewers::new(&config.preview);
+        previewer
+    }
+
+    pub fn with_registry(registry: PreviewerRegistry) -> Self {
+        let capacity = NonZeroUsize::new(CACHE_CAPACITY).unwrap();
+        Self { cache: Mutex::new(LruCache::new(capacity)), registry, external: ExternalPreviewers::default() }
+    }
+
+    /// Adds a previewer that takes precedence over the built-in ones for the
+    /// types it handles.
+    pub fn register(&mut self, previewer: Box<dyn FilePreviewer>) {
+        self.registry.register(previewer);
+    }
+
+    /// The cached preview of `path`, if it hasn't changed since.
+    pub fn cached(&self, path: &Path, metadata: &Metadata) -> Option<Arc<Preview>> {
+        self.cache.lock().unwrap().get(&CacheKey::new(path, metadata)).map(|cached| cached.preview.clone())
+    }
+
+    /// The MIME type detected from the content of `path` when it was last
+    /// previewed, if it hasn't changed since.
+    pub fn mime(&self, path: &Path, metadata: &Metadata) -> Option<&'static str> {
+        self.cache.lock().unwrap().peek(&CacheKey::new(path, metadata)).map(|cached| cached.mime)
+    }
+
+    /// Builds the preview of `path` with the previewer for its MIME type, and
+    /// caches it. Returns `None` if cancelled midway.
+    pub fn generate(&self, vfs: &dyn Vfs, path: &Path, metadata: &Metadata, cancelled: &AtomicBool) -> Option<Arc<Preview>> {
+        let (preview, mime) = self.read_preview(vfs, path, metadata, cancelled)?;
+        let preview = Arc::new(preview);
+        let cached = Cached { preview: preview.clone(), mime };
+        self.cache.lock().unwrap().put(CacheKey::new(path, metadata), cached);
+        Some(preview)
+    }
+
+    /// The preview of `path`, from the cache or freshly built, with the MIME
+    /// type detected from its content. A file that can't be read previews as
+    /// the error. Returns `None` if cancelled midway.
+    pub fn load(&self, vfs: &dyn Vfs, path: &Path, cancelled: &AtomicBool) -> Option<(Arc<Preview>, Option<&'static str>)> {
+        let metadata = match vfs.metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => return Some((Arc::new(Preview::Text(format!("Error opening file:\n{}", e))), None)),
+        };
+        let preview = match self.cached(path, &metadata) {
+            Some(preview) => preview,
+            None => self.generate(vfs, path, &metadata, cancelled)?,
+        };
+        Some((preview, self.mime(path, &metadata)))
+    }
+
+    /// Builds the preview of `path` and returns it with the file's MIME type.
+    /// Directories and special files are described here; the content of
+    /// regular files decides which previewer takes them, unless an external
+    /// command is set up for them.
+    fn read_preview(
+        &self,
+        vfs: &dyn Vfs,
+        path: &Path,
+        metadata: &Metadata,
+        cancelled: &AtomicBool,
+    ) -> Option<(Preview, &'static str)> {
+        // Opening a FIFO blocks until a writer shows up, and reading a device can
+        // have side effects, so these only get a description.
+        if let Some(special) = metadata.special {
+            return Some((Preview::Text(special_file_card(&special, metadata)), special_mime(special.kind)));
+        }
+        if metadata.is_dir {
+            return Some((Preview::Text(directory_listing(vfs, path)), mime::DIRECTORY));
+        }
+
+        let mut file = match vfs.open(path) {
+            Ok(file) => file,
+            Err(e) => return Some((Preview::Text(format!("Error opening file:\n{}", e)), mime::BINARY)),
+        };
+        let mut head = Vec::new();
+        if let Err(e) = file.by_ref().take(SNIFF_LEN).read_to_end(&mut head) {
+            return Some((Preview::Text(format!("Error reading file:\n{}", e)), mime::BINARY));
+        }
+        let mime = mime::detect(path, &head);
+        if let Some((command, local_path)) = self.external.command(path, mime).zip(vfs.local_path(path)) {
+            log::debug!("Previewing {:?} ({}) with {:?}", path, mime, command);
+            if let Some(preview) = self.external.run(command, &local_path, cancelled) {
+                return Some((preview, mime));
+            }
+            if cancelled.load(Ordering::Relaxed) {
+                return None;
+            }
+        }
+        let Some(previewer) = self.registry.find(mime) else {
+            return Some((Preview::Text(format!("No preview for {}", mime)), mime));
+        };
+        log::debug!("Previewing {:?} ({}) with the {} previewer", path, mime, previewer.name());
+        let request = PreviewRequest { vfs, path, metadata, mime, head: &head, cancelled };
+        let preview = previewer.preview(&request, file.as_mut())?;
+        Some((preview, mime))
+    }
+}
+
+/// A preview being generated on the blocking thread pool.
+///
+/// Dropping the job cancels it: a job that hasn't started yet is skipped and a
+/// running one stops at the next chunk it reads.
+pub struct PreviewJob {
+    pub path: PathBuf,
+    cancelled: Arc<AtomicBool>,
+    result: oneshot::Receiver<(Arc<Preview>, Option<&'static str>)>,
+}
+
+impl fmt::Debug for PreviewJob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PreviewJob").field("path", &self.path).finish()
+    }
+}
+
+impl Drop for PreviewJob {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl PreviewJob {
+    /// Starts loading the preview of `path`, see [`Previewer::load`]. Needs a
+    /// tokio runtime. The result is cached even if nobody waits for it any more.
+    pub fn start(vfs: Arc<dyn Vfs>, previewer: Arc<Previewer>, path: PathBuf) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = oneshot::channel();
+        let job_path = path.clone();
+        let job_cancelled = cancelled.clone();
+        tokio::task::spawn_blocking(move || {
+            if job_cancelled.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Some(loaded) = previewer.load(vfs.as_ref(), &job_path, &job_cancelled) {
+                let _ = tx.send(loaded);
+            }
+        });
+        Self { path, cancelled, result: rx }
+    }
+
+    /// Polls for the finished preview and its MIME type; `Ready(None)` if the
+    /// job died.
+    pub fn poll_result(&mut self, cx: &mut Context<'_>) -> Poll<Option<(Arc<Preview>, Option<&'static str>)>> {
+        Pin::new(&mut self.result).poll(cx).map(Result::ok)
+    }
+}
+
+fn directory_listing(vfs: &dyn Vfs, path: &Path) -> String {
+    match vfs.read_dir(path) {
+        Ok(entries) => {
+            let mut names = entries
+                .iter()
+                .map(|entry| {
+                    let mut name = entry.name();
+                    if entry.metadata.is_dir {
+                        name.push('/');
+                    }
+                    name
+                })
+                .collect::<Vec<_>>();
+            names.sort();
+            names.join("\n")
+        }
+        Err(e) => format!("Error reading directory:\n{}", e),
+    }
+}
+
+/// Reads the rest of `file` into `buffer` in chunks, stopping once it holds
+/// more than `limit` bytes. Returns `None` if cancelled midway.
+fn read_up_to<R: Read + ?Sized>(file: &mut R, buffer: &mut Vec<u8>, limit: u64, cancelled: &AtomicBool) -> Option<std::io::Result<()>> {
+    let mut chunk = vec![0; READ_CHUNK];
+    while buffer.len() as u64 <= limit {
+        if cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        match file.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Some(Err(e)),
+        }
+    }
+    Some(Ok(()))
+}
+
+/// The shared-mime-info names for special files.
+fn special_mime(kind: SpecialKind) -> &'static str {
+    match kind {
+        SpecialKind::Fifo => "inode/fifo",
+        SpecialKind::Socket => "inode/socket",
+        SpecialKind::CharDevice => "inode/chardevice",
+        SpecialKind::BlockDevice => "inode/blockdevice",
+    }
+}
+
+fn special_file_card(special: &SpecialFile, metadata: &Metadata) -> String {
+    let (type_char, description) = match special.kind {
+        SpecialKind::Fifo => ('p', "Named pipe (FIFO)"),
+        SpecialKind::Socket => ('s', "Unix domain socket"),
+        SpecialKind::CharDevice => ('c', "Character device"),
+        SpecialKind::BlockDevice => ('b', "Block device"),
+    };
+    let mut card = format!("{}\nNot opened for preview.\n", description);
+    if let Some(mode) = metadata.mode {
+        card.push_str(&format!("\nPermissions: {} ({:04o})", format_permissions(type_char, mode), mode));
+    }
+    if let Some((major, minor)) = special.device {
+        card.push_str(&format!("\nDevice:      {}, {} (major, minor)", major, minor));
+    }
+    if let Some(modified) = metadata.modified {
+        let modified: chrono::DateTime<chrono::Local> = modified.into();
+        card.push_str(&format!("\nModified:    {}", modified.format("%Y-%m-%d %H:%M:%S")));
+    }
+    card
+}
+
+/// Formats permission bits like `ls -l`, e.g. `crw-rw-rw-`.
+pub fn format_permissions(type_char: char, mode: u32) -> String {
+    let mut formatted = String::from(type_char);
+    for (shift, special_bit, special_char) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = (mode >> shift) & 0o7;
+        formatted.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        formatted.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        let execute = bits & 0o1 != 0;
+        formatted.push(match (mode & special_bit != 0, execute) {
+            (true, true) => special_char,
+            (true, false) => special_char.to_ascii_uppercase(),
+            (false, true) => 'x',
+            (false, false) => '-',
+        });
+    }
+    formatted
+}
@@ -0,0 +1,528 @@
+use super::registry::{FilePreviewer, PreviewRequest};
+use super::{Preview, MAX_PREVIEW_SIZE};
+use ::io::vfs::VfsFile;
+use quick_xml::events::{BytesStart, BytesText, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+/// XML parts larger than this are cut off; what's left still makes a preview.
+const MAX_PART_SIZE: u64 = 16 * 1024 * 1024;
+/// The part of a spreadsheet shown as a table.
+const MAX_ROWS: usize = 200;
+const MAX_COLUMNS: usize = 26;
+/// Cells wider than this are cut off.
+const MAX_CELL_WIDTH: usize = 30;
+
+const DOCX: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+const XLSX: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+const ODT: &str = "application/vnd.oasis.opendocument.text";
+const ODS: &str = "application/vnd.oasis.opendocument.spreadsheet";
+
+/// Word processor documents and spreadsheets, in the Office Open XML
+/// (`.docx`, `.xlsx`) and OpenDocument (`.odt`, `.ods`) formats. Both are zip
+/// files of XML parts, so this reads the parts it needs without extracting.
+#[derive(Debug, Default)]
+pub struct OfficePreviewer;
+
+impl FilePreviewer for OfficePreviewer {
+    fn name(&self) -> &'static str {
+        "office"
+    }
+
+    fn handles(&self, mime: &str) -> bool {
+        matches!(mime, DOCX | XLSX | ODT | ODS)
+    }
+
+    fn preview(&self, request: &PreviewRequest, file: &mut dyn VfsFile) -> Option<Preview> {
+        let text = open_zip(file).and_then(|mut zip| match request.mime {
+            DOCX => docx(&mut zip),
+            XLSX => xlsx(&mut zip),
+            ODT => odt(&mut zip),
+            _ => ods(&mut zip),
+        });
+        Some(match text {
+            Ok(text) => Preview::Document { text, image: None },
+            Err(e) => Preview::Text(format!("Error reading document:\n{}", e)),
+        })
+    }
+}
+
+pub(super) fn open_zip<R: Read + Seek>(mut file: R) -> io::Result<ZipArchive<BufReader<R>>> {
+    file.seek(SeekFrom::Start(0))?;
+    Ok(ZipArchive::new(BufReader::new(file))?)
+}
+
+/// The zip member `name` as text; `None` if there's no such member.
+pub(super) fn read_part<R: Read + Seek>(zip: &mut ZipArchive<R>, name: &str) -> io::Result<Option<String>> {
+    let part = match zip.by_name(name) {
+        Ok(part) => part,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut bytes = Vec::new();
+    part.take(MAX_PART_SIZE).read_to_end(&mut bytes)?;
+    Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+fn xml_error(e: quick_xml::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Calls `on_event` for each event of `xml` until the end or an error.
+pub(super) fn walk_xml(xml: &str, mut on_event: impl FnMut(Event)) -> io::Result<()> {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Eof => return Ok(()),
+            event => on_event(event),
+        }
+    }
+}
+
+/// Unescaped text; HTML entities like `&nbsp;` are understood too, since
+/// ebooks use them. Text with an unknown entity is kept as it is.
+pub(super) fn text_of(text: &BytesText) -> String {
+    match text.unescape() {
+        Ok(text) => text.into_owned(),
+        Err(_) => String::from_utf8_lossy(text).into_owned(),
+    }
+}
+
+/// The value of the attribute with local name `name`, ignoring its prefix.
+pub(super) fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|attribute| attribute.key.local_name().as_ref() == name)
+        .and_then(|attribute| attribute.unescape_value().ok().map(|value| value.into_owned()))
+}
+
+/// The text of the first element with each of the local `names`, e.g. the
+/// `dc:title` of a metadata part.
+pub(super) fn element_texts(xml: &str, names: &[&str]) -> io::Result<HashMap<String, String>> {
+    let mut found = HashMap::new();
+    let mut current: Option<String> = None;
+    walk_xml(xml, |event| match event {
+        Event::Start(element) => {
+            let name = String::from_utf8_lossy(element.local_name().as_ref()).into_owned();
+            current = (names.contains(&name.as_str()) && !found.contains_key(&name)).then_some(name);
+        }
+        Event::Text(text) => {
+            if let Some(name) = current.take() {
+                let text = text_of(&text).trim().to_string();
+                if !text.is_empty() {
+                    found.insert(name, text);
+                }
+            }
+        }
+        Event::End(_) => current = None,
+        _ => {}
+    })?;
+    Ok(found)
+}
+
+/// The lines above the content, e.g. `Title:    Report`. Missing values are
+/// left out.
+pub(super) fn header(fields: &[(&str, Option<String>)]) -> String {
+    fields
+        .iter()
+        .filter_map(|(label, value)| value.as_ref().map(|value| format!("{:<9} {}", format!("{}:", label), value)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Words have at least one letter or digit; a lone `&` or `–` isn't one.
+pub(super) fn word_count(text: &str) -> usize {
+    text.split_whitespace().filter(|word| word.chars().any(char::is_alphanumeric)).count()
+}
+
+/// Cuts `text` down to what the preview pane can show.
+pub(super) fn truncate(text: &mut String) {
+    if text.len() as u64 > MAX_PREVIEW_SIZE {
+        let mut end = MAX_PREVIEW_SIZE as usize;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("\n…");
+    }
+}
+
+fn docx<R: Read + Seek>(zip: &mut ZipArchive<R>) -> io::Result<String> {
+    let body = read_part(zip, "word/document.xml")?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "word/document.xml is missing"))?;
+    let mut text = String::new();
+    let (mut in_run, mut in_text) = (false, false);
+    walk_xml(&body, |event| match event {
+        Event::Start(element) => match element.local_name().as_ref() {
+            b"r" => in_run = true,
+            b"t" => in_text = true,
+            _ => {}
+        },
+        Event::End(element) => match element.local_name().as_ref() {
+            b"r" => in_run = false,
+            b"t" => in_text = false,
+            b"p" => text.push('\n'),
+            _ => {}
+        },
+        Event::Empty(element) => match element.local_name().as_ref() {
+            b"tab" if in_run => text.push('\t'),
+            b"br" | b"cr" if in_run => text.push('\n'),
+            b"p" => text.push('\n'),
+            _ => {}
+        },
+        Event::Text(content) if in_text => text.push_str(&text_of(&content)),
+        _ => {}
+    })?;
+
+    let core = match read_part(zip, "docProps/core.xml")? {
+        Some(xml) => element_texts(&xml, &["title", "creator"])?,
+        None => HashMap::new(),
+    };
+    let app = match read_part(zip, "docProps/app.xml")? {
+        Some(xml) => element_texts(&xml, &["Pages"])?,
+        None => HashMap::new(),
+    };
+    Ok(document(&core, app.get("Pages").cloned(), text))
+}
+
+fn odt<R: Read + Seek>(zip: &mut ZipArchive<R>) -> io::Result<String> {
+    let content = read_part(zip, "content.xml")?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "content.xml is missing"))?;
+    let mut text = String::new();
+    let mut depth = 0;
+    walk_xml(&content, |event| match event {
+        Event::Start(element) if matches!(element.local_name().as_ref(), b"p" | b"h") => depth += 1,
+        Event::End(element) if matches!(element.local_name().as_ref(), b"p" | b"h") => {
+            depth -= 1;
+            text.push('\n');
+        }
+        Event::Empty(element) if depth > 0 => match element.local_name().as_ref() {
+            b"s" => {
+                let count = attribute(&element, b"c").and_then(|c| c.parse().ok()).unwrap_or(1);
+                // The count comes from the file; spaces past what the preview
+                // shows would only be cut off again.
+                let room = (MAX_PREVIEW_SIZE as usize + 1).saturating_sub(text.len());
+                text.extend(std::iter::repeat_n(' ', count.min(room)));
+            }
+            b"tab" => text.push('\t'),
+            b"line-break" => text.push('\n'),
+            _ => {}
+        },
+        Event::Empty(element) if matches!(element.local_name().as_ref(), b"p" | b"h") => text.push('\n'),
+        Event::Text(content) if depth > 0 => text.push_str(&text_of(&content)),
+        _ => {}
+    })?;
+
+    let (fields, pages) = odf_meta(zip)?;
+    Ok(document(&fields, pages, text))
+}
+
+/// The title and author from `meta.xml`, and the page count if recorded.
+fn odf_meta<R: Read + Seek>(zip: &mut ZipArchive<R>) -> io::Result<(HashMap<String, String>, Option<String>)> {
+    let Some(meta) = read_part(zip, "meta.xml")? else {
+        return Ok((HashMap::new(), None));
+    };
+    let mut fields = element_texts(&meta, &["title", "creator", "initial-creator"])?;
+    if let Some(creator) = fields.remove("initial-creator") {
+        fields.entry("creator".to_string()).or_insert(creator);
+    }
+    let mut pages = None;
+    walk_xml(&meta, |event| {
+        if let Event::Empty(element) | Event::Start(element) = event {
+            if element.local_name().as_ref() == b"document-statistic" {
+                pages = attribute(&element, b"page-count");
+            }
+        }
+    })?;
+    Ok((fields, pages))
+}
+
+/// The preview of a word processor document: its metadata, then its text.
+fn document(fields: &HashMap<String, String>, pages: Option<String>, text: String) -> String {
+    let mut preview = header(&[
+        ("Title", fields.get("title").cloned()),
+        ("Author", fields.get("creator").cloned()),
+        ("Pages", pages),
+        ("Words", Some(word_count(&text).to_string())),
+    ]);
+    preview.push_str("\n\n");
+    preview.push_str(text.trim_end());
+    truncate(&mut preview);
+    preview
+}
+
+/// A spreadsheet being read: the cells kept for the table and the size of
+/// the whole sheet.
+#[derive(Default)]
+struct Sheet {
+    cells: Vec<Vec<String>>,
+    rows: usize,
+    columns: usize,
+}
+
+impl Sheet {
+    /// Records the value of a cell; only the top-left corner is kept.
+    fn set(&mut self, row: usize, column: usize, value: String) {
+        self.fill(row..row.saturating_add(1), column..column.saturating_add(1), value);
+    }
+
+    /// Records the value of a block of cells, as repeated cells are stored.
+    /// Positions come from the file, so the block can be far out of view.
+    fn fill(&mut self, rows: Range<usize>, columns: Range<usize>, value: String) {
+        if value.is_empty() || rows.is_empty() || columns.is_empty() {
+            return;
+        }
+        self.rows = self.rows.max(rows.end);
+        self.columns = self.columns.max(columns.end);
+        for row in rows.start..rows.end.min(MAX_ROWS) {
+            if self.cells.len() <= row {
+                self.cells.resize(row + 1, Vec::new());
+            }
+            let cells = &mut self.cells[row];
+            let end = columns.end.min(MAX_COLUMNS);
+            if columns.start < end && cells.len() < end {
+                cells.resize(end, String::new());
+            }
+            if let Some(visible) = cells.get_mut(columns.start..end) {
+                visible.fill(value.clone());
+            }
+        }
+    }
+
+    fn describe(&self, title: Option<String>, name: Option<String>, sheets: usize) -> String {
+        let mut preview = header(&[
+            ("Title", title),
+            ("Sheet", name.map(|name| format!("{} (1 of {})", name, sheets))),
+            ("Size", Some(format!("{} rows × {} columns", self.rows, self.columns))),
+        ]);
+        preview.push_str("\n\n");
+        preview.push_str(&format_table(&self.cells));
+        if self.rows > MAX_ROWS || self.columns > MAX_COLUMNS {
+            preview.push_str(&format!("\n… showing {} rows × {} columns", self.rows.min(MAX_ROWS), self.columns.min(MAX_COLUMNS)));
+        }
+        preview
+    }
+}
+
+/// Lays out `rows` in columns as wide as their widest cell.
+pub(super) fn format_table(rows: &[Vec<String>]) -> String {
+    let cell = |value: &String| -> String {
+        let value = value.replace(['\n', '\t'], " ");
+        if value.chars().count() > MAX_CELL_WIDTH {
+            value.chars().take(MAX_CELL_WIDTH - 1).chain(['…']).collect()
+        } else {
+            value
+        }
+    };
+    let rows: Vec<Vec<String>> = rows.iter().map(|row| row.iter().map(cell).collect()).collect();
+    let mut widths: Vec<usize> = Vec::new();
+    for row in &rows {
+        for (column, value) in row.iter().enumerate() {
+            if widths.len() <= column {
+                widths.push(0);
+            }
+            widths[column] = widths[column].max(value.chars().count());
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            // Rows stop at their last value instead of trailing separators.
+            let len = row.iter().rposition(|value| !value.is_empty()).map_or(0, |last| last + 1);
+            row[..len]
+                .iter()
+                .enumerate()
+                .map(|(column, value)| match column + 1 == len {
+                    true => value.clone(),
+                    false => format!("{}{}", value, " ".repeat(widths[column] - value.chars().count())),
+                })
+                .collect::<Vec<_>>()
+                .join(" │ ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The column of a cell reference like `AB12`, counting from 0.
+fn column_index(reference: &str) -> Option<usize> {
+    let letters: Vec<u8> = reference.bytes().take_while(u8::is_ascii_alphabetic).collect();
+    if letters.is_empty() {
+        return None;
+    }
+    let column = letters.iter().try_fold(0usize, |column, letter| {
+        column.checked_mul(26)?.checked_add((letter.to_ascii_uppercase() - b'A') as usize + 1)
+    })?;
+    Some(column - 1)
+}
+
+fn xlsx<R: Read + Seek>(zip: &mut ZipArchive<R>) -> io::Result<String> {
+    let workbook = read_part(zip, "xl/workbook.xml")?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "xl/workbook.xml is missing"))?;
+    let mut sheets = Vec::new();
+    walk_xml(&workbook, |event| {
+        if let Event::Empty(element) | Event::Start(element) = event {
+            if element.local_name().as_ref() == b"sheet" {
+                sheets.push((attribute(&element, b"name"), attribute(&element, b"id")));
+            }
+        }
+    })?;
+    let Some((name, id)) = sheets.first().cloned() else {
+        return Ok("The workbook has no sheets.".to_string());
+    };
+
+    // The first sheet's part is found through the workbook's relationships.
+    let mut target = None;
+    if let (Some(rels), Some(id)) = (read_part(zip, "xl/_rels/workbook.xml.rels")?, id) {
+        walk_xml(&rels, |event| {
+            if let Event::Empty(element) | Event::Start(element) = event {
+                if attribute(&element, b"Id").as_deref() == Some(id.as_str()) {
+                    target = attribute(&element, b"Target");
+                }
+            }
+        })?;
+    }
+    let path = match target {
+        Some(target) if target.starts_with('/') => target.trim_start_matches('/').to_string(),
+        Some(target) => format!("xl/{}", target),
+        None => "xl/worksheets/sheet1.xml".to_string(),
+    };
+
+    let mut shared = Vec::new();
+    if let Some(strings) = read_part(zip, "xl/sharedStrings.xml")? {
+        let mut in_text = false;
+        walk_xml(&strings, |event| match event {
+            Event::Start(element) => match element.local_name().as_ref() {
+                b"si" => shared.push(String::new()),
+                b"t" => in_text = true,
+                _ => {}
+            },
+            Event::End(element) if element.local_name().as_ref() == b"t" => in_text = false,
+            Event::Text(text) if in_text => {
+                if let Some(last) = shared.last_mut() {
+                    last.push_str(&text_of(&text));
+                }
+            }
+            _ => {}
+        })?;
+    }
+
+    let sheet_xml = read_part(zip, &path)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{} is missing", path)))?;
+    let mut sheet = Sheet::default();
+    let (mut row, mut column) = (0usize, 0usize);
+    let mut kind = None;
+    let mut value: Option<String> = None;
+    let mut in_value = false;
+    walk_xml(&sheet_xml, |event| match event {
+        Event::Start(element) | Event::Empty(element) if element.local_name().as_ref() == b"row" => {
+            // Rows are numbered from 1; the number is optional.
+            row = attribute(&element, b"r").and_then(|r| r.parse().ok()).unwrap_or(row.saturating_add(1));
+            column = 0;
+        }
+        Event::Start(element) if element.local_name().as_ref() == b"c" => {
+            if let Some(index) = attribute(&element, b"r").as_deref().and_then(column_index) {
+                column = index;
+            }
+            kind = attribute(&element, b"t");
+            value = None;
+        }
+        Event::Start(element) if matches!(element.local_name().as_ref(), b"v" | b"t") => in_value = true,
+        Event::Text(text) if in_value => value.get_or_insert_with(String::new).push_str(&text_of(&text)),
+        Event::End(element) => match element.local_name().as_ref() {
+            b"v" | b"t" => in_value = false,
+            b"c" => {
+                let cell = match (kind.as_deref(), value.take()) {
+                    (Some("s"), Some(index)) => index.trim().parse::<usize>().ok().and_then(|i| shared.get(i).cloned()),
+                    (Some("b"), Some(flag)) => Some(if flag.trim() == "1" { "TRUE" } else { "FALSE" }.to_string()),
+                    (_, cell) => cell,
+                };
+                sheet.set(row.saturating_sub(1), column, cell.unwrap_or_default());
+                column = column.saturating_add(1);
+            }
+            _ => {}
+        },
+        Event::Empty(element) if element.local_name().as_ref() == b"c" => column = column.saturating_add(1),
+        _ => {}
+    })?;
+
+    let title = match read_part(zip, "docProps/core.xml")? {
+        Some(xml) => element_texts(&xml, &["title"])?.remove("title"),
+        None => None,
+    };
+    Ok(sheet.describe(title, name, sheets.len()))
+}
+
+fn ods<R: Read + Seek>(zip: &mut ZipArchive<R>) -> io::Result<String> {
+    let content = read_part(zip, "content.xml")?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "content.xml is missing"))?;
+    let mut sheet = Sheet::default();
+    let mut names = Vec::new();
+    // Only the first table is read; `names` has the names of all of them.
+    let mut table_depth = 0;
+    let (mut row, mut column) = (0usize, 0usize);
+    let (mut row_repeat, mut column_repeat) = (1usize, 1usize);
+    let mut cell: Option<String> = None;
+    let mut paragraphs = 0;
+    walk_xml(&content, |event| {
+        let first = names.len() == 1 && table_depth > 0;
+        match event {
+            Event::Start(element) if element.local_name().as_ref() == b"table" => {
+                names.push(attribute(&element, b"name"));
+                table_depth += 1;
+            }
+            Event::End(element) if element.local_name().as_ref() == b"table" => table_depth -= 1,
+            Event::Start(element) if first && element.local_name().as_ref() == b"table-row" => {
+                row_repeat = repeat(&element, b"number-rows-repeated");
+                column = 0;
+            }
+            Event::Empty(element) if first && element.local_name().as_ref() == b"table-row" => {
+                row = row.saturating_add(repeat(&element, b"number-rows-repeated"));
+            }
+            Event::End(element) if first && element.local_name().as_ref() == b"table-row" => {
+                row = row.saturating_add(row_repeat);
+            }
+            Event::Start(element) if first && matches!(element.local_name().as_ref(), b"table-cell" | b"covered-table-cell") => {
+                column_repeat = repeat(&element, b"number-columns-repeated");
+                cell = Some(String::new());
+                paragraphs = 0;
+            }
+            Event::Empty(element) if first && matches!(element.local_name().as_ref(), b"table-cell" | b"covered-table-cell") => {
+                column = column.saturating_add(repeat(&element, b"number-columns-repeated"));
+            }
+            Event::Start(element) if first && cell.is_some() && element.local_name().as_ref() == b"p" => {
+                if paragraphs > 0 {
+                    if let Some(cell) = cell.as_mut() {
+                        cell.push(' ');
+                    }
+                }
+                paragraphs += 1;
+            }
+            Event::Text(text) if first => {
+                if let Some(cell) = cell.as_mut() {
+                    cell.push_str(&text_of(&text));
+                }
+            }
+            Event::End(element) if first && matches!(element.local_name().as_ref(), b"table-cell" | b"covered-table-cell") => {
+                let value = cell.take().unwrap_or_default();
+                // Repeats are how empty space is stored, and can run into the
+                // thousands; only the visible ones are kept.
+                let rows = row..row.saturating_add(row_repeat);
+                sheet.fill(rows, column..column.saturating_add(column_repeat), value);
+                column = column.saturating_add(column_repeat);
+            }
+            _ => {}
+        }
+    })?;
+
+    let (fields, _) = odf_meta(zip)?;
+    let name = names.first().cloned().flatten();
+    Ok(sheet.describe(fields.get("title").cloned(), name, names.len()))
+}
+
+fn repeat(element: &BytesStart, name: &[u8]) -> usize {
+    attribute(element, name).and_then(|count| count.parse().ok()).unwrap_or(1).max(1)
+}
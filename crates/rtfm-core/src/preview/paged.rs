@@ -0,0 +1,330 @@
+use super::Preview;
+use crate::mime;
+use io::vfs::{Metadata, Vfs, VfsFile};
+use std::fmt;
+use std::future::Future;
+use std::io::{Read, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use tokio::sync::oneshot;
+
+/// How much of the file one page of text holds, give or take a line.
+pub const TEXT_PAGE_SIZE: u64 = 64 * 1024;
+/// Lines are counted in chunks of this size when jumping to one.
+const SCAN_CHUNK: usize = 1024 * 1024;
+/// Bytes kept from the end of a followed file, to tell whether it's text and
+/// whether it has been replaced.
+const FOLLOW_TAIL: u64 = 1024;
+
+/// One page of whole lines from a text file too large to read at once.
+/// Like a hex dump, it's paged through by seeking, so files of any size
+/// take about `TEXT_PAGE_SIZE` bytes of memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextPage {
+    pub path: PathBuf,
+    /// Size of the whole file.
+    pub len: u64,
+    /// Offset of the first line of the page.
+    pub offset: u64,
+    /// Offset just past the last line of the page.
+    pub end: u64,
+    /// The number of the first line, counting from 1, if it's known: pages
+    /// at the start of the file, pages reached from one that is numbered,
+    /// and pages jumped to by line number.
+    pub first_line: Option<u64>,
+    pub text: String,
+    /// Where each line of `text` starts in the file.
+    pub starts: Vec<u64>,
+}
+
+/// Where to jump to in a paged text file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextTarget {
+    Start,
+    End,
+    Offset(u64),
+    /// A line number, counting from 1.
+    Line(u64),
+}
+
+impl TextPage {
+    /// The page of `path` starting with the first line that starts at or
+    /// after `offset`.
+    pub fn read(vfs: &dyn Vfs, path: &Path, len: u64, offset: u64) -> std::io::Result<Self> {
+        let mut file = vfs.open(path)?;
+        Self::read_page(file.as_mut(), path, len, offset)
+    }
+
+    /// Like [`TextPage::read`], and keeps track of line numbers where the
+    /// new page overlaps this one.
+    pub fn seek(&self, vfs: &dyn Vfs, offset: u64) -> std::io::Result<Self> {
+        let mut page = Self::read(vfs, &self.path, self.len, offset)?;
+        self.number_lines(&mut page);
+        Ok(page)
+    }
+
+    /// This page read again from a file that may have changed. A file that
+    /// shrank was likely rewritten, so it's read from the start.
+    pub fn refresh(&self, vfs: &dyn Vfs) -> std::io::Result<Self> {
+        let len = vfs.metadata(&self.path)?.len;
+        if len < self.len {
+            return Self::read(vfs, &self.path, len, 0);
+        }
+        let mut page = Self::read(vfs, &self.path, len, self.offset)?;
+        self.number_lines(&mut page);
+        Ok(page)
+    }
+
+    /// The page with line `line` of the file, counting from 1, and the row
+    /// it's on. Lines are counted from the start of the file, which stops
+    /// with `Interrupted` once `cancelled` is set. Past the last line, the
+    /// last page with its last row.
+    pub fn seek_line(&self, vfs: &dyn Vfs, line: u64, cancelled: &AtomicBool) -> std::io::Result<(Self, usize)> {
+        let Some(offset) = line_offset(vfs, &self.path, line, cancelled)? else {
+            // The last page is read whole, and the view ends with it.
+            return Ok((self.seek(vfs, self.len.saturating_sub(TEXT_PAGE_SIZE))?, usize::MAX));
+        };
+        let mut page = self.seek(vfs, offset.saturating_sub(TEXT_PAGE_SIZE / 2))?;
+        let row = page.row_of(offset);
+        page.first_line = Some(line - row as u64);
+        Ok((page, row))
+    }
+
+    /// Numbers the lines of `page` from this page's, if they overlap.
+    fn number_lines(&self, page: &mut Self) {
+        if page.first_line.is_none() {
+            page.first_line = self.first_line.and_then(|first| match self.starts.binary_search(&page.offset) {
+                Ok(row) => Some(first + row as u64),
+                Err(_) => page.starts.binary_search(&self.offset).ok().and_then(|row| first.checked_sub(row as u64)),
+            });
+        }
+    }
+
+    fn read_page(file: &mut dyn VfsFile, path: &Path, len: u64, offset: u64) -> std::io::Result<Self> {
+        let offset = offset.min(len);
+        // One byte early, to see whether `offset` starts a line.
+        let from = offset.saturating_sub(1);
+        file.seek(SeekFrom::Start(from))?;
+        let mut bytes = Vec::new();
+        // Nothing past `len`, in case the file is growing.
+        file.take((TEXT_PAGE_SIZE + (offset - from)).min(len - from)).read_to_end(&mut bytes)?;
+
+        let mut start = ((offset - from) as usize).min(bytes.len());
+        if offset > 0 && bytes.first() != Some(&b'\n') {
+            // Skip the end of a line cut in two, unless it fills the page.
+            if let Some(newline) = bytes[start..].iter().position(|&byte| byte == b'\n') {
+                start += newline + 1;
+            }
+        }
+        let mut end = bytes.len();
+        if from + (bytes.len() as u64) < len {
+            // The last line is cut off too; it's read whole with the next page.
+            if let Some(newline) = bytes[start..].iter().rposition(|&byte| byte == b'\n') {
+                end = start + newline + 1;
+            }
+        }
+
+        let page = &bytes[start..end];
+        let page_offset = from + start as u64;
+        let mut starts = Vec::new();
+        if !page.is_empty() {
+            starts.push(page_offset);
+        }
+        starts.extend(
+            page.iter()
+                .enumerate()
+                .filter(|&(i, &byte)| byte == b'\n' && i + 1 < page.len())
+                .map(|(i, _)| page_offset + i as u64 + 1),
+        );
+        let text = String::from_utf8_lossy(page).lines().collect::<Vec<_>>().join("\n");
+        Ok(Self {
+            path: path.to_path_buf(),
+            len,
+            offset: page_offset,
+            end: from + end as u64,
+            first_line: (page_offset == 0).then_some(1),
+            text,
+            starts,
+        })
+    }
+
+    /// Number of lines in this page.
+    pub fn rows(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// The row of the line that `offset` is in.
+    pub fn row_of(&self, offset: u64) -> usize {
+        match self.starts.binary_search(&offset) {
+            Ok(row) => row,
+            Err(row) => row.saturating_sub(1),
+        }
+    }
+
+    /// One-line description for the pane title with `row` at the top, e.g.
+    /// `line 1200, 37% of 3.2 GiB`.
+    pub fn header(&self, row: usize) -> String {
+        let offset = self.starts.get(row).copied().unwrap_or(self.offset);
+        let percent = (offset * 100).checked_div(self.len).unwrap_or(100);
+        let size = humansize::format_size(self.len, humansize::BINARY);
+        match self.first_line {
+            Some(first) => format!("line {}, {}% of {}", first + row as u64, percent, size),
+            None => format!("{}% of {}", percent, size),
+        }
+    }
+}
+
+/// A text file whose end is kept in view as it grows, like `tail -f`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Follow {
+    pub path: PathBuf,
+    len: u64,
+    modified: Option<SystemTime>,
+    /// The last bytes of the file when it was `len` long.
+    tail: Vec<u8>,
+}
+
+impl Follow {
+    /// Starts following `path` and reads its last page, numbering its lines
+    /// from `page` where they overlap. `None` if it isn't a text file.
+    pub fn start(vfs: &dyn Vfs, path: &Path, page: Option<&TextPage>) -> std::io::Result<Option<(Self, TextPage)>> {
+        let metadata = vfs.metadata(path)?;
+        if metadata.is_dir || metadata.special.is_some() {
+            return Ok(None);
+        }
+        let mut follow = Self { path: path.to_path_buf(), len: 0, modified: None, tail: Vec::new() };
+        let last = follow.read_last(vfs, &metadata, page)?;
+        // The tail can start in the middle of a character.
+        let start = follow.tail.iter().take(3).take_while(|&&byte| byte & 0xc0 == 0x80).count();
+        Ok((!mime::looks_binary(&follow.tail[start..])).then_some((follow, last)))
+    }
+
+    /// The last page of the file if it changed since last time. Lines
+    /// appended to `page` keep their numbers; a file that was truncated, or
+    /// replaced as logs are when they're rotated, is read afresh.
+    pub fn poll(&mut self, vfs: &dyn Vfs, page: Option<&TextPage>) -> std::io::Result<Option<TextPage>> {
+        let metadata = vfs.metadata(&self.path)?;
+        if (metadata.len, metadata.modified) == (self.len, self.modified) {
+            return Ok(None);
+        }
+        self.read_last(vfs, &metadata, page).map(Some)
+    }
+
+    fn read_last(&mut self, vfs: &dyn Vfs, metadata: &Metadata, page: Option<&TextPage>) -> std::io::Result<TextPage> {
+        // A file that still has the bytes it ended with has only grown.
+        let replaced = metadata.len < self.len || read_range(vfs, &self.path, self.len - self.tail.len() as u64, self.len)? != self.tail;
+        let mut last = TextPage::read(vfs, &self.path, metadata.len, metadata.len.saturating_sub(TEXT_PAGE_SIZE))?;
+        if let Some(page) = page.filter(|page| !replaced && page.path == self.path) {
+            page.number_lines(&mut last);
+        }
+        self.len = metadata.len;
+        self.modified = metadata.modified;
+        self.tail = read_range(vfs, &self.path, metadata.len.saturating_sub(FOLLOW_TAIL), metadata.len)?;
+        Ok(last)
+    }
+}
+
+fn read_range(vfs: &dyn Vfs, path: &Path, start: u64, end: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = vfs.open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.take(end - start).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The offset where line `line` of `path` starts, counting from 1, found by
+/// counting the lines before it. `None` if the file has fewer lines.
+/// Stops with `Interrupted` once `cancelled` is set.
+pub fn line_offset(vfs: &dyn Vfs, path: &Path, line: u64, cancelled: &AtomicBool) -> std::io::Result<Option<u64>> {
+    if line <= 1 {
+        return Ok(Some(0));
+    }
+    let mut file = vfs.open(path)?;
+    let mut chunk = vec![0; SCAN_CHUNK];
+    let (mut offset, mut newlines) = (0, 0);
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "cancelled"));
+        }
+        let n = match file.read(&mut chunk) {
+            Ok(0) => return Ok(None),
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for (i, _) in chunk[..n].iter().enumerate().filter(|&(_, &byte)| byte == b'\n') {
+            newlines += 1;
+            if newlines == line - 1 {
+                return Ok(Some(offset + i as u64 + 1));
+            }
+        }
+        offset += n as u64;
+    }
+}
+
+/// Another page of a paged text or hex preview, and the row to show on top.
+pub type PageRead = std::io::Result<(Preview, i64)>;
+
+/// A followed file after [`Follow::poll`], with the last page if it changed.
+pub type FollowPoll = (Follow, std::io::Result<Option<TextPage>>);
+
+/// Work that's too slow for the UI thread, like counting lines in a large
+/// text file or connecting to a remote host, done on the blocking thread pool.
+///
+/// Dropping the job cancels it: the work is told to stop, and its result is
+/// thrown away.
+pub struct PageJob<T> {
+    cancelled: Arc<AtomicBool>,
+    result: oneshot::Receiver<T>,
+}
+
+impl<T> fmt::Debug for PageJob<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PageJob").finish_non_exhaustive()
+    }
+}
+
+impl<T> Drop for PageJob<T> {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl<T: Send + 'static> PageJob<T> {
+    /// Starts `job`, which should check the flag it's given and stop early
+    /// once it's set. Needs a tokio runtime.
+    pub fn start(job: impl FnOnce(&AtomicBool) -> T + Send + 'static) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = oneshot::channel();
+        let job_cancelled = cancelled.clone();
+        tokio::task::spawn_blocking(move || {
+            if !job_cancelled.load(Ordering::Relaxed) {
+                let _ = tx.send(job(&job_cancelled));
+            }
+        });
+        Self { cancelled, result: rx }
+    }
+
+    /// Polls for the result; `Ready(None)` if the job died.
+    pub fn poll_result(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        Pin::new(&mut self.result).poll(cx).map(Result::ok)
+    }
+}
+
+/// Parses where to jump to in a text file: a line number, a percentage of
+/// the file such as `50%`, `^` for the start or `$` for the end.
+pub fn parse_text_target(input: &str, len: u64) -> Option<TextTarget> {
+    match input.trim() {
+        "^" => Some(TextTarget::Start),
+        "$" => Some(TextTarget::End),
+        input if input.ends_with('%') => {
+            let percent: f64 = input.trim_end_matches('%').trim().parse().ok().filter(|p| (0.0..=100.0).contains(p))?;
+            Some(TextTarget::Offset((len as f64 * percent / 100.0) as u64))
+        }
+        input => input.parse().ok().filter(|&line| line > 0).map(TextTarget::Line),
+    }
+}
@@ -0,0 +1,178 @@
+use super::registry::{FilePreviewer, PreviewRequest};
+use super::{external, read_up_to, ImagePreview, Preview, MAX_PREVIEW_SIZE};
+use ::image::ImageFormat;
+use config::Resolution;
+use humansize::{format_size, BINARY};
+use io::vfs::VfsFile;
+use lopdf::{decode_text_string, Dictionary, Document};
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+/// PDFs are parsed in memory.
+const MAX_PDF_SIZE: u64 = 64 * 1024 * 1024;
+/// Text is extracted from this many pages at most.
+const TEXT_PAGES: u32 = 3;
+/// Document information fields shown, in order, with their labels.
+const INFO_FIELDS: &[(&[u8], &str)] = &[
+    (b"Title", "Title"),
+    (b"Author", "Author"),
+    (b"Subject", "Subject"),
+    (b"Creator", "Creator"),
+    (b"Producer", "Producer"),
+    (b"CreationDate", "Created"),
+    (b"ModDate", "Modified"),
+];
+
+/// The document information and the text of the first pages, plus a picture
+/// of the first page if enabled and `pdftoppm` is installed.
+#[derive(Debug)]
+pub struct PdfPreviewer {
+    raster: bool,
+    resolution: Resolution,
+    /// How long `pdftoppm` may take, like an external previewer.
+    timeout: Duration,
+}
+
+impl PdfPreviewer {
+    pub fn new(raster: bool, resolution: Resolution, timeout: Duration) -> Self {
+        Self { raster, resolution, timeout }
+    }
+
+    /// Renders the first page with poppler's `pdftoppm`. `None` if it isn't
+    /// installed, fails on this file, takes too long or is cancelled.
+    fn render_first_page(&self, bytes: &[u8], cancelled: &AtomicBool) -> Option<ImagePreview> {
+        let scale = self.resolution.width.max(self.resolution.height).to_string();
+        let mut child = match Command::new("pdftoppm")
+            .args(["-png", "-f", "1", "-l", "1", "-singlefile", "-scale-to", &scale, "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) if e.kind() == ErrorKind::NotFound => return None,
+            Err(e) => {
+                log::warn!("Failed to run pdftoppm: {}", e);
+                return None;
+            }
+        };
+        // Written from another thread so a full stdout pipe can't deadlock us.
+        let mut stdin = child.stdin.take()?;
+        let input = bytes.to_vec();
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+        let output = external::wait_for_output(child, "pdftoppm", MAX_PDF_SIZE, self.timeout, cancelled);
+        // Finishes once pdftoppm has read everything or is gone.
+        let _ = writer.join();
+        let (status, output) = output?;
+        if !status.success() {
+            log::warn!("pdftoppm failed with {}", status);
+            return None;
+        }
+        ImagePreview::decode(&output, ImageFormat::Png, &self.resolution).ok()
+    }
+}
+
+impl FilePreviewer for PdfPreviewer {
+    fn name(&self) -> &'static str {
+        "pdf"
+    }
+
+    fn handles(&self, mime: &str) -> bool {
+        mime == "application/pdf"
+    }
+
+    fn preview(&self, request: &PreviewRequest, file: &mut dyn VfsFile) -> Option<Preview> {
+        if request.metadata.len > MAX_PDF_SIZE {
+            return Some(Preview::Text(format!(
+                "PDF is too large to preview ({})",
+                format_size(request.metadata.len, BINARY)
+            )));
+        }
+        let mut buffer = request.head.to_vec();
+        if let Err(e) = read_up_to(file, &mut buffer, MAX_PDF_SIZE, request.cancelled)? {
+            return Some(Preview::Text(format!("Error reading file:\n{}", e)));
+        }
+        let mut document = match Document::load_mem(&buffer) {
+            Ok(document) => document,
+            Err(e) => return Some(Preview::Text(format!("Error reading PDF:\n{}", e))),
+        };
+        if request.is_cancelled() {
+            return None;
+        }
+
+        // Most encrypted PDFs only restrict editing and open with an empty password.
+        let locked = document.is_encrypted() && document.decrypt("").is_err();
+        let mut text = describe(&document, locked);
+        if !locked {
+            let pages = document.get_pages().len() as u32;
+            for page in 1..=pages.min(TEXT_PAGES) {
+                if request.is_cancelled() {
+                    return None;
+                }
+                text.push_str(&format!("\n\n── Page {} ──\n", page));
+                match document.extract_text(&[page]) {
+                    Ok(page_text) => text.push_str(page_text.trim_end()),
+                    Err(e) => text.push_str(&format!("(no text: {})", e)),
+                }
+                if text.len() as u64 > MAX_PREVIEW_SIZE {
+                    break;
+                }
+            }
+        }
+
+        let image = if self.raster && !locked { self.render_first_page(&buffer, request.cancelled) } else { None };
+        if request.is_cancelled() {
+            return None;
+        }
+        Some(Preview::Document { text, image })
+    }
+}
+
+/// The header of the preview: document information, page count and version.
+fn describe(document: &Document, locked: bool) -> String {
+    let info = document
+        .trailer
+        .get_deref(b"Info", document)
+        .and_then(|info| info.as_dict())
+        .ok()
+        .filter(|_| !locked);
+    let mut lines = Vec::new();
+    for (key, label) in INFO_FIELDS {
+        if let Some(value) = info.and_then(|info| info_string(document, info, key)) {
+            let value = if key.ends_with(b"Date") { format_date(&value) } else { value };
+            lines.push(format!("{:<9} {}", format!("{}:", label), value));
+        }
+    }
+    lines.push(format!("{:<9} {}", "Pages:", document.get_pages().len()));
+    lines.push(format!("{:<9} {}", "Version:", document.version));
+    if locked {
+        lines.push("Encrypted: a password is needed to read the text.".to_string());
+    }
+    lines.join("\n")
+}
+
+fn info_string(document: &Document, info: &Dictionary, key: &[u8]) -> Option<String> {
+    let value = decode_text_string(info.get_deref(key, document).ok()?).ok()?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Turns a PDF date like `D:20240131154500+01'00'` into `2024-01-31 15:45:00`.
+/// Anything that doesn't look like one is returned as is.
+fn format_date(date: &str) -> String {
+    let digits = date.strip_prefix("D:").unwrap_or(date);
+    let digits: String = digits.chars().take_while(|c| c.is_ascii_digit()).collect();
+    if digits.len() < 8 {
+        return date.to_string();
+    }
+    let mut formatted = format!("{}-{}-{}", &digits[0..4], &digits[4..6], &digits[6..8]);
+    if digits.len() >= 12 {
+        formatted.push_str(&format!(" {}:{}", &digits[8..10], &digits[10..12]));
+        if digits.len() >= 14 {
+            formatted.push_str(&format!(":{}", &digits[12..14]));
+        }
+    }
+    formatted
+}
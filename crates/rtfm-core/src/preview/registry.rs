@@ -0,0 +1,64 @@
+use super::Preview;
+use io::vfs::{Metadata, Vfs, VfsFile};
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// What a previewer gets to work with.
+pub struct PreviewRequest<'a> {
+    pub vfs: &'a dyn Vfs,
+    pub path: &'a Path,
+    pub metadata: &'a Metadata,
+    /// The MIME type detected from `head` and the file name.
+    pub mime: &'static str,
+    /// The start of the file, already read to detect its type.
+    pub head: &'a [u8],
+    pub cancelled: &'a AtomicBool,
+}
+
+impl PreviewRequest<'_> {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Renders the preview of files of some MIME types.
+pub trait FilePreviewer: Send + Sync {
+    /// Identifies the previewer in logs.
+    fn name(&self) -> &'static str;
+
+    fn handles(&self, mime: &str) -> bool;
+
+    /// Builds the preview. `file` is positioned right after `request.head`.
+    /// Errors are shown as text; `None` means the request was cancelled.
+    fn preview(&self, request: &PreviewRequest, file: &mut dyn VfsFile) -> Option<Preview>;
+}
+
+/// The previewers to choose from, by MIME type. The one registered last wins
+/// when several handle the same type, so a catch-all goes first.
+#[derive(Default)]
+pub struct PreviewerRegistry {
+    previewers: Vec<Box<dyn FilePreviewer>>,
+}
+
+impl fmt::Debug for PreviewerRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.previewers.iter().map(|previewer| previewer.name())).finish()
+    }
+}
+
+impl PreviewerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `previewer`, taking precedence over the ones registered before.
+    pub fn register(&mut self, previewer: Box<dyn FilePreviewer>) {
+        self.previewers.push(previewer);
+    }
+
+    /// The previewer for files of type `mime`.
+    pub fn find(&self, mime: &str) -> Option<&dyn FilePreviewer> {
+        self.previewers.iter().rev().find(|previewer| previewer.handles(mime)).map(|previewer| previewer.as_ref())
+    }
+}
@@ -0,0 +1,379 @@
+use super::office::format_table;
+use super::registry::{FilePreviewer, PreviewRequest};
+use super::text::read_text;
+use super::{Preview, SpanStyle, StyledLine, StyledSpan};
+use ::io::vfs::VfsFile;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+
+/// Tables are laid out whole to line up their columns, so long CSV files
+/// stop after this many rows.
+const MAX_TABLE_ROWS: usize = 1000;
+const ERROR_COLOR: (u8, u8, u8) = (0xe0, 0x6c, 0x75);
+
+/// Data files shown by their structure instead of as text: JSON, TOML and
+/// YAML as a tree that can be folded, CSV and TSV as a table. A file that
+/// doesn't parse is shown as text with the error marked.
+#[derive(Debug, Default)]
+pub struct StructuredPreviewer;
+
+impl FilePreviewer for StructuredPreviewer {
+    fn name(&self) -> &'static str {
+        "structured"
+    }
+
+    fn handles(&self, mime: &str) -> bool {
+        matches!(
+            mime,
+            "application/json" | "application/toml" | "application/yaml" | "text/csv" | "text/tab-separated-values"
+        )
+    }
+
+    fn preview(&self, request: &PreviewRequest, file: &mut dyn VfsFile) -> Option<Preview> {
+        let text = match read_text(request, file)? {
+            Ok(text) => text,
+            Err(preview) => return Some(preview),
+        };
+        let preview = match request.mime {
+            "text/csv" => table("CSV", &text, b','),
+            "text/tab-separated-values" => table("TSV", &text, b'\t'),
+            "application/toml" => parse_toml(&text).map(|root| Preview::Tree(DataTree::new("TOML", root))),
+            "application/yaml" => parse_yaml(&text).map(|root| Preview::Tree(DataTree::new("YAML", root))),
+            _ => parse_json(&text).map(|root| Preview::Tree(DataTree::new("JSON", root))),
+        };
+        Some(preview.unwrap_or_else(|error| error.preview(&text)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Object,
+    Array,
+    String,
+    Number,
+    Bool,
+    Null,
+    Date,
+}
+
+/// A member of an object or an element of an array, on a row of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeNode {
+    pub depth: usize,
+    /// The member's name, or `[i]` for an array element. Empty if the whole
+    /// document is a single value.
+    pub key: String,
+    pub kind: ValueKind,
+    /// Scalars as JSON would write them; containers as `{n}` or `[n]`.
+    pub value: String,
+    /// Where the node is in the document, e.g. `.servers[0].host`.
+    pub path: String,
+    /// The index after the node's last descendant.
+    pub end: usize,
+}
+
+/// A JSON, TOML or YAML document as a tree of rows whose containers can be
+/// folded. The top-level members are the roots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataTree {
+    /// `JSON`, `TOML` or `YAML`.
+    pub format: &'static str,
+    /// Parents come before their children, in document order.
+    pub nodes: Vec<TreeNode>,
+    /// Every row with nothing folded.
+    pub text: String,
+}
+
+impl DataTree {
+    fn new(format: &'static str, root: Value) -> Self {
+        let mut nodes = Vec::new();
+        let empty = match &root {
+            Value::Map(members) => members.is_empty(),
+            Value::List(items) => items.is_empty(),
+            Value::Scalar(..) => true,
+        };
+        match empty {
+            true => add_node(&mut nodes, 0, String::new(), ".".to_string(), root),
+            false => add_children(&mut nodes, 0, "", root),
+        }
+        let mut tree = Self { format, nodes, text: String::new() };
+        let folded = BTreeSet::new();
+        tree.text = tree.rows(&folded).map(|index| tree.line(index, false)).collect::<Vec<_>>().join("\n");
+        tree
+    }
+
+    /// Whether the node has children to fold.
+    pub fn is_foldable(&self, index: usize) -> bool {
+        self.nodes[index].end > index + 1
+    }
+
+    /// The nodes that are shown while the `folded` ones hide their children.
+    pub fn rows<'a>(&'a self, folded: &'a BTreeSet<usize>) -> impl Iterator<Item = usize> + 'a {
+        let mut index = 0;
+        std::iter::from_fn(move || {
+            let node = self.nodes.get(index)?;
+            let row = index;
+            index = if folded.contains(&row) { node.end } else { row + 1 };
+            Some(row)
+        })
+    }
+
+    /// The indentation and fold marker in front of a node's key.
+    pub fn prefix(&self, index: usize, folded: bool) -> String {
+        let marker = match (self.is_foldable(index), folded) {
+            (false, _) => "  ",
+            (true, false) => "▾ ",
+            (true, true) => "▸ ",
+        };
+        format!("{}{}", "  ".repeat(self.nodes[index].depth), marker)
+    }
+
+    /// A node's row as text, e.g. `  ▾ servers: [2]`.
+    pub fn line(&self, index: usize, folded: bool) -> String {
+        let node = &self.nodes[index];
+        match node.key.is_empty() {
+            true => format!("{}{}", self.prefix(index, folded), node.value),
+            false => format!("{}{}: {}", self.prefix(index, folded), node.key, node.value),
+        }
+    }
+}
+
+/// A parsed document, whatever its format.
+enum Value {
+    Map(Vec<(String, Value)>),
+    List(Vec<Value>),
+    Scalar(ValueKind, String),
+}
+
+fn add_node(nodes: &mut Vec<TreeNode>, depth: usize, key: String, path: String, value: Value) {
+    let index = nodes.len();
+    let (kind, summary) = match &value {
+        Value::Map(members) => (ValueKind::Object, format!("{{{}}}", members.len())),
+        Value::List(items) => (ValueKind::Array, format!("[{}]", items.len())),
+        Value::Scalar(kind, text) => (*kind, text.clone()),
+    };
+    nodes.push(TreeNode { depth, key, kind, value: summary, path: path.clone(), end: 0 });
+    add_children(nodes, depth + 1, &path, value);
+    nodes[index].end = nodes.len();
+}
+
+fn add_children(nodes: &mut Vec<TreeNode>, depth: usize, path: &str, value: Value) {
+    match value {
+        Value::Map(members) => {
+            for (key, value) in members {
+                let path = format!("{}{}", path, member_path(&key));
+                add_node(nodes, depth, key, path, value);
+            }
+        }
+        Value::List(items) => {
+            for (i, value) in items.into_iter().enumerate() {
+                add_node(nodes, depth, format!("[{}]", i), format!("{}[{}]", path, i), value);
+            }
+        }
+        Value::Scalar(..) => {}
+    }
+}
+
+/// `.name` for keys that read like identifiers, `["odd key"]` for the rest.
+fn member_path(key: &str) -> String {
+    let plain = key.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    match plain {
+        true => format!(".{}", key),
+        false => format!("[{}]", quote(key)),
+    }
+}
+
+fn quote(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_default()
+}
+
+fn parse_json(text: &str) -> Result<Value, ParseError> {
+    fn convert(value: serde_json::Value) -> Value {
+        match value {
+            serde_json::Value::Object(members) => Value::Map(members.into_iter().map(|(k, v)| (k, convert(v))).collect()),
+            serde_json::Value::Array(items) => Value::List(items.into_iter().map(convert).collect()),
+            serde_json::Value::String(s) => Value::Scalar(ValueKind::String, quote(&s)),
+            serde_json::Value::Number(n) => Value::Scalar(ValueKind::Number, n.to_string()),
+            serde_json::Value::Bool(b) => Value::Scalar(ValueKind::Bool, b.to_string()),
+            serde_json::Value::Null => Value::Scalar(ValueKind::Null, "null".to_string()),
+        }
+    }
+    serde_json::from_str(text).map(convert).map_err(|e| {
+        // The column counts bytes from 1, and points at the offending one.
+        let line = e.line().max(1);
+        let line_text = text.lines().nth(line - 1).unwrap_or_default();
+        let mut byte = e.column().saturating_sub(1).min(line_text.len());
+        while !line_text.is_char_boundary(byte) {
+            byte -= 1;
+        }
+        ParseError::new("JSON", &e.to_string(), line, line_text[..byte].chars().count())
+    })
+}
+
+fn parse_toml(text: &str) -> Result<Value, ParseError> {
+    fn convert(value: toml::Value) -> Value {
+        match value {
+            toml::Value::Table(members) => Value::Map(members.into_iter().map(|(k, v)| (k, convert(v))).collect()),
+            toml::Value::Array(items) => Value::List(items.into_iter().map(convert).collect()),
+            toml::Value::String(s) => Value::Scalar(ValueKind::String, quote(&s)),
+            toml::Value::Integer(i) => Value::Scalar(ValueKind::Number, i.to_string()),
+            toml::Value::Float(f) => Value::Scalar(ValueKind::Number, format!("{:?}", f)),
+            toml::Value::Boolean(b) => Value::Scalar(ValueKind::Bool, b.to_string()),
+            toml::Value::Datetime(date) => Value::Scalar(ValueKind::Date, date.to_string()),
+        }
+    }
+    match toml::from_str::<toml::Table>(text) {
+        Ok(table) => Ok(convert(toml::Value::Table(table))),
+        Err(e) => {
+            let start = e.span().map_or(0, |span| span.start).min(text.len());
+            let before = &text[..start];
+            let line = before.matches('\n').count() + 1;
+            let column = before[before.rfind('\n').map_or(0, |at| at + 1)..].chars().count();
+            Err(ParseError::new("TOML", e.message(), line, column))
+        }
+    }
+}
+
+/// A stream of several documents becomes a list of them.
+fn parse_yaml(text: &str) -> Result<Value, ParseError> {
+    fn scalar(value: &serde_yaml::Value) -> Option<(ValueKind, String)> {
+        match value {
+            serde_yaml::Value::String(s) => Some((ValueKind::String, quote(s))),
+            serde_yaml::Value::Number(n) => Some((ValueKind::Number, n.to_string())),
+            serde_yaml::Value::Bool(b) => Some((ValueKind::Bool, b.to_string())),
+            serde_yaml::Value::Null => Some((ValueKind::Null, "null".to_string())),
+            _ => None,
+        }
+    }
+    fn convert(value: serde_yaml::Value) -> Value {
+        if let Some((kind, text)) = scalar(&value) {
+            return Value::Scalar(kind, text);
+        }
+        match value {
+            serde_yaml::Value::Mapping(members) => Value::Map(
+                members
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let key = match key {
+                            serde_yaml::Value::String(key) => key,
+                            key => scalar(&key).map_or_else(|| "?".to_string(), |(_, text)| text),
+                        };
+                        (key, convert(value))
+                    })
+                    .collect(),
+            ),
+            serde_yaml::Value::Sequence(items) => Value::List(items.into_iter().map(convert).collect()),
+            serde_yaml::Value::Tagged(tagged) => convert(tagged.value),
+            _ => unreachable!("scalars are converted above"),
+        }
+    }
+    let mut documents = Vec::new();
+    for document in serde_yaml::Deserializer::from_str(text) {
+        let value = serde_yaml::Value::deserialize(document).map_err(|e| {
+            let (line, column) = e.location().map_or((1, 0), |at| (at.line(), at.column().saturating_sub(1)));
+            ParseError::new("YAML", &e.to_string(), line, column)
+        })?;
+        documents.push(convert(value));
+    }
+    Ok(match documents.len() {
+        0 => Value::Scalar(ValueKind::Null, "null".to_string()),
+        1 => documents.pop().unwrap(),
+        _ => Value::List(documents),
+    })
+}
+
+/// CSV or TSV as a table with the first row as its header, set off in bold
+/// above a rule.
+fn table(format: &'static str, text: &str, delimiter: u8) -> Result<Preview, ParseError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        // Quotes are just text in TSV.
+        .quoting(delimiter != b'\t')
+        .from_reader(text.as_bytes());
+    let mut rows = Vec::new();
+    let mut more = 0;
+    for record in reader.records() {
+        let record = record.map_err(|e| {
+            let line = e.position().map_or(1, |position| position.line() as usize);
+            ParseError::new(format, &e.to_string(), line, 0)
+        })?;
+        if rows.len() > MAX_TABLE_ROWS {
+            more += 1;
+        } else {
+            rows.push(record.iter().map(str::to_string).collect::<Vec<_>>());
+        }
+    }
+
+    let mut lines = header_table(&rows);
+    if more > 0 {
+        lines.push(format!("… {} more rows", more));
+    }
+    let header = SpanStyle { bold: true, ..SpanStyle::default() };
+    let styled = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| vec![span(line, if i == 0 { header } else { SpanStyle::default() })])
+        .collect();
+    Ok(Preview::Highlighted { text: lines.join("\n"), lines: styled })
+}
+
+/// The lines of a table whose first row is a header, with a rule under it.
+pub(super) fn header_table(rows: &[Vec<String>]) -> Vec<String> {
+    let mut lines: Vec<String> = format_table(rows).lines().map(str::to_string).collect();
+    if lines.len() > 1 {
+        // Under the widest row, the rule crosses every column separator.
+        let widest = lines.iter().max_by_key(|line| line.chars().count()).cloned().unwrap_or_default();
+        let rule = widest.chars().map(|c| if c == '│' { '┼' } else { '─' }).collect();
+        lines.insert(1, rule);
+    }
+    lines
+}
+
+fn span(text: &str, style: SpanStyle) -> StyledSpan {
+    StyledSpan { text: text.to_string(), style }
+}
+
+/// Where a file stopped parsing, with the parser's message.
+struct ParseError {
+    format: &'static str,
+    message: String,
+    line: usize,
+    /// In characters from the start of the line, counting from 0.
+    column: usize,
+}
+
+impl ParseError {
+    fn new(format: &'static str, message: &str, line: usize, column: usize) -> Self {
+        // The position is shown separately, so it's cut from the message.
+        let message = message.split(" at line ").next().unwrap_or(message).trim().to_string();
+        Self { format, message, line, column }
+    }
+
+    /// The raw text under the error message, with the rest of the line from
+    /// where the error is underlined.
+    fn preview(&self, text: &str) -> Preview {
+        let heading = format!("{} error at line {}, column {}: {}", self.format, self.line, self.column + 1, self.message);
+        let error = SpanStyle { fg: Some(ERROR_COLOR), bold: true, ..SpanStyle::default() };
+        let mut lines: Vec<StyledLine> = vec![vec![span(&heading, error)], Vec::new()];
+        for (number, line) in text.lines().enumerate() {
+            if number + 1 != self.line {
+                lines.push(vec![span(line, SpanStyle::default())]);
+                continue;
+            }
+            let at = line.char_indices().nth(self.column).map_or(line.len(), |(at, _)| at);
+            let (before, after) = line.split_at(at);
+            lines.push(match after.is_empty() {
+                true => vec![span(before, SpanStyle::default()), span("◀", error)],
+                false => vec![span(before, SpanStyle::default()), span(after, SpanStyle { underline: true, ..error })],
+            });
+        }
+        // Errors at the end of the file can be on the line after the last one.
+        if self.line > text.lines().count() {
+            lines.push(vec![span("◀", error)]);
+        }
+        Preview::Highlighted { text: format!("{}\n\n{}", heading, text), lines }
+    }
+}
@@ -0,0 +1,67 @@
+use super::registry::{FilePreviewer, PreviewRequest};
+use super::{read_up_to, HexDump, Highlighter, Preview, TextPage, MAX_PREVIEW_SIZE};
+use crate::mime;
+use io::vfs::VfsFile;
+
+/// Text files and source code, highlighted where the syntax is known.
+#[derive(Debug)]
+pub struct TextPreviewer {
+    highlighter: Highlighter,
+}
+
+impl TextPreviewer {
+    pub fn new(highlighter: Highlighter) -> Self {
+        Self { highlighter }
+    }
+}
+
+impl FilePreviewer for TextPreviewer {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn handles(&self, mime: &str) -> bool {
+        mime::is_textual(mime)
+    }
+
+    fn preview(&self, request: &PreviewRequest, file: &mut dyn VfsFile) -> Option<Preview> {
+        let text = match read_text(request, file)? {
+            Ok(text) => text,
+            Err(preview) => return Some(preview),
+        };
+        match self.highlighter.highlight(request.path, &text, request.cancelled) {
+            Some(lines) => Some(Preview::Highlighted { text, lines }),
+            None if request.is_cancelled() => None,
+            None => Some(Preview::Text(text)),
+        }
+    }
+}
+
+/// Reads a text file whole. Files that are too large or turn out not to be
+/// text get the preview to show instead; `None` if cancelled.
+pub(super) fn read_text(request: &PreviewRequest, file: &mut dyn VfsFile) -> Option<Result<String, Preview>> {
+    // The name can promise text when the content isn't; that gets a hex
+    // dump like any other binary file.
+    if request.metadata.len > MAX_PREVIEW_SIZE {
+        if mime::looks_binary(request.head) {
+            return Some(Err(match HexDump::read(request.vfs, request.path, request.metadata.len, 0) {
+                Ok(dump) => Preview::Hex(dump),
+                Err(e) => Preview::Text(format!("Error reading file:\n{}", e)),
+            }));
+        }
+        return Some(Err(match TextPage::read(request.vfs, request.path, request.metadata.len, 0) {
+            Ok(page) => Preview::Paged(page),
+            Err(e) => Preview::Text(format!("Error reading file:\n{}", e)),
+        }));
+    }
+
+    let mut buffer = request.head.to_vec();
+    if let Err(e) = read_up_to(file, &mut buffer, MAX_PREVIEW_SIZE, request.cancelled)? {
+        return Some(Err(Preview::Text(format!("Error reading file:\n{}", e))));
+    }
+    Some(match String::from_utf8(buffer) {
+        Ok(text) if !text.contains('\0') => Ok(text),
+        Ok(text) => Err(Preview::Hex(HexDump::from_bytes(request.path, text.as_bytes()))),
+        Err(e) => Err(Preview::Hex(HexDump::from_bytes(request.path, e.as_bytes()))),
+    })
+}
@@ -3,6 +3,7 @@ use uuid::Uuid;
 use std::path::PathBuf;
 use tokio::sync::mpsc;
 use io::fs_ops;
+use io::vfs::{self, Vfs};
 
 #[derive(Debug, Clone)]
 pub enum TaskKind {
@@ -11,6 +12,10 @@ pub enum TaskKind {
     Delete { path: PathBuf },
     CreateFile { path: PathBuf },
     CreateDirectory { path: PathBuf },
+    /// Extracts `archive` into `parent`, or a folder in it named after the
+    /// archive. Stops with a conflict if that exists, unless `overwrite`.
+    Extract { archive: PathBuf, parent: PathBuf, overwrite: bool },
+    ExtractEntry { archive: PathBuf, entry: PathBuf, dest: PathBuf },
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -44,8 +49,12 @@ use std::fmt;
 
 pub struct TaskManager {
     tasks: Arc<Mutex<Vec<Task>>>,
+    vfs: Arc<dyn Vfs>,
     progress_rx: mpsc::Receiver<(Uuid, fs_ops::ProgressEvent)>,
     progress_tx: mpsc::Sender<(Uuid, fs_ops::ProgressEvent)>,
+    /// Tasks that stopped because their destination exists, with what's in
+    /// the way, until they're asked about.
+    conflicts: Vec<(TaskKind, PathBuf)>,
 }
 
 impl fmt::Debug for TaskManager {
@@ -57,12 +66,14 @@ impl fmt::Debug for TaskManager {
 }
 
 impl TaskManager {
-    pub fn new() -> Self {
+    pub fn new(vfs: Arc<dyn Vfs>) -> Self {
         let (tx, rx) = mpsc::channel(100);
         Self {
             tasks: Arc::new(Mutex::new(Vec::new())),
+            vfs,
             progress_rx: rx,
             progress_tx: tx,
+            conflicts: Vec::new(),
         }
     }
 
@@ -84,23 +95,30 @@ impl TaskManager {
                 let task_id = task.id;
                 let kind = task.kind.clone();
                 let progress_tx = self.progress_tx.clone();
+                let vfs = self.vfs.clone();
 
                 tokio::spawn(async move {
                     match kind {
                         TaskKind::Copy { src, dest } => {
-                            fs_ops::copy_file_task(task_id, src, dest, progress_tx).await;
+                            fs_ops::copy_file_task(task_id, vfs, src, dest, progress_tx).await;
                         }
                         TaskKind::Move { src, dest } => {
-                            fs_ops::move_item_task(task_id, src, dest, progress_tx).await;
+                            fs_ops::move_item_task(task_id, vfs, src, dest, progress_tx).await;
                         }
                         TaskKind::Delete { path } => {
-                            fs_ops::delete_item_task(task_id, path, progress_tx).await;
+                            fs_ops::delete_item_task(task_id, vfs, path, progress_tx).await;
                         }
                         TaskKind::CreateFile { path } => {
-                            fs_ops::create_file_task(task_id, path, progress_tx).await;
+                            fs_ops::create_file_task(task_id, vfs, path, progress_tx).await;
                         }
                         TaskKind::CreateDirectory { path } => {
-                            fs_ops::create_directory_task(task_id, path, progress_tx).await;
+                            fs_ops::create_directory_task(task_id, vfs, path, progress_tx).await;
+                        }
+                        TaskKind::Extract { archive, parent, overwrite } => {
+                            fs_ops::extract_archive_task(task_id, archive, parent, overwrite, progress_tx).await;
+                        }
+                        TaskKind::ExtractEntry { archive, entry, dest } => {
+                            fs_ops::extract_entry_task(task_id, archive, entry, dest, progress_tx).await;
                         }
                     }
                 });
@@ -108,6 +126,12 @@ impl TaskManager {
         }
     }
 
+    /// The oldest task that stopped because its destination exists, and
+    /// what's in the way.
+    pub fn take_conflict(&mut self) -> Option<(TaskKind, PathBuf)> {
+        (!self.conflicts.is_empty()).then(|| self.conflicts.remove(0))
+    }
+
     pub async fn wait_for_event(&mut self) -> bool {
         if let Some((task_id, event)) = self.progress_rx.recv().await {
             let mut tasks = self.tasks.lock().unwrap();
@@ -123,6 +147,10 @@ impl TaskManager {
                     fs_ops::ProgressEvent::Update(p) => {
                         task.status = TaskStatus::InProgress(p)
                     }
+                    fs_ops::ProgressEvent::Conflict(path) => {
+                        task.status = TaskStatus::Failed(format!("{} already exists", path.display()));
+                        self.conflicts.push((task.kind.clone(), path));
+                    }
                 }
             }
         }
@@ -132,6 +160,6 @@ impl TaskManager {
 
 impl Default for TaskManager {
     fn default() -> Self {
-        Self::new()
+        Self::new(vfs::local())
     }
 }
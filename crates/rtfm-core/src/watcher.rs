@@ -0,0 +1,217 @@
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{timeout_at, Instant};
+
+/// Quiet time after the last event before changes are reported.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+/// Upper bound on how long a stream of events can delay a refresh.
+const MAX_DELAY: Duration = Duration::from_millis(1000);
+
+/// What changed in a watched directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirChange {
+    /// These entries were created, modified, renamed or removed.
+    Entries(BTreeSet<PathBuf>),
+    /// Events were lost or the directory itself changed; re-read all of it.
+    Rescan,
+}
+
+impl DirChange {
+    fn merge(&mut self, path: PathBuf) {
+        if let DirChange::Entries(paths) = self {
+            paths.insert(path);
+        }
+    }
+
+    /// Adds the changes in `other` to these.
+    pub fn extend(&mut self, other: &DirChange) {
+        match other {
+            DirChange::Entries(paths) => {
+                for path in paths {
+                    self.merge(path.clone());
+                }
+            }
+            DirChange::Rescan => *self = DirChange::Rescan,
+        }
+    }
+}
+
+/// Watches local directories (non-recursively) and reports debounced changes.
+///
+/// Paths that aren't directories on the local disk, such as archive contents
+/// or remote locations, are silently ignored.
+pub struct DirWatcher {
+    watcher: Option<RecommendedWatcher>,
+    /// The directories last asked for, whether or not they could be watched.
+    wanted: HashSet<PathBuf>,
+    watched: HashSet<PathBuf>,
+    events_rx: mpsc::UnboundedReceiver<notify::Result<notify::Event>>,
+    /// Changes collected but not reported yet. Kept here rather than in
+    /// [`DirWatcher::next_changes`] so they survive it being cancelled.
+    pending: HashMap<PathBuf, DirChange>,
+    /// When the pending changes are reported: after `DEBOUNCE` of quiet, or
+    /// `MAX_DELAY` after the first, whichever comes first.
+    quiet_at: Option<Instant>,
+    deadline: Option<Instant>,
+}
+
+impl fmt::Debug for DirWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DirWatcher")
+            .field("enabled", &self.watcher.is_some())
+            .field("watched", &self.watched)
+            .field("pending", &self.pending)
+            .finish()
+    }
+}
+
+impl DirWatcher {
+    pub fn new() -> Self {
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = events_tx.send(event);
+        });
+        match watcher {
+            Ok(watcher) => Self { watcher: Some(watcher), ..Self::with_receiver(events_rx) },
+            Err(e) => {
+                log::error!("Failed to start the directory watcher: {}", e);
+                Self::disabled()
+            }
+        }
+    }
+
+    /// A watcher that never reports anything.
+    pub fn disabled() -> Self {
+        let (_, events_rx) = mpsc::unbounded_channel();
+        Self::with_receiver(events_rx)
+    }
+
+    fn with_receiver(events_rx: mpsc::UnboundedReceiver<notify::Result<notify::Event>>) -> Self {
+        Self {
+            watcher: None,
+            wanted: HashSet::new(),
+            watched: HashSet::new(),
+            events_rx,
+            pending: HashMap::new(),
+            quiet_at: None,
+            deadline: None,
+        }
+    }
+
+    /// Makes `dirs` the set of watched directories. Called on every turn of
+    /// the main loop, so nothing is done, not even a stat, unless the set
+    /// changed: a directory on a hung mount would block every time.
+    pub fn sync<I: IntoIterator<Item = PathBuf>>(&mut self, dirs: I) {
+        let Some(watcher) = self.watcher.as_mut() else {
+            return;
+        };
+        let wanted: HashSet<PathBuf> = dirs.into_iter().collect();
+        if wanted == self.wanted {
+            return;
+        }
+
+        for dir in self.watched.difference(&wanted) {
+            // Fails if the directory is already gone, which is fine.
+            let _ = watcher.unwatch(dir);
+        }
+        let mut watched = HashSet::new();
+        for dir in &wanted {
+            if self.watched.contains(dir) {
+                watched.insert(dir.clone());
+                continue;
+            }
+            // Fails for anything that isn't a local directory, which is fine.
+            match watcher.watch(dir, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    watched.insert(dir.clone());
+                }
+                Err(e) => log::debug!("Not watching {:?}: {}", dir, e),
+            }
+        }
+        self.watched = watched;
+        self.wanted = wanted;
+    }
+
+    pub fn is_watching(&self, dir: &Path) -> bool {
+        self.watched.contains(dir)
+    }
+
+    /// Waits for changes, then keeps collecting until things calm down.
+    /// Never returns while the watcher is disabled. Cancel-safe: changes
+    /// collected by a call that's dropped are reported by the next one.
+    pub async fn next_changes(&mut self) -> HashMap<PathBuf, DirChange> {
+        loop {
+            let (Some(quiet_at), Some(deadline)) = (self.quiet_at, self.deadline) else {
+                match self.events_rx.recv().await {
+                    Some(event) => self.receive(event),
+                    None => std::future::pending().await,
+                }
+                continue;
+            };
+            match timeout_at(quiet_at.min(deadline), self.events_rx.recv()).await {
+                Ok(Some(event)) => self.receive(event),
+                _ => {
+                    self.quiet_at = None;
+                    self.deadline = None;
+                    return std::mem::take(&mut self.pending);
+                }
+            }
+        }
+    }
+
+    /// Adds `event` to the pending changes. Any event, even one that changes
+    /// nothing on screen, restarts the quiet time once changes are pending.
+    fn receive(&mut self, event: notify::Result<notify::Event>) {
+        self.collect(event);
+        if self.pending.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        self.quiet_at = Some(now + DEBOUNCE);
+        self.deadline.get_or_insert(now + MAX_DELAY);
+    }
+
+    fn collect(&mut self, event: notify::Result<notify::Event>) {
+        let changes = &mut self.pending;
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                log::warn!("Directory watcher error: {}", e);
+                for dir in &self.watched {
+                    changes.insert(dir.clone(), DirChange::Rescan);
+                }
+                return;
+            }
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        if event.need_rescan() {
+            for dir in &self.watched {
+                changes.insert(dir.clone(), DirChange::Rescan);
+            }
+            return;
+        }
+
+        for path in event.paths {
+            if self.watched.contains(&path) {
+                changes.insert(path, DirChange::Rescan);
+            } else if let Some(dir) = path.parent().filter(|dir| self.watched.contains(*dir)) {
+                changes
+                    .entry(dir.to_path_buf())
+                    .or_insert_with(|| DirChange::Entries(BTreeSet::new()))
+                    .merge(path);
+            }
+        }
+    }
+}
+
+impl Default for DirWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
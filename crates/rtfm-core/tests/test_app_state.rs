@@ -1,12 +1,24 @@
+use config::Config;
+use io::vfs::{self, MemoryFs};
 use rtfm_core::app_state::AppState;
 use rtfm_core::clipboard::ClipboardMode;
-use rtfm_core::task_manager::TaskKind;
+use rtfm_core::task_manager::{TaskKind, TaskStatus};
 use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
 use tempdir::TempDir;
 
+/// An app state on the local filesystem that starts in `tmp_dir`, with the
+/// default config and no watcher.
+fn app_state_in(tmp_dir: &TempDir) -> AppState {
+    AppState::with_vfs(Config::default(), vfs::local(), tmp_dir.path().to_path_buf())
+}
+
 #[test]
 fn test_new_app_state() {
-    let app_state = AppState::new();
+    let tmp_dir = TempDir::new("test").unwrap();
+    let app_state = app_state_in(&tmp_dir);
     assert_eq!(app_state.tabs.len(), 1);
     assert_eq!(app_state.active_tab_index, 0);
 }
@@ -17,9 +29,7 @@ async fn test_clipboard_yank_and_paste() {
     let file_path = tmp_dir.path().join("file.txt");
     fs::write(&file_path, "hello").unwrap();
 
-    let mut app_state = AppState::new();
-    app_state.get_active_tab_mut().current_dir = tmp_dir.path().to_path_buf();
-    app_state.get_active_tab_mut().update_entries(false);
+    let mut app_state = app_state_in(&tmp_dir);
 
     // Yank the file
     app_state.get_active_tab_mut().cursor = 0; // Assuming the file is the first entry
@@ -49,7 +59,8 @@ async fn test_clipboard_yank_and_paste() {
 
 #[test]
 fn test_new_tab() {
-    let mut app_state = AppState::new();
+    let tmp_dir = TempDir::new("test").unwrap();
+    let mut app_state = app_state_in(&tmp_dir);
     app_state.new_tab();
     assert_eq!(app_state.tabs.len(), 2);
     assert_eq!(app_state.active_tab_index, 1);
@@ -57,9 +68,112 @@ fn test_new_tab() {
 
 #[test]
 fn test_close_tab() {
-    let mut app_state = AppState::new();
+    let tmp_dir = TempDir::new("test").unwrap();
+    let mut app_state = app_state_in(&tmp_dir);
     app_state.new_tab();
     app_state.close_tab();
     assert_eq!(app_state.tabs.len(), 1);
     assert_eq!(app_state.active_tab_index, 0);
 }
+
+/// Runs all queued tasks to completion, then asks about any conflicts.
+async fn run_tasks(app_state: &mut AppState) -> Vec<TaskStatus> {
+    app_state.task_manager.process_pending_tasks();
+    while app_state
+        .task_manager
+        .get_tasks()
+        .iter()
+        .any(|task| matches!(task.status, TaskStatus::Pending | TaskStatus::InProgress(_)))
+    {
+        app_state.task_manager.wait_for_event().await;
+    }
+    app_state.prompt_task_conflicts();
+    app_state.task_manager.get_tasks().into_iter().map(|task| task.status).collect()
+}
+
+#[tokio::test]
+async fn test_extract_conflict_asks_for_confirmation() {
+    let tmp_dir = TempDir::new("test").unwrap();
+    let zip_path = tmp_dir.path().join("project.zip");
+    let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+    zip.start_file("project/readme.txt", zip::write::SimpleFileOptions::default()).unwrap();
+    zip.write_all(b"hello").unwrap();
+    zip.finish().unwrap();
+
+    let mut app_state = app_state_in(&tmp_dir);
+    app_state.extract_selection();
+
+    // Nothing in the way: the archive is listed and extracted by the task.
+    assert!(!app_state.show_confirmation);
+    assert_eq!(run_tasks(&mut app_state).await, [TaskStatus::Completed]);
+    assert_eq!(fs::read_to_string(tmp_dir.path().join("project/readme.txt")).unwrap(), "hello");
+
+    fs::write(tmp_dir.path().join("project/readme.txt"), "changed").unwrap();
+    app_state.extract_selection();
+    let statuses = run_tasks(&mut app_state).await;
+    assert!(matches!(&statuses[1], TaskStatus::Failed(message) if message.ends_with("project already exists")), "{:?}", statuses);
+    assert!(app_state.show_confirmation);
+    assert_eq!(fs::read_to_string(tmp_dir.path().join("project/readme.txt")).unwrap(), "changed");
+
+    app_state.confirm();
+    let tasks = app_state.task_manager.get_tasks();
+    assert_eq!(tasks.len(), 3);
+    match &tasks[2].kind {
+        TaskKind::Extract { archive, parent, overwrite } => {
+            assert_eq!(archive, &zip_path);
+            assert_eq!(parent, tmp_dir.path());
+            assert!(overwrite);
+        }
+        _ => panic!("Wrong task kind"),
+    }
+    assert_eq!(run_tasks(&mut app_state).await[2], TaskStatus::Completed);
+    assert!(!app_state.show_confirmation);
+    assert_eq!(fs::read_to_string(tmp_dir.path().join("project/readme.txt")).unwrap(), "hello");
+}
+
+#[test]
+fn test_browse_archive_and_copy_out() {
+    let tmp_dir = TempDir::new("test").unwrap();
+    let zip_path = tmp_dir.path().join("docs.zip");
+    let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+    zip.start_file("guide/intro.md", zip::write::SimpleFileOptions::default()).unwrap();
+    zip.write_all(b"# Intro").unwrap();
+    zip.finish().unwrap();
+
+    let mut app_state = app_state_in(&tmp_dir);
+
+    // Enter the archive, then the directory inside it.
+    app_state.get_active_tab_mut().enter_directory(false);
+    assert_eq!(app_state.get_active_tab().current_dir, zip_path);
+    assert_eq!(app_state.get_active_tab().entries[0].name, "guide");
+    app_state.get_active_tab_mut().enter_directory(false);
+    assert_eq!(app_state.get_active_tab().entries[0].name, "intro.md");
+    assert_eq!(app_state.get_active_tab().preview_text(), Some("# Intro"));
+
+    // Pasting into the archive is refused.
+    app_state.yank_selection();
+    app_state.paste();
+    assert!(app_state.task_manager.get_tasks().is_empty());
+
+    // So is pasting into a directory that isn't on the local disk.
+    let mut remote = AppState::with_vfs(Config::default(), Arc::new(MemoryFs::new()), PathBuf::from("/"));
+    remote.clipboard = app_state.clipboard.clone();
+    remote.paste();
+    assert!(remote.task_manager.get_tasks().is_empty());
+    assert_eq!(remote.notification.as_deref(), Some("Cannot extract into /: not a local directory"));
+
+    // Pasting into a real directory extracts the entry.
+    app_state.get_active_tab_mut().leave_directory(false);
+    app_state.get_active_tab_mut().leave_directory(false);
+    app_state.paste();
+    let tasks = app_state.task_manager.get_tasks();
+    assert_eq!(tasks.len(), 1);
+    match &tasks[0].kind {
+        TaskKind::ExtractEntry { archive, entry, dest } => {
+            assert_eq!(archive, &zip_path);
+            assert_eq!(entry, std::path::Path::new("guide/intro.md"));
+            assert_eq!(dest, &tmp_dir.path().join("intro.md"));
+        }
+        _ => panic!("Wrong task kind"),
+    }
+}
@@ -0,0 +1,121 @@
+use config::Config;
+use io::vfs::{self, MemoryFs};
+use rtfm_core::app_state::{self, AppState};
+use rtfm_core::watcher::DirChange;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tempdir::TempDir;
+
+/// Applies background updates until no tab is loading any more.
+async fn finish_listings(app_state: &mut AppState) {
+    while app_state.tabs.iter().any(|tab| tab.is_loading() || tab.is_preview_loading()) {
+        let (index, update) = app_state::next_tab_update(&mut app_state.tabs).await;
+        app_state.apply_tab_update(index, update);
+    }
+}
+
+#[tokio::test]
+async fn test_streams_large_directory_in_batches() {
+    let tmp_dir = TempDir::new("test").unwrap();
+    for i in 0..5000 {
+        fs::write(tmp_dir.path().join(format!("file{:04}", i)), "").unwrap();
+    }
+    fs::create_dir(tmp_dir.path().join("zdir")).unwrap();
+
+    let mut app_state = AppState::with_vfs(Config::default(), vfs::local(), tmp_dir.path().to_path_buf());
+    app_state.set_background_jobs(true);
+    app_state.get_active_tab_mut().update_entries(false);
+    assert!(app_state.get_active_tab().is_loading());
+    assert!(app_state.get_active_tab().entries.is_empty());
+
+    // Move the cursor while batches are still arriving: it stays on the same file.
+    while app_state.get_active_tab().entries.is_empty() {
+        let (index, update) = app_state::next_tab_update(&mut app_state.tabs).await;
+        app_state.apply_tab_update(index, update);
+    }
+    app_state.get_active_tab_mut().move_cursor_down(false);
+    let selected = app_state.get_active_tab().get_selected_entry_path();
+
+    finish_listings(&mut app_state).await;
+    let tab = app_state.get_active_tab();
+    assert_eq!(tab.entries.len(), 5001);
+    assert_eq!(tab.entries[0].name, "zdir");
+    assert_eq!(tab.entries[1].name, "file0000");
+    assert!(tab.entries.windows(2).skip(1).all(|pair| pair[0].name < pair[1].name));
+    assert_eq!(tab.get_selected_entry_path(), selected);
+}
+
+#[tokio::test]
+async fn test_navigating_away_cancels_listing() {
+    let fs = MemoryFs::new();
+    fs.add_file("/slow/a.txt", "").add_file("/fast/b.txt", "");
+
+    let mut app_state = AppState::with_vfs(Config::default(), Arc::new(fs), PathBuf::from("/"));
+    app_state.set_background_jobs(true);
+    app_state.get_active_tab_mut().set_current_dir(PathBuf::from("/slow"), false);
+    app_state.get_active_tab_mut().set_current_dir(PathBuf::from("/fast"), false);
+
+    finish_listings(&mut app_state).await;
+    let names: Vec<_> = app_state.get_active_tab().entries.iter().map(|e| e.name.clone()).collect();
+    assert_eq!(names, vec!["b.txt"]);
+    assert_eq!(app_state.get_active_tab().preview_text(), Some(""));
+}
+
+#[tokio::test]
+async fn test_listing_error_is_reported() {
+    let mut app_state = AppState::with_vfs(Config::default(), Arc::new(MemoryFs::new()), PathBuf::from("/"));
+    app_state.set_background_jobs(true);
+    app_state.get_active_tab_mut().set_current_dir(PathBuf::from("/missing"), false);
+
+    finish_listings(&mut app_state).await;
+    assert!(app_state.get_active_tab().entries.is_empty());
+    assert!(app_state.notification.as_deref().unwrap().starts_with("Cannot read /missing"));
+}
+
+#[tokio::test]
+async fn test_changes_during_listing_are_applied_after_it() {
+    let fs = MemoryFs::new();
+    fs.add_file("/dir/a.txt", "");
+
+    let mut app_state = AppState::with_vfs(Config::default(), Arc::new(fs.clone()), PathBuf::from("/"));
+    app_state.set_background_jobs(true);
+    app_state.get_active_tab_mut().set_current_dir(PathBuf::from("/dir"), false);
+    // The directory has been read, but the listing isn't complete yet.
+    let (index, update) = app_state::next_tab_update(&mut app_state.tabs).await;
+    app_state.apply_tab_update(index, update);
+    assert!(app_state.get_active_tab().is_loading());
+
+    fs.add_file("/dir/b.txt", "");
+    let change = DirChange::Entries(BTreeSet::from([PathBuf::from("/dir/b.txt")]));
+    app_state.apply_dir_changes(&HashMap::from([(PathBuf::from("/dir"), change)]));
+
+    finish_listings(&mut app_state).await;
+    let names: Vec<_> = app_state.get_active_tab().entries.iter().map(|e| e.name.clone()).collect();
+    assert_eq!(names, vec!["a.txt", "b.txt"]);
+}
+
+#[tokio::test]
+async fn test_rescans_run_in_background_and_keep_the_entries_shown() {
+    let fs = MemoryFs::new();
+    fs.add_file("/dir/a.txt", "").add_file("/dir/c.txt", "");
+
+    let mut app_state = AppState::with_vfs(Config::default(), Arc::new(fs.clone()), PathBuf::from("/"));
+    app_state.set_background_jobs(true);
+    app_state.get_active_tab_mut().set_current_dir(PathBuf::from("/dir"), false);
+    finish_listings(&mut app_state).await;
+    app_state.get_active_tab_mut().move_cursor_down(false);
+
+    fs.add_file("/dir/b.txt", "");
+    app_state.apply_dir_changes(&HashMap::from([(PathBuf::from("/dir"), DirChange::Rescan)]));
+    let tab = app_state.get_active_tab();
+    assert!(tab.is_loading());
+    assert_eq!(tab.entries.len(), 2);
+
+    finish_listings(&mut app_state).await;
+    let tab = app_state.get_active_tab();
+    let names: Vec<_> = tab.entries.iter().map(|e| e.name.clone()).collect();
+    assert_eq!(names, vec!["a.txt", "b.txt", "c.txt"]);
+    assert_eq!(tab.get_selected_entry_path(), Some(PathBuf::from("/dir/c.txt")));
+}
@@ -0,0 +1,288 @@
+use config::Config;
+use io::vfs::{MemoryFs, Operation, Vfs};
+use rtfm_core::app_state::{next_lookup, AppState};
+use rtfm_core::task_manager::TaskStatus;
+use rtfm_core::watcher::DirChange;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+fn app_state_in(fs: &MemoryFs, start_dir: &str) -> AppState {
+    AppState::with_vfs(Config::default(), Arc::new(fs.clone()), PathBuf::from(start_dir))
+}
+
+fn names(app_state: &AppState) -> Vec<&str> {
+    app_state.get_active_tab().entries.iter().map(|e| e.name.as_str()).collect()
+}
+
+fn read(fs: &MemoryFs, path: &str) -> String {
+    let mut content = String::new();
+    fs.open(Path::new(path)).unwrap().read_to_string(&mut content).unwrap();
+    content
+}
+
+/// Runs all queued tasks to completion and refreshes the active tab.
+async fn run_tasks(app_state: &mut AppState) -> Vec<TaskStatus> {
+    app_state.task_manager.process_pending_tasks();
+    while app_state
+        .task_manager
+        .get_tasks()
+        .iter()
+        .any(|task| matches!(task.status, TaskStatus::Pending | TaskStatus::InProgress(_)))
+    {
+        app_state.task_manager.wait_for_event().await;
+    }
+    let show_hidden = app_state.show_hidden_files;
+    app_state.get_active_tab_mut().update_entries(show_hidden);
+    app_state.task_manager.get_tasks().into_iter().map(|task| task.status).collect()
+}
+
+#[test]
+fn test_navigation() {
+    let fs = MemoryFs::new();
+    fs.add_file("/home/user/notes.txt", "todo")
+        .add_file("/home/user/.hidden", "")
+        .add_file("/home/user/src/main.rs", "fn main() {}");
+
+    let mut app_state = app_state_in(&fs, "/home/user");
+    assert_eq!(names(&app_state), vec!["src", "notes.txt"]);
+    assert_eq!(app_state.get_active_tab().preview_text(), Some("main.rs"));
+
+    app_state.get_active_tab_mut().move_cursor_down(false);
+    assert_eq!(app_state.get_active_tab().preview_text(), Some("todo"));
+
+    app_state.get_active_tab_mut().move_cursor_up(false);
+    app_state.get_active_tab_mut().enter_directory(false);
+    assert_eq!(app_state.get_active_tab().current_dir, Path::new("/home/user/src"));
+    assert_eq!(names(&app_state), vec!["main.rs"]);
+
+    app_state.get_active_tab_mut().leave_directory(false);
+    app_state.get_active_tab_mut().leave_directory(false);
+    assert_eq!(app_state.get_active_tab().current_dir, Path::new("/home"));
+
+    app_state.toggle_hidden_files();
+    app_state.get_active_tab_mut().enter_directory(true);
+    assert_eq!(names(&app_state), vec!["src", ".hidden", "notes.txt"]);
+
+    // New tabs open where the app started, wherever the active one is.
+    app_state.get_active_tab_mut().leave_directory(true);
+    app_state.new_tab();
+    assert_eq!(app_state.get_active_tab().current_dir, Path::new("/home/user"));
+
+    // Bookmarks are kept in memory only.
+    app_state.add_bookmark();
+    assert_eq!(app_state.bookmarks.last(), Some(&("user".to_string(), PathBuf::from("/home/user"))));
+    assert!(!app_state.persist_config);
+}
+
+#[test]
+fn test_archives_are_only_entered_on_their_own_filesystem() {
+    // A real zip on the local disk, shadowed by a plain file in memory.
+    let tmp_dir = tempdir::TempDir::new("test").unwrap();
+    let zip_path = tmp_dir.path().join("backup.zip");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+    zip.start_file("a.txt", zip::write::SimpleFileOptions::default()).unwrap();
+    zip.finish().unwrap();
+    let fs = MemoryFs::new();
+    fs.add_file(&zip_path, "not a zip");
+
+    let mut app_state = app_state_in(&fs, tmp_dir.path().to_str().unwrap());
+    app_state.get_active_tab_mut().enter_directory(false);
+    assert_eq!(app_state.get_active_tab().current_dir, tmp_dir.path());
+    assert_eq!(names(&app_state), vec!["backup.zip"]);
+}
+
+#[test]
+fn test_unreadable_directory_is_empty() {
+    let fs = MemoryFs::new();
+    fs.add_file("/data/a.txt", "a");
+    fs.fail(Operation::ReadDir, "/data", ErrorKind::PermissionDenied);
+
+    let app_state = app_state_in(&fs, "/data");
+    assert!(app_state.get_active_tab().entries.is_empty());
+    assert_eq!(app_state.get_active_tab().preview_text(), None);
+}
+
+#[tokio::test]
+async fn test_paste_and_conflict() {
+    let fs = MemoryFs::new();
+    fs.add_file("/src/docs/a.txt", "new").add_file("/dest/docs/a.txt", "old").add_dir("/dest/docs/keep");
+
+    let mut app_state = app_state_in(&fs, "/src");
+    app_state.yank_selection();
+
+    // A copy into an empty directory runs straight away.
+    fs.add_dir("/empty");
+    app_state.get_active_tab_mut().set_current_dir(PathBuf::from("/empty"), false);
+    app_state.paste();
+    assert!(!app_state.show_confirmation);
+    assert_eq!(run_tasks(&mut app_state).await, vec![TaskStatus::Completed]);
+    assert_eq!(names(&app_state), vec!["docs"]);
+    assert_eq!(read(&fs, "/empty/docs/a.txt"), "new");
+
+    // A name clash asks first; cancelling leaves the destination alone.
+    app_state.get_active_tab_mut().set_current_dir(PathBuf::from("/dest"), false);
+    app_state.paste();
+    assert!(app_state.show_confirmation);
+    app_state.cancel();
+    assert_eq!(app_state.task_manager.get_tasks().len(), 1);
+    assert_eq!(read(&fs, "/dest/docs/a.txt"), "old");
+
+    // Confirming overwrites files and keeps unrelated ones.
+    app_state.paste();
+    app_state.confirm();
+    assert_eq!(run_tasks(&mut app_state).await[1], TaskStatus::Completed);
+    assert_eq!(read(&fs, "/dest/docs/a.txt"), "new");
+    assert!(fs.exists(Path::new("/dest/docs/keep")));
+}
+
+#[tokio::test]
+async fn test_locations_and_paste_conflicts_are_looked_up_in_background() {
+    let fs = MemoryFs::new();
+    fs.add_file("/src/a.txt", "new").add_file("/dest/a.txt", "old");
+
+    let mut app_state = app_state_in(&fs, "/src");
+    app_state.set_background_jobs(true);
+
+    // The tab only opens once the location turned out to be a directory.
+    app_state.open_location(PathBuf::from("/dest"));
+    assert_eq!(app_state.tabs.len(), 1);
+    assert!(app_state.lookup.as_ref().unwrap().0.status().contains("/dest"));
+    let answer = next_lookup(&mut app_state.lookup).await;
+    app_state.apply_lookup(answer);
+    assert!(app_state.lookup.is_none());
+    assert_eq!(app_state.tabs.len(), 2);
+    assert_eq!(app_state.get_active_tab().current_dir, Path::new("/dest"));
+
+    app_state.open_location(PathBuf::from("/src/a.txt"));
+    let answer = next_lookup(&mut app_state.lookup).await;
+    app_state.apply_lookup(answer);
+    assert_eq!(app_state.tabs.len(), 2);
+    assert_eq!(app_state.notification.as_deref(), Some("/src/a.txt is not a directory"));
+
+    // A name clash is found without blocking the paste call.
+    app_state.clipboard.yank(vec![PathBuf::from("/src/a.txt")]);
+    app_state.paste();
+    assert!(!app_state.show_confirmation);
+    let answer = next_lookup(&mut app_state.lookup).await;
+    app_state.apply_lookup(answer);
+    assert!(app_state.show_confirmation);
+    assert!(app_state.task_manager.get_tasks().is_empty());
+}
+
+#[test]
+fn test_directories_are_not_pasted_into_themselves() {
+    let fs = MemoryFs::new();
+    fs.add_file("/src/docs/a.txt", "a").add_dir("/src/docs/sub");
+
+    let mut app_state = app_state_in(&fs, "/src");
+    app_state.yank_selection();
+    for dir in ["/src", "/src/docs", "/src/docs/sub"] {
+        app_state.get_active_tab_mut().set_current_dir(PathBuf::from(dir), false);
+        app_state.paste();
+        assert!(!app_state.show_confirmation);
+        assert_eq!(app_state.notification.as_deref(), Some("Cannot paste \"docs\" into itself"));
+    }
+    assert!(app_state.task_manager.get_tasks().is_empty());
+
+    let result = io::vfs::copy_recursive(&fs, Path::new("/src/docs"), Path::new("/src/docs/sub/docs"), &mut |_| {});
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert!(!fs.exists(Path::new("/src/docs/sub/docs")));
+}
+
+#[tokio::test]
+async fn test_move_and_delete() {
+    let fs = MemoryFs::new();
+    fs.add_file("/a/file.txt", "content").add_dir("/b");
+
+    let mut app_state = app_state_in(&fs, "/a");
+    app_state.cut_selection();
+    app_state.get_active_tab_mut().set_current_dir(PathBuf::from("/b"), false);
+    app_state.paste();
+    assert!(app_state.clipboard.paths.is_empty());
+    assert_eq!(run_tasks(&mut app_state).await, vec![TaskStatus::Completed]);
+    assert!(!fs.exists(Path::new("/a/file.txt")));
+    assert_eq!(names(&app_state), vec!["file.txt"]);
+
+    app_state.delete_selection();
+    assert!(app_state.show_confirmation);
+    app_state.confirm();
+    run_tasks(&mut app_state).await;
+    assert!(app_state.get_active_tab().entries.is_empty());
+    assert!(!fs.exists(Path::new("/b/file.txt")));
+}
+
+#[tokio::test]
+async fn test_io_errors_fail_tasks() {
+    let fs = MemoryFs::new();
+    fs.add_file("/src/a.txt", "a").add_file("/src/b.txt", "b").add_dir("/dest");
+    fs.fail(Operation::Create, "/dest/a.txt", ErrorKind::StorageFull);
+    fs.fail(Operation::Remove, "/src/b.txt", ErrorKind::PermissionDenied);
+
+    let mut app_state = app_state_in(&fs, "/src");
+    app_state.yank_selection();
+    app_state.get_active_tab_mut().set_current_dir(PathBuf::from("/dest"), false);
+    app_state.paste();
+
+    app_state.get_active_tab_mut().set_current_dir(PathBuf::from("/src"), false);
+    app_state.get_active_tab_mut().move_cursor_down(false);
+    app_state.delete_selection();
+    app_state.confirm();
+
+    let statuses = run_tasks(&mut app_state).await;
+    assert_eq!(statuses.len(), 2);
+    assert!(statuses.iter().all(|status| matches!(status, TaskStatus::Failed(_))), "{:?}", statuses);
+    assert!(!fs.exists(Path::new("/dest/a.txt")));
+    assert_eq!(names(&app_state), vec!["a.txt", "b.txt"]);
+
+    // The same operations succeed once the disk recovers.
+    fs.clear_failures();
+    app_state.get_active_tab_mut().move_cursor_down(false);
+    app_state.delete_selection();
+    app_state.confirm();
+    assert_eq!(run_tasks(&mut app_state).await[2], TaskStatus::Completed);
+    assert_eq!(names(&app_state), vec!["a.txt"]);
+}
+
+#[test]
+fn test_incremental_refresh_keeps_cursor() {
+    let fs = MemoryFs::new();
+    fs.add_file("/w/b.txt", "b").add_file("/w/c.txt", "c").add_dir("/w/sub");
+
+    let mut app_state = app_state_in(&fs, "/w");
+    app_state.get_active_tab_mut().move_cursor_down(false);
+    app_state.get_active_tab_mut().move_cursor_down(false);
+    assert_eq!(app_state.get_active_tab().entries[2].name, "c.txt");
+
+    // A new file sorts before the selection; a removed one disappears.
+    fs.add_file("/w/a.txt", "a");
+    fs.remove(Path::new("/w/b.txt")).unwrap();
+    fs.add_file("/w/c.txt", "changed");
+    fs.add_file("/w/sub/inner.txt", "");
+    let changes = HashMap::from([
+        (
+            PathBuf::from("/w"),
+            DirChange::Entries(["/w/a.txt", "/w/b.txt", "/w/c.txt"].into_iter().map(PathBuf::from).collect()),
+        ),
+        (PathBuf::from("/w/sub"), DirChange::Entries(BTreeSet::from([PathBuf::from("/w/sub/inner.txt")]))),
+    ]);
+    app_state.apply_dir_changes(&changes);
+
+    let tab = app_state.get_active_tab();
+    assert_eq!(names(&app_state), vec!["sub", "a.txt", "c.txt"]);
+    assert_eq!(tab.entries[tab.cursor].name, "c.txt");
+    assert_eq!(tab.preview_text(), Some("changed"));
+
+    // The selected file is removed: the cursor stays in place.
+    fs.remove(Path::new("/w/c.txt")).unwrap();
+    app_state.apply_dir_changes(&HashMap::from([(PathBuf::from("/w"), DirChange::Rescan)]));
+    let tab = app_state.get_active_tab();
+    assert_eq!(tab.entries[tab.cursor].name, "a.txt");
+
+    // The previewed directory is refreshed too.
+    app_state.get_active_tab_mut().move_cursor_up(false);
+    fs.add_file("/w/sub/more.txt", "");
+    app_state.apply_dir_changes(&HashMap::from([(PathBuf::from("/w/sub"), DirChange::Rescan)]));
+    assert_eq!(app_state.get_active_tab().preview_text(), Some("inner.txt\nmore.txt"));
+}
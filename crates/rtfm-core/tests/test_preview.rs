@@ -0,0 +1,1356 @@
+use config::Config;
+use io::vfs::{MemoryFs, Operation, Vfs};
+use rtfm_core::app_state::{self, AppState, TabUpdate};
+use rtfm_core::mime;
+use rtfm_core::preview::{
+    format_rows, parse_offset, row_start, FilePreviewer, HexPreviewer, Preview, PreviewRequest, Previewer,
+    PreviewerRegistry, TextTarget, BYTES_PER_ROW, HEX_PAGE_SIZE, MAX_PREVIEW_SIZE, TEXT_PAGE_SIZE,
+};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+async fn finish_preview(app_state: &mut AppState) {
+    while app_state.get_active_tab().is_preview_loading() {
+        let (index, update) = app_state::next_tab_update(&mut app_state.tabs).await;
+        app_state.apply_tab_update(index, update);
+    }
+}
+
+fn preview(app_state: &AppState) -> Option<&str> {
+    app_state.get_active_tab().preview_text()
+}
+
+/// Generates the preview of `path` with `previewer` straight away.
+fn generate_with(previewer: &Previewer, fs: &dyn Vfs, path: impl AsRef<Path>) -> Arc<Preview> {
+    let path = path.as_ref();
+    let metadata = fs.metadata(path).unwrap();
+    previewer.generate(fs, path, &metadata, &AtomicBool::new(false)).unwrap()
+}
+
+#[tokio::test]
+async fn test_previews_load_in_background_and_are_cached() {
+    let fs = MemoryFs::new();
+    fs.add_file("/p/a.txt", "first").add_file("/p/b.txt", "second").add_file("/p/c.txt", "third");
+
+    let mut app_state = AppState::with_vfs(Config::default(), Arc::new(fs.clone()), PathBuf::from("/"));
+    app_state.set_background_jobs(true);
+    app_state.get_active_tab_mut().set_current_dir(PathBuf::from("/p"), false);
+    let tab = app_state.get_active_tab();
+    assert!(tab.is_loading());
+
+    // The first batch selects a.txt and starts its preview.
+    let (index, update) = app_state::next_tab_update(&mut app_state.tabs).await;
+    app_state.apply_tab_update(index, update);
+    assert!(app_state.get_active_tab().is_preview_loading());
+    assert_eq!(preview(&app_state), None);
+    finish_preview(&mut app_state).await;
+    assert_eq!(preview(&app_state), Some("first"));
+
+    // Moving on cancels the job in flight: only the last selection is shown.
+    app_state.get_active_tab_mut().move_cursor_down(false);
+    app_state.get_active_tab_mut().move_cursor_down(false);
+    assert_eq!(app_state.get_active_tab().preview_job.as_ref().unwrap().path, Path::new("/p/c.txt"));
+    loop {
+        let (index, update) = app_state::next_tab_update(&mut app_state.tabs).await;
+        let is_preview = matches!(update, TabUpdate::Preview(_));
+        app_state.apply_tab_update(index, update);
+        if is_preview {
+            break;
+        }
+    }
+    assert_eq!(preview(&app_state), Some("third"));
+
+    // The job stats the file too, which can be slow, and finds cached
+    // previews unless the file changed.
+    fs.fail(Operation::Metadata, "/p/b.txt", ErrorKind::PermissionDenied);
+    app_state.get_active_tab_mut().move_cursor_up(false);
+    assert!(app_state.get_active_tab().is_preview_loading());
+    finish_preview(&mut app_state).await;
+    assert!(preview(&app_state).unwrap().starts_with("Error opening file"));
+    app_state.get_active_tab_mut().move_cursor_up(false);
+    finish_preview(&mut app_state).await;
+    assert_eq!(preview(&app_state), Some("first"));
+
+    fs.add_file("/p/a.txt", "edited");
+    app_state.get_active_tab_mut().update_preview();
+    assert!(app_state.get_active_tab().is_preview_loading());
+    finish_preview(&mut app_state).await;
+    assert_eq!(preview(&app_state), Some("edited"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_special_files_are_described_not_opened() {
+    use io::vfs::LocalFs;
+    use std::os::unix::net::UnixListener;
+    use tempdir::TempDir;
+
+    let tmp_dir = TempDir::new("test").unwrap();
+    let fifo = tmp_dir.path().join("a.fifo");
+    let status = std::process::Command::new("mkfifo").arg(&fifo).status().unwrap();
+    assert!(status.success());
+    let _listener = UnixListener::bind(tmp_dir.path().join("b.sock")).unwrap();
+
+    // Opening the FIFO would block this test forever.
+    let mut app_state = AppState::with_vfs(Config::default(), io::vfs::local(), tmp_dir.path().to_path_buf());
+    let card = preview(&app_state).unwrap();
+    assert!(card.starts_with("Named pipe (FIFO)"), "{}", card);
+    assert!(card.contains("Permissions: prw"), "{}", card);
+
+    app_state.get_active_tab_mut().move_cursor_down(false);
+    assert!(preview(&app_state).unwrap().starts_with("Unix domain socket"));
+
+    let null = generate_with(&Previewer::default(), &LocalFs, "/dev/null");
+    let null = null.text();
+    assert!(null.contains("Permissions: crw-rw-rw- (0666)"), "{}", null);
+    assert!(null.contains("Device:      1, 3"), "{}", null);
+}
+
+#[test]
+fn test_format_permissions() {
+    use rtfm_core::preview::format_permissions;
+    assert_eq!(format_permissions('-', 0o644), "-rw-r--r--");
+    assert_eq!(format_permissions('d', 0o1777), "drwxrwxrwt");
+    assert_eq!(format_permissions('-', 0o4754), "-rwsr-xr--");
+    assert_eq!(format_permissions('-', 0o2644), "-rw-r-Sr--");
+}
+
+#[test]
+fn test_syntax_detection() {
+    use rtfm_core::preview::detect_syntax;
+
+    let name = |path: &str, text: &str| detect_syntax(Path::new(path), text).map(|syntax| syntax.name.as_str());
+    assert_eq!(name("main.rs", "fn main() {}"), Some("Rust"));
+    assert_eq!(name("Cargo.toml", "[package]"), Some("TOML"));
+    assert_eq!(name("data.JSON", "{}"), Some("JSON"));
+    assert_eq!(name("build", "#!/usr/bin/env python3\nprint(1)"), Some("Python"));
+    assert_eq!(name("run", "#!/bin/bash\necho hi"), Some("Bourne Again Shell (bash)"));
+    assert_eq!(name("notes", "title: x\n# vim: set ft=yaml:"), Some("YAML"));
+    assert_eq!(name("script.txt", "# -*- mode: python -*-\nprint(1)"), Some("Python"));
+    assert_eq!(name("Makefile", "all:\n\tcc main.c"), Some("Makefile"));
+    assert_eq!(name("README", "Just some words."), None);
+}
+
+#[test]
+fn test_highlighted_preview_uses_theme_colors() {
+    let fs = MemoryFs::new();
+    fs.add_file("/src/main.rs", "fn main() {\n    let x = 1;\n}\n").add_file("/src/notes.txt", "plain");
+
+    let generate = |theme: &str, path: &str| {
+        let mut config = Config::default();
+        config.theme.syntax = theme.to_string();
+        generate_with(&Previewer::new(&config), &fs, path)
+    };
+
+    let dark = generate("base16-ocean.dark", "/src/main.rs");
+    let Preview::Highlighted { text, lines } = dark.as_ref() else {
+        panic!("not highlighted: {:?}", dark);
+    };
+    assert_eq!(text, "fn main() {\n    let x = 1;\n}\n");
+    assert_eq!(lines.len(), 3);
+    let line: String = lines[1].iter().map(|span| span.text.as_str()).collect();
+    assert_eq!(line, "    let x = 1;");
+    let keyword = lines[0].iter().find(|span| span.text == "fn").unwrap();
+
+    let light = generate("InspiredGitHub", "/src/main.rs");
+    let Preview::Highlighted { lines: light_lines, .. } = light.as_ref() else {
+        panic!("not highlighted");
+    };
+    let light_keyword = light_lines[0].iter().find(|span| span.text == "fn").unwrap();
+    assert_ne!(keyword.style.fg, light_keyword.style.fg);
+
+    // Unknown themes fall back to the default one.
+    assert_eq!(generate("no-such-theme", "/src/main.rs"), dark);
+    assert_eq!(*generate("base16-ocean.dark", "/src/notes.txt"), Preview::Text("plain".to_string()));
+}
+
+#[test]
+fn test_hex_dump_format_and_offsets() {
+    assert_eq!(
+        format_rows(0x20, b"PNG\x00\x01 hello, world!\xff"),
+        "00000020  50 4e 47 00 01 20 68 65  6c 6c 6f 2c 20 77 6f 72  |PNG.. hello, wor|\n\
+         00000030  6c 64 21 ff                                       |ld!.|"
+    );
+    assert_eq!(parse_offset("1024", 0), Some(1024));
+    assert_eq!(parse_offset(" 0x400 ", 0), Some(1024));
+    assert_eq!(parse_offset("50%", 4096), Some(2048));
+    assert_eq!(parse_offset("150%", 4096), None);
+    assert_eq!(parse_offset("lots", 4096), None);
+}
+
+#[test]
+fn test_binary_files_get_a_paged_hex_dump() {
+    let fs = MemoryFs::new();
+    let mut sqlite = b"SQLite format 3\0".to_vec();
+    sqlite.resize(100, 0);
+    // Three and a half pages, with each byte recording which 256-byte block it's in.
+    let large: Vec<u8> = (0..HEX_PAGE_SIZE * 7 / 2).map(|i| (i / 256) as u8).collect();
+    let large = [b"\x7fELF".as_slice(), &large[4..], &vec![0; MAX_PREVIEW_SIZE as usize]].concat();
+    fs.add_file("/bin/data.db", sqlite).add_file("/bin/large", large.clone());
+
+    let mut app_state = AppState::with_vfs(Config::default(), Arc::new(fs), PathBuf::from("/bin"));
+    let Some(Preview::Hex(dump)) = app_state.get_active_tab().preview_content.as_deref() else {
+        panic!("no hex dump: {:?}", preview(&app_state));
+    };
+    assert_eq!(dump.kind, Some("application/vnd.sqlite3"));
+    assert_eq!(dump.rows(), 7);
+    assert!(dump.header().starts_with("application/vnd.sqlite3, 0x0-0x63 of 100 B"));
+
+    // Larger than MAX_PREVIEW_SIZE: only one page is read at a time.
+    app_state.get_active_tab_mut().move_cursor_down(false);
+    let tab = app_state.get_active_tab_mut();
+    let Some(Preview::Hex(dump)) = tab.preview_content.as_deref() else {
+        panic!("no hex dump: {:?}", tab.preview_text());
+    };
+    assert_eq!(dump.kind, Some("application/x-executable"));
+    assert_eq!((dump.offset, dump.end()), (0, HEX_PAGE_SIZE));
+
+    let top_row = |tab: &rtfm_core::app_state::TabState| {
+        let Some(Preview::Hex(dump)) = tab.preview_content.as_deref() else { panic!() };
+        let row = dump.text.lines().nth(tab.preview_scroll.0 as usize).unwrap();
+        u64::from_str_radix(&row[..8], 16).unwrap()
+    };
+
+    // Scrolling towards the end of the page reads the next one.
+    tab.scroll_preview((HEX_PAGE_SIZE / BYTES_PER_ROW) as i32 - 100);
+    assert_eq!(top_row(tab), HEX_PAGE_SIZE - 100 * BYTES_PER_ROW);
+    for _ in 0..200 {
+        tab.scroll_preview(1);
+    }
+    assert_eq!(top_row(tab), HEX_PAGE_SIZE + 100 * BYTES_PER_ROW);
+    let Some(Preview::Hex(dump)) = tab.preview_content.as_deref() else { panic!() };
+    assert!(dump.offset > 0);
+
+    // Jumping far ahead seeks straight there.
+    tab.seek_preview(0x30110).unwrap();
+    assert_eq!(top_row(tab), 0x30110);
+    let text = tab.preview_text().unwrap();
+    assert!(text.contains("00030110  01 01 01 01"), "{}", text);
+    tab.seek_preview(u64::MAX).unwrap();
+    assert_eq!(top_row(tab), row_start(large.len() as u64 - 1));
+
+    // And back to the start.
+    tab.seek_preview(0).unwrap();
+    assert_eq!(top_row(tab), 0);
+    tab.scroll_preview(-5);
+    assert_eq!(top_row(tab), 0);
+}
+
+#[test]
+fn test_large_text_files_are_paged_through() {
+    let fs = MemoryFs::new();
+    // 13 bytes a line, about 2.6 MB.
+    let log: String = (1..=200_000).map(|n| format!("line {:07}\n", n)).collect();
+    fs.add_file("/logs/big.log", log.clone().into_bytes());
+
+    let mut app_state = AppState::with_vfs(Config::default(), Arc::new(fs), PathBuf::from("/logs"));
+    let tab = app_state.get_active_tab_mut();
+    tab.preview_rows = 10;
+    let Some(Preview::Paged(page)) = tab.preview_content.as_deref() else {
+        panic!("no paged text: {:?}", tab.preview_text());
+    };
+    assert_eq!((page.offset, page.first_line), (0, Some(1)));
+    assert!(page.end <= TEXT_PAGE_SIZE);
+    assert!(page.text.starts_with("line 0000001\nline 0000002"));
+    assert_eq!(page.header(0), "line 1, 0% of 2.48 MiB");
+
+    let top_line = |tab: &rtfm_core::app_state::TabState| {
+        let Some(Preview::Paged(page)) = tab.preview_content.as_deref() else { panic!() };
+        let line = page.text.lines().nth(tab.preview_scroll.0 as usize).unwrap();
+        line[5..].parse::<u64>().unwrap()
+    };
+
+    // Scrolling past the page reads the next one, keeping count of lines.
+    let rows = (TEXT_PAGE_SIZE / 13) as i32;
+    for _ in 0..rows / 20 + 10 {
+        tab.scroll_preview(20);
+    }
+    assert_eq!(top_line(tab), (rows / 20 + 10) as u64 * 20 + 1);
+    let Some(Preview::Paged(page)) = tab.preview_content.as_deref() else { panic!() };
+    assert!(page.offset > 0);
+    let row = tab.preview_scroll.0 as usize;
+    assert!(page.header(row).starts_with(&format!("line {},", top_line(tab))), "{}", page.header(row));
+    for _ in 0..rows / 20 + 10 {
+        tab.scroll_preview(-20);
+    }
+    assert_eq!(top_line(tab), 1);
+
+    // The tail fills the view with the last lines.
+    tab.jump_preview(TextTarget::End).unwrap();
+    assert_eq!(top_line(tab), 199_991);
+    tab.scroll_preview(5);
+    assert_eq!(top_line(tab), 199_991);
+    let Some(Preview::Paged(page)) = tab.preview_content.as_deref() else { panic!() };
+    assert_eq!(page.end, log.len() as u64);
+    assert!(page.header(tab.preview_scroll.0 as usize).starts_with("99% of"));
+
+    // Lines are found by counting; percentages by seeking.
+    tab.jump_preview(TextTarget::Line(123_456)).unwrap();
+    assert_eq!(top_line(tab), 123_456);
+    let Some(Preview::Paged(page)) = tab.preview_content.as_deref() else { panic!() };
+    assert_eq!(page.header(tab.preview_scroll.0 as usize), "line 123456, 61% of 2.48 MiB");
+    tab.jump_preview(TextTarget::Line(300_000)).unwrap();
+    assert_eq!(top_line(tab), 199_991);
+    tab.jump_preview(TextTarget::Offset(log.len() as u64 / 2)).unwrap();
+    assert_eq!(top_line(tab), 100_001);
+    tab.jump_preview(TextTarget::Start).unwrap();
+    assert_eq!(top_line(tab), 1);
+
+    // Ctrl-g takes the same targets.
+    app_state.prompt_preview_offset();
+    app_state.input_buffer = "25%".to_string();
+    app_state.seek_preview_from_input();
+    assert_eq!(top_line(app_state.get_active_tab()), 50_001);
+    app_state.prompt_preview_offset();
+    app_state.input_buffer = "0x10".to_string();
+    app_state.seek_preview_from_input();
+    assert!(app_state.show_input_dialog);
+    app_state.input_buffer = "$".to_string();
+    app_state.seek_preview_from_input();
+    assert_eq!(top_line(app_state.get_active_tab()), 199_991);
+}
+
+#[test]
+fn test_changed_files_keep_the_page_being_read() {
+    let fs = MemoryFs::new();
+    let lines = |range: std::ops::RangeInclusive<u32>| range.map(|n| format!("line {:07}\n", n)).collect::<String>();
+    fs.add_file("/logs/app.log", lines(1..=200_000)).add_file("/logs/other.log", "");
+
+    let mut app_state = AppState::with_vfs(Config::default(), Arc::new(fs.clone()), PathBuf::from("/logs"));
+    let tab = app_state.get_active_tab_mut();
+    tab.preview_rows = 10;
+    tab.jump_preview(TextTarget::Line(123_456)).unwrap();
+    let page_of = |tab: &rtfm_core::app_state::TabState| {
+        let Some(Preview::Paged(page)) = tab.preview_content.as_deref() else { panic!() };
+        (page.offset, page.header(tab.preview_scroll.0 as usize))
+    };
+    let offset = page_of(tab).0;
+
+    // Lines appended to the file, or a sibling changing, leave the view where it is.
+    fs.add_file("/logs/app.log", lines(1..=200_100));
+    tab.apply_changes(&[PathBuf::from("/logs/app.log")].into(), false);
+    assert_eq!(page_of(tab).0, offset);
+    assert!(page_of(tab).1.starts_with("line 123456, 61% of"), "{:?}", page_of(tab));
+    fs.add_file("/logs/other.log", "changed");
+    tab.apply_changes(&[PathBuf::from("/logs/other.log")].into(), false);
+    assert_eq!(page_of(tab).0, offset);
+    assert!(page_of(tab).1.starts_with("line 123456, 61% of"), "{:?}", page_of(tab));
+
+    // A file that shrank is read from the start.
+    fs.add_file("/logs/app.log", lines(1..=100));
+    tab.apply_changes(&[PathBuf::from("/logs/app.log")].into(), false);
+    assert_eq!(page_of(tab), (0, "line 1, 0% of 1.27 KiB".to_string()));
+}
+
+#[tokio::test]
+async fn test_pages_are_read_in_background() {
+    let fs = MemoryFs::new();
+    let log: String = (1..=200_000).map(|n| format!("line {:07}\n", n)).collect();
+    fs.add_file("/logs/big.log", log.into_bytes());
+
+    let mut app_state = AppState::with_vfs(Config::default(), Arc::new(fs), PathBuf::from("/logs"));
+    app_state.set_background_jobs(true);
+    app_state.get_active_tab_mut().update_preview();
+    finish_preview(&mut app_state).await;
+    let tab = app_state.get_active_tab_mut();
+    tab.preview_rows = 10;
+
+    // The page stays put until the line is found; a second jump replaces the first.
+    tab.jump_preview(TextTarget::Line(50_000)).unwrap();
+    tab.jump_preview(TextTarget::Line(123_456)).unwrap();
+    assert_eq!(tab.jumping_to(), Some(123_456));
+    // The watcher reporting the file changed doesn't cancel it.
+    tab.apply_changes(&[PathBuf::from("/logs/big.log")].into(), false);
+    assert_eq!(tab.jumping_to(), Some(123_456));
+    let Some(Preview::Paged(page)) = tab.preview_content.as_deref() else { panic!() };
+    assert_eq!(page.offset, 0);
+
+    let (index, update) = app_state::next_tab_update(&mut app_state.tabs).await;
+    assert!(matches!(update, TabUpdate::Page(Some(Ok(_)))));
+    app_state.apply_tab_update(index, update);
+    let tab = app_state.get_active_tab();
+    assert_eq!(tab.jumping_to(), None);
+    let Some(Preview::Paged(page)) = tab.preview_content.as_deref() else { panic!() };
+    assert_eq!(page.header(tab.preview_scroll.0 as usize), "line 123456, 61% of 2.48 MiB");
+
+    // Scrolling off the page reads the next one on a job too.
+    let end = page.end;
+    let tab = app_state.get_active_tab_mut();
+    while tab.page_job.is_none() {
+        tab.scroll_preview(20);
+    }
+    let (index, update) = app_state::next_tab_update(&mut app_state.tabs).await;
+    assert!(matches!(update, TabUpdate::Page(Some(Ok(_)))));
+    app_state.apply_tab_update(index, update);
+    let Some(Preview::Paged(page)) = app_state.get_active_tab().preview_content.as_deref() else { panic!() };
+    assert!(page.end > end);
+}
+
+#[test]
+fn test_followed_files_show_lines_as_they_are_appended() {
+    let fs = MemoryFs::new();
+    let lines = |range: std::ops::RangeInclusive<u32>| range.map(|n| format!("line {:07}\n", n)).collect::<String>();
+    fs.add_file("/logs/app.log", lines(1..=200_000)).add_file("/logs/core", b"\x7fELF\0\0\0".to_vec());
+
+    let mut app_state = AppState::with_vfs(Config::default(), Arc::new(fs.clone()), PathBuf::from("/logs"));
+    let header = |app_state: &AppState| {
+        let tab = app_state.get_active_tab();
+        let Some(Preview::Paged(page)) = tab.preview_content.as_deref() else {
+            panic!("no paged text: {:?}", tab.preview_text());
+        };
+        let top = page.text.lines().nth(tab.preview_scroll.0 as usize).unwrap().to_string();
+        (top, page.header(tab.preview_scroll.0 as usize))
+    };
+    let tab = app_state.get_active_tab_mut();
+    tab.preview_rows = 10;
+    tab.jump_preview(TextTarget::Line(199_000)).unwrap();
+
+    // Following starts at the end, still counting lines.
+    app_state.toggle_follow();
+    assert!(app_state.is_following());
+    assert_eq!(header(&app_state).0, "line 0199991");
+    assert!(header(&app_state).1.starts_with("line 199991, 99% of"), "{:?}", header(&app_state));
+
+    // Nothing new, nothing to do.
+    app_state.poll_follows();
+    assert_eq!(header(&app_state).0, "line 0199991");
+    fs.add_file("/logs/app.log", lines(1..=200_005));
+    app_state.poll_follows();
+    assert!(header(&app_state).1.starts_with("line 199996, 99% of"), "{:?}", header(&app_state));
+
+    // The watcher reporting the file changed has the same effect.
+    fs.add_file("/logs/app.log", lines(1..=200_010));
+    app_state.get_active_tab_mut().apply_changes(&[PathBuf::from("/logs/app.log")].into(), false);
+    assert!(app_state.is_following());
+    assert_eq!(header(&app_state).0, "line 0200001");
+
+    // Truncated, then rotated to a new file that outgrows the old one before
+    // it's looked at again: both are read afresh.
+    fs.add_file("/logs/app.log", lines(1..=3));
+    app_state.poll_follows();
+    assert_eq!(header(&app_state), ("line 0000001".to_string(), "line 1, 0% of 39 B".to_string()));
+    fs.add_file("/logs/app.log", lines(1..=100).replace("line", "next"));
+    app_state.poll_follows();
+    assert_eq!(header(&app_state).0, "next 0000091");
+    fs.add_file("/logs/app.log", lines(1..=150_000).replace("line", "rotd"));
+    app_state.poll_follows();
+    assert_eq!(header(&app_state).0, "rotd 0149991");
+    assert!(header(&app_state).1.starts_with("99% of"), "{:?}", header(&app_state));
+
+    // Scrolling back stops following, and so does the key.
+    app_state.get_active_tab_mut().scroll_preview(-1);
+    assert!(!app_state.is_following());
+    app_state.toggle_follow();
+    assert!(app_state.is_following());
+    app_state.toggle_follow();
+    assert!(!app_state.is_following());
+
+    // Binary files can't be followed.
+    app_state.get_active_tab_mut().move_cursor_down(false);
+    app_state.toggle_follow();
+    assert!(!app_state.is_following());
+    assert_eq!(app_state.notification.as_deref(), Some("Only text files can be followed"));
+}
+
+#[tokio::test]
+async fn test_followed_files_are_checked_in_background() {
+    let fs = MemoryFs::new();
+    let lines = |range: std::ops::RangeInclusive<u32>| range.map(|n| format!("line {:07}\n", n)).collect::<String>();
+    fs.add_file("/logs/app.log", lines(1..=100));
+
+    let mut app_state = AppState::with_vfs(Config::default(), Arc::new(fs.clone()), PathBuf::from("/logs"));
+    app_state.set_background_jobs(true);
+    app_state.get_active_tab_mut().preview_rows = 10;
+    app_state.toggle_follow();
+    let top = |app_state: &AppState| {
+        let tab = app_state.get_active_tab();
+        let Some(Preview::Paged(page)) = tab.preview_content.as_deref() else { panic!() };
+        page.text.lines().nth(tab.preview_scroll.0 as usize).unwrap().to_string()
+    };
+    assert_eq!(top(&app_state), "line 0000091");
+
+    // The new lines show once the check comes back.
+    fs.add_file("/logs/app.log", lines(1..=105));
+    app_state.poll_follows();
+    assert_eq!(top(&app_state), "line 0000091");
+    let (index, update) = app_state::next_tab_update(&mut app_state.tabs).await;
+    assert!(matches!(update, TabUpdate::Follow(Some(_))));
+    app_state.apply_tab_update(index, update);
+    assert!(app_state.is_following());
+    assert_eq!(top(&app_state), "line 0000096");
+
+    // A check that comes back after following stopped changes nothing.
+    fs.add_file("/logs/app.log", lines(1..=110));
+    app_state.poll_follows();
+    app_state.get_active_tab_mut().follow = None;
+    let (index, update) = app_state::next_tab_update(&mut app_state.tabs).await;
+    app_state.apply_tab_update(index, update);
+    assert!(!app_state.is_following());
+    assert_eq!(top(&app_state), "line 0000096");
+}
+
+#[test]
+fn test_images_are_decoded_and_scaled_down() {
+    let encode = |width: u32, height: u32, format: image::ImageFormat| {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image::RgbaImage::from_pixel(width, height, image::Rgba([0, 128, 255, 255]))
+            .write_to(&mut bytes, format)
+            .unwrap();
+        bytes.into_inner()
+    };
+    let fs = MemoryFs::new();
+    fs.add_file("/img/wide.png", encode(1600, 100, image::ImageFormat::Png))
+        .add_file("/img/small.gif", encode(30, 20, image::ImageFormat::Gif))
+        .add_file("/img/broken.png", b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDRtruncated".to_vec());
+    let generate = |path: &str| generate_with(&Previewer::default(), &fs, path);
+
+    let wide = generate("/img/wide.png");
+    let Preview::Image(image) = wide.as_ref() else {
+        panic!("not an image: {:?}", wide);
+    };
+    assert_eq!(image.description, "PNG image, 1600x100");
+    // The default resolution is 800x600.
+    assert_eq!(image.pixels.dimensions(), (800, 50));
+    assert_eq!(image.pixels.get_pixel(400, 25).0, [0, 128, 255, 255]);
+
+    let small = generate("/img/small.gif");
+    let Preview::Image(image) = small.as_ref() else {
+        panic!("not an image: {:?}", small);
+    };
+    assert_eq!((image.description.as_str(), image.pixels.dimensions()), ("GIF image, 30x20", (30, 20)));
+
+    assert!(generate("/img/broken.png").text().starts_with("Error decoding image:"));
+}
+
+#[test]
+fn test_mime_detection_prefers_content_over_name() {
+    let zip = b"PK\x03\x04\x14\x00\x00\x00\x08\x00";
+    // Magic bytes win over a misleading name.
+    assert_eq!(mime::detect(Path::new("photo.txt"), b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR"), "image/png");
+    // Generic containers keep the more specific type from the name.
+    assert_eq!(mime::detect(Path::new("book.epub"), zip), "application/epub+zip");
+    assert_eq!(mime::detect(Path::new("data.zip"), zip), "application/zip");
+    // Without magic the extension decides, then whether it reads as text.
+    assert_eq!(mime::detect(Path::new("Cargo.TOML"), b"[package]"), "application/toml");
+    assert_eq!(mime::detect(Path::new("README"), "caf\u{e9}".as_bytes()), mime::TEXT);
+    assert_eq!(mime::detect(Path::new("blob"), b"\x00\x01\x02"), mime::BINARY);
+    assert_eq!(mime::from_extension(Path::new("noext")), None);
+    assert!(mime::is_textual("text/x-rust") && mime::is_textual("application/json"));
+    assert!(!mime::is_textual("application/pdf"));
+}
+
+#[test]
+fn test_entries_get_the_mime_type_detected_when_previewed() {
+    let fs = MemoryFs::new();
+    fs.add_file("/m/notes", "plain words").add_file("/m/picture.txt", b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR".to_vec());
+    fs.add_dir("/m/sub");
+    let mut app_state = AppState::with_vfs(Config::default(), Arc::new(fs), PathBuf::from("/m"));
+    let mime_of = |app_state: &AppState, name: &str| {
+        let tab = app_state.get_active_tab();
+        tab.entries.iter().find(|entry| entry.name == name).unwrap().mime
+    };
+
+    // Listing only guesses from the name.
+    assert_eq!(mime_of(&app_state, "sub"), Some(mime::DIRECTORY));
+    assert_eq!(mime_of(&app_state, "notes"), None);
+    assert_eq!(mime_of(&app_state, "picture.txt"), Some("text/plain"));
+
+    // Previewing looks at the content.
+    let tab = app_state.get_active_tab_mut();
+    tab.move_cursor_down(false);
+    assert_eq!(mime_of(&app_state, "notes"), Some(mime::TEXT));
+    app_state.get_active_tab_mut().move_cursor_down(false);
+    assert_eq!(mime_of(&app_state, "picture.txt"), Some("image/png"));
+    assert!(preview(&app_state).unwrap().starts_with("Error decoding image:"));
+}
+
+#[test]
+fn test_registered_previewers_take_precedence() {
+    struct Shout;
+    impl FilePreviewer for Shout {
+        fn name(&self) -> &'static str {
+            "shout"
+        }
+        fn handles(&self, mime: &str) -> bool {
+            mime == "text/markdown"
+        }
+        fn preview(&self, request: &PreviewRequest, _file: &mut dyn io::vfs::VfsFile) -> Option<Preview> {
+            Some(Preview::Text(String::from_utf8_lossy(request.head).to_uppercase()))
+        }
+    }
+
+    let fs = MemoryFs::new();
+    fs.add_file("/r/a.md", "# title").add_file("/r/b.txt", "# title");
+    let metadata = |path: &str| fs.metadata(Path::new(path)).unwrap();
+    let mut previewer = Previewer::default();
+    previewer.register(Box::new(Shout));
+    let generate = |path: &str| generate_with(&previewer, &fs, path);
+
+    assert_eq!(generate("/r/a.md").text(), "# TITLE");
+    assert_eq!(generate("/r/b.txt").text(), "# title");
+    assert_eq!(previewer.mime(Path::new("/r/a.md"), &metadata("/r/a.md")), Some("text/markdown"));
+
+    // Nothing but the fallback: everything is a hex dump.
+    let mut registry = PreviewerRegistry::new();
+    registry.register(Box::new(HexPreviewer));
+    let previewer = Previewer::with_registry(registry);
+    let dump = generate_with(&previewer, &fs, "/r/b.txt");
+    assert!(matches!(dump.as_ref(), Preview::Hex(_)));
+}
+
+/// A PDF with one line of Helvetica text per page.
+fn make_pdf(title: &str, pages: &[&str]) -> Vec<u8> {
+    use lopdf::content::{Content, Operation};
+    use lopdf::{dictionary, Document, Object, Stream};
+
+    let mut document = Document::with_version("1.5");
+    let pages_id = document.new_object_id();
+    let font_id = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+    });
+    let resources_id = document.add_object(dictionary! {
+        "Font" => dictionary! { "F1" => font_id },
+    });
+    let kids: Vec<Object> = pages
+        .iter()
+        .map(|text| {
+            let content = Content {
+                operations: vec![
+                    Operation::new("BT", vec![]),
+                    Operation::new("Tf", vec!["F1".into(), 24.into()]),
+                    Operation::new("Td", vec![100.into(), 600.into()]),
+                    Operation::new("Tj", vec![Object::string_literal(*text)]),
+                    Operation::new("ET", vec![]),
+                ],
+            };
+            let content_id = document.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+            document
+                .add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "Contents" => content_id,
+                })
+                .into()
+        })
+        .collect();
+    document.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Count" => kids.len() as i64,
+            "Kids" => kids,
+            "Resources" => resources_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        }),
+    );
+    let catalog_id = document.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    let info_id = document.add_object(dictionary! {
+        "Title" => Object::string_literal(title),
+        "Author" => Object::string_literal("A. Writer"),
+        "CreationDate" => Object::string_literal("D:20240131154500+01'00'"),
+    });
+    document.trailer.set("Root", catalog_id);
+    document.trailer.set("Info", info_id);
+    let mut bytes = Vec::new();
+    document.save_to(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn test_pdfs_show_metadata_and_text_of_the_first_pages() {
+    let fs = MemoryFs::new();
+    fs.add_file("/docs/report.pdf", make_pdf("Quarterly Report", &["First page", "Second page", "Third", "Fourth"]))
+        .add_file("/docs/broken.pdf", "%PDF-1.4\nnot really");
+    let metadata = |path: &str| fs.metadata(Path::new(path)).unwrap();
+    let previewer = Previewer::default();
+    let generate = |path: &str| generate_with(&previewer, &fs, path);
+
+    let report = generate("/docs/report.pdf");
+    let Preview::Document { text, image } = report.as_ref() else {
+        panic!("not a document: {:?}", report);
+    };
+    // The first page is only rendered when enabled.
+    assert!(image.is_none());
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(
+        lines[..5],
+        [
+            "Title:    Quarterly Report",
+            "Author:   A. Writer",
+            "Created:  2024-01-31 15:45:00",
+            "Pages:    4",
+            "Version:  1.5",
+        ]
+    );
+    assert!(text.contains("── Page 1 ──\nFirst page"));
+    assert!(text.contains("── Page 3 ──\nThird"));
+    assert!(!text.contains("Fourth"));
+    assert_eq!(previewer.mime(Path::new("/docs/report.pdf"), &metadata("/docs/report.pdf")), Some("application/pdf"));
+
+    assert!(generate("/docs/broken.pdf").text().starts_with("Error reading PDF:"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pdf_rendering_is_killed_when_it_takes_too_long() {
+    use std::os::unix::fs::PermissionsExt;
+    // A pdftoppm that hangs, found before any real one.
+    let bin = tempdir::TempDir::new("bin").unwrap();
+    let pdftoppm = bin.path().join("pdftoppm");
+    std::fs::write(&pdftoppm, "#!/bin/sh\nexec sleep 5\n").unwrap();
+    std::fs::set_permissions(&pdftoppm, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::join_paths(std::iter::once(bin.path().to_path_buf()).chain(std::env::split_paths(
+        &std::env::var_os("PATH").unwrap_or_default(),
+    )))
+    .unwrap();
+    std::env::set_var("PATH", path);
+
+    let fs = MemoryFs::new();
+    fs.add_file("/docs/report.pdf", make_pdf("Quarterly Report", &["First page"]));
+    let mut config = Config::default();
+    config.preview.pdf_raster = true;
+    config.preview.external_timeout = 0.2;
+    let started = std::time::Instant::now();
+    let preview = generate_with(&Previewer::new(&config), &fs, "/docs/report.pdf");
+    assert!(started.elapsed() < std::time::Duration::from_secs(2));
+    let Preview::Document { text, image } = preview.as_ref() else {
+        panic!("not a document: {:?}", preview);
+    };
+    assert!(image.is_none());
+    assert!(text.contains("── Page 1 ──\nFirst page"));
+}
+
+#[test]
+fn test_archives_list_their_entries_as_a_tree() {
+    use std::io::Write;
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let deflated = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let stored = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    zip.add_directory("src/", stored).unwrap();
+    for (name, content, options) in [
+        ("src/main.rs", "fn main() {}\n".repeat(100), deflated),
+        ("src/util/mod.rs", "x".repeat(2048), stored),
+        ("README", "hi".to_string(), stored),
+    ] {
+        zip.start_file(name, options).unwrap();
+        zip.write_all(content.as_bytes()).unwrap();
+    }
+    let zip = zip.finish().unwrap().into_inner();
+
+    let fs = MemoryFs::new();
+    fs.add_file("/a/code.zip", zip).add_file("/a/broken.zip", b"PK\x03\x04\x14\x00\x00\x00".to_vec());
+    let generate = |path: &str| generate_with(&Previewer::default(), &fs, path);
+
+    let listing = generate("/a/code.zip");
+    let lines: Vec<&str> = listing.text().lines().collect();
+    assert_eq!(lines[0], "3 files, 2 directories");
+    assert!(lines[1].starts_with("Total: 3.27 KiB ("), "{}", lines[1]);
+    assert_eq!(
+        lines[3..],
+        [
+            "src/            3.27 KiB   62%",
+            "├── util/          2 KiB  100%",
+            "│   └── mod.rs     2 KiB  100%",
+            "└── main.rs     1.27 KiB    3%",
+            "README               2 B  100%",
+        ]
+    );
+
+    assert!(generate("/a/broken.zip").text().starts_with("Error reading archive:"));
+
+    // Sizes that add up past u64::MAX are shown as the most there can be.
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for name in ["big/a", "big/b"] {
+        zip.start_file(name, stored.large_file(true)).unwrap();
+        zip.write_all(b"x").unwrap();
+    }
+    let mut zip = zip.finish().unwrap().into_inner();
+    // The zip64 extra field of each central directory entry holds the sizes.
+    let mut at = 0;
+    while let Some(found) = zip[at..].windows(4).position(|window| window == b"PK\x01\x02") {
+        at += found + 4;
+        let extra = at + zip[at..].windows(4).position(|window| window == b"\x01\x00\x10\x00").unwrap() + 4;
+        zip[extra..extra + 16].copy_from_slice(&[[0xff; 8], [0xff; 8]].concat());
+    }
+    fs.add_file("/a/huge.zip", zip);
+    let listing = generate("/a/huge.zip");
+    assert_eq!(listing.text().lines().nth(3), Some("big/   16 EiB  100%"));
+}
+
+fn make_zip(files: &[(&str, &str)]) -> Vec<u8> {
+    use std::io::Write;
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (name, content) in files {
+        zip.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(content.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+fn document_text(fs: &MemoryFs, path: &str) -> String {
+    let preview = generate_with(&Previewer::default(), fs, path);
+    let Preview::Document { text, .. } = preview.as_ref() else {
+        panic!("not a document: {:?}", preview);
+    };
+    text.clone()
+}
+
+#[test]
+fn test_word_processor_documents_and_ebooks_show_their_text() {
+    let docx = make_zip(&[
+        (
+            "word/document.xml",
+            r#"<w:document xmlns:w="w"><w:body>
+                <w:p><w:pPr><w:tabs><w:tab w:val="left"/></w:tabs></w:pPr><w:r><w:t>Design</w:t></w:r><w:r><w:t xml:space="preserve"> spec</w:t></w:r></w:p>
+                <w:p><w:r><w:t>Goals</w:t><w:tab/><w:t>fast &amp; small</w:t></w:r></w:p>
+            </w:body></w:document>"#,
+        ),
+        ("docProps/core.xml", r#"<cp:coreProperties xmlns:dc="dc"><dc:title>Spec</dc:title><dc:creator>Ada</dc:creator></cp:coreProperties>"#),
+        ("docProps/app.xml", "<Properties><Pages>2</Pages></Properties>"),
+    ]);
+    let odt = make_zip(&[
+        (
+            "content.xml",
+            r#"<office:document-content><office:body><office:text>
+                <text:h>Notes</text:h><text:p>one<text:s text:c="2"/>two<text:line-break/>three</text:p>
+            </office:text></office:body></office:document-content>"#,
+        ),
+        (
+            "meta.xml",
+            r#"<office:meta><meta:initial-creator>Bob</meta:initial-creator><meta:document-statistic meta:page-count="1" meta:word-count="4"/></office:meta>"#,
+        ),
+    ]);
+    let epub = make_zip(&[
+        ("mimetype", "application/epub+zip"),
+        ("META-INF/container.xml", r#"<container><rootfiles><rootfile full-path="OEBPS/content.opf"/></rootfiles></container>"#),
+        (
+            "OEBPS/content.opf",
+            r#"<package><metadata><dc:title>A Tale</dc:title><dc:creator>C. Author</dc:creator><dc:language>en</dc:language></metadata>
+               <manifest><item id="c1" href="text/chapter%201.xhtml"/><item id="c2" href="text/ch2.xhtml"/></manifest>
+               <spine><itemref idref="c1"/><itemref idref="c2"/></spine></package>"#,
+        ),
+        (
+            "OEBPS/text/chapter 1.xhtml",
+            "<html><head><title>skip me</title><style>p {}</style></head><body>\n  <h1>Chapter One</h1>\n  <p>It was a\n     <em>dark</em>&nbsp;night.</p></body></html>",
+        ),
+        ("OEBPS/text/ch2.xhtml", "<html><body><p>The end.</p></body></html>"),
+    ]);
+    // A space count that would take more memory than there is.
+    let spaces = make_zip(&[(
+        "content.xml",
+        r#"<office:document-content><office:body><office:text>
+            <text:p>a<text:s text:c="18446744073709551615"/>b</text:p>
+        </office:text></office:body></office:document-content>"#,
+    )]);
+    let fs = MemoryFs::new();
+    fs.add_file("/d/spec.docx", docx).add_file("/d/notes.odt", odt).add_file("/d/tale.epub", epub);
+    fs.add_file("/d/spaces.odt", spaces);
+
+    assert_eq!(
+        document_text(&fs, "/d/spec.docx"),
+        "Title:    Spec\nAuthor:   Ada\nPages:    2\nWords:    5\n\nDesign spec\nGoals\tfast & small"
+    );
+    assert_eq!(document_text(&fs, "/d/notes.odt"), "Author:   Bob\nPages:    1\nWords:    4\n\nNotes\none  two\nthree");
+    assert_eq!(
+        document_text(&fs, "/d/tale.epub"),
+        "Title:    A Tale\nAuthor:   C. Author\nLanguage: en\nChapters: 2\nWords:    9\n\nChapter One\nIt was a dark night.\n\nThe end."
+    );    let text = document_text(&fs, "/d/spaces.odt");
+    assert!(text.starts_with("Words:    2\n\na   "));
+    assert!(text.len() as u64 <= MAX_PREVIEW_SIZE + 4);
+}
+
+#[test]
+fn test_spreadsheets_show_their_first_sheet_as_a_table() {
+    let xlsx = make_zip(&[
+        (
+            "xl/workbook.xml",
+            r#"<workbook><sheets><sheet name="Budget" r:id="rId1"/><sheet name="Other" r:id="rId2"/></sheets></workbook>"#,
+        ),
+        (
+            "xl/_rels/workbook.xml.rels",
+            r#"<Relationships><Relationship Id="rId2" Target="worksheets/sheet2.xml"/><Relationship Id="rId1" Target="worksheets/first.xml"/></Relationships>"#,
+        ),
+        ("xl/sharedStrings.xml", "<sst><si><t>Item</t></si><si><r><t>Co</t></r><r><t>st</t></r></si><si><t>Rent</t></si></sst>"),
+        (
+            "xl/worksheets/first.xml",
+            r#"<worksheet><sheetData>
+                <row r="1"><c r="A1" t="s"><v>0</v></c><c r="B1" t="s"><v>1</v></c></row>
+                <row r="3"><c r="A3" t="s"><v>2</v></c><c r="B3"><v>1200.5</v></c><c r="D3" t="b"><v>1</v></c></row>
+            </sheetData></worksheet>"#,
+        ),
+    ]);
+    let ods = make_zip(&[
+        (
+            "content.xml",
+            r#"<office:document-content><office:body><office:spreadsheet>
+                <table:table table:name="Data">
+                  <table:table-row><table:table-cell><text:p>a</text:p></table:table-cell><table:table-cell table:number-columns-repeated="2"><text:p>b</text:p></table:table-cell><table:table-cell table:number-columns-repeated="1000"/></table:table-row>
+                  <table:table-row table:number-rows-repeated="2"><table:table-cell/><table:table-cell><text:p>long</text:p><text:p>cell</text:p></table:table-cell></table:table-row>
+                  <table:table-row table:number-rows-repeated="100000"><table:table-cell table:number-columns-repeated="1000"/></table:table-row>
+                </table:table>
+                <table:table table:name="Second"><table:table-row><table:table-cell><text:p>x</text:p></table:table-cell></table:table-row></table:table>
+            </office:spreadsheet></office:body></office:document-content>"#,
+        ),
+        ("meta.xml", "<office:meta><dc:title>Numbers</dc:title></office:meta>"),
+    ]);
+    // Positions and repeats past what any sheet could hold.
+    let huge_xlsx = make_zip(&[
+        ("xl/workbook.xml", r#"<workbook><sheets><sheet name="Huge"/></sheets></workbook>"#),
+        (
+            "xl/worksheets/sheet1.xml",
+            r#"<worksheet><sheetData>
+                <row r="18446744073709551615"><c r="ZZZZZZZZZZZZZZZZZZZZ1"><v>1</v></c><c><v>2</v></c></row><row><c><v>3</v></c></row>
+            </sheetData></worksheet>"#,
+        ),
+    ]);
+    let huge_ods = make_zip(&[(
+        "content.xml",
+        r#"<office:document-content><office:body><office:spreadsheet><table:table table:name="Huge">
+            <table:table-row><table:table-cell table:number-columns-repeated="18446744073709551615"><text:p>x</text:p></table:table-cell><table:table-cell><text:p>y</text:p></table:table-cell></table:table-row>
+            <table:table-row table:number-rows-repeated="18446744073709551615"/>
+            <table:table-row table:number-rows-repeated="18446744073709551615"><table:table-cell><text:p>z</text:p></table:table-cell></table:table-row>
+        </table:table></office:spreadsheet></office:body></office:document-content>"#,
+    )]);
+    let fs = MemoryFs::new();
+    fs.add_file("/s/budget.xlsx", xlsx).add_file("/s/data.ods", ods);
+    fs.add_file("/s/huge.xlsx", huge_xlsx).add_file("/s/huge.ods", huge_ods);
+
+    assert_eq!(
+        document_text(&fs, "/s/budget.xlsx"),
+        "Sheet:    Budget (1 of 2)\nSize:     3 rows × 4 columns\n\nItem │ Cost\n\nRent │ 1200.5 │  │ TRUE"
+    );
+    assert_eq!(
+        document_text(&fs, "/s/data.ods"),
+        "Title:    Numbers\nSheet:    Data (1 of 2)\nSize:     3 rows × 3 columns\n\na │ b         │ b\n  │ long cell\n  │ long cell"
+    );    assert!(document_text(&fs, "/s/huge.xlsx")
+        .starts_with("Sheet:    Huge (1 of 1)\nSize:     18446744073709551615 rows × 2 columns\n"));
+    let huge = document_text(&fs, "/s/huge.ods");
+    assert!(huge.starts_with("Sheet:    Huge (1 of 1)\nSize:     1 rows × 18446744073709551615 columns\n\nx │ x │"), "{}", huge);
+    assert!(huge.ends_with("… showing 1 rows × 26 columns"));
+}
+
+#[test]
+fn test_photos_show_their_exif_data_below_them() {
+    use exif::{experimental::Writer, Field, In, Tag, Value};
+    let field = |tag, value| Field { tag, ifd_num: In::PRIMARY, value };
+    let ascii = |text: &str| Value::Ascii(vec![text.as_bytes().to_vec()]);
+    let rationals = |values: &[(u32, u32)]| Value::Rational(values.iter().map(|&value| value.into()).collect());
+    let fields = [
+        field(Tag::Make, ascii("Canon")),
+        field(Tag::Model, ascii("Canon EOS R5")),
+        field(Tag::DateTimeOriginal, ascii("2024:05:01 12:34:56")),
+        field(Tag::ExposureTime, rationals(&[(1, 125)])),
+        field(Tag::FNumber, rationals(&[(28, 10)])),
+        field(Tag::PhotographicSensitivity, Value::Short(vec![400])),
+        field(Tag::FocalLength, rationals(&[(35, 1)])),
+        field(Tag::GPSLatitudeRef, ascii("N")),
+        field(Tag::GPSLatitude, rationals(&[(35, 1), (39, 1), (3090, 100)])),
+        field(Tag::GPSLongitudeRef, ascii("W")),
+        field(Tag::GPSLongitude, rationals(&[(139, 1), (44, 1), (4356, 100)])),
+    ];
+    let mut writer = Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut tiff = std::io::Cursor::new(Vec::new());
+    writer.write(&mut tiff, false).unwrap();
+    let tiff = tiff.into_inner();
+
+    // The EXIF data goes in an APP1 segment right after the start of the JPEG.
+    let mut jpeg = std::io::Cursor::new(Vec::new());
+    image::RgbImage::new(8, 6).write_to(&mut jpeg, image::ImageFormat::Jpeg).unwrap();
+    let jpeg = jpeg.into_inner();
+    let mut photo = jpeg[..2].to_vec();
+    photo.extend_from_slice(&[0xff, 0xe1]);
+    photo.extend_from_slice(&(8 + tiff.len() as u16).to_be_bytes());
+    photo.extend_from_slice(b"Exif\0\0");
+    photo.extend_from_slice(&tiff);
+    photo.extend_from_slice(&jpeg[2..]);
+
+    let fs = MemoryFs::new();
+    fs.add_file("/img/photo.jpg", photo);
+    let preview = generate_with(&Previewer::default(), &fs, "/img/photo.jpg");
+    let Preview::Document { text, image: Some(image) } = preview.as_ref() else {
+        panic!("not a photo with EXIF data: {:?}", preview);
+    };
+    assert_eq!(image.description, "JPG image, 8x6");
+    assert_eq!(
+        text,
+        "Camera:   Canon EOS R5\nTaken:    2024-05-01 12:34:56\nExposure: 1/125 s, f/2.8, ISO 400, 35 mm\n\
+         GPS:      35.658583, -139.745433\nSize:     8x6"
+    );
+}
+
+fn ogg_page(granule: u64, sequence: u32, packet: &[u8]) -> Vec<u8> {
+    let mut page = b"OggS\0\0".to_vec();
+    page.extend_from_slice(&granule.to_le_bytes());
+    page.extend_from_slice(&7u32.to_le_bytes());
+    page.extend_from_slice(&sequence.to_le_bytes());
+    page.extend_from_slice(&[0; 4]);
+    // Lacing: 255-byte segments, then a shorter one to end the packet.
+    let mut segments = vec![255; packet.len() / 255];
+    segments.push((packet.len() % 255) as u8);
+    page.push(segments.len() as u8);
+    page.extend_from_slice(&segments);
+    page.extend_from_slice(packet);
+    page
+}
+
+fn vorbis_comment(comments: &[&str]) -> Vec<u8> {
+    let mut block = 4u32.to_le_bytes().to_vec();
+    block.extend_from_slice(b"test");
+    block.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        block.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        block.extend_from_slice(comment.as_bytes());
+    }
+    block
+}
+
+#[test]
+fn test_audio_files_show_their_tags_and_duration() {
+    use id3::TagLike;
+    // An ID3v2 tag, then eight seconds of 128 kb/s frames at 44.1 kHz.
+    let mut tag = id3::Tag::new();
+    tag.set_title("Song");
+    tag.set_artist("Band");
+    tag.set_album("Album");
+    tag.set_year(2021);
+    tag.set_track(3);
+    tag.set_total_tracks(12);
+    tag.set_genre("Rock");
+    let mut mp3 = Vec::new();
+    tag.write_to(&mut mp3, id3::Version::Id3v24).unwrap();
+    for _ in 0..300 {
+        let mut frame = vec![0; 417];
+        frame[..4].copy_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+        mp3.extend_from_slice(&frame);
+    }
+
+    // Stream info for 185 seconds of 16-bit stereo at 44.1 kHz, then the tags.
+    let mut flac = b"fLaC\x00\x00\x00\x22".to_vec();
+    flac.extend_from_slice(&[0; 10]);
+    let samples = 44100 * 185;
+    flac.extend_from_slice(&(44100u64 << 44 | 1 << 41 | 15 << 36 | samples).to_be_bytes());
+    flac.extend_from_slice(&[0; 16]);
+    let comments = vorbis_comment(&["TITLE=Night", "artist=Someone", "DATE=1999"]);
+    flac.extend_from_slice(&(0x8400_0000 | comments.len() as u32).to_be_bytes());
+    flac.extend_from_slice(&comments);
+
+    let mut wav = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0\x01\0\x02\0".to_vec();
+    wav.extend_from_slice(&44100u32.to_le_bytes());
+    wav.extend_from_slice(&176400u32.to_le_bytes());
+    wav.extend_from_slice(b"\x04\0\x10\0LIST\x14\0\0\0INFOINAM\x07\0\0\0Take 1\0\0data");
+    wav.extend_from_slice(&352800u32.to_le_bytes());
+    wav.extend_from_slice(&vec![0; 352800]);
+
+    let mut opus = ogg_page(0, 0, b"OpusHead\x01\x02\x38\x01\x80\xbb\0\0\0\0\0");
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&vorbis_comment(&["TITLE=Voice memo"]));
+    opus.extend_from_slice(&ogg_page(0, 1, &tags));
+    opus.extend_from_slice(&ogg_page(48000 * 62 + 312, 2, &[0; 300]));
+
+    let fs = MemoryFs::new();
+    fs.add_file("/m/song.mp3", mp3)
+        .add_file("/m/night.flac", flac)
+        .add_file("/m/take.wav", wav)
+        .add_file("/m/memo.opus", opus);
+
+    assert_eq!(
+        document_text(&fs, "/m/song.mp3"),
+        "Title:    Song\nArtist:   Band\nAlbum:    Album\nDate:     2021\nTrack:    3/12\nGenre:    Rock\n\
+         Duration: 0:08\nBitrate:  128 kb/s\nAudio:    MP3, 44.1 kHz, stereo"
+    );
+    assert_eq!(
+        document_text(&fs, "/m/night.flac"),
+        "Title:    Night\nArtist:   Someone\nDate:     1999\nDuration: 3:05\nAudio:    FLAC, 44.1 kHz, 16-bit, stereo"
+    );
+    assert_eq!(
+        document_text(&fs, "/m/take.wav"),
+        "Title:    Take 1\nDuration: 0:02\nBitrate:  1411 kb/s\nAudio:    PCM, 44.1 kHz, 16-bit, stereo"
+    );
+    assert_eq!(document_text(&fs, "/m/memo.opus"), "Title:    Voice memo\nDuration: 1:02\nAudio:    Opus, 48 kHz, stereo");
+}
+
+fn mp4_box(kind: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut mp4_box = (8 + body.len() as u32).to_be_bytes().to_vec();
+    mp4_box.extend_from_slice(kind);
+    mp4_box.extend_from_slice(body);
+    mp4_box
+}
+
+fn ebml(id: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut element = id.to_vec();
+    if body.len() < 127 {
+        element.push(0x80 | body.len() as u8);
+    } else {
+        element.push(0x01);
+        element.extend_from_slice(&(body.len() as u64).to_be_bytes()[1..]);
+    }
+    element.extend_from_slice(body);
+    element
+}
+
+#[test]
+fn test_videos_show_their_duration_and_streams() {
+    let track = |handler: &[u8], tkhd: &[u8], entry: Vec<u8>| {
+        let mut hdlr = vec![0; 8];
+        hdlr.extend_from_slice(handler);
+        hdlr.extend_from_slice(&[0; 13]);
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend_from_slice(&entry);
+        let stbl = mp4_box(b"stbl", &mp4_box(b"stsd", &stsd));
+        let mdia = [mp4_box(b"hdlr", &hdlr), mp4_box(b"minf", &stbl)].concat();
+        mp4_box(b"trak", &[mp4_box(b"tkhd", tkhd), mp4_box(b"mdia", &mdia)].concat())
+    };
+    let mut mvhd = vec![0; 12];
+    mvhd.extend_from_slice(&1000u32.to_be_bytes());
+    mvhd.extend_from_slice(&3_725_000u32.to_be_bytes());
+    mvhd.extend_from_slice(&[0; 80]);
+    let mut tkhd = vec![0; 76];
+    tkhd.extend_from_slice(&(1920u32 << 16).to_be_bytes());
+    tkhd.extend_from_slice(&(1080u32 << 16).to_be_bytes());
+    let video = track(b"vide", &tkhd, mp4_box(b"avc1", &[0; 78]));
+    let mut mp4a = vec![0; 16];
+    mp4a.extend_from_slice(&[0, 2, 0, 16, 0, 0, 0, 0]);
+    mp4a.extend_from_slice(&(48000u32 << 16).to_be_bytes());
+    let audio = track(b"soun", &[0; 84], mp4_box(b"mp4a", &mp4a));
+    let title = mp4_box(b"\xa9nam", &mp4_box(b"data", b"\0\0\0\x01\0\0\0\0Clip"));
+    let meta = [vec![0; 4], mp4_box(b"hdlr", &[0; 25]), mp4_box(b"ilst", &title)].concat();
+    let moov = [mp4_box(b"mvhd", &mvhd), video, audio, mp4_box(b"udta", &mp4_box(b"meta", &meta))].concat();
+    let mp4 = [mp4_box(b"ftyp", b"isom\0\0\0\0isom"), mp4_box(b"moov", &moov), mp4_box(b"mdat", &[0; 64])].concat();
+
+    let header = [ebml(b"\x42\x82", b"matroska"), ebml(b"\x42\x87", &[4]), ebml(b"\x42\x85", &[2])].concat();
+    let info = [
+        ebml(b"\x2a\xd7\xb1", &[0x0f, 0x42, 0x40]),
+        ebml(b"\x44\x89", &90_500f32.to_be_bytes()),
+        ebml(b"\x7b\xa9", b"Trailer"),
+    ]
+    .concat();
+    let video = [
+        ebml(b"\x83", &[1]),
+        ebml(b"\x86", b"V_VP9"),
+        ebml(b"\xe0", &[ebml(b"\xb0", &640u16.to_be_bytes()), ebml(b"\xba", &360u16.to_be_bytes())].concat()),
+    ]
+    .concat();
+    let audio = [
+        ebml(b"\x83", &[2]),
+        ebml(b"\x86", b"A_OPUS"),
+        ebml(b"\x22\xb5\x9c", b"eng"),
+        ebml(b"\xe1", &[ebml(b"\xb5", &48000f32.to_be_bytes()), ebml(b"\x9f", &[2])].concat()),
+    ]
+    .concat();
+    let tracks = [ebml(b"\xae", &video), ebml(b"\xae", &audio)].concat();
+    let segment = [
+        ebml(b"\x15\x49\xa9\x66", &info),
+        ebml(b"\x16\x54\xae\x6b", &tracks),
+        ebml(b"\x1f\x43\xb6\x75", &[0; 200]),
+    ]
+    .concat();
+    let mkv = [ebml(b"\x1a\x45\xdf\xa3", &header), ebml(b"\x18\x53\x80\x67", &segment)].concat();
+
+    // Durations that aren't known, or are too long to be real.
+    let movie = |mvhd: Vec<u8>| [mp4_box(b"ftyp", b"isom\0\0\0\0isom"), mp4_box(b"moov", &mp4_box(b"mvhd", &mvhd))].concat();
+    let unknown = movie([vec![0; 12], 1000u32.to_be_bytes().to_vec(), vec![0xff; 4], vec![0; 80]].concat());
+    let endless = movie([vec![1; 1], vec![0; 19], 1u32.to_be_bytes().to_vec(), (u64::MAX - 1).to_be_bytes().to_vec(), vec![0; 80]].concat());
+    let info = [ebml(b"\x2a\xd7\xb1", &[0x0f, 0x42, 0x40]), ebml(b"\x44\x89", &1e300f64.to_be_bytes())].concat();
+    let endless_mkv = [ebml(b"\x1a\x45\xdf\xa3", &header), ebml(b"\x18\x53\x80\x67", &ebml(b"\x15\x49\xa9\x66", &info))].concat();
+
+    let fs = MemoryFs::new();
+    fs.add_file("/v/clip.mp4", mp4).add_file("/v/trailer.mkv", mkv);
+    fs.add_file("/v/unknown.mp4", unknown).add_file("/v/endless.mp4", endless).add_file("/v/endless.mkv", endless_mkv);
+
+    assert_eq!(
+        document_text(&fs, "/v/clip.mp4"),
+        "Title:    Clip\nDuration: 1:02:05\nVideo:    H.264, 1920x1080\nAudio:    AAC, 48 kHz, stereo"
+    );
+    assert_eq!(
+        document_text(&fs, "/v/trailer.mkv"),
+        "Title:    Trailer\nDuration: 1:31\nVideo:    VP9, 640x360\nAudio:    Opus, 48 kHz, stereo, eng"
+    );    for path in ["/v/unknown.mp4", "/v/endless.mp4", "/v/endless.mkv"] {
+        assert!(!generate_with(&Previewer::default(), &fs, path).text().contains("Duration"), "{}", path);
+    }
+}
+
+#[test]
+fn test_data_files_are_shown_as_a_tree_that_folds() {
+    let fs = MemoryFs::new();
+    fs.add_file("/c/app.json", r#"{"name": "rtfm", "servers": [{"host": "a", "port": 22}, {}], "odd key": null}"#)
+        .add_file("/c/app.toml", "title = \"x\"\n[owner]\nborn = 1979-05-27\nratio = 2.0\n")
+        .add_file("/c/app.yaml", "a: 1\n---\n- true\n");
+
+    let Preview::Tree(tree) = generate_with(&Previewer::default(), &fs, "/c/app.toml").as_ref().clone() else {
+        panic!("not a tree");
+    };
+    assert_eq!(tree.format, "TOML");
+    assert_eq!(tree.text, "  title: \"x\"\n▾ owner: {2}\n    born: 1979-05-27\n    ratio: 2.0");
+    let Preview::Tree(tree) = generate_with(&Previewer::default(), &fs, "/c/app.yaml").as_ref().clone() else {
+        panic!("not a tree");
+    };
+    assert_eq!(tree.text, "▾ [0]: {1}\n    a: 1\n▾ [1]: [1]\n    [0]: true");
+
+    let mut app_state = AppState::with_vfs(Config::default(), Arc::new(fs), PathBuf::from("/"));
+    let tab = app_state.get_active_tab_mut();
+    tab.set_current_dir(PathBuf::from("/c"), false);
+    assert_eq!(tab.get_selected_entry_path(), Some(PathBuf::from("/c/app.json")));
+    let Some(Preview::Tree(tree)) = tab.preview_content.clone().as_deref().cloned() else {
+        panic!("not a tree");
+    };
+    assert_eq!(
+        tree.text,
+        "  name: \"rtfm\"\n▾ servers: [2]\n  ▾ [0]: {2}\n      host: \"a\"\n      port: 22\n    [1]: {0}\n  odd key: null"
+    );
+    let paths = |tab: &app_state::TabState| -> Vec<&str> {
+        tab.preview_folds.iter().map(|&node| tree.nodes[node].path.as_str()).collect()
+    };
+
+    // The top row is the one that folds; it stays on top.
+    tab.scroll_preview(2);
+    assert_eq!(tree.nodes[tab.preview_tree_node().unwrap()].path, ".servers[0]");
+    tab.toggle_preview_fold();
+    assert_eq!(paths(tab), [".servers[0]"]);
+    tab.scroll_preview(1);
+    assert_eq!(tree.nodes[tab.preview_tree_node().unwrap()].path, ".servers[1]");
+    tab.scroll_preview(10);
+    assert_eq!(tree.nodes[tab.preview_tree_node().unwrap()].path, "[\"odd key\"]");
+
+    // With anything folded, everything unfolds first.
+    tab.toggle_all_preview_folds();
+    assert!(tab.preview_folds.is_empty());
+    assert_eq!(tab.preview_scroll.0, 0);
+    tab.toggle_all_preview_folds();
+    assert_eq!(paths(tab), [".servers", ".servers[0]"]);
+    let rows = tree.rows(&tab.preview_folds).map(|node| tree.line(node, tab.preview_folds.contains(&node)));
+    assert_eq!(rows.collect::<Vec<_>>(), ["  name: \"rtfm\"", "▸ servers: [2]", "  odd key: null"]);
+}
+
+#[test]
+fn test_csv_files_are_shown_as_a_table() {
+    let fs = MemoryFs::new();
+    fs.add_file("/t/people.csv", "name,age,city\nAda,36,\"London, UK\"\nBob,7\n")
+        .add_file("/t/plain.tsv", "a\t\"b\nccc\td\n");
+
+    let Preview::Highlighted { text, lines } = generate_with(&Previewer::default(), &fs, "/t/people.csv").as_ref().clone() else {
+        panic!("not a table");
+    };
+    assert_eq!(text, "name │ age │ city\n─────┼─────┼───────────\nAda  │ 36  │ London, UK\nBob  │ 7");
+    assert!(lines[0][0].style.bold);
+    assert!(!lines[2][0].style.bold);
+    assert_eq!(generate_with(&Previewer::default(), &fs, "/t/plain.tsv").text(), "a   │ \"b\n────┼───\nccc │ d");
+}
+
+#[test]
+fn test_data_files_that_dont_parse_show_the_error_location() {
+    let fs = MemoryFs::new();
+    fs.add_file("/e/bad.json", "{\n  \"a\": 1,\n  \"b\" 2\n}\n")
+        .add_file("/e/bad.toml", "a = 1\nb = \n")
+        .add_file("/e/bad.yaml", "a: [1, 2\n");
+
+    let Preview::Highlighted { text, lines } = generate_with(&Previewer::default(), &fs, "/e/bad.json").as_ref().clone() else {
+        panic!("not highlighted");
+    };
+    assert_eq!(text, "JSON error at line 3, column 7: expected `:`\n\n{\n  \"a\": 1,\n  \"b\" 2\n}\n");
+    // The heading and a blank line come first; the error is on line 3.
+    let error_line = &lines[4];
+    assert_eq!(error_line[0].text, "  \"b\" ");
+    assert_eq!(error_line[1].text, "2");
+    assert!(error_line[1].style.underline && error_line[1].style.fg.is_some());
+
+    assert!(generate_with(&Previewer::default(), &fs, "/e/bad.toml").text().starts_with("TOML error at line 2, column 5: "));
+    assert!(generate_with(&Previewer::default(), &fs, "/e/bad.yaml").text().starts_with("YAML error at line 2, column 1: "));
+}
+
+#[test]
+fn test_markdown_is_rendered_with_a_source_view() {
+    let readme = "# Title\n\nSome *emphasis* and `code`, see [docs](https://x.dev).\n\n\
+        - one\n- two\n  1. nested\n- [x] done\n\n> quoted\n\n```rust\nfn main() {}\n```\n\n\
+        | a | b |\n|---|---|\n| 1 | 22 |\n\n---\n";
+    let fs = MemoryFs::new();
+    fs.add_file("/m/README.md", readme);
+
+    let Preview::Markdown { text, rendered, source } = generate_with(&Previewer::default(), &fs, "/m/README.md").as_ref().clone() else {
+        panic!("not markdown");
+    };
+    assert_eq!(text, readme);
+    assert_eq!(source.len(), readme.lines().count());
+    let lines: Vec<String> = rendered.iter().map(|line| line.iter().map(|span| span.text.as_str()).collect()).collect();
+    assert_eq!(
+        lines,
+        [
+            "Title",
+            "",
+            "Some emphasis and code, see docs (https://x.dev).",
+            "",
+            "• one",
+            "• two",
+            "  1. nested",
+            "• [x] done",
+            "",
+            "│ quoted",
+            "",
+            "  fn main() {}",
+            "",
+            "a │ b",
+            "──┼───",
+            "1 │ 22",
+            "",
+            "────────────────────────────────────────",
+        ]
+    );
+    assert!(rendered[0][0].style.bold && rendered[0][0].style.underline);
+    assert!(rendered[2][1].style.italic);
+    // Code blocks are highlighted as their language.
+    assert!(rendered[11].len() > 2);
+
+    let mut app_state = AppState::with_vfs(Config::default(), Arc::new(fs), PathBuf::from("/"));
+    let tab = app_state.get_active_tab_mut();
+    tab.set_current_dir(PathBuf::from("/m"), false);
+    tab.scroll_preview(3);
+    tab.toggle_markdown_source();
+    assert!(tab.markdown_source);
+    assert_eq!(tab.preview_scroll, (0, 0));
+}
+
+#[test]
+fn test_external_previewers_run_on_local_files() {
+    let dir = tempdir::TempDir::new("external").unwrap();
+    std::fs::write(dir.path().join("colors.log"), "\x1b[1;31mred\x1b[0m plain\n\x1b[38;5;21mblue\n").unwrap();
+    std::fs::write(dir.path().join("notes.txt"), "notes").unwrap();
+    std::fs::write(dir.path().join("data.json"), "{}").unwrap();
+    std::fs::write(dir.path().join("slow.csv"), "a,b").unwrap();
+
+    let mut config = Config::default();
+    config.preview.external_timeout = 0.2;
+    config.preview.external.insert("*.log".to_string(), "cat".to_string());
+    config.preview.external.insert("text/plain".to_string(), "printf 'words: '; wc -w < \"$1\"".to_string());
+    config.preview.external.insert("*.json".to_string(), "false".to_string());
+    config.preview.external.insert("*.csv".to_string(), "sleep 5; cat \"$1\"".to_string());
+    let previewer = Previewer::new(&config);
+    let generate = |name: &str| generate_with(&previewer, &io::vfs::LocalFs, dir.path().join(name));
+
+    // Colors are kept, other than the background.
+    let Preview::Highlighted { text, lines } = generate("colors.log").as_ref().clone() else {
+        panic!("not highlighted");
+    };
+    assert_eq!(text, "red plain\nblue");
+    assert_eq!(lines[0][0].style, rtfm_core::preview::SpanStyle { fg: Some((205, 0, 0)), bold: true, ..Default::default() });
+    assert_eq!(lines[0][1].text, " plain");
+    assert_eq!(lines[0][1].style, Default::default());
+    assert_eq!(lines[1][0].style.fg, Some((0, 0, 255)));
+
+    assert_eq!(generate("notes.txt").text().trim(), "words: 1");
+    // Failures and timeouts fall back to the built-in previewers.
+    assert!(matches!(generate("data.json").as_ref(), Preview::Tree(_)));
+    let started = std::time::Instant::now();
+    assert_eq!(generate("slow.csv").text(), "a │ b");
+    assert!(started.elapsed() < std::time::Duration::from_secs(2), "{:?}", started.elapsed());
+
+    // Files that aren't on the local disk are left to the built-in ones.
+    let fs = MemoryFs::new();
+    fs.add_file("/m/colors.log", "plain");
+    assert_eq!(generate_with(&previewer, &fs, "/m/colors.log").text(), "plain");
+}
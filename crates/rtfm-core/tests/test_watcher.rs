@@ -0,0 +1,60 @@
+use rtfm_core::watcher::{DirChange, DirWatcher};
+use std::fs;
+use std::time::Duration;
+use tempdir::TempDir;
+
+#[tokio::test]
+async fn test_watcher_reports_debounced_changes() {
+    let tmp_dir = TempDir::new("test").unwrap();
+    let dir = tmp_dir.path().to_path_buf();
+    let other = dir.join("other");
+    fs::create_dir(&other).unwrap();
+
+    let mut watcher = DirWatcher::new();
+    watcher.sync([dir.clone(), dir.join("missing"), dir.join("file.zip/inner")]);
+    assert!(watcher.is_watching(&dir));
+    assert!(!watcher.is_watching(&dir.join("missing")));
+
+    for i in 0..20 {
+        fs::write(dir.join(format!("{}.txt", i)), "x").unwrap();
+    }
+    fs::write(other.join("ignored.txt"), "x").unwrap();
+
+    let changes = tokio::time::timeout(Duration::from_secs(5), watcher.next_changes()).await.unwrap();
+    assert_eq!(changes.len(), 1);
+    match &changes[&dir] {
+        DirChange::Entries(paths) => {
+            assert!(paths.contains(&dir.join("0.txt")));
+            assert!(paths.contains(&dir.join("19.txt")));
+        }
+        DirChange::Rescan => {}
+    }
+
+    watcher.sync([]);
+    fs::write(dir.join("late.txt"), "x").unwrap();
+    assert!(tokio::time::timeout(Duration::from_millis(500), watcher.next_changes()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_watcher_keeps_changes_when_cancelled() {
+    let tmp_dir = TempDir::new("test").unwrap();
+    let dir = tmp_dir.path().to_path_buf();
+    let mut watcher = DirWatcher::new();
+    watcher.sync([dir.clone()]);
+
+    fs::write(dir.join("a.txt"), "x").unwrap();
+    // Dropped while it waits for things to calm down, as `select!` does when
+    // another branch wins.
+    let select = tokio::select! {
+        changes = watcher.next_changes() => Some(changes),
+        _ = tokio::time::sleep(Duration::from_millis(50)) => None,
+    };
+    let changes = match select {
+        Some(changes) => changes,
+        None => tokio::time::timeout(Duration::from_secs(5), watcher.next_changes()).await.unwrap(),
+    };
+    match &changes[&dir] {
+        DirChange::Entries(paths) => assert!(paths.contains(&dir.join("a.txt"))),
+        DirChange::Rescan => {}
+    }
+}
@@ -0,0 +1,216 @@
+//! Working out which graphics protocol the terminal speaks.
+//!
+//! The environment gives a first guess. At startup the terminal is then asked
+//! directly: a Kitty graphics query, the cell size (`CSI 16 t`) and the
+//! primary device attributes (DA1), whose reply lists Sixel support. Every
+//! terminal answers DA1, so once its reply is in, all replies are. Except
+//! inside tmux, which answers DA1 itself while the Kitty query is still on
+//! its way to the outer terminal.
+
+use super::{CellSize, ColorMode};
+use config::BackendType;
+use std::io::{self, IsTerminal, Write};
+use std::time::Duration;
+
+/// How long to wait for the terminal to answer, e.g. over a slow SSH link.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+/// Asks whether a 1x1 image can be loaded, without storing or showing it.
+const KITTY_QUERY: &[u8] = b"\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\";
+const KITTY_QUERY_OK: &str = "i=31;OK";
+/// Attribute 4 in a DA1 reply means Sixel graphics.
+const DA1_SIXEL: &str = "4";
+
+/// What the terminal can display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub kitty: bool,
+    pub sixel: bool,
+    pub truecolor: bool,
+    /// Running inside tmux: Kitty sequences have to be passed through.
+    pub tmux: bool,
+}
+
+impl Capabilities {
+    /// Guesses from environment variables such as `TERM`, `TERM_PROGRAM` and
+    /// `COLORTERM`, looked up with `var`. Inside tmux and screen the outer
+    /// terminal is unknown, so graphics are left to probing.
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let term = var("TERM").unwrap_or_default();
+        let program = var("TERM_PROGRAM").unwrap_or_default();
+        let tmux = var("TMUX").is_some() || term.starts_with("tmux");
+        let multiplexed = tmux || term.starts_with("screen");
+        let kitty = term == "xterm-kitty"
+            || term == "xterm-ghostty"
+            || var("KITTY_WINDOW_ID").is_some()
+            || matches!(program.as_str(), "WezTerm" | "ghostty");
+        let sixel = term.starts_with("foot") || term.starts_with("mlterm") || program == "WezTerm";
+        let truecolor = matches!(var("COLORTERM").as_deref(), Some("truecolor" | "24bit")) || kitty;
+        Self { kitty: kitty && !multiplexed, sixel: sixel && !multiplexed, truecolor, tmux }
+    }
+
+    /// Replaces the guesses with the terminal's answers, if it answered.
+    pub fn with_replies(self, replies: &Replies) -> Self {
+        if !replies.answered {
+            return self;
+        }
+        Self { kitty: replies.kitty, sixel: replies.sixel, ..self }
+    }
+
+    /// The backend to use when `configured` is wanted. `Auto` picks the best
+    /// one available; a protocol the terminal doesn't support falls back to
+    /// half blocks.
+    pub fn backend(&self, configured: BackendType) -> BackendType {
+        match configured {
+            BackendType::Auto if self.kitty => BackendType::Kitty,
+            BackendType::Auto if self.sixel => BackendType::Sixel,
+            BackendType::Kitty if self.kitty => BackendType::Kitty,
+            BackendType::Sixel if self.sixel => BackendType::Sixel,
+            _ => BackendType::HalfBlocks,
+        }
+    }
+
+    pub fn color_mode(&self) -> ColorMode {
+        if self.truecolor {
+            ColorMode::TrueColor
+        } else {
+            ColorMode::Indexed256
+        }
+    }
+}
+
+/// What the terminal said in reply to `queries`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Replies {
+    pub kitty: bool,
+    pub sixel: bool,
+    pub cell: Option<CellSize>,
+    /// The DA1 reply arrived, so the terminal answered.
+    pub answered: bool,
+    /// The terminal has answered everything it will.
+    pub complete: bool,
+}
+
+/// The probe sent to the terminal. Inside tmux the Kitty query goes to the
+/// outer terminal, which needs `allow-passthrough` to be on.
+pub fn queries(tmux: bool) -> Vec<u8> {
+    let mut out = if tmux { tmux_passthrough(KITTY_QUERY) } else { KITTY_QUERY.to_vec() };
+    out.extend_from_slice(b"\x1b[16t\x1b[c");
+    out
+}
+
+/// Wraps `sequence` so that tmux hands it to the outer terminal unchanged.
+pub fn tmux_passthrough(sequence: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(sequence.len() + sequence.len() / 64 + 16);
+    out.extend_from_slice(b"\x1bPtmux;");
+    for &byte in sequence {
+        if byte == 0x1b {
+            out.push(0x1b);
+        }
+        out.push(byte);
+    }
+    out.extend_from_slice(b"\x1b\\");
+    out
+}
+
+/// Picks the replies to `queries(tmux)` out of whatever the terminal sent
+/// back. Anything else, such as keys pressed meanwhile, is ignored.
+pub fn parse_replies(input: &[u8], tmux: bool) -> Replies {
+    let input = String::from_utf8_lossy(input);
+    let mut replies = Replies::default();
+    let mut kitty_replied = false;
+    let mut rest = input.as_ref();
+    while let Some(start) = rest.find('\x1b') {
+        rest = &rest[start + 1..];
+        if let Some(apc) = rest.strip_prefix("_G") {
+            let Some(end) = apc.find("\x1b\\") else { break };
+            replies.kitty |= apc[..end] == *KITTY_QUERY_OK;
+            kitty_replied = true;
+            rest = &apc[end..];
+        } else if let Some(csi) = rest.strip_prefix('[') {
+            let Some(end) = csi.find(|c: char| c.is_ascii_alphabetic() || c == '~') else { break };
+            let params = &csi[..end];
+            match &csi[end..end + 1] {
+                "c" if params.starts_with('?') => {
+                    replies.sixel = params[1..].split(';').any(|attribute| attribute == DA1_SIXEL);
+                    replies.answered = true;
+                }
+                "t" => {
+                    let fields: Vec<u16> = params.split(';').filter_map(|field| field.parse().ok()).collect();
+                    if let [6, height, width] = fields[..] {
+                        if width > 0 && height > 0 {
+                            replies.cell = Some(CellSize { width, height });
+                        }
+                    }
+                }
+                _ => {}
+            }
+            rest = &csi[end + 1..];
+        }
+    }
+    // An outer terminal that doesn't speak Kitty never replies; the probe
+    // then times out.
+    replies.complete = replies.answered && (kitty_replied || !tmux);
+    replies
+}
+
+/// Probes the terminal, which must be in raw mode with nothing else reading
+/// its input. Returns the capabilities and the size of a cell in pixels,
+/// preferring `TIOCGWINSZ` to the terminal's reply.
+pub fn probe(env: Capabilities) -> (Capabilities, CellSize) {
+    let replies = query_terminal(env.tmux).unwrap_or_else(|e| {
+        log::warn!("Failed to probe the terminal: {}", e);
+        Replies::default()
+    });
+    let capabilities = env.with_replies(&replies);
+    let cell = CellSize::from_window_size().or(replies.cell).unwrap_or_default();
+    log::info!("Terminal graphics: {:?} ({:?}), cell size {:?}", capabilities, replies, cell);
+    (capabilities, cell)
+}
+
+fn query_terminal(tmux: bool) -> io::Result<Replies> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Ok(Replies::default());
+    }
+    let mut stdout = io::stdout();
+    stdout.write_all(&queries(tmux))?;
+    stdout.flush()?;
+    read_replies(tmux)
+}
+
+#[cfg(unix)]
+fn read_replies(tmux: bool) -> io::Result<Replies> {
+    use rustix::event::{poll, PollFd, PollFlags, Timespec};
+    use std::os::fd::AsFd;
+    use std::time::Instant;
+
+    let stdin = io::stdin();
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+    let mut input = Vec::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        let replies = parse_replies(&input, tmux);
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if replies.complete || remaining.is_zero() {
+            return Ok(replies);
+        }
+        let timeout = Timespec { tv_sec: remaining.as_secs() as _, tv_nsec: remaining.subsec_nanos() as _ };
+        let mut fds = [PollFd::new(&stdin, PollFlags::IN)];
+        match poll(&mut fds, Some(&timeout)) {
+            Ok(0) => return Ok(replies),
+            Ok(_) => {}
+            Err(rustix::io::Errno::INTR) => continue,
+            Err(e) => return Err(e.into()),
+        }
+        let read = rustix::io::read(stdin.as_fd(), &mut buf)?;
+        if read == 0 {
+            return Ok(replies);
+        }
+        input.extend_from_slice(&buf[..read]);
+    }
+}
+
+#[cfg(not(unix))]
+fn read_replies(_tmux: bool) -> io::Result<Replies> {
+    // Without a way to read with a timeout, the environment has to do.
+    Ok(Replies::default())
+}
@@ -0,0 +1,95 @@
+//! Images drawn with `▀` characters straight into the ratatui buffer, two
+//! pixels per cell, for terminals without a graphics protocol.
+
+use super::{fit, CellSize};
+use image::{imageops, Rgba, RgbaImage};
+use ratatui::buffer::Buffer;
+use ratatui::prelude::{Color, Rect};
+use ratatui::widgets::Widget;
+
+/// A cell holds one pixel across and two down.
+const HALF_BLOCK_CELL: CellSize = CellSize { width: 1, height: 2 };
+/// Pixels more transparent than this show the terminal's background.
+const ALPHA_THRESHOLD: u8 = 128;
+/// Channel values of the 6x6x6 color cube in the 256-color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    TrueColor,
+    Indexed256,
+}
+
+/// Renders an image scaled to fit its area, keeping the aspect ratio, in
+/// the top left corner.
+pub struct HalfBlockImage<'a> {
+    image: &'a RgbaImage,
+    colors: ColorMode,
+}
+
+impl<'a> HalfBlockImage<'a> {
+    pub fn new(image: &'a RgbaImage, colors: ColorMode) -> Self {
+        Self { image, colors }
+    }
+}
+
+impl Widget for HalfBlockImage<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.is_empty() {
+            return;
+        }
+        let fit = fit(self.image.width(), self.image.height(), area, HALF_BLOCK_CELL);
+        // A box filter: fast, and averaging is what's wanted when shrinking this much.
+        let scaled = imageops::thumbnail(self.image, fit.width, fit.height);
+
+        for row in 0..fit.rows {
+            for col in 0..fit.cols {
+                let top = scaled.get_pixel_checked(col as u32, row as u32 * 2);
+                let bottom = scaled.get_pixel_checked(col as u32, row as u32 * 2 + 1);
+                let cell = buf.get_mut(area.x + col, area.y + row);
+                cell.set_symbol("▀").set_fg(self.color(top)).set_bg(self.color(bottom));
+            }
+        }
+    }
+}
+
+impl HalfBlockImage<'_> {
+    fn color(&self, pixel: Option<&Rgba<u8>>) -> Color {
+        match pixel {
+            Some(Rgba([r, g, b, a])) if *a >= ALPHA_THRESHOLD => match self.colors {
+                ColorMode::TrueColor => Color::Rgb(*r, *g, *b),
+                ColorMode::Indexed256 => Color::Indexed(indexed_color(*r, *g, *b)),
+            },
+            _ => Color::Reset,
+        }
+    }
+}
+
+/// The closest color in the 6x6x6 cube or the grayscale ramp of the
+/// 256-color palette. The first 16 colors are left out since terminals
+/// theme them.
+pub fn indexed_color(r: u8, g: u8, b: u8) -> u8 {
+    let nearest_level = |channel: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - channel as i32).abs())
+            .unwrap()
+    };
+    let (ri, gi, bi) = (nearest_level(r), nearest_level(g), nearest_level(b));
+    let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+
+    // The ramp runs from 8 to 238 in steps of 10.
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_index = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray_level = 8 + gray_index * 10;
+
+    let distance = |(cr, cg, cb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, cr) + d(g, cg) + d(b, cb)
+    };
+    if distance((gray_level, gray_level, gray_level)) < distance(cube) {
+        232 + gray_index
+    } else {
+        16 + 36 * ri as u8 + 6 * gi as u8 + bi as u8
+    }
+}
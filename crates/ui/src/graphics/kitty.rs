@@ -0,0 +1,51 @@
+//! Escape sequences of the Kitty graphics protocol.
+//!
+//! See <https://sw.kovidgoyal.net/kitty/graphics-protocol/>.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use image::RgbaImage;
+
+/// Largest payload allowed in one escape sequence.
+const CHUNK_SIZE: usize = 4096;
+
+/// Transmits `image` as raw RGBA and places it at the cursor, scaled to
+/// `cols` x `rows` cells. The cursor doesn't move and the terminal doesn't
+/// reply.
+pub fn transmit(image: &RgbaImage, id: u32, cols: u16, rows: u16) -> Vec<u8> {
+    let payload = BASE64.encode(image.as_raw());
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(CHUNK_SIZE).collect();
+    let mut out = Vec::with_capacity(payload.len() + chunks.len() * 16 + 64);
+    out.extend_from_slice(
+        format!(
+            "\x1b_Ga=T,f=32,s={},v={},i={},c={},r={},C=1,q=2",
+            image.width(),
+            image.height(),
+            id,
+            cols,
+            rows
+        )
+        .as_bytes(),
+    );
+    if chunks.len() > 1 {
+        out.extend_from_slice(b",m=1");
+    }
+    for (index, chunk) in chunks.iter().enumerate() {
+        if index > 0 {
+            let more = if index + 1 < chunks.len() { 1 } else { 0 };
+            out.extend_from_slice(format!("\x1b_Gm={}", more).as_bytes());
+        }
+        out.push(b';');
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\x1b\\");
+    }
+    if chunks.is_empty() {
+        out.extend_from_slice(b";\x1b\\");
+    }
+    out
+}
+
+/// Deletes the image with `id` along with its placements, freeing its data.
+pub fn delete(id: u32) -> Vec<u8> {
+    format!("\x1b_Ga=d,d=I,i={},q=2\x1b\\", id).into_bytes()
+}
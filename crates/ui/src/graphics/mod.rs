@@ -0,0 +1,265 @@
+//! Images in the preview pane, drawn over the text with a terminal graphics
+//! protocol (Kitty or Sixel) after each frame, or with half blocks as part of
+//! the frame where neither is available.
+
+pub mod detect;
+pub mod halfblock;
+pub mod kitty;
+pub mod sixel;
+
+pub use self::detect::Capabilities;
+pub use self::halfblock::{ColorMode, HalfBlockImage};
+
+use config::{BackendType, PreviewConfig};
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use ratatui::prelude::Rect;
+use rtfm_core::preview::{ImagePreview, Preview};
+use std::io::{self, Write};
+use std::sync::Arc;
+
+/// Used when the terminal doesn't report its size in pixels.
+const DEFAULT_CELL_SIZE: CellSize = CellSize { width: 8, height: 16 };
+/// Images smaller than this many pixels are sent in one go even when
+/// rendering progressively.
+const PROGRESSIVE_MIN_PIXELS: u32 = 128 * 128;
+/// The low-resolution pass is this many times smaller in each direction.
+const PROGRESSIVE_SCALE: u32 = 4;
+
+/// Size of one terminal cell in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellSize {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl CellSize {
+    /// The cell size from the window size the terminal reports with
+    /// `TIOCGWINSZ`, if it includes pixels.
+    pub fn from_window_size() -> Option<Self> {
+        match crossterm::terminal::window_size() {
+            Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => Some(Self {
+                width: size.width / size.columns,
+                height: size.height / size.rows,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Default for CellSize {
+    fn default() -> Self {
+        DEFAULT_CELL_SIZE
+    }
+}
+
+/// How an image of `width` x `height` pixels fits into `area` without being
+/// stretched or enlarged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fit {
+    /// Size to scale the image to, in pixels.
+    pub width: u32,
+    pub height: u32,
+    /// Cells covered by the scaled image.
+    pub cols: u16,
+    pub rows: u16,
+}
+
+pub fn fit(width: u32, height: u32, area: Rect, cell: CellSize) -> Fit {
+    let max_width = area.width as f64 * cell.width as f64;
+    let max_height = area.height as f64 * cell.height as f64;
+    let scale = (max_width / width.max(1) as f64).min(max_height / height.max(1) as f64).min(1.0);
+    let width = ((width as f64 * scale) as u32).max(1);
+    let height = ((height as f64 * scale) as u32).max(1);
+    Fit {
+        width,
+        height,
+        cols: (width.div_ceil(cell.width as u32) as u16).clamp(1, area.width.max(1)),
+        rows: (height.div_ceil(cell.height as u32) as u16).clamp(1, area.height.max(1)),
+    }
+}
+
+/// What's on screen at the moment.
+#[derive(Debug)]
+struct Shown {
+    preview: Arc<Preview>,
+    area: Rect,
+    backend: BackendType,
+    id: u32,
+}
+
+/// The Sixel sequence last drawn, kept because encoding takes a while and the
+/// same image is drawn again whenever a dialog stops covering it.
+#[derive(Debug)]
+struct Encoded {
+    preview: Arc<Preview>,
+    area: Rect,
+    sixel: Vec<u8>,
+}
+
+/// Keeps the image in the preview pane in sync with the selection: draws
+/// new images and deletes the previous one.
+#[derive(Debug)]
+pub struct ImageLayer {
+    cell: CellSize,
+    capabilities: Capabilities,
+    shown: Option<Shown>,
+    encoded: Option<Encoded>,
+    next_id: u32,
+}
+
+impl ImageLayer {
+    pub fn new(cell: CellSize, capabilities: Capabilities) -> Self {
+        Self { cell, capabilities, shown: None, encoded: None, next_id: 1 }
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Shows the image of `preview` in `area`, replacing the image shown before.
+    /// `None`, or a preview without an image, clears it. Nothing is
+    /// written if the same image is already shown in the same place, or if
+    /// it's drawn with half blocks, which are part of the frame.
+    pub fn show<W: Write>(&mut self, out: &mut W, image: Option<(&Arc<Preview>, Rect)>, config: &PreviewConfig) -> io::Result<()> {
+        let backend = self.capabilities.backend(config.backend);
+        let image = image.and_then(|(preview, area)| match preview.image() {
+            Some(image) if !area.is_empty() && backend != BackendType::HalfBlocks => Some((preview, image, area)),
+            _ => None,
+        });
+        if let (Some(shown), Some((preview, _, area))) = (&self.shown, image) {
+            if Arc::ptr_eq(&shown.preview, preview) && shown.area == area && shown.backend == backend {
+                return Ok(());
+            }
+        }
+
+        self.clear(out)?;
+        if let Some((preview, image, area)) = image {
+            let id = match backend {
+                BackendType::Sixel => self.draw_sixel(out, preview, image, area)?,
+                _ => self.draw_kitty(out, image, area, config)?,
+            };
+            self.shown = Some(Shown { preview: preview.clone(), area, backend, id });
+        }
+        out.flush()
+    }
+
+    /// Removes the image on screen unless it's `preview`. Call this before
+    /// drawing a frame: Sixel images are erased by blanking their cells,
+    /// which would wipe out anything drawn there since.
+    pub fn retain<W: Write>(&mut self, out: &mut W, preview: Option<&Arc<Preview>>) -> io::Result<()> {
+        let keep = match (&self.shown, preview) {
+            (Some(shown), Some(preview)) => Arc::ptr_eq(&shown.preview, preview),
+            _ => false,
+        };
+        if !keep {
+            self.clear(out)?;
+        }
+        Ok(())
+    }
+
+    /// Where the image on screen is, if any.
+    pub fn area(&self) -> Option<Rect> {
+        self.shown.as_ref().map(|shown| shown.area)
+    }
+
+    /// Whether removing the image on screen overwrites the cells under it.
+    pub fn blanks_cells(&self) -> bool {
+        self.shown.as_ref().is_some_and(|shown| shown.backend == BackendType::Sixel)
+    }
+
+    /// Removes the image on screen, if any.
+    pub fn clear<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let Some(shown) = self.shown.take() else {
+            return Ok(());
+        };
+        match shown.backend {
+            BackendType::Kitty => out.write_all(&self.wrap(kitty::delete(shown.id))),
+            // Never recorded: `show` resolves `Auto`, and half blocks are part
+            // of the frame.
+            BackendType::Auto | BackendType::HalfBlocks => Ok(()),
+            BackendType::Sixel => {
+                // Sixel pixels stay until the cells under them are overwritten.
+                let area = shown.area;
+                out.write_all(b"\x1b7")?;
+                for y in area.top()..area.bottom() {
+                    write!(out, "\x1b[{};{}H\x1b[{}X", y + 1, area.x + 1, area.width)?;
+                }
+                out.write_all(b"\x1b8")
+            }
+        }
+    }
+
+    /// Scales `image` to fit `area` in pixels, keeping its aspect ratio.
+    fn scale(&self, image: &ImagePreview, area: Rect) -> (RgbaImage, Fit) {
+        let (width, height) = image.pixels.dimensions();
+        let fit = fit(width, height, area, self.cell);
+        let scaled = if (fit.width, fit.height) == (width, height) {
+            image.pixels.clone()
+        } else {
+            imageops::resize(&image.pixels, fit.width, fit.height, FilterType::Triangle)
+        };
+        (scaled, fit)
+    }
+
+    fn draw_sixel<W: Write>(&mut self, out: &mut W, preview: &Arc<Preview>, image: &ImagePreview, area: Rect) -> io::Result<u32> {
+        let cached = self.encoded.take().filter(|encoded| Arc::ptr_eq(&encoded.preview, preview) && encoded.area == area);
+        let encoded = cached.unwrap_or_else(|| {
+            // Sixel images can't be scaled by the terminal, so there's no point
+            // in a low-res pass.
+            let (scaled, _) = self.scale(image, area);
+            Encoded { preview: preview.clone(), area, sixel: sixel::encode(&scaled, sixel::MAX_COLORS) }
+        });
+        write!(out, "\x1b7\x1b[{};{}H", area.y + 1, area.x + 1)?;
+        out.write_all(&encoded.sixel)?;
+        self.encoded = Some(encoded);
+        out.write_all(b"\x1b8")?;
+        Ok(0)
+    }
+
+    fn draw_kitty<W: Write>(&mut self, out: &mut W, image: &ImagePreview, area: Rect, config: &PreviewConfig) -> io::Result<u32> {
+        let (scaled, fit) = self.scale(image, area);
+        let id = self.allocate_id();
+        // Save the cursor and move it to the top left of the pane.
+        let move_to = format!("\x1b7\x1b[{};{}H", area.y + 1, area.x + 1);
+        if config.progressive && fit.width * fit.height >= PROGRESSIVE_MIN_PIXELS {
+            // A small version goes out first, so something shows up while the
+            // full one is being transferred. It's stretched over the same cells.
+            let low_res = imageops::resize(
+                &scaled,
+                (fit.width / PROGRESSIVE_SCALE).max(1),
+                (fit.height / PROGRESSIVE_SCALE).max(1),
+                FilterType::Nearest,
+            );
+            let low_res_id = self.allocate_id();
+            out.write_all(move_to.as_bytes())?;
+            out.write_all(&self.wrap(kitty::transmit(&low_res, low_res_id, fit.cols, fit.rows)))?;
+            out.write_all(b"\x1b8")?;
+            out.flush()?;
+            out.write_all(move_to.as_bytes())?;
+            out.write_all(&self.wrap(kitty::transmit(&scaled, id, fit.cols, fit.rows)))?;
+            out.write_all(&self.wrap(kitty::delete(low_res_id)))?;
+        } else {
+            out.write_all(move_to.as_bytes())?;
+            out.write_all(&self.wrap(kitty::transmit(&scaled, id, fit.cols, fit.rows)))?;
+        }
+        out.write_all(b"\x1b8")?;
+        Ok(id)
+    }
+
+    /// Kitty sequences need to be passed through tmux to reach the terminal.
+    fn wrap(&self, sequence: Vec<u8>) -> Vec<u8> {
+        if self.capabilities.tmux {
+            detect::tmux_passthrough(&sequence)
+        } else {
+            sequence
+        }
+    }
+
+    fn allocate_id(&mut self) -> u32 {
+        let id = self.next_id;
+        // Kitty treats 0 as "no id".
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        id
+    }
+}
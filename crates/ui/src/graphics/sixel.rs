@@ -0,0 +1,127 @@
+//! Sixel encoding, for foot, WezTerm, mlterm, xterm and other terminals
+//! without the Kitty protocol.
+
+use color_quant::NeuQuant;
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Palette registers used. Most Sixel terminals have at least 256.
+pub const MAX_COLORS: usize = 256;
+/// NeuQuant looks at one in this many pixels; lower is slower but more accurate.
+const SAMPLE_FACTOR: i32 = 10;
+/// Pixels more transparent than this are left out.
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// Encodes `image` as a Sixel sequence that draws it at the cursor, pixel
+/// for pixel. Images with more than `colors` colors are quantized.
+pub fn encode(image: &RgbaImage, colors: usize) -> Vec<u8> {
+    let (palette, indices) = quantize(image, colors.clamp(1, MAX_COLORS));
+    let (width, height) = (image.width() as usize, image.height() as usize);
+
+    // P2 = 1: pixels that aren't set keep the background.
+    let mut out = format!("\x1bP0;1q\"1;1;{};{}", width, height);
+    for (index, [r, g, b]) in palette.iter().enumerate() {
+        let _ = write!(out, "#{};2;{};{};{}", index, percent(*r), percent(*g), percent(*b));
+    }
+
+    // One row of sixels per color, filled in a single pass over each band.
+    // Rows are emptied after use, and an empty row marks an unused color.
+    let mut rows = vec![Vec::new(); palette.len()];
+    let mut used = Vec::new();
+    for band in (0..height).step_by(6) {
+        let band_height = 6.min(height - band);
+        let pixels = &indices[band * width..(band + band_height) * width];
+        for (dy, line) in pixels.chunks_exact(width).enumerate() {
+            for (x, color) in line.iter().enumerate() {
+                let Some(color) = *color else {
+                    continue;
+                };
+                let row = &mut rows[color as usize];
+                if row.is_empty() {
+                    row.resize(width, 0);
+                    used.push(color);
+                }
+                row[x] |= 1 << dy;
+            }
+        }
+
+        used.sort_unstable();
+        for (n, &color) in used.iter().enumerate() {
+            if n > 0 {
+                // Back to the start of the band for the next color.
+                out.push('$');
+            }
+            let _ = write!(out, "#{}", color);
+            push_run_length_encoded(&mut out, &rows[color as usize]);
+            rows[color as usize].clear();
+        }
+        used.clear();
+        if band + 6 < height {
+            out.push('-');
+        }
+    }
+    out.push_str("\x1b\\");
+    out.into_bytes()
+}
+
+/// Sixel colors are given in percent.
+fn percent(channel: u8) -> u32 {
+    (channel as u32 * 100 + 127) / 255
+}
+
+/// Appends the sixels of `row`, with runs written as `!<count><sixel>` and
+/// trailing blanks dropped.
+fn push_run_length_encoded(out: &mut String, row: &[u8]) {
+    let end = row.iter().rposition(|&bits| bits != 0).map_or(0, |last| last + 1);
+    let mut x = 0;
+    while x < end {
+        let bits = row[x];
+        let run = row[x..end].iter().take_while(|&&other| other == bits).count();
+        let sixel = (b'?' + bits) as char;
+        if run > 3 {
+            let _ = write!(out, "!{}{}", run, sixel);
+        } else {
+            out.extend(std::iter::repeat_n(sixel, run));
+        }
+        x += run;
+    }
+}
+
+/// Picks a palette of at most `colors` colors and maps each pixel to it;
+/// `None` for transparent pixels. Images with few enough colors keep them
+/// exactly, in order of appearance.
+fn quantize(image: &RgbaImage, colors: usize) -> (Vec<[u8; 3]>, Vec<Option<u8>>) {
+    let mut exact: HashMap<[u8; 3], u8> = HashMap::new();
+    let mut palette = Vec::new();
+    let mut indices = Vec::with_capacity(image.width() as usize * image.height() as usize);
+    for pixel in image.pixels() {
+        let [r, g, b, a] = pixel.0;
+        if a < ALPHA_THRESHOLD {
+            indices.push(None);
+            continue;
+        }
+        let index = match exact.get(&[r, g, b]) {
+            Some(&index) => index,
+            None if palette.len() < colors => {
+                let index = palette.len() as u8;
+                palette.push([r, g, b]);
+                exact.insert([r, g, b], index);
+                index
+            }
+            None => return quantize_neuquant(image, colors),
+        };
+        indices.push(Some(index));
+    }
+    (palette, indices)
+}
+
+fn quantize_neuquant(image: &RgbaImage, colors: usize) -> (Vec<[u8; 3]>, Vec<Option<u8>>) {
+    let quantizer = NeuQuant::new(SAMPLE_FACTOR, colors, image.as_raw());
+    let palette = quantizer.color_map_rgb().chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect();
+    let indices = image
+        .pixels()
+        .map(|pixel| (pixel.0[3] >= ALPHA_THRESHOLD).then(|| quantizer.index_of(&pixel.0) as u8))
+        .collect();
+    (palette, indices)
+}
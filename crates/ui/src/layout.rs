@@ -7,6 +7,7 @@ use ratatui::{
 };
 use rtfm_core::app_state::{AppState, CreateFileType};
 use rtfm_core::clipboard::ClipboardMode;
+use rtfm_core::preview::Preview;
 
 use rtfm_core::app_state::InputMode;
 
@@ -21,6 +22,11 @@ fn render_input_dialog(frame: &mut Frame, app_state: &AppState) {
             format!("Create new {}", file_type)
         }
         InputMode::Rename => "Rename".to_string(),
+        InputMode::Open => "Open location (path or sftp://user@host/path)".to_string(),
+        InputMode::Offset => match app_state.get_active_tab().preview_content.as_deref() {
+            Some(Preview::Paged(_)) => "Jump to line (1200, 50%, ^ or $)".to_string(),
+            _ => "Jump to offset (1024, 0x400 or 50%)".to_string(),
+        },
         _ => "Input".to_string(),
     };
 
@@ -73,7 +79,10 @@ pub fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
 }
 
 
-pub fn render_main_layout(frame: &mut Frame, app_state: &AppState) {
+/// Renders everything but images. Returns where the preview image goes, unless
+/// there's none or a dialog is covering it, and how many rows of text the
+/// preview pane has.
+pub fn render_main_layout(frame: &mut Frame, app_state: &AppState) -> (Option<Rect>, u16) {
     let top_bar_height = if app_state.show_tabs { 2 } else { 0 };
     let main_chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -115,15 +124,20 @@ pub fn render_main_layout(frame: &mut Frame, app_state: &AppState) {
     render_left_pane(frame, left_pane_inner_area, app_state);
 
     // Middle Pane
+    let mut middle_pane_title = format!("Current: {}", active_tab.current_dir.display());
+    if active_tab.is_loading() {
+        middle_pane_title.push_str(&format!(" [loading… {}]", active_tab.entries.len()));
+    }
     let middle_pane_block = Block::default()
-        .title(format!("Current: {}", active_tab.current_dir.display()))
+        .title(middle_pane_title)
         .borders(Borders::ALL);
     let middle_pane_inner_area = middle_pane_block.inner(middle_pane_area);
     frame.render_widget(middle_pane_block, middle_pane_area);
     middle_pane::render_middle_pane(frame, middle_pane_inner_area, active_tab);
 
     // Right Pane
-    right_pane::render_right_pane(frame, right_pane_area, active_tab);
+    let image_area = right_pane::render_right_pane(frame, right_pane_area, active_tab);
+    let preview_rows = Block::default().borders(Borders::ALL).inner(right_pane_area).height;
 
     // --- Footer (Tasks, Info) ---
     let footer_chunks = Layout::default()
@@ -143,6 +157,7 @@ pub fn render_main_layout(frame: &mut Frame, app_state: &AppState) {
     if app_state.show_input_dialog {
         render_input_dialog(frame, app_state);
     }
+    (image_area.filter(|_| !app_state.show_confirmation && !app_state.show_input_dialog), preview_rows)
 }
 
 fn render_left_pane(frame: &mut Frame, area: Rect, app_state: &AppState) {
@@ -196,12 +211,22 @@ fn render_info_panel(frame: &mut Frame, area: Rect, app_state: &AppState) {
     };
     info_text.push_str(&clipboard_info);
 
+    let active_tab = app_state.get_active_tab();
+    if let Some(mime) = active_tab.entries.get(active_tab.cursor).and_then(|entry| entry.mime) {
+        info_text.push_str(&format!("\nType: {}", mime));
+    }
+
     // Display notification if there is one
     if let Some(notification) = &app_state.notification {
         info_text.push_str("\n\n");
         info_text.push_str(notification);
     }
 
+    if let Some((lookup, _)) = &app_state.lookup {
+        info_text.push_str("\n\n");
+        info_text.push_str(&lookup.status());
+    }
+
 
     let paragraph = Paragraph::new(info_text)
         .wrap(Wrap { trim: true });
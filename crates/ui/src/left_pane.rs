@@ -54,20 +54,20 @@ pub fn render_bookmarks_block(frame: &mut Frame, area: Rect, app_state: &AppStat
     frame.render_stateful_widget(list, area, &mut list_state);
 }
 
-#[cfg(feature = "mounts")]
-pub fn render_mounts_block(frame: &mut Frame, area: Rect, app_state: &AppState) {
-    let items: Vec<ListItem> = app_state
-        .mounts
+fn remote_items(app_state: &AppState) -> impl Iterator<Item = ListItem<'_>> {
+    app_state
+        .remotes
         .iter()
-        .map(|mount| ListItem::new(mount.dest.to_string_lossy().to_string()))
-        .collect();
+        .map(|(name, _url)| ListItem::new(format!("sftp: {}", name)))
+}
 
+fn render_disks_list(frame: &mut Frame, area: Rect, app_state: &AppState, title: &str, items: Vec<ListItem>) {
     let is_focused = app_state.focus == FocusBlock::Disks;
     let title_style = if is_focused { Style::default().fg(Color::Yellow) } else { Style::default() };
     let highlight_style = if is_focused { Style::default().bg(Color::Blue) } else { Style::default().bg(Color::DarkGray) };
 
     let list = List::new(items)
-        .block(Block::default().title(Title::from(Span::styled("Mounts", title_style))))
+        .block(Block::default().title(Title::from(Span::styled(title, title_style))))
         .highlight_style(highlight_style);
 
     let mut list_state = ListState::default();
@@ -76,8 +76,24 @@ pub fn render_mounts_block(frame: &mut Frame, area: Rect, app_state: &AppState)
     frame.render_stateful_widget(list, area, &mut list_state);
 }
 
+#[cfg(feature = "mounts")]
+pub fn render_mounts_block(frame: &mut Frame, area: Rect, app_state: &AppState) {
+    let items: Vec<ListItem> = app_state
+        .mounts
+        .iter()
+        .map(|mount| ListItem::new(mount.dest.to_string_lossy().to_string()))
+        .chain(remote_items(app_state))
+        .collect();
+
+    render_disks_list(frame, area, app_state, "Mounts", items);
+}
+
 #[cfg(not(feature = "mounts"))]
-pub fn render_mounts_block(frame: &mut Frame, area: Rect, _app_state: &AppState) {
-    let block = Block::new().borders(Borders::ALL).title("Mounts (unsupported)");
-    frame.render_widget(block, area);
+pub fn render_mounts_block(frame: &mut Frame, area: Rect, app_state: &AppState) {
+    if app_state.remotes.is_empty() {
+        let block = Block::new().borders(Borders::ALL).title("Mounts (unsupported)");
+        frame.render_widget(block, area);
+        return;
+    }
+    render_disks_list(frame, area, app_state, "Remotes", remote_items(app_state).collect());
 }
@@ -4,3 +4,4 @@ pub mod left_pane;
 pub mod top_bar;
 pub mod middle_pane;
 pub mod right_pane;
+pub mod graphics;
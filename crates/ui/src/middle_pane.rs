@@ -9,7 +9,7 @@ fn get_icon_for_file(name: &str, is_dir: bool) -> &'static str {
     if is_dir {
         return ""; // Folder icon
     }
-    match name.split('.').last() {
+    match name.split('.').next_back() {
         Some("rs") => "",   // Rust
         Some("js") => "",   // JavaScript
         Some("html") => "", // HTML
@@ -1,24 +1,141 @@
 use ratatui::{
-    prelude::{Rect, Style},
+    prelude::{Color, Constraint, Direction, Layout, Line, Modifier, Rect, Span, Style, Text},
     widgets::{Block, Borders, Paragraph},
     Frame,
 };
 use rtfm_core::app_state::TabState;
+use rtfm_core::preview::{DataTree, HexDump, Preview, SpanStyle, StyledLine, TextPage, ValueKind};
+use std::collections::BTreeSet;
 
-pub fn render_right_pane(frame: &mut Frame, area: Rect, tab_state: &TabState) {
-    let preview_content = match &tab_state.preview_content {
-        Some(content) => content.clone(),
-        None => "No item selected".to_string(),
+fn span_style(style: &SpanStyle) -> Style {
+    let mut result = Style::default();
+    if let Some((r, g, b)) = style.fg {
+        result = result.fg(Color::Rgb(r, g, b));
+    }
+    if style.bold {
+        result = result.add_modifier(Modifier::BOLD);
+    }
+    if style.italic {
+        result = result.add_modifier(Modifier::ITALIC);
+    }
+    if style.underline {
+        result = result.add_modifier(Modifier::UNDERLINED);
+    }
+    result
+}
+
+fn styled_text<'a>(lines: impl Iterator<Item = &'a StyledLine>) -> Text<'a> {
+    lines
+        .map(|line| {
+            Line::from(
+                line.iter()
+                    .map(|span| Span::styled(span.text.as_str(), span_style(&span.style)))
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+fn value_style(kind: ValueKind) -> Style {
+    match kind {
+        ValueKind::Object | ValueKind::Array => Style::default().fg(Color::DarkGray),
+        ValueKind::String => Style::default().fg(Color::Green),
+        ValueKind::Number => Style::default().fg(Color::Yellow),
+        ValueKind::Bool | ValueKind::Null => Style::default().fg(Color::Magenta),
+        ValueKind::Date => Style::default().fg(Color::Blue),
+    }
+}
+
+/// The visible rows of a data tree. The top one is the current node, which
+/// folding applies to, and is highlighted.
+fn tree_text<'a>(tree: &'a DataTree, folded: &BTreeSet<usize>, scroll: usize, visible: usize) -> Text<'a> {
+    tree.rows(folded)
+        .skip(scroll)
+        .take(visible)
+        .enumerate()
+        .map(|(row, index)| {
+            let node = &tree.nodes[index];
+            let mut spans = vec![Span::raw(tree.prefix(index, folded.contains(&index)))];
+            if !node.key.is_empty() {
+                spans.push(Span::styled(node.key.as_str(), Style::default().fg(Color::Cyan)));
+                spans.push(Span::raw(": "));
+            }
+            spans.push(Span::styled(node.value.as_str(), value_style(node.kind)));
+            let line = Line::from(spans);
+            match row {
+                0 => line.style(Style::default().add_modifier(Modifier::REVERSED)),
+                _ => line,
+            }
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Renders the preview pane. Returns the area left free for an image, which is
+/// drawn separately by the graphics layer.
+pub fn render_right_pane(frame: &mut Frame, area: Rect, tab_state: &TabState) -> Option<Rect> {
+    let title = match tab_state.preview_content.as_deref() {
+        Some(Preview::Hex(dump)) => format!("Preview: {}", dump.header()),
+        Some(Preview::Paged(page)) if tab_state.jumping_to().is_some() => {
+            let line = tab_state.jumping_to().unwrap_or_default();
+            format!("Preview: {} [finding line {}…]", page.header(tab_state.preview_scroll.0 as usize), line)
+        }
+        Some(Preview::Paged(page)) if tab_state.follow.is_some() => {
+            format!("Preview: {} [following]", page.header(tab_state.preview_scroll.0 as usize))
+        }
+        Some(Preview::Paged(page)) => format!("Preview: {}", page.header(tab_state.preview_scroll.0 as usize)),
+        Some(Preview::Image(image)) => format!("Preview: {}", image.description),
+        Some(Preview::Tree(tree)) => match tab_state.preview_tree_node() {
+            Some(index) => format!("Preview: {} {}", tree.format, tree.nodes[index].path),
+            None => format!("Preview: {}", tree.format),
+        },
+        Some(Preview::Markdown { .. }) if tab_state.markdown_source => "Preview: Markdown source".to_string(),
+        Some(Preview::Markdown { .. }) => "Preview: Markdown".to_string(),
+        _ => "Preview".to_string(),
     };
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let mut inner_area = block.inner(area);
+    frame.render_widget(block, area);
 
-    let block = Block::default().borders(Borders::ALL).title("Preview");
-    let inner_area = block.inner(area);
+    // A document's picture goes on top. The text below gets the lines it
+    // needs, up to half the pane, so a photo's few EXIF lines leave it room.
+    let mut image_area = None;
+    match tab_state.preview_content.as_deref() {
+        Some(Preview::Image(_)) => image_area = Some(inner_area),
+        Some(Preview::Document { text, image: Some(_) }) => {
+            let text_height = (text.lines().count() as u16).min(inner_area.height / 2);
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(text_height)])
+                .split(inner_area);
+            image_area = Some(chunks[0]);
+            inner_area = chunks[1];
+        }
+        _ => {}
+    }
+
+    // Only the visible lines are converted; previews can be a megabyte long.
+    let (scroll_y, scroll_x) = tab_state.preview_scroll;
+    let visible = inner_area.height as usize;
+    let preview_content = match tab_state.preview_content.as_deref() {
+        Some(Preview::Text(text)) | Some(Preview::Hex(HexDump { text, .. })) | Some(Preview::Paged(TextPage { text, .. })) | Some(Preview::Document { text, .. }) => text.lines().skip(scroll_y as usize).take(visible).map(Line::from).collect(),
+        Some(Preview::Highlighted { lines, .. }) => styled_text(lines.iter().skip(scroll_y as usize).take(visible)),
+        Some(Preview::Markdown { source, .. }) if tab_state.markdown_source => {
+            styled_text(source.iter().skip(scroll_y as usize).take(visible))
+        }
+        Some(Preview::Markdown { rendered, .. }) => styled_text(rendered.iter().skip(scroll_y as usize).take(visible)),
+        Some(Preview::Tree(tree)) => tree_text(tree, &tab_state.preview_folds, scroll_y as usize, visible),
+        Some(Preview::Image(_)) => Text::default(),
+        None if tab_state.is_preview_loading() => Text::from("loading…"),
+        None => Text::from("No item selected"),
+    };
 
     let paragraph = Paragraph::new(preview_content)
         .style(Style::default())
         .block(Block::default())
-        .scroll(tab_state.preview_scroll);
+        .scroll((0, scroll_x));
 
-    frame.render_widget(block, area);
     frame.render_widget(paragraph, inner_area);
+    image_area
 }
@@ -6,24 +6,66 @@ use crossterm::{
 use ratatui::prelude::{CrosstermBackend, Terminal};
 use std::io::{self, stdout, Stdout};
 use rtfm_core::app_state::{AppState, InputMode, CreateFileType};
+use crate::graphics::{detect, Capabilities, CellSize, HalfBlockImage, ImageLayer};
+use config::BackendType;
+use crate::layout;
+
+/// Rows scrolled by Ctrl-d and Ctrl-u in the preview.
+const PREVIEW_PAGE_ROWS: i32 = 20;
 
 pub struct Tui {
     pub terminal: Terminal<CrosstermBackend<Stdout>>,
+    images: ImageLayer,
 }
 
 impl Tui {
     pub fn new() -> io::Result<Self> {
         let terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
-        Ok(Self { terminal })
+        let capabilities = Capabilities::from_env(|name| std::env::var(name).ok());
+        let cell = CellSize::from_window_size().unwrap_or_default();
+        Ok(Self { terminal, images: ImageLayer::new(cell, capabilities) })
+    }
+
+    /// Draws a frame, then the preview image on top of it. Records how many
+    /// rows the preview pane has, which paging through large files needs.
+    pub fn draw(&mut self, app_state: &mut AppState) -> io::Result<()> {
+        let covered = app_state.show_confirmation || app_state.show_input_dialog;
+        let preview = app_state.get_active_tab().preview_content.as_ref().filter(|_| !covered);
+        self.images.retain(self.terminal.backend_mut(), preview)?;
+        let capabilities = self.images.capabilities();
+        let half_blocks = capabilities.backend(app_state.config.preview.backend) == BackendType::HalfBlocks;
+        let render = |frame: &mut ratatui::Frame| {
+            let (image_area, preview_rows) = layout::render_main_layout(frame, app_state);
+            if let (true, Some(image), Some(area)) = (half_blocks, preview.and_then(|p| p.image()), image_area) {
+                frame.render_widget(HalfBlockImage::new(&image.pixels, capabilities.color_mode()), area);
+            }
+            (image_area, preview_rows)
+        };
+        let (mut image_area, mut preview_rows) = (None, 0);
+        self.terminal.draw(|frame| (image_area, preview_rows) = render(frame))?;
+        if self.images.blanks_cells() && self.images.area() != image_area {
+            // The layout changed under a Sixel image. Erasing it blanks cells
+            // the frame has just drawn, so draw it again.
+            self.images.clear(self.terminal.backend_mut())?;
+            self.terminal.clear()?;
+            self.terminal.draw(|frame| (image_area, preview_rows) = render(frame))?;
+        }
+        self.images.show(self.terminal.backend_mut(), preview.zip(image_area), &app_state.config.preview)?;
+        app_state.get_active_tab_mut().preview_rows = preview_rows;
+        Ok(())
     }
 
     pub fn enter(&mut self) -> io::Result<()> {
         enable_raw_mode()?;
+        // Before anything else reads input, or the replies would show up as keys.
+        let (capabilities, cell) = detect::probe(Capabilities::from_env(|name| std::env::var(name).ok()));
+        self.images = ImageLayer::new(cell, capabilities);
         stdout().execute(EnterAlternateScreen)?;
         Ok(())
     }
 
     pub fn exit(&mut self) -> io::Result<()> {
+        self.images.clear(self.terminal.backend_mut())?;
         disable_raw_mode()?;
         stdout().execute(LeaveAlternateScreen)?;
         Ok(())
@@ -53,13 +95,23 @@ pub fn handle_key_press(key: KeyEvent, app_state: &mut AppState) -> bool {
                 return true;
             }
             KeyCode::Char('j') => {
-                let active_tab = app_state.get_active_tab_mut();
-                active_tab.preview_scroll.0 = active_tab.preview_scroll.0.saturating_add(1);
+                app_state.get_active_tab_mut().scroll_preview(1);
                 return true;
             }
             KeyCode::Char('k') => {
-                let active_tab = app_state.get_active_tab_mut();
-                active_tab.preview_scroll.0 = active_tab.preview_scroll.0.saturating_sub(1);
+                app_state.get_active_tab_mut().scroll_preview(-1);
+                return true;
+            }
+            KeyCode::Char('d') => {
+                app_state.get_active_tab_mut().scroll_preview(PREVIEW_PAGE_ROWS);
+                return true;
+            }
+            KeyCode::Char('u') => {
+                app_state.get_active_tab_mut().scroll_preview(-PREVIEW_PAGE_ROWS);
+                return true;
+            }
+            KeyCode::Char('g') => {
+                app_state.prompt_preview_offset();
                 return true;
             }
             _ => {}
@@ -121,12 +173,14 @@ pub fn handle_key_press(key: KeyEvent, app_state: &mut AppState) -> bool {
                 return true;
             }
             KeyCode::Enter => {
-                if app_state.input_mode == InputMode::Rename {
-                    app_state.rename_item();
-                } else {
-                    app_state.create_item();
-                }
+                // Close first so that a failed action can reopen the dialog with an error.
                 app_state.show_input_dialog = false;
+                match app_state.input_mode {
+                    InputMode::Rename => app_state.rename_item(),
+                    InputMode::Open => app_state.open_location_from_input(),
+                    InputMode::Offset => app_state.seek_preview_from_input(),
+                    _ => app_state.create_item(),
+                }
                 return true;
             }
             KeyCode::Esc => {
@@ -173,24 +227,33 @@ pub fn handle_key_press(key: KeyEvent, app_state: &mut AppState) -> bool {
                         }
                     }
                 },
-                KeyCode::Char('h') | KeyCode::Left => {
-                    if app_state.focus == FocusBlock::Middle {
-                        let show_hidden = app_state.show_hidden_files;
-                        app_state.get_active_tab_mut().leave_directory(show_hidden);
-                    }
+                KeyCode::Char('h') | KeyCode::Left if app_state.focus == FocusBlock::Middle => {
+                    let show_hidden = app_state.show_hidden_files;
+                    app_state.get_active_tab_mut().leave_directory(show_hidden);
                 },
-                KeyCode::Char('l') | KeyCode::Right | KeyCode::Enter => {
-                    if app_state.focus == FocusBlock::Middle {
-                        let show_hidden = app_state.show_hidden_files;
-                        app_state.get_active_tab_mut().enter_directory(show_hidden);
-                    }
+                KeyCode::Char('l') | KeyCode::Right | KeyCode::Enter if app_state.focus == FocusBlock::Middle => {
+                    let show_hidden = app_state.show_hidden_files;
+                    app_state.get_active_tab_mut().enter_directory(show_hidden);
                 },
+                KeyCode::Char('l') | KeyCode::Right | KeyCode::Enter => app_state.open_left_pane_selection(),
+                KeyCode::Char('o') => {
+                    app_state.input_mode = InputMode::Open;
+                    app_state.input_buffer.clear();
+                    app_state.show_input_dialog = true;
+                    app_state.input_dialog_error = None;
+                    return true;
+                }
                 KeyCode::Char('y') => app_state.yank_selection(),
                 KeyCode::Char('x') => app_state.cut_selection(),
                 KeyCode::Char('d') => app_state.delete_selection(),
                 KeyCode::Char('p') => app_state.paste(),
                 KeyCode::Char('m') => app_state.add_bookmark(),
                 KeyCode::Char('r') => app_state.rename_selection(),
+                KeyCode::Char('e') => app_state.extract_selection(),
+                KeyCode::Char('z') => app_state.get_active_tab_mut().toggle_preview_fold(),
+                KeyCode::Char('Z') => app_state.get_active_tab_mut().toggle_all_preview_folds(),
+                KeyCode::Char('v') => app_state.get_active_tab_mut().toggle_markdown_source(),
+                KeyCode::Char('F') => app_state.toggle_follow(),
                 _ => {}
             }
         },
@@ -214,7 +277,7 @@ pub fn handle_key_press(key: KeyEvent, app_state: &mut AppState) -> bool {
                 return true;
             }
         },
-        InputMode::Rename => {
+        InputMode::Rename | InputMode::Open | InputMode::Offset => {
             // Handled by the `show_input_dialog` block
         }
     }
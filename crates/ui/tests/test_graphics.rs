@@ -0,0 +1,339 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use config::{BackendType, PreviewConfig};
+use image::{Rgba, RgbaImage};
+use ratatui::buffer::Buffer;
+use ratatui::prelude::{Color, Rect};
+use ratatui::widgets::Widget;
+use std::collections::HashMap;
+use rtfm_core::preview::{ImagePreview, Preview};
+use std::sync::Arc;
+use ui::graphics::detect::{parse_replies, queries, tmux_passthrough, Replies};
+use ui::graphics::halfblock::indexed_color;
+use ui::graphics::{fit, kitty, sixel, Capabilities, CellSize, ColorMode, Fit, HalfBlockImage, ImageLayer};
+
+const CELL: CellSize = CellSize { width: 10, height: 20 };
+const ALL: Capabilities = Capabilities { kitty: true, sixel: true, truecolor: true, tmux: false };
+
+fn image_preview(width: u32, height: u32) -> Arc<Preview> {
+    Arc::new(Preview::Image(ImagePreview {
+        description: format!("PNG image, {}x{}", width, height),
+        width,
+        height,
+        pixels: RgbaImage::from_pixel(width, height, Rgba([255, 0, 0, 255])),
+    }))
+}
+
+/// Splits output into the escape sequences of the graphics protocol.
+fn graphics_commands(output: &[u8]) -> Vec<String> {
+    let output = String::from_utf8_lossy(output);
+    output.split("\x1b_G").skip(1).map(|command| command.split("\x1b\\").next().unwrap().to_string()).collect()
+}
+
+#[test]
+fn test_kitty_transmit_and_delete() {
+    let pixel = RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 255]));
+    assert_eq!(
+        kitty::transmit(&pixel, 7, 2, 1),
+        b"\x1b_Ga=T,f=32,s=1,v=1,i=7,c=2,r=1,C=1,q=2;/wAA/w==\x1b\\".to_vec()
+    );
+    assert_eq!(kitty::delete(7), b"\x1b_Ga=d,d=I,i=7,q=2\x1b\\".to_vec());
+
+    // 40x40 RGBA is 8536 base64 characters, sent in three chunks.
+    let image = RgbaImage::from_fn(40, 40, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+    let commands = graphics_commands(&kitty::transmit(&image, 3, 4, 2));
+    assert_eq!(commands.len(), 3);
+    assert!(commands[0].starts_with("a=T,f=32,s=40,v=40,i=3,c=4,r=2,C=1,q=2,m=1;"));
+    assert!(commands[1].starts_with("m=1;"));
+    assert!(commands[2].starts_with("m=0;"));
+    let payload: String = commands.iter().map(|command| command.split_once(';').unwrap().1).collect();
+    assert_eq!(commands[0].split_once(';').unwrap().1.len(), 4096);
+    assert_eq!(BASE64.decode(payload).unwrap(), image.into_raw());
+}
+
+#[test]
+fn test_images_fit_the_pane_without_stretching() {
+    let area = Rect::new(0, 0, 10, 10);
+    // Too wide: scaled down to the pane's 100 pixels of width.
+    assert_eq!(fit(200, 100, area, CELL), Fit { width: 100, height: 50, cols: 10, rows: 3 });
+    // Too tall: scaled to 200 pixels of height.
+    assert_eq!(fit(100, 400, area, CELL), Fit { width: 50, height: 200, cols: 5, rows: 10 });
+    // Small images aren't enlarged.
+    assert_eq!(fit(15, 15, area, CELL), Fit { width: 15, height: 15, cols: 2, rows: 1 });
+}
+
+#[test]
+fn test_image_layer_replaces_and_deletes_images() {
+    let config = PreviewConfig::default();
+    let mut layer = ImageLayer::new(CELL, ALL);
+    let area = Rect::new(40, 2, 30, 20);
+    let first = image_preview(20, 10);
+    let second = image_preview(30, 30);
+
+    let mut out = Vec::new();
+    layer.show(&mut out, Some((&first, area)), &config).unwrap();
+    assert!(out.starts_with(b"\x1b7\x1b[3;41H"), "{:?}", String::from_utf8_lossy(&out));
+    assert!(out.ends_with(b"\x1b8"));
+    let commands = graphics_commands(&out);
+    assert_eq!(commands.len(), 1);
+    assert_eq!(commands[0].split_once(';').unwrap().0, "a=T,f=32,s=20,v=10,i=1,c=2,r=1,C=1,q=2");
+
+    // Redrawing the same image in the same place sends nothing.
+    out.clear();
+    layer.show(&mut out, Some((&first, area)), &config).unwrap();
+    assert!(out.is_empty());
+
+    // Moving to another image deletes the first one.
+    layer.show(&mut out, Some((&second, area)), &config).unwrap();
+    let commands = graphics_commands(&out);
+    assert_eq!(commands[0], "a=d,d=I,i=1,q=2");
+    assert!(commands[1].starts_with("a=T,f=32,s=30,v=30,i=2,"));
+
+    // And to something else clears the pane.
+    out.clear();
+    let text = Arc::new(Preview::Text("hello".to_string()));
+    layer.show(&mut out, Some((&text, area)), &config).unwrap();
+    assert_eq!(graphics_commands(&out), ["a=d,d=I,i=2,q=2"]);
+    out.clear();
+    layer.show(&mut out, None, &config).unwrap();
+    assert!(out.is_empty());
+
+    // Documents can come with a picture of their first page.
+    let Preview::Image(page) = image_preview(30, 40).as_ref().clone() else { unreachable!() };
+    let document = Arc::new(Preview::Document { text: "Pages: 1".to_string(), image: Some(page) });
+    layer.show(&mut out, Some((&document, area)), &config).unwrap();
+    assert!(graphics_commands(&out)[0].starts_with("a=T,f=32,s=30,v=40,i=3,"));
+}
+
+#[test]
+fn test_progressive_rendering_sends_a_low_res_pass_first() {
+    let config = PreviewConfig { progressive: true, ..PreviewConfig::default() };
+    let mut layer = ImageLayer::new(CELL, ALL);
+    let mut out = Vec::new();
+    layer.show(&mut out, Some((&image_preview(200, 160), Rect::new(0, 0, 40, 20))), &config).unwrap();
+
+    let commands: Vec<String> = graphics_commands(&out)
+        .into_iter()
+        .filter(|command| command.starts_with("a="))
+        .map(|command| command.split(';').next().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        commands,
+        [
+            "a=T,f=32,s=50,v=40,i=2,c=20,r=8,C=1,q=2,m=1",
+            "a=T,f=32,s=200,v=160,i=1,c=20,r=8,C=1,q=2,m=1",
+            "a=d,d=I,i=2,q=2",
+        ]
+    );
+}
+
+#[test]
+fn test_sixel_encoding() {
+    let red = Rgba([255, 0, 0, 255]);
+    let blue = Rgba([0, 0, 255, 255]);
+    let clear = Rgba([0, 0, 0, 0]);
+
+    let image = RgbaImage::from_vec(2, 1, [red.0, blue.0].concat()).unwrap();
+    assert_eq!(
+        String::from_utf8(sixel::encode(&image, 256)).unwrap(),
+        "\x1bP0;1q\"1;1;2;1#0;2;100;0;0#1;2;0;0;100#0@$#1?@\x1b\\"
+    );
+
+    // Runs are compressed, transparent pixels are skipped and rows past the
+    // sixth start a new band.
+    let image = RgbaImage::from_fn(8, 7, |x, y| match (x, y) {
+        (0, _) => clear,
+        (_, 6) => blue,
+        _ => red,
+    });
+    assert_eq!(
+        String::from_utf8(sixel::encode(&image, 256)).unwrap(),
+        "\x1bP0;1q\"1;1;8;7#0;2;100;0;0#1;2;0;0;100#0?!7~-#1?!7@\x1b\\"
+    );
+}
+
+#[test]
+fn test_sixel_palette_is_quantized() {
+    let image = RgbaImage::from_fn(64, 64, |x, y| Rgba([(x * 4) as u8, (y * 4) as u8, 128, 255]));
+    let encoded = String::from_utf8(sixel::encode(&image, 16)).unwrap();
+    let palette: Vec<&str> = encoded.split('#').filter(|entry| entry.contains(";2;")).collect();
+    assert_eq!(palette.len(), 16);
+    assert!(encoded.starts_with("\x1bP0;1q\"1;1;64;64#0;2;"));
+    assert!(encoded.ends_with("\x1b\\"));
+    // 64 rows make 11 bands of six.
+    assert_eq!(encoded.matches('-').count(), 10);
+}
+
+#[test]
+fn test_sixel_images_are_erased_by_blanking_cells() {
+    let config = PreviewConfig { backend: BackendType::Sixel, ..PreviewConfig::default() };
+    let mut layer = ImageLayer::new(CELL, ALL);
+    let area = Rect::new(40, 2, 3, 2);
+    let image = image_preview(20, 10);
+
+    let mut out = Vec::new();
+    layer.show(&mut out, Some((&image, area)), &config).unwrap();
+    let output = String::from_utf8(out.clone()).unwrap();
+    assert!(output.starts_with("\x1b7\x1b[3;41H\x1bP0;1q\"1;1;20;10#0;2;100;0;0#0!20~-#0!20N\x1b\\"), "{:?}", output);
+    assert!(output.ends_with("\x1b8"));
+    assert!(layer.blanks_cells());
+
+    out.clear();
+    layer.retain(&mut out, Some(&image)).unwrap();
+    assert!(out.is_empty());
+    layer.retain(&mut out, None).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\x1b7\x1b[3;41H\x1b[3X\x1b[4;41H\x1b[3X\x1b8");
+    assert_eq!(layer.area(), None);
+
+    // Drawn again the same, e.g. once a dialog over it is closed.
+    let mut again = Vec::new();
+    layer.show(&mut again, Some((&image, area)), &config).unwrap();
+    assert_eq!(String::from_utf8(again).unwrap(), output);
+}
+
+#[test]
+fn test_half_block_rendering() {
+    let red = Rgba([255, 0, 0, 255]);
+    let blue = Rgba([0, 0, 255, 255]);
+    // Red on top, blue below and a transparent corner, two pixels per cell.
+    let image = RgbaImage::from_fn(2, 4, |x, y| match (x, y) {
+        (1, 3) => Rgba([0, 0, 0, 0]),
+        (_, 0 | 1) => red,
+        _ => blue,
+    });
+    let area = Rect::new(1, 1, 4, 4);
+    let mut buffer = Buffer::empty(Rect::new(0, 0, 6, 6));
+    HalfBlockImage::new(&image, ColorMode::TrueColor).render(area, &mut buffer);
+
+    let cell = |x, y| buffer.get(x, y).clone();
+    assert_eq!((cell(1, 1).symbol(), cell(1, 1).fg, cell(1, 1).bg), ("▀", Color::Rgb(255, 0, 0), Color::Rgb(255, 0, 0)));
+    assert_eq!((cell(1, 2).fg, cell(1, 2).bg), (Color::Rgb(0, 0, 255), Color::Rgb(0, 0, 255)));
+    assert_eq!((cell(2, 2).fg, cell(2, 2).bg), (Color::Rgb(0, 0, 255), Color::Reset));
+    // The image isn't enlarged, so the rest of the area is untouched.
+    assert_eq!(cell(3, 1).symbol(), " ");
+    assert_eq!(cell(1, 3).symbol(), " ");
+
+    let mut buffer = Buffer::empty(Rect::new(0, 0, 6, 6));
+    HalfBlockImage::new(&image, ColorMode::Indexed256).render(area, &mut buffer);
+    assert_eq!((buffer.get(1, 1).fg, buffer.get(1, 2).bg), (Color::Indexed(196), Color::Indexed(21)));
+}
+
+#[test]
+fn test_indexed_colors() {
+    assert_eq!(indexed_color(0, 0, 0), 16);
+    assert_eq!(indexed_color(255, 255, 255), 231);
+    assert_eq!(indexed_color(255, 135, 0), 208);
+    // Grays go to the ramp, which is finer than the cube's diagonal.
+    assert_eq!(indexed_color(128, 128, 128), 244);
+    assert_eq!(indexed_color(30, 30, 30), 234);
+}
+
+#[test]
+fn test_backend_falls_back_to_half_blocks() {
+    let env = |vars: &[(&str, &str)]| {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Capabilities::from_env(move |name| vars.get(name).cloned())
+    };
+
+    let kitty = env(&[("TERM", "xterm-kitty")]);
+    assert_eq!(kitty, Capabilities { kitty: true, sixel: false, truecolor: true, tmux: false });
+    assert_eq!(kitty.backend(BackendType::Kitty), BackendType::Kitty);
+    assert_eq!(kitty.backend(BackendType::Sixel), BackendType::HalfBlocks);
+
+    let foot = env(&[("TERM", "foot"), ("COLORTERM", "truecolor")]);
+    assert_eq!(foot.backend(BackendType::Sixel), BackendType::Sixel);
+    assert_eq!(foot.backend(BackendType::Kitty), BackendType::HalfBlocks);
+    assert_eq!(foot.color_mode(), ColorMode::TrueColor);
+
+    let tmux = env(&[("TERM", "tmux-256color"), ("TMUX", "/tmp/tmux-1000/default,1,0"), ("KITTY_WINDOW_ID", "1")]);
+    assert_eq!(tmux, Capabilities { kitty: false, sixel: false, truecolor: true, tmux: true });
+    let plain = env(&[("TERM", "xterm-256color")]);
+    assert_eq!(plain.backend(BackendType::Kitty), BackendType::HalfBlocks);
+    assert_eq!(plain.color_mode(), ColorMode::Indexed256);
+
+    // Half blocks are part of the frame: the layer sends nothing.
+    let mut layer = ImageLayer::new(CELL, plain);
+    let mut out = Vec::new();
+    layer.show(&mut out, Some((&image_preview(20, 10), Rect::new(0, 0, 10, 10))), &PreviewConfig::default()).unwrap();
+    assert!(out.is_empty());
+    assert_eq!(layer.area(), None);
+}
+
+#[test]
+fn test_terminal_queries() {
+    assert_eq!(queries(false), b"\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[16t\x1b[c".to_vec());
+    assert_eq!(
+        queries(true),
+        b"\x1bPtmux;\x1b\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\x1b\\\x1b\\\x1b[16t\x1b[c".to_vec()
+    );
+    assert_eq!(tmux_passthrough(b"\x1b_Ga=d\x1b\\"), b"\x1bPtmux;\x1b\x1b_Ga=d\x1b\x1b\\\x1b\\".to_vec());
+}
+
+#[test]
+fn test_parse_terminal_replies() {
+    // kitty: all three answered.
+    assert_eq!(
+        parse_replies(b"\x1b_Gi=31;OK\x1b\\\x1b[6;20;10t\x1b[?62;22c", false),
+        Replies { kitty: true, sixel: false, cell: Some(CELL), answered: true, complete: true }
+    );
+    // foot: no Kitty, Sixel in DA1, no cell size.
+    assert_eq!(
+        parse_replies(b"\x1b[?62;4;22;28c", false),
+        Replies { kitty: false, sixel: true, cell: None, answered: true, complete: true }
+    );
+    // An error for the Kitty query, with a key press mixed in.
+    assert_eq!(
+        parse_replies(b"\x1b_Gi=31;ENOTSUPPORTED:no\x1b\\j\x1b[?1;2c", false),
+        Replies { kitty: false, sixel: false, cell: None, answered: true, complete: true }
+    );
+    // Still waiting for DA1, and a reply cut off midway.
+    assert_eq!(
+        parse_replies(b"\x1b_Gi=31;OK\x1b\\\x1b[?62;4", false),
+        Replies { kitty: true, sixel: false, cell: None, answered: false, complete: false }
+    );
+
+    // tmux answers DA1 before the outer terminal's Kitty reply comes back
+    // through it, so that's waited for too.
+    let (da1, kitty) = (b"\x1b[?1;2;4c", b"\x1b_Gi=31;OK\x1b\\");
+    assert_eq!(
+        parse_replies(da1, true),
+        Replies { kitty: false, sixel: true, cell: None, answered: true, complete: false }
+    );
+    let replies = parse_replies(&[&da1[..], kitty].concat(), true);
+    assert_eq!(replies, Replies { kitty: true, sixel: true, cell: None, answered: true, complete: true });
+    let env = Capabilities::from_env(|name| (name == "TMUX").then(|| "/tmp/tmux-0/default".to_string()));
+    assert_eq!(env.with_replies(&replies).backend(BackendType::Auto), BackendType::Kitty);
+}
+
+#[test]
+fn test_replies_decide_the_auto_backend() {
+    let env = Capabilities { kitty: false, sixel: false, truecolor: true, tmux: false };
+    assert_eq!(env.backend(BackendType::Auto), BackendType::HalfBlocks);
+
+    let xterm = env.with_replies(&parse_replies(b"\x1b[?63;1;2;4;6;9;15;22c", false));
+    assert_eq!(xterm.backend(BackendType::Auto), BackendType::Sixel);
+    let wezterm = env.with_replies(&parse_replies(b"\x1b_Gi=31;OK\x1b\\\x1b[?65;4;6;18;22c", false));
+    assert_eq!(wezterm.backend(BackendType::Auto), BackendType::Kitty);
+    assert_eq!(wezterm.backend(BackendType::Sixel), BackendType::Sixel);
+
+    // No answer: the guess from the environment stands.
+    let kitty = Capabilities { kitty: true, ..env };
+    assert_eq!(kitty.with_replies(&Replies::default()), kitty);
+    assert_eq!(kitty.with_replies(&parse_replies(b"\x1b[?62c", false)).backend(BackendType::Auto), BackendType::HalfBlocks);
+}
+
+#[test]
+fn test_kitty_images_are_passed_through_tmux() {
+    let tmux = Capabilities { tmux: true, ..ALL };
+    let mut layer = ImageLayer::new(CELL, tmux);
+    let mut out = Vec::new();
+    let image = image_preview(2, 2);
+    layer.show(&mut out, Some((&image, Rect::new(0, 0, 10, 10))), &PreviewConfig::default()).unwrap();
+    let output = String::from_utf8(out.clone()).unwrap();
+    assert!(output.starts_with("\x1b7\x1b[1;1H\x1bPtmux;\x1b\x1b_Ga=T,f=32,s=2,v=2,i=1,"), "{:?}", output);
+    assert!(output.ends_with("\x1b\x1b\\\x1b\\\x1b8"), "{:?}", output);
+
+    out.clear();
+    layer.clear(&mut out).unwrap();
+    assert_eq!(out, b"\x1bPtmux;\x1b\x1b_Ga=d,d=I,i=1,q=2\x1b\x1b\\\x1b\\".to_vec());
+}